@@ -0,0 +1,339 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use axum::http::{HeaderMap, Request, Response, Version, header};
+use chrono::{DateTime, NaiveDate, Utc};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config::{AccessLogConfig, AccessLogFormat};
+use crate::metrics::increment_access_log_dropped;
+use crate::utils::log_redaction;
+
+enum Command {
+    Line(String),
+    /// Close and reopen the file, e.g. after logrotate moved it away
+    Reopen(oneshot::Sender<io::Result<()>>),
+}
+
+/// Handle to the access log writer task. Requests never wait on the disk: lines are
+/// queued, and dropped once `buffer_lines` are pending.
+#[derive(Clone)]
+pub struct AccessLog {
+    format: AccessLogFormat,
+    sender: mpsc::Sender<Command>,
+}
+
+impl AccessLog {
+    /// Open the log file and spawn the task writing to it
+    pub async fn open(config: &AccessLogConfig) -> io::Result<Self> {
+        let writer = Writer::open(config.clone()).await?;
+        let (sender, receiver) = mpsc::channel(config.buffer_lines.max(1));
+        tokio::spawn(writer.run(receiver));
+        Ok(Self {
+            format: config.format,
+            sender,
+        })
+    }
+
+    /// Queue one line for `entry`, completed by `response`
+    pub fn log<B>(&self, entry: &AccessLogEntry, response: &Response<B>) {
+        let bytes = content_length(response.headers());
+        let line = entry.format(
+            self.format,
+            response.status().as_u16(),
+            bytes,
+            entry.started.elapsed(),
+        );
+        if self.sender.try_send(Command::Line(line)).is_err() {
+            increment_access_log_dropped();
+        }
+    }
+
+    /// Reopen the log file once the lines queued so far are written
+    pub async fn reopen(&self) -> io::Result<()> {
+        let (ack, done) = oneshot::channel();
+        let closed = || io::Error::other("access log writer has stopped");
+        self.sender
+            .send(Command::Reopen(ack))
+            .await
+            .map_err(|_| closed())?;
+        done.await.map_err(|_| closed())?
+    }
+}
+
+/// What the access log needs from a request, taken before it is handed off
+pub struct AccessLogEntry {
+    remote_addr: SocketAddr,
+    method: String,
+    uri: String,
+    version: Version,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    time: DateTime<Utc>,
+    started: Instant,
+}
+
+impl AccessLogEntry {
+    pub fn new<B>(req: &Request<B>, remote_addr: SocketAddr) -> Self {
+        let header = |name: header::HeaderName| {
+            req.headers()
+                .get(&name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| log_redaction::header_value(name.as_str(), value).to_string())
+        };
+        Self {
+            remote_addr,
+            method: req.method().to_string(),
+            uri: req
+                .uri()
+                .path_and_query()
+                .map_or_else(|| req.uri().path().to_string(), |pq| pq.to_string()),
+            version: req.version(),
+            referer: header(header::REFERER),
+            user_agent: header(header::USER_AGENT),
+            time: Utc::now(),
+            started: Instant::now(),
+        }
+    }
+
+    fn format(
+        &self,
+        format: AccessLogFormat,
+        status: u16,
+        bytes: Option<u64>,
+        duration: Duration,
+    ) -> String {
+        let duration_ms = duration.as_secs_f64() * 1000.0;
+        match format {
+            AccessLogFormat::Combined => format!(
+                "{} - - [{}] \"{} {} {:?}\" {} {} \"{}\" \"{}\" {:.3}\n",
+                self.remote_addr.ip(),
+                self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+                escape(&self.method),
+                escape(&self.uri),
+                self.version,
+                status,
+                bytes.map_or_else(|| "-".to_string(), |bytes| bytes.to_string()),
+                self.referer
+                    .as_deref()
+                    .map_or_else(|| "-".to_string(), escape),
+                self.user_agent
+                    .as_deref()
+                    .map_or_else(|| "-".to_string(), escape),
+                duration_ms
+            ),
+            AccessLogFormat::Json => {
+                let line = serde_json::json!({
+                    "time": self.time.to_rfc3339(),
+                    "remote_addr": self.remote_addr.ip().to_string(),
+                    "method": self.method,
+                    "uri": self.uri,
+                    "version": format!("{:?}", self.version),
+                    "status": status,
+                    "bytes": bytes,
+                    "referer": self.referer,
+                    "user_agent": self.user_agent,
+                    "duration_ms": duration_ms,
+                });
+                format!("{line}\n")
+            }
+        }
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Keep client-supplied values from breaking out of their quoted field
+fn escape(value: &str) -> String {
+    value.escape_default().to_string()
+}
+
+struct Writer {
+    config: AccessLogConfig,
+    file: BufWriter<File>,
+    size: u64,
+    opened_on: NaiveDate,
+}
+
+impl Writer {
+    async fn open(config: AccessLogConfig) -> io::Result<Self> {
+        let file = open_append(Path::new(&config.path)).await?;
+        let size = file.metadata().await?.len();
+        Ok(Self {
+            config,
+            file: BufWriter::new(file),
+            size,
+            opened_on: Utc::now().date_naive(),
+        })
+    }
+
+    async fn run(mut self, mut receiver: mpsc::Receiver<Command>) {
+        while let Some(command) = receiver.recv().await {
+            self.handle(command).await;
+            // Write whatever else is queued before paying for a flush
+            while let Ok(command) = receiver.try_recv() {
+                self.handle(command).await;
+            }
+            if let Err(e) = self.file.flush().await {
+                tracing::error!("Failed to write access log {}: {}", self.config.path, e);
+            }
+        }
+    }
+
+    async fn handle(&mut self, command: Command) {
+        match command {
+            Command::Line(line) => {
+                if let Err(e) = self.write(line.as_bytes()).await {
+                    tracing::error!("Failed to write access log {}: {}", self.config.path, e);
+                }
+            }
+            Command::Reopen(ack) => {
+                let result = self.reopen().await;
+                match &result {
+                    Ok(()) => tracing::info!("Reopened access log {}", self.config.path),
+                    Err(e) => {
+                        tracing::error!("Failed to reopen access log {}: {}", self.config.path, e)
+                    }
+                }
+                let _ = ack.send(result);
+            }
+        }
+    }
+
+    async fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.due_for_rotation(line.len() as u64) {
+            self.rotate().await?;
+        }
+        self.file.write_all(line).await?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn due_for_rotation(&self, next_line: u64) -> bool {
+        let too_big = self
+            .config
+            .max_size_bytes
+            .is_some_and(|max| self.size > 0 && self.size + next_line > max);
+        let new_day = self.config.rotate_daily && Utc::now().date_naive() != self.opened_on;
+        too_big || new_day
+    }
+
+    /// Shift `<path>.N` to `<path>.N+1`, dropping the oldest, and start a fresh file
+    async fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush().await?;
+        let path = PathBuf::from(&self.config.path);
+        if self.config.max_files == 0 {
+            tokio::fs::remove_file(&path).await?;
+        } else {
+            for n in (1..self.config.max_files).rev() {
+                match tokio::fs::rename(rotated(&path, n), rotated(&path, n + 1)).await {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            tokio::fs::rename(&path, rotated(&path, 1)).await?;
+        }
+        self.reopen().await
+    }
+
+    async fn reopen(&mut self) -> io::Result<()> {
+        self.file.flush().await?;
+        let file = open_append(Path::new(&self.config.path)).await?;
+        self.size = file.metadata().await?.len();
+        self.file = BufWriter::new(file);
+        self.opened_on = Utc::now().date_naive();
+        Ok(())
+    }
+}
+
+async fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{n}"));
+    rotated.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combined_format() {
+        let req = Request::builder()
+            .method("GET")
+            .uri("/api/users?page=2")
+            .header(header::USER_AGENT, "curl/8.0 \"quoted\"")
+            .body(())
+            .unwrap();
+        let entry = AccessLogEntry::new(&req, "10.0.0.1:5555".parse().unwrap());
+
+        let line = entry.format(
+            AccessLogFormat::Combined,
+            200,
+            Some(512),
+            Duration::from_millis(12),
+        );
+        assert!(line.starts_with("10.0.0.1 - - ["), "{line}");
+        assert!(
+            line.ends_with(
+                "\"GET /api/users?page=2 HTTP/1.1\" 200 512 \"-\" \"curl/8.0 \\\"quoted\\\"\" 12.000\n"
+            ),
+            "{line}"
+        );
+
+        let line = entry.format(AccessLogFormat::Json, 404, None, Duration::ZERO);
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["status"], 404);
+        assert_eq!(json["uri"], "/api/users?page=2");
+        assert!(json["bytes"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_rotates_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let config = AccessLogConfig {
+            path: path.to_string_lossy().into_owned(),
+            format: AccessLogFormat::Combined,
+            max_size_bytes: Some(100),
+            rotate_daily: false,
+            max_files: 2,
+            buffer_lines: 16,
+        };
+        let access_log = AccessLog::open(&config).await.unwrap();
+        let req = Request::builder().uri("/").body(()).unwrap();
+        let response = Response::new(());
+        for _ in 0..4 {
+            access_log.log(
+                &AccessLogEntry::new(&req, "127.0.0.1:1".parse().unwrap()),
+                &response,
+            );
+        }
+        // Queued behind the lines, so they are all written once it returns
+        access_log.reopen().await.unwrap();
+
+        // Each line is over half the limit, so every one starts a new file
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert!(rotated(&path, 1).exists());
+        assert!(rotated(&path, 2).exists());
+        assert!(!rotated(&path, 3).exists());
+    }
+}
@@ -2,26 +2,437 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
 
-use anyhow::{Context, Result, anyhow};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, anyhow, bail};
+use aws_lc_rs::rand::SystemRandom;
+use aws_lc_rs::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair};
+use base64::Engine;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use futures_util::future::BoxFuture;
+use http_body_util::{BodyExt, Full};
+use hyper::{Method, Request, header};
 use instant_acme::{
-    Account, AuthorizationStatus, ChallengeType, Identifier, NewAccount, NewOrder, OrderStatus,
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, ExternalAccountKey,
+    Identifier, NewAccount, NewOrder, Order, OrderStatus,
 };
 use rcgen::CertificateParams;
-use tokio::time::sleep;
-use tracing::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::time::{Instant, sleep};
+use tracing::{debug, error, info, warn};
+
+use crate::adapters::{dns, tls};
+use crate::config::models::{AcmeChallengeType, AcmeConfig, AcmeDnsConfig, DnsProviderConfig};
+use crate::utils::x509;
+
+/// Default time allowed for a DNS-01 record to become visible
+const DEFAULT_PROPAGATION_TIMEOUT: Duration = Duration::from_secs(120);
+/// TTL of the DNS-01 TXT records, kept short so stale values expire quickly
+const CHALLENGE_RECORD_TTL: u32 = 60;
+/// Directory under the storage path holding one subdirectory per certificate
+const CERTIFICATES_DIR: &str = "certs";
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+const METADATA_FILE: &str = "metadata.json";
+
+/// Publishes and removes the `_acme-challenge` TXT records of DNS-01 challenges
+pub trait DnsProvider: Send + Sync {
+    /// Add `value` to the TXT records of `name`, keeping any other values
+    fn create_txt_record<'a>(&'a self, name: &'a str, value: &'a str) -> BoxFuture<'a, Result<()>>;
+
+    /// Remove the `value` TXT record of `name`
+    fn delete_txt_record<'a>(&'a self, name: &'a str, value: &'a str) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Build the provider selected in the configuration
+pub fn dns_provider(config: &DnsProviderConfig) -> Result<Box<dyn DnsProvider>> {
+    match config {
+        DnsProviderConfig::Cloudflare { api_token, zone_id } => Ok(Box::new(
+            CloudflareProvider::new(api_token.clone(), zone_id.clone())?,
+        )),
+        DnsProviderConfig::Rfc2136 {
+            server,
+            zone,
+            tsig_key_name,
+            tsig_secret,
+            tsig_algorithm,
+        } => {
+            let server = AcmeDnsConfig::parse_name_server(server)
+                .ok_or_else(|| anyhow!("Invalid RFC 2136 server address: {server}"))?;
+            let key = match (tsig_key_name, tsig_secret) {
+                (Some(name), Some(secret)) => Some(dns::TsigKey {
+                    name: name.clone(),
+                    algorithm: *tsig_algorithm,
+                    secret: base64::engine::general_purpose::STANDARD
+                        .decode(secret)
+                        .context("Invalid TSIG secret")?,
+                }),
+                _ => None,
+            };
+            Ok(Box::new(Rfc2136Provider {
+                server,
+                zone: zone.clone(),
+                key,
+            }))
+        }
+    }
+}
+
+/// Cloudflare v4 API provider
+pub struct CloudflareProvider {
+    api_base: String,
+    api_token: String,
+    zone_id: Option<String>,
+    client: tls::HttpsClient,
+}
+
+impl CloudflareProvider {
+    pub fn new(api_token: String, zone_id: Option<String>) -> Result<Self> {
+        Ok(Self {
+            api_base: "https://api.cloudflare.com/client/v4".to_string(),
+            api_token,
+            zone_id,
+            client: tls::https_client()?,
+        })
+    }
+
+    /// Point the provider at a fake API
+    #[cfg(test)]
+    fn with_api_base(mut self, api_base: impl Into<String>) -> Self {
+        self.api_base = api_base.into();
+        self
+    }
+
+    /// Call an API endpoint and return the `result` of a successful response
+    async fn call(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value> {
+        let body = body.map_or_else(Bytes::new, |body| Bytes::from(body.to_string()));
+        let request = Request::builder()
+            .method(method.clone())
+            .uri(format!("{}{path}", self.api_base))
+            .header(header::AUTHORIZATION, format!("Bearer {}", self.api_token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Full::new(body))?;
+        let response = self
+            .client
+            .request(request)
+            .await
+            .with_context(|| format!("Cloudflare API request {method} {path} failed"))?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        let mut reply: Value = serde_json::from_slice(&body)
+            .with_context(|| format!("Cloudflare API returned {status} with an invalid body"))?;
+        if reply["success"] != Value::Bool(true) {
+            bail!(
+                "Cloudflare API request {method} {path} failed with {status}: {}",
+                reply["errors"]
+            );
+        }
+        Ok(reply["result"].take())
+    }
+
+    /// The configured zone, or the closest enclosing zone of `name` in the account
+    async fn zone_id(&self, name: &str) -> Result<String> {
+        if let Some(zone_id) = &self.zone_id {
+            return Ok(zone_id.clone());
+        }
+        let mut candidate = name;
+        while let Some((_, parent)) = candidate.split_once('.') {
+            let zones = self
+                .call(Method::GET, &format!("/zones?name={parent}"), None)
+                .await?;
+            if let Some(id) = zones[0]["id"].as_str() {
+                return Ok(id.to_string());
+            }
+            candidate = parent;
+        }
+        bail!("No Cloudflare zone found for {name}")
+    }
+}
+
+impl DnsProvider for CloudflareProvider {
+    fn create_txt_record<'a>(&'a self, name: &'a str, value: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let zone_id = self.zone_id(name).await?;
+            let record = json!({
+                "type": "TXT",
+                "name": name,
+                "content": value,
+                "ttl": CHALLENGE_RECORD_TTL,
+            });
+            self.call(
+                Method::POST,
+                &format!("/zones/{zone_id}/dns_records"),
+                Some(record),
+            )
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn delete_txt_record<'a>(&'a self, name: &'a str, value: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let zone_id = self.zone_id(name).await?;
+            let records = self
+                .call(
+                    Method::GET,
+                    &format!("/zones/{zone_id}/dns_records?type=TXT&name={name}&content={value}"),
+                    None,
+                )
+                .await?;
+            for id in records
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|record| record["id"].as_str())
+            {
+                self.call(
+                    Method::DELETE,
+                    &format!("/zones/{zone_id}/dns_records/{id}"),
+                    None,
+                )
+                .await?;
+            }
+            Ok(())
+        })
+    }
+}
 
-use crate::config::models::AcmeConfig;
+/// RFC 2136 dynamic update provider, e.g. for BIND, Knot or PowerDNS
+pub struct Rfc2136Provider {
+    server: SocketAddr,
+    zone: String,
+    key: Option<dns::TsigKey>,
+}
+
+impl DnsProvider for Rfc2136Provider {
+    fn create_txt_record<'a>(&'a self, name: &'a str, value: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(dns::send_update(
+            self.server,
+            &self.zone,
+            name,
+            value,
+            dns::TxtUpdate::Add {
+                ttl: CHALLENGE_RECORD_TTL,
+            },
+            self.key.as_ref(),
+        ))
+    }
+
+    fn delete_txt_record<'a>(&'a self, name: &'a str, value: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(dns::send_update(
+            self.server,
+            &self.zone,
+            name,
+            value,
+            dns::TxtUpdate::Delete,
+            self.key.as_ref(),
+        ))
+    }
+}
+
+/// Everything needed to answer DNS-01 challenges
+struct DnsChallenge {
+    provider: Box<dyn DnsProvider>,
+    resolvers: Vec<SocketAddr>,
+    propagation_timeout: Duration,
+}
+
+impl DnsChallenge {
+    fn new(config: &AcmeDnsConfig) -> Result<Self> {
+        let resolvers = if config.resolvers.is_empty() {
+            dns::system_resolvers()
+        } else {
+            config
+                .resolvers
+                .iter()
+                .map(|resolver| {
+                    AcmeDnsConfig::parse_name_server(resolver)
+                        .ok_or_else(|| anyhow!("Invalid resolver address: {resolver}"))
+                })
+                .collect::<Result<_>>()?
+        };
+        let propagation_timeout = config
+            .propagation_timeout
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()
+            .context("Invalid propagation_timeout")?
+            .unwrap_or(DEFAULT_PROPAGATION_TIMEOUT);
+        Ok(Self {
+            provider: dns_provider(&config.provider)?,
+            resolvers,
+            propagation_timeout,
+        })
+    }
+
+    /// Poll the resolvers until every one of them returns `value` for `name`
+    async fn wait_for_propagation(&self, name: &str, value: &str) -> Result<()> {
+        let deadline = Instant::now() + self.propagation_timeout;
+        loop {
+            let mut missing = Vec::new();
+            for resolver in &self.resolvers {
+                match dns::query_txt(*resolver, name).await {
+                    Ok(values) if values.iter().any(|v| v == value) => {}
+                    Ok(_) => missing.push(*resolver),
+                    Err(e) => {
+                        debug!(
+                            "Propagation check of {} on {} failed: {}",
+                            name, resolver, e
+                        );
+                        missing.push(*resolver);
+                    }
+                }
+            }
+            if missing.is_empty() {
+                info!("TXT record {} is visible on all resolvers", name);
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                bail!(
+                    "TXT record {} not visible on {:?} after {}",
+                    name,
+                    missing,
+                    humantime::format_duration(self.propagation_timeout)
+                );
+            }
+            sleep(Duration::from_secs(5)).await;
+        }
+    }
+}
+
+/// Problem types meaning the CA no longer accepts the stored account
+const ACCOUNT_REJECTED_PROBLEMS: [&str; 2] = [
+    "urn:ietf:params:acme:error:accountDoesNotExist",
+    "urn:ietf:params:acme:error:unauthorized",
+];
+
+/// ACME account kept in `<storage_path>/account.json` and reused for every order
+#[derive(Serialize, Deserialize)]
+struct StoredAccount {
+    /// Contact registered with the CA, e.g. "mailto:admin@example.com"
+    contact: String,
+    credentials: AccountCredentials,
+}
+
+/// The credential fields `instant-acme` keeps private: account URL, PKCS#8 key and directory
+fn credential_fields(credentials: &AccountCredentials) -> Result<(String, Vec<u8>, String)> {
+    let fields = serde_json::to_value(credentials)?;
+    let field = |name: &str| {
+        fields[name]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Stored ACME account has no {name}"))
+    };
+    let key = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(field("key_pkcs8")?)?;
+    Ok((field("id")?, key, field("directory")?))
+}
+
+/// A JWS-signed ACME request body (RFC 8555 section 6.2) using the account's P-256 key
+fn signed_request(
+    key_pkcs8: &[u8],
+    account_url: &str,
+    nonce: &str,
+    url: &str,
+    payload: &Value,
+) -> Result<Value> {
+    let encode = |bytes: &[u8]| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, key_pkcs8)
+        .map_err(|e| anyhow!("Invalid ACME account key: {e}"))?;
+    let protected = encode(
+        json!({ "alg": "ES256", "kid": account_url, "nonce": nonce, "url": url })
+            .to_string()
+            .as_bytes(),
+    );
+    let payload = encode(payload.to_string().as_bytes());
+    let signature = key
+        .sign(
+            &SystemRandom::new(),
+            format!("{protected}.{payload}").as_bytes(),
+        )
+        .map_err(|_| anyhow!("Failed to sign ACME request"))?;
+    Ok(json!({
+        "protected": protected,
+        "payload": payload,
+        "signature": encode(signature.as_ref()),
+    }))
+}
+
+/// Path prefix the CA fetches HTTP-01 key authorizations from
+pub const HTTP01_CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+/// Key authorizations of the HTTP-01 challenges in flight, keyed by token. Shared with the
+/// request handler, which answers `/.well-known/acme-challenge/<token>` from memory.
+#[derive(Debug, Clone, Default)]
+pub struct Http01Challenges(Arc<DashMap<String, String>>);
+
+impl Http01Challenges {
+    pub fn key_authorization(&self, token: &str) -> Option<String> {
+        self.0.get(token).map(|entry| entry.value().clone())
+    }
+
+    pub fn insert(&self, token: &str, key_authorization: &str) {
+        self.0
+            .insert(token.to_string(), key_authorization.to_string());
+    }
+
+    pub fn remove(&self, token: &str) {
+        self.0.remove(token);
+    }
+}
 
 pub struct AcmeService {
     config: AcmeConfig,
     storage_path: PathBuf,
+    http01_challenges: Http01Challenges,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CertificateInfo {
     pub cert_path: String,
     pub key_path: String,
     pub expires_at: SystemTime,
+    /// DNS names the certificate is valid for
+    pub subject_alt_names: Vec<String>,
+    pub issuer: String,
+}
+
+/// `metadata.json` kept next to each stored certificate, so lookups don't have to parse
+/// every certificate
+#[derive(Debug, Serialize, Deserialize)]
+struct CertificateMetadata {
+    /// DNS names the certificate covers, wildcards included
+    subject_alt_names: Vec<String>,
+    not_after: DateTime<Utc>,
+    issuer: String,
+}
+
+impl CertificateMetadata {
+    fn read(dir: &Path) -> Option<Self> {
+        let data = fs::read(dir.join(METADATA_FILE)).ok()?;
+        serde_json::from_slice(&data)
+            .inspect_err(|e| warn!("Invalid certificate metadata in {:?}: {}", dir, e))
+            .ok()
+    }
+
+    fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(METADATA_FILE);
+        fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Failed to save certificate metadata to {path:?}"))
+    }
+}
+
+impl From<&CertificateInfo> for CertificateMetadata {
+    fn from(info: &CertificateInfo) -> Self {
+        Self {
+            subject_alt_names: info.subject_alt_names.clone(),
+            not_after: info.expires_at.into(),
+            issuer: info.issuer.clone(),
+        }
+    }
 }
 
 impl AcmeService {
@@ -38,22 +449,26 @@ impl AcmeService {
             format!("Failed to create ACME storage directory: {storage_path:?}")
         })?;
 
-        Ok(Self {
+        let service = Self {
             config,
             storage_path,
-        })
+            http01_challenges: Http01Challenges::default(),
+        };
+        service.migrate_flat_layout();
+        Ok(service)
+    }
+
+    /// Publish HTTP-01 key authorizations in `challenges`, the store the handler serves
+    pub fn with_http01_challenges(mut self, challenges: Http01Challenges) -> Self {
+        self.http01_challenges = challenges;
+        self
     }
 
     /// Get the ACME directory URL based on configuration
-    fn get_directory_url(&self) -> &'static str {
-        if let Some(ref _ca_url) = self.config.ca_url {
-            // For custom URLs, we'll need to handle this differently
-            // For now, fall back to Let's Encrypt
-            if self.config.staging.unwrap_or(false) {
-                instant_acme::LetsEncrypt::Staging.url()
-            } else {
-                instant_acme::LetsEncrypt::Production.url()
-            }
+    fn get_directory_url(&self) -> &str {
+        if let Some(ca_url) = &self.config.ca_url {
+            // Any RFC 8555 CA: ZeroSSL, Buypass, step-ca, Pebble...
+            ca_url
         } else if self.config.staging.unwrap_or(false) {
             instant_acme::LetsEncrypt::Staging.url()
         } else {
@@ -61,61 +476,164 @@ impl AcmeService {
         }
     }
 
-    /// Get certificate paths for a domain
-    fn get_cert_paths(&self, domain: &str) -> (PathBuf, PathBuf) {
-        let cert_path = self.storage_path.join(format!("{domain}.crt"));
-        let key_path = self.storage_path.join(format!("{domain}.key"));
-        (cert_path, key_path)
+    /// Directory holding the certificate ordered with `primary_domain` first
+    fn certificate_dir(&self, primary_domain: &str) -> PathBuf {
+        // "*.example.com" is stored as "_.example.com"
+        self.storage_path
+            .join(CERTIFICATES_DIR)
+            .join(primary_domain.replace('*', "_"))
     }
 
-    /// Check if certificate exists and is valid
-    pub fn check_certificate(&self, domain: &str) -> Option<CertificateInfo> {
-        let (cert_path, key_path) = self.get_cert_paths(domain);
+    /// Get the certificate and key paths of the certificate ordered with `primary_domain`
+    /// first
+    pub fn get_cert_paths(&self, primary_domain: &str) -> (PathBuf, PathBuf) {
+        let dir = self.certificate_dir(primary_domain);
+        (dir.join(CERT_FILE), dir.join(KEY_FILE))
+    }
 
-        if !cert_path.exists() || !key_path.exists() {
-            return None;
-        }
+    /// Save a certificate chain and its key under `primary_domain`, along with the metadata
+    /// used to find it again
+    fn store_certificate(
+        &self,
+        primary_domain: &str,
+        cert_chain: &str,
+        key_pem: &str,
+    ) -> Result<CertificateInfo> {
+        let dir = self.certificate_dir(primary_domain);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create certificate directory {dir:?}"))?;
+        let (cert_path, key_path) = self.get_cert_paths(primary_domain);
+        fs::write(&cert_path, cert_chain).with_context(|| "Failed to save certificate")?;
+        fs::write(&key_path, key_pem).with_context(|| "Failed to save private key")?;
+
+        let cert_info =
+            CertificateInfo::load(&cert_path.to_string_lossy(), &key_path.to_string_lossy())?;
+        CertificateMetadata::from(&cert_info).write(&dir)?;
+        Ok(cert_info)
+    }
 
-        // For now, we'll use a simple file modification time check
-        // In a production system, you'd want to parse the certificate and check expiration
-        match fs::metadata(&cert_path) {
-            Ok(metadata) => {
-                if let Ok(modified) = metadata.modified() {
-                    let renewal_threshold_days =
-                        self.config.renewal_days_before_expiry.unwrap_or(30);
+    /// Every stored certificate directory with its metadata. Metadata that is missing is
+    /// rebuilt from the certificate.
+    fn stored_certificates(&self) -> Vec<(PathBuf, CertificateMetadata)> {
+        let Ok(entries) = fs::read_dir(self.storage_path.join(CERTIFICATES_DIR)) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|dir| dir.join(CERT_FILE).exists())
+            .filter_map(|dir| {
+                if let Some(metadata) = CertificateMetadata::read(&dir) {
+                    return Some((dir, metadata));
+                }
+                let certificate = x509::parse_pem_file(&dir.join(CERT_FILE).to_string_lossy())?;
+                let metadata = CertificateMetadata {
+                    subject_alt_names: certificate.dns_names,
+                    not_after: certificate.not_after,
+                    issuer: certificate.issuer,
+                };
+                if let Err(e) = metadata.write(&dir) {
+                    warn!("{:#}", e);
+                }
+                Some((dir, metadata))
+            })
+            .collect()
+    }
 
-                    // Assume certificates are valid for 90 days (Let's Encrypt default)
-                    let expires_at = modified + Duration::from_secs(90 * 24 * 60 * 60);
+    /// The stored certificate valid for `domain`, directly or through a wildcard. When
+    /// several are, the one expiring last wins.
+    pub fn find_certificate(&self, domain: &str) -> Option<CertificateInfo> {
+        let mut candidates: Vec<_> = self
+            .stored_certificates()
+            .into_iter()
+            .filter(|(_, metadata)| {
+                metadata
+                    .subject_alt_names
+                    .iter()
+                    .any(|name| x509::name_matches(name, domain))
+            })
+            .collect();
+        candidates.sort_by_key(|(_, metadata)| std::cmp::Reverse(metadata.not_after));
+        candidates.into_iter().find_map(|(dir, _)| {
+            CertificateInfo::load(
+                &dir.join(CERT_FILE).to_string_lossy(),
+                &dir.join(KEY_FILE).to_string_lossy(),
+            )
+            .inspect_err(|e| warn!("Unusable certificate in {:?}: {:#}", dir, e))
+            .ok()
+        })
+    }
 
-                    if SystemTime::now()
-                        + Duration::from_secs(renewal_threshold_days * 24 * 60 * 60)
-                        < expires_at
-                    {
-                        info!("Valid certificate found for domain: {}", domain);
-                        let cert_info = CertificateInfo {
-                            cert_path: cert_path.to_string_lossy().to_string(),
-                            key_path: key_path.to_string_lossy().to_string(),
-                            expires_at,
-                        };
-                        cert_info.log_info();
-                        return Some(cert_info);
-                    } else {
-                        info!(
-                            "Certificate for domain {} expires soon, needs renewal",
-                            domain
-                        );
-                    }
-                }
+    /// Move certificates saved by earlier versions as `<domain>.crt` and `<domain>.key` at
+    /// the root of the storage directory into their own directory
+    fn migrate_flat_layout(&self) {
+        let Ok(entries) = fs::read_dir(&self.storage_path) else {
+            return;
+        };
+        for cert_path in entries.flatten().map(|entry| entry.path()) {
+            if cert_path
+                .extension()
+                .is_none_or(|extension| extension != "crt")
+            {
+                continue;
             }
-            Err(e) => {
-                warn!(
-                    "Failed to read certificate metadata for domain {}: {}",
-                    domain, e
-                );
+            let key_path = cert_path.with_extension("key");
+            let Some(primary_domain) = cert_path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if !key_path.exists() || self.certificate_dir(primary_domain).exists() {
+                continue;
+            }
+
+            let migrated = fs::read_to_string(&cert_path)
+                .and_then(|cert| Ok((cert, fs::read_to_string(&key_path)?)))
+                .map_err(anyhow::Error::from)
+                .and_then(|(cert, key)| self.store_certificate(primary_domain, &cert, &key));
+            match migrated {
+                Ok(cert_info) => {
+                    info!(
+                        "Migrated certificate {:?} to {}",
+                        cert_path, cert_info.cert_path
+                    );
+                    let _ = fs::remove_file(&cert_path);
+                    let _ = fs::remove_file(&key_path);
+                }
+                Err(e) => warn!("Failed to migrate certificate {:?}: {:#}", cert_path, e),
             }
         }
+    }
+
+    /// Find the certificate valid for `domain`, checking it covers every configured domain,
+    /// matches its key and is not due for renewal
+    pub fn check_certificate(&self, domain: &str) -> Option<CertificateInfo> {
+        let cert_info = self.find_certificate(domain)?;
+
+        let uncovered: Vec<&String> = self
+            .config
+            .domains
+            .iter()
+            .filter(|d| !cert_info.covers(d))
+            .collect();
+        if !uncovered.is_empty() {
+            info!(
+                "Certificate for domain {} does not cover {:?}, needs renewal",
+                domain, uncovered
+            );
+            return None;
+        }
 
-        None
+        let renewal_threshold_days = self.config.renewal_days_before_expiry.unwrap_or(30);
+        if cert_info.expires_within_days(renewal_threshold_days) {
+            info!(
+                "Certificate for domain {} expires soon, needs renewal",
+                domain
+            );
+            return None;
+        }
+
+        info!("Valid certificate found for domain: {}", domain);
+        cert_info.log_info();
+        Some(cert_info)
     }
 
     /// Request a new certificate for the given domains
@@ -127,33 +645,46 @@ impl AcmeService {
         let primary_domain = &domains[0];
         info!("Requesting certificate for domains: {:?}", domains);
 
-        // Create account
-        let directory_url = self.get_directory_url();
-        let (account, _credentials) = Account::create(
-            &NewAccount {
-                contact: &[&format!("mailto:{email}", email = self.config.email)],
-                terms_of_service_agreed: true,
-                only_return_existing: false,
-            },
-            directory_url,
-            None,
-        )
-        .await
-        .context("Failed to create ACME account")?;
-
         // Create identifiers for all domains
         let identifiers: Vec<Identifier> = domains
             .iter()
             .map(|domain| Identifier::Dns(domain.clone()))
             .collect();
+        let new_order = NewOrder {
+            identifiers: &identifiers,
+        };
 
-        // Create order
-        let mut order = account
-            .new_order(&NewOrder {
-                identifiers: &identifiers,
-            })
-            .await
-            .context("Failed to create new order")?;
+        // Create order, registering a new account if the stored one is no longer accepted
+        let mut order = match self.account().await?.new_order(&new_order).await {
+            Err(instant_acme::Error::Api(problem))
+                if problem
+                    .r#type
+                    .as_deref()
+                    .is_some_and(|kind| ACCOUNT_REJECTED_PROBLEMS.contains(&kind)) =>
+            {
+                warn!(
+                    "Stored ACME account was rejected ({}), registering a new one",
+                    problem
+                );
+                self.create_account()
+                    .await?
+                    .new_order(&new_order)
+                    .await
+                    .context("Failed to create new order")?
+            }
+            result => result.context("Failed to create new order")?,
+        };
+
+        let dns_challenge = match self.config.challenge_type {
+            AcmeChallengeType::Http01 => None,
+            AcmeChallengeType::Dns01 => {
+                let config =
+                    self.config.dns.as_ref().ok_or_else(|| {
+                        anyhow!("dns-01 challenges need an acme.dns configuration")
+                    })?;
+                Some(DnsChallenge::new(config)?)
+            }
+        };
 
         // Process authorizations
         let authorizations = order
@@ -170,97 +701,61 @@ impl AcmeService {
                 continue;
             }
 
-            // Find HTTP-01 challenge
+            let Identifier::Dns(domain) = &authorization.identifier;
+            let wanted = match self.config.challenge_type {
+                AcmeChallengeType::Http01 => ChallengeType::Http01,
+                AcmeChallengeType::Dns01 => ChallengeType::Dns01,
+            };
             let challenge = authorization
                 .challenges
                 .iter()
-                .find(|c| c.r#type == ChallengeType::Http01)
-                .ok_or_else(|| anyhow!("No HTTP-01 challenge found"))?;
-
-            let token = &challenge.token;
+                .find(|c| c.r#type == wanted)
+                .ok_or_else(|| anyhow!("No {:?} challenge found for {}", wanted, domain))?;
             let key_authorization = order.key_authorization(challenge);
 
-            info!(
-                "Setting up HTTP challenge for domain: {:?}",
-                authorization.identifier
-            );
-            info!("Token: {}", token);
-
-            // Create challenge directory and file
-            let well_known_path = Path::new("./static/.well-known/acme-challenge");
-            fs::create_dir_all(well_known_path)
-                .with_context(|| "Failed to create .well-known directory")?;
+            match &dns_challenge {
+                None => {
+                    let token = &challenge.token;
+                    info!("Setting up HTTP challenge for domain: {}", domain);
+                    info!("Token: {}", token);
+                    self.http01_challenges
+                        .insert(token, key_authorization.as_str());
 
-            let challenge_file = well_known_path.join(token);
-            fs::write(&challenge_file, key_authorization.as_str())
-                .with_context(|| "Failed to write challenge file")?;
-
-            info!("Created challenge file: {:?}", challenge_file);
-
-            // Validate challenge
-            order
-                .set_challenge_ready(&challenge.url)
-                .await
-                .context("Failed to validate challenge")?;
+                    let validated =
+                        Self::wait_for_authorization(&mut order, &challenge.url, domain).await;
 
-            // Wait for validation
-            let mut attempts = 0;
-            loop {
-                sleep(Duration::from_secs(2)).await;
-                attempts += 1;
-
-                let updated_authorizations = order
-                    .authorizations()
-                    .await
-                    .context("Failed to get updated authorizations")?;
-
-                let updated_auth = updated_authorizations
-                    .iter()
-                    .find(|auth| auth.identifier == authorization.identifier)
-                    .ok_or_else(|| {
-                        anyhow!(
-                            "Authorization not found for domain: {:?}",
-                            authorization.identifier
-                        )
-                    })?;
+                    // Stop answering the challenge whatever the outcome
+                    self.http01_challenges.remove(token);
+                    validated?;
+                }
+                Some(dns_challenge) => {
+                    let name = format!("_acme-challenge.{domain}");
+                    let value = key_authorization.dns_value();
+                    info!("Creating DNS challenge record {}", name);
+                    dns_challenge
+                        .provider
+                        .create_txt_record(&name, &value)
+                        .await
+                        .with_context(|| format!("Failed to create TXT record {name}"))?;
 
-                match updated_auth.status {
-                    AuthorizationStatus::Valid => {
-                        info!(
-                            "Challenge validated for domain: {:?}",
-                            authorization.identifier
-                        );
-                        break;
-                    }
-                    AuthorizationStatus::Invalid => {
-                        return Err(anyhow!(
-                            "Challenge validation failed for domain: {:?}",
-                            authorization.identifier
-                        ));
-                    }
-                    AuthorizationStatus::Pending => {
-                        if attempts > 30 {
-                            return Err(anyhow!(
-                                "Challenge validation timeout for domain: {:?}",
-                                authorization.identifier
-                            ));
+                    let validated = match dns_challenge.wait_for_propagation(&name, &value).await {
+                        Ok(()) => {
+                            Self::wait_for_authorization(&mut order, &challenge.url, domain).await
                         }
-                        continue;
-                    }
-                    _ => {
-                        if attempts > 30 {
-                            return Err(anyhow!(
-                                "Challenge validation timeout for domain: {:?}",
-                                authorization.identifier
-                            ));
-                        }
-                        continue;
+                        Err(e) => Err(e),
+                    };
+
+                    // Remove the record whether or not validation succeeded
+                    if let Err(e) = dns_challenge
+                        .provider
+                        .delete_txt_record(&name, &value)
+                        .await
+                    {
+                        warn!("Failed to remove TXT record {}: {:#}", name, e);
                     }
+                    validated?;
                 }
             }
-
-            // Clean up challenge file
-            let _ = fs::remove_file(&challenge_file);
         }
 
         // Generate CSR using rcgen 0.13 API
@@ -289,27 +784,16 @@ impl AcmeService {
                         .await
                         .context("Failed to download certificate")?
                     {
-                        // Save certificate and private key
-                        let (cert_path, key_path) = self.get_cert_paths(primary_domain);
-
-                        fs::write(&cert_path, &cert_chain)
-                            .with_context(|| "Failed to save certificate")?;
-                        fs::write(&key_path, key_pair.serialize_pem())
-                            .with_context(|| "Failed to save private key")?;
-
+                        let cert_info = self.store_certificate(
+                            primary_domain,
+                            &cert_chain,
+                            &key_pair.serialize_pem(),
+                        )?;
                         info!(
-                            "Certificate saved for domain: {} at {:?}",
-                            primary_domain, cert_path
+                            "Certificate saved for domain: {} at {}",
+                            primary_domain, cert_info.cert_path
                         );
-
-                        // Calculate expiration time (Let's Encrypt certificates are valid for 90 days)
-                        let expires_at = SystemTime::now() + Duration::from_secs(90 * 24 * 60 * 60);
-
-                        return Ok(CertificateInfo {
-                            cert_path: cert_path.to_string_lossy().to_string(),
-                            key_path: key_path.to_string_lossy().to_string(),
-                            expires_at,
-                        });
+                        return Ok(cert_info);
                     } else {
                         return Err(anyhow!("Order is valid but no certificate available"));
                     }
@@ -333,6 +817,210 @@ impl AcmeService {
         }
     }
 
+    fn account_path(&self) -> PathBuf {
+        self.storage_path.join("account.json")
+    }
+
+    fn contact(&self) -> String {
+        format!("mailto:{}", self.config.email)
+    }
+
+    fn load_account(&self) -> Option<StoredAccount> {
+        let path = self.account_path();
+        let data = fs::read(&path).ok()?;
+        serde_json::from_slice(&data)
+            .inspect_err(|e| warn!("Ignoring unreadable ACME account {:?}: {}", path, e))
+            .ok()
+    }
+
+    /// Write the account with owner-only permissions, as it holds the account key
+    fn save_account(&self, account: &StoredAccount) -> Result<()> {
+        let path = self.account_path();
+        let data = serde_json::to_vec_pretty(account)?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // `mode` only applies to new files
+            if path.exists() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        std::io::Write::write_all(&mut options.open(&path)?, &data)
+            .with_context(|| format!("Failed to save ACME account to {path:?}"))
+    }
+
+    /// The External Account Binding key, for CAs that tie ACME accounts to a customer account
+    fn external_account_key(&self) -> Result<Option<ExternalAccountKey>> {
+        let (Some(kid), Some(hmac_key)) = (&self.config.eab_kid, &self.config.eab_hmac_key) else {
+            return Ok(None);
+        };
+        let key = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(hmac_key.trim_end_matches('='))
+            .context("eab_hmac_key must be base64url")?;
+        Ok(Some(ExternalAccountKey::new(kid.clone(), &key)))
+    }
+
+    /// Register a new account and store its credentials
+    async fn create_account(&self) -> Result<Account> {
+        let contact = self.contact();
+        let (account, credentials) = Account::create(
+            &NewAccount {
+                contact: &[&contact],
+                terms_of_service_agreed: true,
+                only_return_existing: false,
+            },
+            self.get_directory_url(),
+            self.external_account_key()?.as_ref(),
+        )
+        .await
+        .context("Failed to create ACME account")?;
+        info!("Registered ACME account {}", account.id());
+        self.save_account(&StoredAccount {
+            contact,
+            credentials,
+        })?;
+        Ok(account)
+    }
+
+    /// The stored account, updated to the configured contact, or a newly registered one
+    async fn account(&self) -> Result<Account> {
+        let Some(mut stored) = self.load_account() else {
+            return self.create_account().await;
+        };
+        let (account_url, key, directory) = credential_fields(&stored.credentials)?;
+        if directory != self.get_directory_url() {
+            info!(
+                "Stored ACME account belongs to {}, registering with {}",
+                directory,
+                self.get_directory_url()
+            );
+            return self.create_account().await;
+        }
+
+        if stored.contact != self.contact() {
+            match self.update_contact(&account_url, &key, &directory).await {
+                Ok(()) => {
+                    info!("Updated ACME account contact to {}", self.contact());
+                    stored.contact = self.contact();
+                    self.save_account(&stored)?;
+                }
+                Err(e) => warn!("Failed to update ACME account contact: {:#}", e),
+            }
+        }
+
+        match Account::from_credentials(stored.credentials).await {
+            Ok(account) => Ok(account),
+            Err(e) => {
+                warn!(
+                    "Stored ACME account is unusable ({}), registering a new one",
+                    e
+                );
+                self.create_account().await
+            }
+        }
+    }
+
+    /// Replace the account's contact (RFC 8555 section 7.3.2)
+    async fn update_contact(&self, account_url: &str, key: &[u8], directory: &str) -> Result<()> {
+        let client = tls::https_client()?;
+        let get = |url: &str, method: Method| {
+            Request::builder()
+                .method(method)
+                .uri(url)
+                .body(Full::new(Bytes::new()))
+        };
+
+        let response = client.request(get(directory, Method::GET)?).await?;
+        let body = response.into_body().collect().await?.to_bytes();
+        let directory: Value = serde_json::from_slice(&body)?;
+        let new_nonce = directory["newNonce"]
+            .as_str()
+            .ok_or_else(|| anyhow!("ACME directory has no newNonce"))?;
+        let response = client.request(get(new_nonce, Method::HEAD)?).await?;
+        let nonce = response
+            .headers()
+            .get("replay-nonce")
+            .and_then(|nonce| nonce.to_str().ok())
+            .ok_or_else(|| anyhow!("ACME server returned no nonce"))?;
+
+        let body = signed_request(
+            key,
+            account_url,
+            nonce,
+            account_url,
+            &json!({ "contact": [self.contact()] }),
+        )?;
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(account_url)
+            .header(header::CONTENT_TYPE, "application/jose+json")
+            .body(Full::new(Bytes::from(body.to_string())))?;
+        let response = client.request(request).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.into_body().collect().await?.to_bytes();
+            bail!(
+                "ACME server answered {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            );
+        }
+        Ok(())
+    }
+
+    /// Tell the CA a challenge is ready and wait until its authorization is decided
+    async fn wait_for_authorization(
+        order: &mut Order,
+        challenge_url: &str,
+        domain: &str,
+    ) -> Result<()> {
+        order
+            .set_challenge_ready(challenge_url)
+            .await
+            .context("Failed to validate challenge")?;
+
+        let mut attempts = 0;
+        loop {
+            sleep(Duration::from_secs(2)).await;
+            attempts += 1;
+
+            let updated_authorizations = order
+                .authorizations()
+                .await
+                .context("Failed to get updated authorizations")?;
+
+            // "example.com" and "*.example.com" share an identifier, so match on the challenge
+            let updated_auth = updated_authorizations
+                .iter()
+                .find(|auth| auth.challenges.iter().any(|c| c.url == challenge_url))
+                .ok_or_else(|| anyhow!("Authorization not found for domain: {}", domain))?;
+
+            match updated_auth.status {
+                AuthorizationStatus::Valid => {
+                    info!("Challenge validated for domain: {}", domain);
+                    return Ok(());
+                }
+                AuthorizationStatus::Invalid => {
+                    return Err(anyhow!(
+                        "Challenge validation failed for domain: {}",
+                        domain
+                    ));
+                }
+                _ => {
+                    if attempts > 30 {
+                        return Err(anyhow!(
+                            "Challenge validation timeout for domain: {}",
+                            domain
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
     /// Get certificate for the configured domains, requesting new one if needed
     pub async fn get_certificate(&self) -> Result<CertificateInfo> {
         if self.config.domains.is_empty() {
@@ -379,12 +1067,18 @@ impl AcmeService {
     }
 
     /// Start a background task to monitor and renew certificates
-    pub fn start_renewal_task(&self) -> tokio::task::JoinHandle<()> {
+    /// Spawn the daily renewal check. Each renewed certificate is broadcast on `renewed` so
+    /// the TCP and HTTP/3 listeners can swap it in.
+    pub fn start_renewal_task(
+        &self,
+        renewed: Option<tokio::sync::broadcast::Sender<CertificateInfo>>,
+    ) -> tokio::task::JoinHandle<()> {
         let config = self.config.clone();
+        let http01_challenges = self.http01_challenges.clone();
 
         tokio::spawn(async move {
             let service = match AcmeService::new(config) {
-                Ok(service) => service,
+                Ok(service) => service.with_http01_challenges(http01_challenges),
                 Err(e) => {
                     error!("Failed to create ACME service for renewal task: {}", e);
                     return;
@@ -449,6 +1143,10 @@ impl AcmeService {
                         Ok(cert_info) => {
                             info!("Successfully renewed/obtained certificate");
                             cert_info.log_info();
+                            // Nobody listening just means no listener needs reloading
+                            if let Some(renewed) = &renewed {
+                                let _ = renewed.send(cert_info);
+                            }
                         }
                         Err(e) => {
                             error!("Failed to renew/obtain certificate: {}", e);
@@ -464,20 +1162,20 @@ impl AcmeService {
     /// Check if any of the configured domains has an expired certificate
     pub fn has_expired_certificate(&self) -> bool {
         for domain in &self.config.domains {
-            if matches!(self.check_certificate(domain), Some(cert_info) if cert_info.is_expired()) {
+            if matches!(self.find_certificate(domain), Some(cert_info) if cert_info.is_expired()) {
                 return true;
             }
         }
         false
     }
 
-    /// Get certificate status for all configured domains
+    /// Get the certificate covering each configured domain
     pub fn get_certificate_status(&self) -> Vec<(String, Option<CertificateInfo>)> {
         self.config
             .domains
             .iter()
             .map(|domain| {
-                let cert_info = self.check_certificate(domain);
+                let cert_info = self.find_certificate(domain);
                 (domain.clone(), cert_info)
             })
             .collect()
@@ -485,6 +1183,27 @@ impl AcmeService {
 }
 
 impl CertificateInfo {
+    /// Read the expiry, SANs and issuer from a certificate file, checking the key matches it
+    pub fn load(cert_path: &str, key_path: &str) -> Result<Self> {
+        tls::load_certified_key(cert_path, key_path)?;
+        let certificate = x509::parse_pem_file(cert_path)
+            .ok_or_else(|| anyhow!("Failed to parse certificate {cert_path}"))?;
+        Ok(Self {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            expires_at: certificate.not_after.into(),
+            subject_alt_names: certificate.dns_names,
+            issuer: certificate.issuer,
+        })
+    }
+
+    /// Whether the certificate is valid for `domain`
+    pub fn covers(&self, domain: &str) -> bool {
+        self.subject_alt_names
+            .iter()
+            .any(|name| x509::name_matches(name, domain))
+    }
+
     /// Check if the certificate is expired
     #[allow(dead_code)]
     pub fn is_expired(&self) -> bool {
@@ -516,18 +1235,18 @@ impl CertificateInfo {
         let days_until_expiry = self.days_until_expiry();
         if days_until_expiry < 0 {
             error!(
-                "Certificate EXPIRED {} days ago! cert={}",
-                -days_until_expiry, self.cert_path
+                "Certificate EXPIRED {} days ago! cert={} issuer={} names={:?}",
+                -days_until_expiry, self.cert_path, self.issuer, self.subject_alt_names
             );
         } else if days_until_expiry < 30 {
             warn!(
-                "Certificate expires in {} days. cert={}",
-                days_until_expiry, self.cert_path
+                "Certificate expires in {} days. cert={} issuer={} names={:?}",
+                days_until_expiry, self.cert_path, self.issuer, self.subject_alt_names
             );
         } else {
             info!(
-                "Certificate valid for {} days. cert={}",
-                days_until_expiry, self.cert_path
+                "Certificate valid for {} days. cert={} issuer={} names={:?}",
+                days_until_expiry, self.cert_path, self.issuer, self.subject_alt_names
             );
         }
     }
@@ -551,6 +1270,10 @@ mod tests {
             staging: Some(true),
             storage_path: None, // Will be set by individual tests
             renewal_days_before_expiry: Some(30),
+            challenge_type: AcmeChallengeType::Http01,
+            dns: None,
+            eab_kid: None,
+            eab_hmac_key: None,
         }
     }
 
@@ -582,6 +1305,24 @@ mod tests {
         config.staging = Some(false);
         let service = AcmeService::new(config.clone()).expect("Failed to create ACME service");
         assert!(!service.get_directory_url().contains("staging"));
+
+        // A custom CA wins over the Let's Encrypt choice
+        config.staging = Some(true);
+        config.ca_url = Some("https://acme.zerossl.com/v2/DV90".to_string());
+        let service = AcmeService::new(config.clone()).expect("Failed to create ACME service");
+        assert_eq!(
+            service.get_directory_url(),
+            "https://acme.zerossl.com/v2/DV90"
+        );
+        assert!(service.external_account_key().unwrap().is_none());
+
+        config.eab_kid = Some("kid-1".to_string());
+        config.eab_hmac_key = Some("c2VjcmV0LWhtYWMta2V5".to_string());
+        let service = AcmeService::new(config.clone()).expect("Failed to create ACME service");
+        assert!(service.external_account_key().unwrap().is_some());
+        config.eab_hmac_key = Some("not base64!".to_string());
+        let service = AcmeService::new(config).expect("Failed to create ACME service");
+        assert!(service.external_account_key().is_err());
     }
 
     #[test]
@@ -590,6 +1331,8 @@ mod tests {
         let expired_cert = CertificateInfo {
             cert_path: "/test/cert.pem".to_string(),
             key_path: "/test/key.pem".to_string(),
+            subject_alt_names: Vec::new(),
+            issuer: String::new(),
             expires_at: SystemTime::now() - Duration::from_secs(86400), // 1 day ago
         };
         assert!(expired_cert.is_expired());
@@ -598,6 +1341,8 @@ mod tests {
         let valid_cert = CertificateInfo {
             cert_path: "/test/cert.pem".to_string(),
             key_path: "/test/key.pem".to_string(),
+            subject_alt_names: Vec::new(),
+            issuer: String::new(),
             expires_at: SystemTime::now() + Duration::from_secs(86400), // 1 day in the future
         };
         assert!(!valid_cert.is_expired());
@@ -608,6 +1353,8 @@ mod tests {
         let cert = CertificateInfo {
             cert_path: "/test/cert.pem".to_string(),
             key_path: "/test/key.pem".to_string(),
+            subject_alt_names: Vec::new(),
+            issuer: String::new(),
             expires_at: SystemTime::now() + Duration::from_secs(15 * 24 * 60 * 60), // 15 days
         };
 
@@ -622,6 +1369,8 @@ mod tests {
         let future_cert = CertificateInfo {
             cert_path: "/test/cert.pem".to_string(),
             key_path: "/test/key.pem".to_string(),
+            subject_alt_names: Vec::new(),
+            issuer: String::new(),
             expires_at: SystemTime::now() + Duration::from_secs(10 * 24 * 60 * 60), // 10 days
         };
         let days = future_cert.days_until_expiry();
@@ -631,12 +1380,322 @@ mod tests {
         let expired_cert = CertificateInfo {
             cert_path: "/test/cert.pem".to_string(),
             key_path: "/test/key.pem".to_string(),
+            subject_alt_names: Vec::new(),
+            issuer: String::new(),
             expires_at: SystemTime::now() - Duration::from_secs(5 * 24 * 60 * 60), // 5 days ago
         };
         let days = expired_cert.days_until_expiry();
         assert!((-5..=-4).contains(&days)); // Negative for expired certs
     }
 
+    /// Self-signed certificate and key PEM for `domains`, expiring `days` from now (at
+    /// midnight UTC)
+    fn certificate_pem(domains: &[&str], days: i64) -> (String, String) {
+        use chrono::Datelike;
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let mut params =
+            CertificateParams::new(domains.iter().map(|d| d.to_string()).collect::<Vec<_>>())
+                .unwrap();
+        let not_after = chrono::Utc::now() + chrono::Duration::days(days);
+        params.not_after = rcgen::date_time_ymd(
+            not_after.year(),
+            not_after.month() as u8,
+            not_after.day() as u8,
+        );
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Prox Test CA");
+        let cert = params.self_signed(&key_pair).unwrap();
+        (cert.pem(), key_pair.serialize_pem())
+    }
+
+    /// Store a certificate for `domains` in the service's storage under `primary`
+    fn write_certificate(service: &AcmeService, primary: &str, domains: &[&str], days: i64) {
+        let (cert, key) = certificate_pem(domains, days);
+        service.store_certificate(primary, &cert, &key).unwrap();
+    }
+
+    #[test]
+    fn test_check_certificate_parses_fixture() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let mut config = create_test_acme_config();
+        config.storage_path = Some(temp_dir.path().to_string_lossy().to_string());
+        let service = AcmeService::new(config).expect("Failed to create ACME service");
+        let domains = ["test.example.com", "www.test.example.com"];
+
+        // The real notAfter is used, not the file's modification time
+        write_certificate(&service, domains[0], &domains, 60);
+        let info = service.check_certificate(domains[0]).unwrap();
+        assert!((59..=60).contains(&info.days_until_expiry()));
+        assert_eq!(info.subject_alt_names, domains);
+        assert_eq!(info.issuer, "CN=Prox Test CA");
+
+        // Freshly written but inside the renewal window
+        write_certificate(&service, domains[0], &domains, 10);
+        assert!(service.check_certificate(domains[0]).is_none());
+
+        // Missing a configured domain
+        write_certificate(&service, domains[0], &domains[..1], 60);
+        assert!(service.check_certificate(domains[0]).is_none());
+
+        // Key from another certificate
+        write_certificate(&service, domains[0], &domains, 60);
+        write_certificate(&service, "other", &["other.example.com"], 60);
+        let (_, key_path) = service.get_cert_paths(domains[0]);
+        fs::copy(service.get_cert_paths("other").1, key_path).unwrap();
+        assert!(service.check_certificate(domains[0]).is_none());
+    }
+
+    #[test]
+    fn test_find_certificate_by_san() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let mut config = create_test_acme_config();
+        config.storage_path = Some(temp_dir.path().to_string_lossy().to_string());
+        let service = AcmeService::new(config).expect("Failed to create ACME service");
+
+        write_certificate(&service, "*.example.com", &["*.example.com"], 60);
+        write_certificate(&service, "api.example.com", &["api.example.com"], 30);
+        write_certificate(&service, "shop.test", &["shop.test", "www.shop.test"], 60);
+        assert!(
+            temp_dir
+                .path()
+                .join("certs/_.example.com/metadata.json")
+                .exists()
+        );
+
+        // Resolved through the wildcard, even for a name with its own certificate, since
+        // the wildcard expires later
+        let info = service.find_certificate("app.example.com").unwrap();
+        assert_eq!(info.subject_alt_names, ["*.example.com"]);
+        let info = service.find_certificate("api.example.com").unwrap();
+        assert_eq!(info.subject_alt_names, ["*.example.com"]);
+        // A secondary SAN finds the certificate ordered under another primary name
+        let info = service.find_certificate("www.shop.test").unwrap();
+        assert!(info.cert_path.ends_with("shop.test/cert.pem"));
+        assert!(service.find_certificate("a.b.example.com").is_none());
+
+        // Metadata is rebuilt when missing
+        fs::remove_file(temp_dir.path().join("certs/shop.test/metadata.json")).unwrap();
+        assert!(service.find_certificate("shop.test").is_some());
+        assert!(
+            temp_dir
+                .path()
+                .join("certs/shop.test/metadata.json")
+                .exists()
+        );
+    }
+
+    #[test]
+    fn test_migrate_flat_layout() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let (cert, key) = certificate_pem(&["test.example.com", "www.test.example.com"], 60);
+        fs::write(temp_dir.path().join("test.example.com.crt"), cert).unwrap();
+        fs::write(temp_dir.path().join("test.example.com.key"), key).unwrap();
+
+        let mut config = create_test_acme_config();
+        config.storage_path = Some(temp_dir.path().to_string_lossy().to_string());
+        let service = AcmeService::new(config).expect("Failed to create ACME service");
+
+        assert!(!temp_dir.path().join("test.example.com.crt").exists());
+        assert!(!temp_dir.path().join("test.example.com.key").exists());
+        let dir = temp_dir.path().join("certs/test.example.com");
+        assert!(dir.join("cert.pem").exists() && dir.join("key.pem").exists());
+        let info = service.check_certificate("www.test.example.com").unwrap();
+        assert_eq!(
+            info.cert_path,
+            dir.join("cert.pem").to_string_lossy().into_owned()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cloudflare_provider() {
+        use axum::extract::{Path as UrlPath, Query, State};
+        use axum::http::HeaderMap;
+        use axum::routing::{delete, get};
+        use axum::{Json, Router};
+        use std::collections::HashMap;
+        use std::sync::{Arc, Mutex};
+
+        type Records = Arc<Mutex<Vec<(String, String, String)>>>;
+        let authorized = |headers: &HeaderMap| {
+            assert_eq!(headers[header::AUTHORIZATION], "Bearer secret-token");
+        };
+        let zones = move |headers: HeaderMap, Query(query): Query<HashMap<String, String>>| async move {
+            authorized(&headers);
+            let zones = if query["name"] == "example.com" {
+                json!([{ "id": "zone-1" }])
+            } else {
+                json!([])
+            };
+            Json(json!({ "success": true, "result": zones }))
+        };
+        let list = |State(records): State<Records>,
+                    Query(query): Query<HashMap<String, String>>| async move {
+            let records = records.lock().unwrap();
+            let matching: Vec<Value> = records
+                .iter()
+                .filter(|(_, name, content)| {
+                    query["type"] == "TXT" && query["name"] == *name && query["content"] == *content
+                })
+                .map(|(id, _, _)| json!({ "id": id }))
+                .collect();
+            Json(json!({ "success": true, "result": matching }))
+        };
+        let create = |State(records): State<Records>, Json(record): Json<Value>| async move {
+            let mut records = records.lock().unwrap();
+            let id = format!("record-{}", records.len());
+            let name = record["name"].as_str().unwrap().to_string();
+            let content = record["content"].as_str().unwrap().to_string();
+            records.push((id.clone(), name, content));
+            Json(json!({ "success": true, "result": { "id": id } }))
+        };
+        let remove = |State(records): State<Records>, UrlPath(id): UrlPath<String>| async move {
+            records
+                .lock()
+                .unwrap()
+                .retain(|(record, _, _)| *record != id);
+            Json(json!({ "success": true, "result": { "id": id } }))
+        };
+
+        let records: Records = Arc::default();
+        let app = Router::new()
+            .route("/zones", get(zones))
+            .route("/zones/zone-1/dns_records", get(list).post(create))
+            .route("/zones/zone-1/dns_records/{id}", delete(remove))
+            .with_state(records.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let provider = CloudflareProvider::new("secret-token".to_string(), None)
+            .unwrap()
+            .with_api_base(api_base);
+        let name = "_acme-challenge.www.example.com";
+        provider.create_txt_record(name, "first").await.unwrap();
+        provider.create_txt_record(name, "second").await.unwrap();
+        provider.delete_txt_record(name, "first").await.unwrap();
+        let remaining: Vec<String> = records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, name, content)| format!("{name}={content}"))
+            .collect();
+        assert_eq!(remaining, [format!("{name}=second")]);
+
+        // Outside any zone of the account
+        assert!(
+            provider
+                .create_txt_record("_acme-challenge.other.org", "x")
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_account_storage_and_signing() {
+        use aws_lc_rs::signature::{ECDSA_P256_SHA256_FIXED, KeyPair, UnparsedPublicKey};
+
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let mut config = create_test_acme_config();
+        config.storage_path = Some(temp_dir.path().to_string_lossy().to_string());
+        let service = AcmeService::new(config).expect("Failed to create ACME service");
+        assert!(service.load_account().is_none());
+
+        let pkcs8 =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+                .unwrap();
+        let encoded = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(pkcs8.as_ref());
+        let credentials: AccountCredentials = serde_json::from_value(json!({
+            "id": "https://ca.example/acct/7",
+            "key_pkcs8": encoded,
+            "directory": service.get_directory_url(),
+        }))
+        .unwrap();
+        service
+            .save_account(&StoredAccount {
+                contact: "mailto:old@example.com".to_string(),
+                credentials,
+            })
+            .unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(service.account_path())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let stored = service.load_account().unwrap();
+        assert_eq!(stored.contact, "mailto:old@example.com");
+        let (account_url, key, directory) = credential_fields(&stored.credentials).unwrap();
+        assert_eq!(account_url, "https://ca.example/acct/7");
+        assert_eq!(directory, service.get_directory_url());
+
+        // The contact update is signed with the account key under its account URL
+        let body = signed_request(
+            &key,
+            &account_url,
+            "nonce-1",
+            &account_url,
+            &json!({ "contact": [service.contact()] }),
+        )
+        .unwrap();
+        let decode = |field: &str| {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .decode(body[field].as_str().unwrap())
+                .unwrap()
+        };
+        let protected: Value = serde_json::from_slice(&decode("protected")).unwrap();
+        assert_eq!(protected["kid"], "https://ca.example/acct/7");
+        assert_eq!(protected["nonce"], "nonce-1");
+        let payload: Value = serde_json::from_slice(&decode("payload")).unwrap();
+        assert_eq!(payload["contact"][0], "mailto:test@example.com");
+
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+        let signing_input = format!(
+            "{}.{}",
+            body["protected"].as_str().unwrap(),
+            body["payload"].as_str().unwrap()
+        );
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, key_pair.public_key().as_ref())
+            .verify(signing_input.as_bytes(), &decode("signature"))
+            .unwrap();
+    }
+
+    /// Issues real certificates from Pebble, run with its validation disabled and its CA
+    /// trusted: `PEBBLE_VA_ALWAYS_VALID=1` and `SSL_CERT_FILE=pebble.minica.pem`
+    #[cfg(feature = "acme-pebble-tests")]
+    #[tokio::test]
+    async fn test_pebble_issuance() {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let mut config = create_test_acme_config();
+        config.storage_path = Some(temp_dir.path().to_string_lossy().to_string());
+        config.ca_url = Some(
+            std::env::var("PEBBLE_DIRECTORY")
+                .unwrap_or_else(|_| "https://localhost:14000/dir".to_string()),
+        );
+        let service = AcmeService::new(config).expect("Failed to create ACME service");
+
+        let info = service
+            .request_certificate(&service.config.domains)
+            .await
+            .unwrap();
+        assert!(info.issuer.contains("Pebble"), "issuer: {}", info.issuer);
+        assert!(info.covers("www.test.example.com"));
+
+        // The second order reuses the stored account
+        let account = fs::read(service.account_path()).unwrap();
+        service
+            .request_certificate(&service.config.domains)
+            .await
+            .unwrap();
+        assert_eq!(fs::read(service.account_path()).unwrap(), account);
+    }
+
     #[tokio::test]
     async fn test_get_certificate_no_domains() {
         let temp_dir = TempDir::new().expect("Failed to create temp directory");
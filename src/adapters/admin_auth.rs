@@ -0,0 +1,338 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::task::{Context, Poll};
+
+use aws_lc_rs::constant_time::verify_slices_are_equal;
+use axum::response::{IntoResponse, Response as AxumResponse};
+use axum_server::accept::Accept;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures_util::future::BoxFuture;
+use hyper::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use hyper::{HeaderMap, Request, StatusCode};
+use tokio_rustls::server::TlsStream;
+use tower::Service;
+
+use crate::config::{AdminAuth, AdminConfig};
+use crate::core::ip_range::IpRange;
+use crate::utils::x509;
+
+/// Why an admin request was turned away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminRejection {
+    /// The client is outside `admin.allowed_ips`
+    AddressNotAllowed,
+    /// Credentials missing or wrong; carries the `WWW-Authenticate` challenge
+    Unauthorized(&'static str),
+    /// The endpoint needs an authenticated caller, but neither `admin.auth` nor client
+    /// certificates are configured
+    AuthenticationRequired,
+}
+
+impl AdminRejection {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            AdminRejection::AddressNotAllowed => "address not allowed",
+            AdminRejection::Unauthorized(_) => "missing or invalid credentials",
+            AdminRejection::AuthenticationRequired => "no admin authentication configured",
+        }
+    }
+}
+
+impl IntoResponse for AdminRejection {
+    fn into_response(self) -> AxumResponse {
+        match self {
+            AdminRejection::AddressNotAllowed => {
+                (StatusCode::FORBIDDEN, "Forbidden").into_response()
+            }
+            AdminRejection::AuthenticationRequired => (
+                StatusCode::FORBIDDEN,
+                "Config updates require admin.auth or admin.tls.client_ca_path",
+            )
+                .into_response(),
+            AdminRejection::Unauthorized(challenge) => (
+                StatusCode::UNAUTHORIZED,
+                [(WWW_AUTHENTICATE, challenge)],
+                "Unauthorized",
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// Subject of the verified certificate a client presented to the admin listener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate(pub String);
+
+/// Who an admin request was accepted from, for the audit log
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdminIdentity {
+    /// "bearer token" or "user <name>" when `admin.auth` is set
+    pub credentials: Option<String>,
+    /// Subject of the client certificate, with mutual TLS
+    pub certificate: Option<String>,
+}
+
+impl AdminIdentity {
+    pub fn is_authenticated(&self) -> bool {
+        self.credentials.is_some() || self.certificate.is_some()
+    }
+}
+
+impl fmt::Display for AdminIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.credentials, &self.certificate) {
+            (Some(credentials), Some(subject)) => {
+                write!(f, "{credentials}, certificate \"{subject}\"")
+            }
+            (Some(credentials), None) => f.write_str(credentials),
+            (None, Some(subject)) => write!(f, "certificate \"{subject}\""),
+            (None, None) => f.write_str("anonymous"),
+        }
+    }
+}
+
+/// Check an admin request against the allowlist, then the credentials
+pub fn authorize(
+    config: &AdminConfig,
+    client_ip: IpAddr,
+    headers: &HeaderMap,
+    client_certificate: Option<&ClientCertificate>,
+) -> Result<AdminIdentity, AdminRejection> {
+    if !config.allowed_ips.is_empty()
+        && !config
+            .allowed_ips
+            .iter()
+            .filter_map(|entry| entry.parse::<IpRange>().ok())
+            .any(|range| range.contains(client_ip))
+    {
+        return Err(AdminRejection::AddressNotAllowed);
+    }
+
+    let mut identity = AdminIdentity {
+        credentials: None,
+        certificate: client_certificate.map(|certificate| certificate.0.clone()),
+    };
+    let Some(auth) = &config.auth else {
+        return Ok(identity);
+    };
+    let credentials = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let (challenge, valid) = match auth {
+        AdminAuth::Bearer { token, token_file } => {
+            let valid = credentials
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .zip(bearer_token(token, token_file.as_deref()))
+                .is_some_and(|(given, token)| secrets_match(given.as_bytes(), token.as_bytes()));
+            identity.credentials = Some("bearer token".to_string());
+            ("Bearer", valid)
+        }
+        AdminAuth::Basic { username, password } => {
+            let valid = credentials
+                .and_then(|value| value.strip_prefix("Basic "))
+                .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+                .is_some_and(|given| {
+                    secrets_match(&given, format!("{username}:{password}").as_bytes())
+                });
+            identity.credentials = Some(format!("user {username}"));
+            ("Basic realm=\"prox admin\"", valid)
+        }
+    };
+    if valid {
+        Ok(identity)
+    } else {
+        Err(AdminRejection::Unauthorized(challenge))
+    }
+}
+
+/// The expected bearer token, read from `token_file` on every request so a rotated
+/// token applies at once
+fn bearer_token(token: &str, token_file: Option<&str>) -> Option<String> {
+    let Some(path) = token_file else {
+        return Some(token.to_string());
+    };
+    match std::fs::read_to_string(path) {
+        Ok(contents) if !contents.trim().is_empty() => Some(contents.trim().to_string()),
+        Ok(_) => {
+            tracing::error!("Admin token file {} is empty", path);
+            None
+        }
+        Err(e) => {
+            tracing::error!("Failed to read admin token file {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Compare without leaking how much of the secret was guessed right
+fn secrets_match(given: &[u8], expected: &[u8]) -> bool {
+    verify_slices_are_equal(given, expected).is_ok()
+}
+
+/// Wraps the admin listener's TLS acceptor to hand the subject of the client's verified
+/// certificate, if any, to the requests of the connection
+#[derive(Debug, Clone)]
+pub struct ClientCertAcceptor<A> {
+    inner: A,
+}
+
+impl<A> ClientCertAcceptor<A> {
+    pub fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+impl<A, I, S> Accept<I, S> for ClientCertAcceptor<A>
+where
+    A: Accept<I, S, Stream = TlsStream<I>>,
+    A::Future: Send + 'static,
+    A::Service: Send + 'static,
+    I: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = WithClientCertificate<A::Service>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let accept = self.inner.accept(stream, service);
+        Box::pin(async move {
+            let (stream, service) = accept.await?;
+            let certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|chain| chain.first())
+                .and_then(|leaf| x509::parse(leaf))
+                .map(|parsed| ClientCertificate(parsed.subject));
+            Ok((
+                stream,
+                WithClientCertificate {
+                    inner: service,
+                    certificate,
+                },
+            ))
+        })
+    }
+}
+
+/// Adds the connection's [`ClientCertificate`] to each request's extensions
+#[derive(Debug, Clone)]
+pub struct WithClientCertificate<S> {
+    inner: S,
+    certificate: Option<ClientCertificate>,
+}
+
+impl<S, B> Service<Request<B>> for WithClientCertificate<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if let Some(certificate) = &self.certificate {
+            req.extensions_mut().insert(certificate.clone());
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_bearer_and_basic_auth() {
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        let bearer = AdminConfig {
+            auth: Some(AdminAuth::Bearer {
+                token: "s3cret".to_string(),
+                token_file: None,
+            }),
+            ..Default::default()
+        };
+        assert!(authorize(&bearer, local, &headers("Bearer s3cret"), None).is_ok());
+        assert_eq!(
+            authorize(&bearer, local, &headers("Bearer s3cre"), None),
+            Err(AdminRejection::Unauthorized("Bearer"))
+        );
+        assert!(authorize(&bearer, local, &HeaderMap::new(), None).is_err());
+
+        let basic = AdminConfig {
+            auth: Some(AdminAuth::Basic {
+                username: "ops".to_string(),
+                password: "pw".to_string(),
+            }),
+            ..Default::default()
+        };
+        // "ops:pw"
+        assert!(authorize(&basic, local, &headers("Basic b3BzOnB3"), None).is_ok());
+        assert!(authorize(&basic, local, &headers("Bearer b3BzOnB3"), None).is_err());
+    }
+
+    #[test]
+    fn test_token_file_and_caller_identity() {
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let token_file = dir.path().join("token");
+        std::fs::write(&token_file, "from-file\n").unwrap();
+        let config = AdminConfig {
+            auth: Some(AdminAuth::Bearer {
+                token: String::new(),
+                token_file: Some(token_file.to_string_lossy().into_owned()),
+            }),
+            ..Default::default()
+        };
+        let certificate = ClientCertificate("CN=ops".to_string());
+        let identity = authorize(
+            &config,
+            local,
+            &headers("Bearer from-file"),
+            Some(&certificate),
+        )
+        .unwrap();
+        assert_eq!(identity.to_string(), "bearer token, certificate \"CN=ops\"");
+
+        // Rotated on disk, picked up without a reload
+        std::fs::write(&token_file, "rotated").unwrap();
+        assert!(authorize(&config, local, &headers("Bearer from-file"), None).is_err());
+        assert!(authorize(&config, local, &headers("Bearer rotated"), None).is_ok());
+
+        let open = AdminConfig::default();
+        let identity = authorize(&open, local, &HeaderMap::new(), None).unwrap();
+        assert!(!identity.is_authenticated());
+        assert_eq!(identity.to_string(), "anonymous");
+    }
+
+    #[test]
+    fn test_allowlist_checked_before_credentials() {
+        let config = AdminConfig {
+            auth: Some(AdminAuth::Bearer {
+                token: "s3cret".to_string(),
+                token_file: None,
+            }),
+            allowed_ips: vec!["10.0.0.0/8".to_string()],
+            ..Default::default()
+        };
+        let inside: IpAddr = "10.1.2.3".parse().unwrap();
+        let outside: IpAddr = "192.0.2.1".parse().unwrap();
+        assert!(authorize(&config, inside, &headers("Bearer s3cret"), None).is_ok());
+        assert_eq!(
+            authorize(&config, outside, &headers("Bearer s3cret"), None),
+            Err(AdminRejection::AddressNotAllowed)
+        );
+    }
+}
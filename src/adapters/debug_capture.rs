@@ -0,0 +1,502 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::body::Body as AxumBody;
+use axum::response::Response as AxumResponse;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use http_body::{Body, Frame, SizeHint};
+use hyper::{HeaderMap, Request};
+use serde::{Deserialize, Serialize};
+
+use crate::core::router::MatchedRoute;
+use crate::utils::log_redaction;
+
+/// Bytes of each request and response body kept in a capture
+pub const MAX_BODY_BYTES: usize = 4096;
+/// Captures kept in the ring buffer, and the most one arming may ask for
+pub const MAX_CAPTURES: usize = 100;
+/// How long capture stays armed when `ttl_secs` is not given
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+/// Longest `ttl_secs` accepted
+const MAX_TTL: Duration = Duration::from_secs(3600);
+
+/// Backend a request was sent to, placed in the response extensions by the handler
+#[derive(Debug, Clone)]
+pub struct SelectedBackend(pub String);
+
+/// Route actions that were applied to the exchange, in order
+#[derive(Debug, Clone, Default)]
+pub struct AppliedActions(pub Vec<&'static str>);
+
+/// Body of `POST /-/debug/capture`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CaptureRequest {
+    #[serde(default = "default_path_prefix")]
+    pub path_prefix: String,
+    pub count: usize,
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+fn default_path_prefix() -> String {
+    "/".to_string()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedBody {
+    /// Lossy UTF-8 of the first `MAX_BODY_BYTES`
+    pub content: String,
+    pub truncated: bool,
+}
+
+/// Sanitized metadata of one captured request/response pair
+#[derive(Debug, Clone, Serialize)]
+pub struct CapturedExchange {
+    pub time: DateTime<Utc>,
+    pub client: SocketAddr,
+    pub method: String,
+    pub uri: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: CapturedBody,
+    pub route: Option<String>,
+    pub backend: Option<String>,
+    pub actions: Vec<&'static str>,
+    pub status: u16,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: CapturedBody,
+    pub duration_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArmedCapture {
+    pub path_prefix: String,
+    pub remaining: usize,
+    pub expires_in_secs: u64,
+}
+
+/// Response of `GET /-/debug/capture`
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureReport {
+    pub armed: Option<ArmedCapture>,
+    pub captures: Vec<CapturedExchange>,
+}
+
+struct Arming {
+    path_prefix: String,
+    remaining: usize,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct CaptureState {
+    arming: Option<Arming>,
+    captures: VecDeque<CapturedExchange>,
+}
+
+impl CaptureState {
+    /// Drop the arming once it has expired
+    fn current_arming(&mut self) -> Option<&mut Arming> {
+        if self
+            .arming
+            .as_ref()
+            .is_some_and(|arming| Instant::now() >= arming.expires_at)
+        {
+            self.arming = None;
+        }
+        self.arming.as_mut()
+    }
+}
+
+/// Captures the next requests under a path prefix for `/-/debug/capture`
+#[derive(Clone, Default)]
+pub struct DebugCapture {
+    /// Lets requests skip the lock while nothing is armed
+    armed: Arc<AtomicBool>,
+    state: Arc<Mutex<CaptureState>>,
+}
+
+impl DebugCapture {
+    /// Arm capture of the next `count` requests, replacing any previous arming
+    pub fn arm(&self, request: CaptureRequest) -> Result<ArmedCapture, String> {
+        if request.count == 0 || request.count > MAX_CAPTURES {
+            return Err(format!("count must be between 1 and {MAX_CAPTURES}"));
+        }
+        if !request.path_prefix.starts_with('/') {
+            return Err("path_prefix must start with '/'".to_string());
+        }
+        let ttl = request.ttl_secs.map_or(DEFAULT_TTL, Duration::from_secs);
+        if ttl.is_zero() || ttl > MAX_TTL {
+            return Err(format!(
+                "ttl_secs must be between 1 and {}",
+                MAX_TTL.as_secs()
+            ));
+        }
+
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        state.arming = Some(Arming {
+            path_prefix: request.path_prefix.clone(),
+            remaining: request.count,
+            expires_at: Instant::now() + ttl,
+        });
+        self.armed.store(true, Ordering::Release);
+        Ok(ArmedCapture {
+            path_prefix: request.path_prefix,
+            remaining: request.count,
+            expires_in_secs: ttl.as_secs(),
+        })
+    }
+
+    /// Cancel a pending arming; captures already taken are kept
+    pub fn disarm(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.arming = None;
+        }
+        self.armed.store(false, Ordering::Release);
+    }
+
+    pub fn report(&self) -> CaptureReport {
+        let Ok(mut state) = self.state.lock() else {
+            return CaptureReport {
+                armed: None,
+                captures: Vec::new(),
+            };
+        };
+        let now = Instant::now();
+        let armed = state.current_arming().map(|arming| ArmedCapture {
+            path_prefix: arming.path_prefix.clone(),
+            remaining: arming.remaining,
+            expires_in_secs: arming.expires_at.saturating_duration_since(now).as_secs(),
+        });
+        CaptureReport {
+            armed,
+            captures: state.captures.iter().cloned().collect(),
+        }
+    }
+
+    /// Start capturing `req` if capture is armed for its path, counting it against the arming
+    pub fn begin(
+        &self,
+        req: Request<AxumBody>,
+        client: SocketAddr,
+    ) -> (Request<AxumBody>, Option<PendingCapture>) {
+        if !self.armed.load(Ordering::Acquire) || !self.take_slot(req.uri().path()) {
+            return (req, None);
+        }
+
+        let request_body = Arc::new(Mutex::new(BodyPrefix::default()));
+        let (parts, body) = req.into_parts();
+        let pending = PendingCapture {
+            capture: self.clone(),
+            started: Instant::now(),
+            time: Utc::now(),
+            client,
+            method: parts.method.to_string(),
+            uri: parts
+                .uri
+                .path_and_query()
+                .map_or_else(|| parts.uri.path().to_string(), |pq| pq.to_string()),
+            request_headers: redacted(&parts.headers),
+            request_body: request_body.clone(),
+        };
+        let body = AxumBody::new(RequestTee {
+            inner: body,
+            prefix: request_body,
+        });
+        (Request::from_parts(parts, body), Some(pending))
+    }
+
+    fn take_slot(&self, path: &str) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return false;
+        };
+        let Some(arming) = state.current_arming() else {
+            self.armed.store(false, Ordering::Release);
+            return false;
+        };
+        if !path.starts_with(&arming.path_prefix) {
+            return false;
+        }
+        arming.remaining -= 1;
+        if arming.remaining == 0 {
+            state.arming = None;
+            self.armed.store(false, Ordering::Release);
+        }
+        true
+    }
+
+    fn store(&self, exchange: CapturedExchange) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.captures.len() == MAX_CAPTURES {
+                state.captures.pop_front();
+            }
+            state.captures.push_back(exchange);
+        }
+    }
+}
+
+/// A captured request waiting for its response
+pub struct PendingCapture {
+    capture: DebugCapture,
+    started: Instant,
+    time: DateTime<Utc>,
+    client: SocketAddr,
+    method: String,
+    uri: String,
+    request_headers: Vec<(String, String)>,
+    request_body: Arc<Mutex<BodyPrefix>>,
+}
+
+impl PendingCapture {
+    /// Attach the capture to `response`; it is stored once the response body is done
+    pub fn finish(self, response: AxumResponse) -> AxumResponse {
+        let extensions = response.extensions();
+        let route = extensions.get::<MatchedRoute>().map(|r| r.0.clone());
+        let backend = extensions.get::<SelectedBackend>().map(|b| b.0.clone());
+        let actions = extensions
+            .get::<AppliedActions>()
+            .map(|a| a.0.clone())
+            .unwrap_or_default();
+        let status = response.status().as_u16();
+        let response_headers = redacted(response.headers());
+
+        let exchange = CapturedExchange {
+            time: self.time,
+            client: self.client,
+            method: self.method,
+            uri: self.uri,
+            request_headers: self.request_headers,
+            // Filled in when the response completes, after the backend read the request
+            request_body: BodyPrefix::default().captured(),
+            route,
+            backend,
+            actions,
+            status,
+            response_headers,
+            response_body: BodyPrefix::default().captured(),
+            duration_ms: 0,
+        };
+        let (parts, body) = response.into_parts();
+        let body = AxumBody::new(ResponseTee {
+            inner: body,
+            prefix: BodyPrefix::default(),
+            pending: Some((exchange, self.request_body, self.started, self.capture)),
+        });
+        AxumResponse::from_parts(parts, body)
+    }
+}
+
+fn redacted(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            (
+                name.to_string(),
+                log_redaction::header_value(name.as_str(), &value).to_string(),
+            )
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct BodyPrefix {
+    bytes: Vec<u8>,
+    truncated: bool,
+}
+
+impl BodyPrefix {
+    fn record(&mut self, data: &Bytes) {
+        let room = MAX_BODY_BYTES - self.bytes.len();
+        if data.len() > room {
+            self.truncated = true;
+        }
+        self.bytes.extend_from_slice(&data[..data.len().min(room)]);
+    }
+
+    fn captured(&self) -> CapturedBody {
+        CapturedBody {
+            content: String::from_utf8_lossy(&self.bytes).into_owned(),
+            truncated: self.truncated,
+        }
+    }
+}
+
+/// Request body that copies its first bytes into the capture as it streams
+struct RequestTee {
+    inner: AxumBody,
+    prefix: Arc<Mutex<BodyPrefix>>,
+}
+
+impl Body for RequestTee {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(data) = frame.data_ref()
+            && let Ok(mut prefix) = self.prefix.lock()
+        {
+            prefix.record(data);
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Response body that copies its first bytes and stores the capture when dropped
+struct ResponseTee {
+    inner: AxumBody,
+    prefix: BodyPrefix,
+    pending: Option<(
+        CapturedExchange,
+        Arc<Mutex<BodyPrefix>>,
+        Instant,
+        DebugCapture,
+    )>,
+}
+
+impl Body for ResponseTee {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(data) = frame.data_ref()
+        {
+            self.prefix.record(data);
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for ResponseTee {
+    fn drop(&mut self) {
+        let Some((mut exchange, request_body, started, capture)) = self.pending.take() else {
+            return;
+        };
+        if let Ok(request_body) = request_body.lock() {
+            exchange.request_body = request_body.captured();
+        }
+        exchange.response_body = self.prefix.captured();
+        exchange.duration_ms = started.elapsed().as_millis();
+        capture.store(exchange);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    fn request(path: &str, body: &'static str) -> Request<AxumBody> {
+        Request::builder()
+            .uri(path)
+            .header("authorization", "Bearer secret")
+            .header("accept", "text/plain")
+            .body(AxumBody::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_capture_arms_records_and_disarms() {
+        let capture = DebugCapture::default();
+        let client: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        capture
+            .arm(CaptureRequest {
+                path_prefix: "/api".to_string(),
+                count: 1,
+                ttl_secs: None,
+            })
+            .unwrap();
+
+        let (_, skipped) = capture.begin(request("/static/a", ""), client);
+        assert!(skipped.is_none());
+
+        let large = "x".repeat(MAX_BODY_BYTES + 10);
+        let (req, pending) = capture.begin(request("/api/users", "hello"), client);
+        let pending = pending.expect("matching request is captured");
+        req.into_body().collect().await.unwrap();
+        let mut response = AxumResponse::new(AxumBody::from(large));
+        response
+            .extensions_mut()
+            .insert(MatchedRoute("/api".to_string()));
+        response
+            .extensions_mut()
+            .insert(SelectedBackend("http://10.0.0.1:8080".to_string()));
+        response
+            .extensions_mut()
+            .insert(AppliedActions(vec!["request_headers"]));
+        pending
+            .finish(response)
+            .into_body()
+            .collect()
+            .await
+            .unwrap();
+
+        // The only slot is used up
+        let (_, after) = capture.begin(request("/api/users", ""), client);
+        assert!(after.is_none());
+
+        let report = capture.report();
+        assert!(report.armed.is_none());
+        let [exchange] = report.captures.as_slice() else {
+            panic!("expected one capture, got {:?}", report.captures);
+        };
+        assert_eq!(exchange.uri, "/api/users");
+        assert_eq!(exchange.route.as_deref(), Some("/api"));
+        assert_eq!(exchange.backend.as_deref(), Some("http://10.0.0.1:8080"));
+        assert_eq!(exchange.actions, vec!["request_headers"]);
+        assert_eq!(exchange.request_body.content, "hello");
+        assert_eq!(exchange.response_body.content.len(), MAX_BODY_BYTES);
+        assert!(exchange.response_body.truncated);
+        assert!(exchange.request_headers.contains(&(
+            "authorization".to_string(),
+            log_redaction::REDACTED.to_string()
+        )));
+    }
+
+    #[test]
+    fn test_arm_rejects_unbounded_requests() {
+        let capture = DebugCapture::default();
+        let arm = |count, ttl_secs| {
+            capture.arm(CaptureRequest {
+                path_prefix: "/".to_string(),
+                count,
+                ttl_secs,
+            })
+        };
+        assert!(arm(0, None).is_err());
+        assert!(arm(MAX_CAPTURES + 1, None).is_err());
+        assert!(arm(1, Some(0)).is_err());
+        assert!(arm(MAX_CAPTURES, Some(60)).is_ok());
+    }
+}
@@ -0,0 +1,411 @@
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow, bail};
+use aws_lc_rs::hmac;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+
+use crate::config::models::TsigAlgorithm;
+
+const TYPE_SOA: u16 = 6;
+const TYPE_TXT: u16 = 16;
+const TYPE_TSIG: u16 = 250;
+const CLASS_IN: u16 = 1;
+const CLASS_NONE: u16 = 254;
+const CLASS_ANY: u16 = 255;
+const OPCODE_UPDATE: u16 = 5;
+const RCODE_NXDOMAIN: u8 = 3;
+/// Allowed clock skew for TSIG signatures, in seconds
+const TSIG_FUDGE: u16 = 300;
+const EXCHANGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Algorithm name and HMAC of a TSIG algorithm (RFC 8945 section 6)
+fn tsig_algorithm(algorithm: TsigAlgorithm) -> (&'static str, hmac::Algorithm) {
+    match algorithm {
+        TsigAlgorithm::HmacSha256 => ("hmac-sha256", hmac::HMAC_SHA256),
+        TsigAlgorithm::HmacSha384 => ("hmac-sha384", hmac::HMAC_SHA384),
+        TsigAlgorithm::HmacSha512 => ("hmac-sha512", hmac::HMAC_SHA512),
+    }
+}
+
+/// A shared secret for signing dynamic updates (RFC 8945)
+#[derive(Clone)]
+pub struct TsigKey {
+    pub name: String,
+    pub algorithm: TsigAlgorithm,
+    pub secret: Vec<u8>,
+}
+
+/// Change to a TXT record carried by a dynamic update (RFC 2136)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxtUpdate {
+    Add { ttl: u32 },
+    Delete,
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Append `name` in uncompressed wire format
+fn push_name(buf: &mut Vec<u8>, name: &str) -> Result<()> {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("Invalid DNS name: {name}");
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+    Ok(())
+}
+
+/// Append TXT rdata (with its length), splitting `value` into 255-byte strings
+fn push_txt_rdata(buf: &mut Vec<u8>, value: &str) {
+    let chunks: Vec<&[u8]> = value.as_bytes().chunks(255).collect();
+    let len: usize = chunks.iter().map(|chunk| chunk.len() + 1).sum();
+    push_u16(buf, len as u16);
+    for chunk in chunks {
+        buf.push(chunk.len() as u8);
+        buf.extend_from_slice(chunk);
+    }
+}
+
+fn header(id: u16, flags: u16, counts: [u16; 4]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(512);
+    push_u16(&mut msg, id);
+    push_u16(&mut msg, flags);
+    for count in counts {
+        push_u16(&mut msg, count);
+    }
+    msg
+}
+
+/// A recursive query for the TXT records of `name`
+fn txt_query(id: u16, name: &str) -> Result<Vec<u8>> {
+    // Recursion desired
+    let mut msg = header(id, 0x0100, [1, 0, 0, 0]);
+    push_name(&mut msg, name)?;
+    push_u16(&mut msg, TYPE_TXT);
+    push_u16(&mut msg, CLASS_IN);
+    Ok(msg)
+}
+
+/// A dynamic update adding or deleting one TXT value of `name` in `zone`, signed with
+/// `key` at unix time `now` when given
+fn update_message(
+    id: u16,
+    zone: &str,
+    name: &str,
+    value: &str,
+    update: TxtUpdate,
+    key: Option<&TsigKey>,
+    now: u64,
+) -> Result<Vec<u8>> {
+    // Zone section, no prerequisites, one update
+    let mut msg = header(id, OPCODE_UPDATE << 11, [1, 0, 1, 0]);
+    push_name(&mut msg, zone)?;
+    push_u16(&mut msg, TYPE_SOA);
+    push_u16(&mut msg, CLASS_IN);
+
+    push_name(&mut msg, name)?;
+    push_u16(&mut msg, TYPE_TXT);
+    match update {
+        TxtUpdate::Add { ttl } => {
+            push_u16(&mut msg, CLASS_IN);
+            push_u32(&mut msg, ttl);
+        }
+        // Class NONE deletes only the record with matching rdata (RFC 2136 section 2.5.4)
+        TxtUpdate::Delete => {
+            push_u16(&mut msg, CLASS_NONE);
+            push_u32(&mut msg, 0);
+        }
+    }
+    push_txt_rdata(&mut msg, value);
+
+    if let Some(key) = key {
+        sign(&mut msg, key, now)?;
+    }
+    Ok(msg)
+}
+
+/// Append a TSIG record to `msg` (RFC 8945 section 4.3)
+fn sign(msg: &mut Vec<u8>, key: &TsigKey, now: u64) -> Result<()> {
+    let mut key_name = Vec::new();
+    push_name(&mut key_name, &key.name.to_ascii_lowercase())?;
+    let (algorithm_name, hmac_algorithm) = tsig_algorithm(key.algorithm);
+    let mut algorithm = Vec::new();
+    push_name(&mut algorithm, algorithm_name)?;
+    // 48-bit time signed
+    let time_signed = &now.to_be_bytes()[2..];
+    let original_id = [msg[0], msg[1]];
+
+    // The digest covers the unsigned message followed by the TSIG variables
+    let mut digested = msg.clone();
+    digested.extend_from_slice(&key_name);
+    push_u16(&mut digested, CLASS_ANY);
+    push_u32(&mut digested, 0);
+    digested.extend_from_slice(&algorithm);
+    digested.extend_from_slice(time_signed);
+    push_u16(&mut digested, TSIG_FUDGE);
+    push_u16(&mut digested, 0); // error
+    push_u16(&mut digested, 0); // other len
+    let mac = hmac::sign(&hmac::Key::new(hmac_algorithm, &key.secret), &digested);
+    let mac = mac.as_ref();
+
+    msg.extend_from_slice(&key_name);
+    push_u16(msg, TYPE_TSIG);
+    push_u16(msg, CLASS_ANY);
+    push_u32(msg, 0);
+    push_u16(msg, (algorithm.len() + 16 + mac.len()) as u16);
+    msg.extend_from_slice(&algorithm);
+    msg.extend_from_slice(time_signed);
+    push_u16(msg, TSIG_FUDGE);
+    push_u16(msg, mac.len() as u16);
+    msg.extend_from_slice(mac);
+    msg.extend_from_slice(&original_id);
+    push_u16(msg, 0); // error
+    push_u16(msg, 0); // other len
+
+    let additional = u16::from_be_bytes([msg[10], msg[11]]) + 1;
+    msg[10..12].copy_from_slice(&additional.to_be_bytes());
+    Ok(())
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*msg.get(pos)?, *msg.get(pos + 1)?]))
+}
+
+/// Position just past the (possibly compressed) name starting at `pos`
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        match *msg.get(pos)? {
+            0 => return Some(pos + 1),
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len => pos += 1 + usize::from(len),
+        }
+    }
+}
+
+/// Response code and TXT answers of a response to message `id`
+fn parse_response(msg: &[u8], id: u16) -> Result<(u8, Vec<String>)> {
+    let parse = || -> Option<(u8, Vec<String>)> {
+        if read_u16(msg, 0)? != id || msg.get(2)? & 0x80 == 0 {
+            return None;
+        }
+        let rcode = msg.get(3)? & 0x0f;
+        let questions = read_u16(msg, 4)?;
+        let answers = read_u16(msg, 6)?;
+        let mut pos = 12;
+        for _ in 0..questions {
+            pos = skip_name(msg, pos)? + 4;
+        }
+        let mut records = Vec::new();
+        for _ in 0..answers {
+            pos = skip_name(msg, pos)?;
+            let record_type = read_u16(msg, pos)?;
+            let len = usize::from(read_u16(msg, pos + 8)?);
+            let rdata = msg.get(pos + 10..pos + 10 + len)?;
+            pos += 10 + len;
+            if record_type == TYPE_TXT {
+                let mut value = Vec::new();
+                let mut i = 0;
+                while i < rdata.len() {
+                    let len = usize::from(rdata[i]);
+                    value.extend_from_slice(rdata.get(i + 1..i + 1 + len)?);
+                    i += 1 + len;
+                }
+                records.push(String::from_utf8_lossy(&value).into_owned());
+            }
+        }
+        Some((rcode, records))
+    };
+    parse().ok_or_else(|| anyhow!("Malformed DNS response"))
+}
+
+fn rcode_name(rcode: u8) -> String {
+    match rcode {
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        8 => "NXRRSET".to_string(),
+        9 => "NOTAUTH".to_string(),
+        10 => "NOTZONE".to_string(),
+        other => format!("RCODE {other}"),
+    }
+}
+
+/// TXT values of `name` as seen by `server`; a missing name has none
+pub async fn query_txt(server: SocketAddr, name: &str) -> Result<Vec<String>> {
+    let id = rand::random();
+    let query = txt_query(id, name)?;
+    let exchange = async {
+        let local: SocketAddr = if server.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(server).await?;
+        socket.send(&query).await?;
+        let mut buf = vec![0; 4096];
+        let len = socket.recv(&mut buf).await?;
+        buf.truncate(len);
+        anyhow::Ok(buf)
+    };
+    let response = timeout(EXCHANGE_TIMEOUT, exchange)
+        .await
+        .with_context(|| format!("DNS query to {server} timed out"))?
+        .with_context(|| format!("DNS query to {server} failed"))?;
+    match parse_response(&response, id)? {
+        (0, records) => Ok(records),
+        (RCODE_NXDOMAIN, _) => Ok(Vec::new()),
+        (rcode, _) => bail!(
+            "DNS query for {name} to {server} failed: {}",
+            rcode_name(rcode)
+        ),
+    }
+}
+
+/// Send a dynamic update over TCP to the primary name server of `zone`
+pub async fn send_update(
+    server: SocketAddr,
+    zone: &str,
+    name: &str,
+    value: &str,
+    update: TxtUpdate,
+    key: Option<&TsigKey>,
+) -> Result<()> {
+    let id = rand::random();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let msg = update_message(id, zone, name, value, update, key, now)?;
+    let exchange = async {
+        let mut stream = TcpStream::connect(server).await?;
+        stream.write_all(&(msg.len() as u16).to_be_bytes()).await?;
+        stream.write_all(&msg).await?;
+        let len = stream.read_u16().await?;
+        let mut response = vec![0; usize::from(len)];
+        stream.read_exact(&mut response).await?;
+        anyhow::Ok(response)
+    };
+    let response = timeout(EXCHANGE_TIMEOUT, exchange)
+        .await
+        .with_context(|| format!("DNS update to {server} timed out"))?
+        .with_context(|| format!("DNS update to {server} failed"))?;
+    match parse_response(&response, id)?.0 {
+        0 => Ok(()),
+        rcode => bail!(
+            "DNS update for {name} rejected by {server}: {}",
+            rcode_name(rcode)
+        ),
+    }
+}
+
+/// Name servers from /etc/resolv.conf, falling back to public resolvers
+pub fn system_resolvers() -> Vec<SocketAddr> {
+    let configured: Vec<SocketAddr> = std::fs::read_to_string("/etc/resolv.conf")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|address| address.trim().parse().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect();
+    if configured.is_empty() {
+        vec![([1, 1, 1, 1], 53).into(), ([8, 8, 8, 8], 53).into()]
+    } else {
+        configured
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answer one UDP query with a TXT record carrying `value`
+    async fn fake_resolver(value: String) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0; 512];
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let query = &buf[..len];
+            let mut response = header(read_u16(query, 0).unwrap(), 0x8180, [1, 1, 0, 0]);
+            response.extend_from_slice(&query[12..]);
+            // Compressed pointer to the question name, TXT, IN, TTL 60
+            response.extend_from_slice(&[0xc0, 12]);
+            push_u16(&mut response, TYPE_TXT);
+            push_u16(&mut response, CLASS_IN);
+            push_u32(&mut response, 60);
+            push_txt_rdata(&mut response, &value);
+            socket.send_to(&response, peer).await.unwrap();
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_query_txt() {
+        // Longer than one 255-byte character-string
+        let long = "x".repeat(300);
+        let server = fake_resolver(long.clone()).await;
+        let records = query_txt(server, "_acme-challenge.example.com")
+            .await
+            .unwrap();
+        assert_eq!(records, [long]);
+    }
+
+    #[test]
+    fn test_signed_update_message() {
+        let key = TsigKey {
+            name: "ACME-Key.".to_string(),
+            algorithm: TsigAlgorithm::HmacSha256,
+            secret: b"0123456789abcdef".to_vec(),
+        };
+        let name = "_acme-challenge.example.com";
+        let unsigned =
+            update_message(7, "example.com", name, "token", TxtUpdate::Delete, None, 0).unwrap();
+        let signed = update_message(
+            7,
+            "example.com",
+            name,
+            "token",
+            TxtUpdate::Delete,
+            Some(&key),
+            1_700_000_000,
+        )
+        .unwrap();
+
+        // Opcode UPDATE, one zone, one update, the TSIG record as additional data
+        assert_eq!(signed[2] >> 3, OPCODE_UPDATE as u8);
+        assert_eq!(read_u16(&signed, 8), Some(1));
+        assert_eq!(read_u16(&signed, 10), Some(1));
+        assert_eq!(signed[12..unsigned.len()], unsigned[12..]);
+
+        // The MAC verifies against the unsigned message plus the TSIG variables
+        let tsig = &signed[unsigned.len()..];
+        let key_name = b"\x08acme-key\x00";
+        assert!(tsig.starts_with(key_name));
+        let algorithm = b"\x0bhmac-sha256\x00";
+        let rdata = &tsig[key_name.len() + 10..];
+        assert!(rdata.starts_with(algorithm));
+        let fixed = &rdata[algorithm.len()..];
+        assert_eq!(read_u16(fixed, 8), Some(32));
+        let mac = &fixed[10..42];
+        let mut digested = unsigned.clone();
+        digested.extend_from_slice(key_name);
+        digested.extend_from_slice(&[0, 255, 0, 0, 0, 0]);
+        digested.extend_from_slice(algorithm);
+        digested.extend_from_slice(&fixed[..8]);
+        digested.extend_from_slice(&[0, 0, 0, 0]);
+        let verify_key = hmac::Key::new(hmac::HMAC_SHA256, &key.secret);
+        assert!(hmac::verify(&verify_key, &digested, mac).is_ok());
+        assert_eq!(&fixed[42..44], &[0, 7]);
+    }
+}
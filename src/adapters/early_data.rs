@@ -0,0 +1,143 @@
+use axum::body::Body as AxumBody;
+use axum::response::{IntoResponse, Response as AxumResponse};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Method, Request, StatusCode};
+use tokio::sync::watch;
+
+use crate::config::EarlyDataPolicy;
+
+/// Tells backends the request arrived in early data and could be a replay (RFC 8470)
+pub const EARLY_DATA_HEADER: HeaderName = HeaderName::from_static("early-data");
+
+/// Request extension marking a request received in 0-RTT early data, before the
+/// handshake completed. An attacker can replay early data, so such requests only reach
+/// backends as the route's `early_data` policy allows.
+#[derive(Debug, Clone)]
+pub struct EarlyData {
+    handshake_completed: watch::Receiver<bool>,
+}
+
+impl EarlyData {
+    /// `handshake_completed` turns true once the connection's handshake completes
+    pub fn new(handshake_completed: watch::Receiver<bool>) -> Self {
+        Self {
+            handshake_completed,
+        }
+    }
+
+    /// Wait for the handshake, returning false when the connection closed before it
+    /// completed
+    async fn handshake_completed(&mut self) -> bool {
+        self.handshake_completed
+            .wait_for(|completed| *completed)
+            .await
+            .is_ok()
+    }
+}
+
+/// GET, HEAD and OPTIONS can be replayed without changing anything
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn too_early() -> AxumResponse {
+    (StatusCode::TOO_EARLY, "Too Early").into_response()
+}
+
+/// Apply `policy` to a request received in early data, returning the `425 Too Early`
+/// response when it is refused. Requests let through while still replayable carry
+/// `Early-Data: 1`; requests that waited for the handshake don't need it.
+pub async fn check_early_data(
+    policy: EarlyDataPolicy,
+    req: &mut Request<AxumBody>,
+) -> Option<AxumResponse> {
+    let mut early_data = req.extensions_mut().remove::<EarlyData>()?;
+
+    match policy {
+        EarlyDataPolicy::Allow => {}
+        EarlyDataPolicy::SafeMethods if is_safe(req.method()) => {}
+        EarlyDataPolicy::SafeMethods => return Some(too_early()),
+        EarlyDataPolicy::Defer if is_safe(req.method()) => {}
+        EarlyDataPolicy::Defer => {
+            return (!early_data.handshake_completed().await).then(too_early);
+        }
+    }
+
+    req.headers_mut()
+        .insert(EARLY_DATA_HEADER, HeaderValue::from_static("1"));
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn early_request(method: Method, handshake: &watch::Sender<bool>) -> Request<AxumBody> {
+        let mut req = Request::builder()
+            .method(method)
+            .uri("/api/items")
+            .body(AxumBody::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(EarlyData::new(handshake.subscribe()));
+        req
+    }
+
+    #[tokio::test]
+    async fn test_early_data_policies() {
+        let (handshake, _) = watch::channel(false);
+
+        // Requests after the handshake are left alone
+        let mut req = Request::post("/api/items").body(AxumBody::empty()).unwrap();
+        assert!(
+            check_early_data(EarlyDataPolicy::SafeMethods, &mut req)
+                .await
+                .is_none()
+        );
+        assert!(!req.headers().contains_key(EARLY_DATA_HEADER));
+
+        let mut req = early_request(Method::GET, &handshake);
+        assert!(
+            check_early_data(EarlyDataPolicy::SafeMethods, &mut req)
+                .await
+                .is_none()
+        );
+        assert_eq!(req.headers()[EARLY_DATA_HEADER], "1");
+
+        let mut req = early_request(Method::POST, &handshake);
+        let response = check_early_data(EarlyDataPolicy::SafeMethods, &mut req).await;
+        assert_eq!(response.unwrap().status(), StatusCode::TOO_EARLY);
+
+        let mut req = early_request(Method::POST, &handshake);
+        assert!(
+            check_early_data(EarlyDataPolicy::Allow, &mut req)
+                .await
+                .is_none()
+        );
+        assert_eq!(req.headers()[EARLY_DATA_HEADER], "1");
+    }
+
+    #[tokio::test]
+    async fn test_early_data_deferred() {
+        let (handshake, _) = watch::channel(false);
+
+        let mut req = early_request(Method::POST, &handshake);
+        let deferred = tokio::spawn(async move {
+            let response = check_early_data(EarlyDataPolicy::Defer, &mut req).await;
+            (response, req)
+        });
+        tokio::task::yield_now().await;
+        assert!(!deferred.is_finished());
+        handshake.send(true).unwrap();
+        let (response, req) = deferred.await.unwrap();
+        assert!(response.is_none());
+        assert!(!req.headers().contains_key(EARLY_DATA_HEADER));
+
+        // The connection closed before completing the handshake
+        let (handshake, _) = watch::channel(false);
+        let mut req = early_request(Method::POST, &handshake);
+        drop(handshake);
+        let response = check_early_data(EarlyDataPolicy::Defer, &mut req).await;
+        assert_eq!(response.unwrap().status(), StatusCode::TOO_EARLY);
+    }
+}
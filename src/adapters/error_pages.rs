@@ -0,0 +1,175 @@
+use axum::body::Body as AxumBody;
+use axum::response::Response as AxumResponse;
+use hyper::header::{self, HeaderValue};
+use std::collections::HashMap;
+
+use crate::config::{ErrorPageConfig, RouteOptions};
+
+/// Marker placed in the extensions of responses that came from a backend, so error pages
+/// only replace prox-generated errors unless the route opts into intercepting backend ones.
+#[derive(Debug, Clone, Copy)]
+pub struct BackendOriginated;
+
+/// Find the page for a status: route exact, route class, global exact, global class
+pub fn find_error_page<'a>(
+    status: u16,
+    route_pages: Option<&'a HashMap<String, ErrorPageConfig>>,
+    global_pages: &'a HashMap<String, ErrorPageConfig>,
+) -> Option<&'a ErrorPageConfig> {
+    let exact = status.to_string();
+    let class = format!("{}xx", status / 100);
+    route_pages
+        .into_iter()
+        .chain(std::iter::once(global_pages))
+        .find_map(|pages| {
+            pages
+                .get(&exact)
+                .or_else(|| pages.get(&class))
+                .or_else(|| pages.get(&class.to_uppercase()))
+        })
+}
+
+/// Replace the body of an error response with the configured error page, if any
+pub async fn apply_error_page(
+    response: AxumResponse,
+    route_options: Option<&RouteOptions>,
+    global_pages: &HashMap<String, ErrorPageConfig>,
+) -> AxumResponse {
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+
+    let from_backend = response.extensions().get::<BackendOriginated>().is_some();
+    if from_backend && !route_options.is_some_and(|o| o.intercept_backend_errors) {
+        return response;
+    }
+
+    let Some(page) = find_error_page(
+        status.as_u16(),
+        route_options.map(|o| &o.error_pages),
+        global_pages,
+    ) else {
+        return response;
+    };
+
+    let (body, content_type) = match (&page.file, &page.body) {
+        (Some(file), _) => match tokio::fs::read(file).await {
+            Ok(bytes) => {
+                let mime = mime_guess::from_path(file).first_or_octet_stream();
+                let content_type = if mime.type_() == mime_guess::mime::TEXT {
+                    format!("{mime}; charset=utf-8")
+                } else {
+                    mime.to_string()
+                };
+                (bytes, content_type)
+            }
+            Err(e) => {
+                tracing::error!("Failed to read error page '{}': {}", file, e);
+                return response;
+            }
+        },
+        (None, Some(body)) => (
+            body.clone().into_bytes(),
+            "text/html; charset=utf-8".to_string(),
+        ),
+        (None, None) => return response,
+    };
+    let content_type = page.content_type.clone().unwrap_or(content_type);
+
+    let (mut parts, _) = response.into_parts();
+    for name in [
+        header::CONTENT_ENCODING,
+        header::CONTENT_RANGE,
+        header::TRANSFER_ENCODING,
+        header::ETAG,
+        header::LAST_MODIFIED,
+    ] {
+        parts.headers.remove(name);
+    }
+    match HeaderValue::from_str(&content_type) {
+        Ok(value) => {
+            parts.headers.insert(header::CONTENT_TYPE, value);
+        }
+        Err(_) => {
+            parts.headers.remove(header::CONTENT_TYPE);
+        }
+    }
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+
+    AxumResponse::from_parts(parts, AxumBody::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+    use http_body_util::BodyExt;
+    use hyper::StatusCode;
+
+    fn inline(body: &str) -> ErrorPageConfig {
+        ErrorPageConfig {
+            body: Some(body.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lookup_precedence() {
+        let mut route = HashMap::new();
+        route.insert("5xx".to_string(), inline("route 5xx"));
+        let mut global = HashMap::new();
+        global.insert("502".to_string(), inline("global 502"));
+        global.insert("4xx".to_string(), inline("global 4xx"));
+
+        let page =
+            |status| find_error_page(status, Some(&route), &global).and_then(|p| p.body.clone());
+        assert_eq!(page(502).as_deref(), Some("route 5xx"));
+        assert_eq!(page(404).as_deref(), Some("global 4xx"));
+        assert_eq!(page(302), None);
+        assert_eq!(
+            find_error_page(502, None, &global).and_then(|p| p.body.as_deref()),
+            Some("global 502")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_page_sets_content_type_and_skips_backend_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("502.json");
+        std::fs::write(&file, br#"{"error":"bad gateway"}"#).unwrap();
+        let mut global = HashMap::new();
+        global.insert(
+            "502".to_string(),
+            ErrorPageConfig {
+                file: Some(file.to_str().unwrap().to_string()),
+                ..Default::default()
+            },
+        );
+
+        let generated = (StatusCode::BAD_GATEWAY, "Proxy request failed").into_response();
+        let response = apply_error_page(generated, None, &global).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], br#"{"error":"bad gateway"}"#);
+
+        let mut from_backend = (StatusCode::BAD_GATEWAY, "upstream body").into_response();
+        from_backend.extensions_mut().insert(BackendOriginated);
+        let response = apply_error_page(from_backend, None, &global).await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"upstream body");
+
+        let mut from_backend = (StatusCode::BAD_GATEWAY, "upstream body").into_response();
+        from_backend.extensions_mut().insert(BackendOriginated);
+        let options = RouteOptions {
+            intercept_backend_errors: true,
+            ..Default::default()
+        };
+        let response = apply_error_page(from_backend, Some(&options), &global).await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], br#"{"error":"bad gateway"}"#);
+    }
+}
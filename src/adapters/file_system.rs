@@ -1,19 +1,692 @@
 use axum::body::Body as AxumBody;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
 use http_body_util::BodyExt;
-use hyper::{Request, Response};
+use hyper::{Request, Response, StatusCode, header, header::HeaderValue};
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, utf8_percent_encode};
 use std::convert::TryFrom;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
 use tower::ServiceExt;
 use tower_http::services::ServeDir;
 
+use crate::adapters::static_cache::{CacheLookup, CachedFile, CachedVariant, StaticFileCache};
+use crate::config::{PrecompressedEncoding, StaticCacheConfig, StaticOptions};
+use crate::metrics::increment_static_cache_request;
 use crate::ports::file_system::{FileSystem, FileSystemError, FileSystemResult};
 
+/// Characters escaped when turning a file name into a link in a directory listing
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
 #[derive(Debug, Default, Clone)]
-pub struct TowerFileSystem;
+pub struct TowerFileSystem {
+    /// In-memory caches of static routes, keyed by root and cache settings
+    caches: Arc<DashMap<String, Arc<StaticFileCache>>>,
+}
+
+/// A single entry of a rendered directory listing
+#[derive(Debug, serde::Serialize)]
+struct DirEntryInfo {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<DateTime<Utc>>,
+}
+
+/// Validators for the file a request resolves to
+struct FileValidators {
+    etag: HeaderValue,
+    last_modified: Option<HeaderValue>,
+    /// ETags of precompressed sidecars the client accepts, keyed by content coding
+    encoded_etags: Vec<(&'static str, HeaderValue)>,
+}
+
+impl FileValidators {
+    /// The ETag of the representation actually served
+    fn etag_for(&self, content_encoding: Option<&HeaderValue>) -> &HeaderValue {
+        content_encoding
+            .and_then(|coding| {
+                self.encoded_etags
+                    .iter()
+                    .find(|(name, _)| coding == name)
+                    .map(|(_, etag)| etag)
+            })
+            .unwrap_or(&self.etag)
+    }
+}
+
+/// What a request maps to under the static root
+enum Resolved {
+    /// A regular file; `uri_path` is the percent-encoded path handed to ServeDir
+    File {
+        uri_path: String,
+        fs_path: PathBuf,
+        meta: std::fs::Metadata,
+    },
+    /// A directory without any of the configured index files
+    Directory(PathBuf),
+    /// A directory requested without its trailing slash
+    Redirect(String),
+    NotFound,
+}
+
+/// How a `Range` header applies to a representation of a given length
+#[derive(Debug, PartialEq, Eq)]
+enum RangeOutcome {
+    /// Serve the whole body: no usable range, or a multi-range request (no multipart support)
+    Full,
+    /// Serve the inclusive byte range with 206
+    Single(u64, u64),
+    /// None of the requested ranges overlap the body; answer 416
+    Unsatisfiable,
+}
 
 impl TowerFileSystem {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    /// The cache for a route, recreated whenever its settings change
+    fn cache_for(&self, root: &str, config: &StaticCacheConfig) -> Arc<StaticFileCache> {
+        let key = format!(
+            "{root}:{}:{}:{}",
+            config.max_entry_size, config.max_total_bytes, config.ttl
+        );
+        self.caches
+            .entry(key)
+            .or_insert_with(|| Arc::new(StaticFileCache::new(config.clone())))
+            .clone()
+    }
+
+    /// Look a request up in the route cache, re-checking the file on disk once the TTL expired
+    async fn cached_file(cache: &StaticFileCache, key: &str) -> Option<Arc<CachedFile>> {
+        match cache.get(key) {
+            CacheLookup::Fresh(file) => Some(file),
+            CacheLookup::Stale(file) => match tokio::fs::metadata(&file.fs_path).await {
+                Ok(meta) if meta.len() == file.len && meta.modified().ok() == file.modified => {
+                    cache.refresh(key);
+                    Some(file)
+                }
+                _ => {
+                    cache.remove(key);
+                    None
+                }
+            },
+            CacheLookup::Miss => None,
+        }
+    }
+
+    /// Read a file (and any precompressed sidecars) into a cache entry
+    async fn load_cached_file(
+        uri_path: &str,
+        fs_path: &Path,
+        meta: &std::fs::Metadata,
+        cache: &StaticFileCache,
+        options: &StaticOptions,
+    ) -> Option<CachedFile> {
+        let bytes = tokio::fs::read(fs_path).await.ok()?;
+        if bytes.len() as u64 != meta.len() {
+            // Changed while we were reading it; serve from disk this time
+            return None;
+        }
+
+        let mut encoded = Vec::new();
+        for encoding in &options.precompressed {
+            let mut sidecar = fs_path.as_os_str().to_owned();
+            sidecar.push(".");
+            sidecar.push(encoding.extension());
+            let Ok(sidecar_meta) = tokio::fs::metadata(&sidecar).await else {
+                continue;
+            };
+            if !cache.accepts(sidecar_meta.len()) {
+                continue;
+            }
+            if let (Ok(sidecar_bytes), Some(etag)) = (
+                tokio::fs::read(&sidecar).await,
+                etag_from_metadata(&sidecar_meta),
+            ) {
+                encoded.push(CachedVariant {
+                    encoding: Some(encoding.as_str()),
+                    bytes: Bytes::from(sidecar_bytes),
+                    etag,
+                });
+            }
+        }
+
+        let content_type = HeaderValue::from_str(
+            mime_guess::from_path(fs_path)
+                .first_or_octet_stream()
+                .as_ref(),
+        )
+        .ok()?;
+
+        Some(CachedFile {
+            uri_path: uri_path.to_string(),
+            fs_path: fs_path.to_path_buf(),
+            modified: meta.modified().ok(),
+            len: meta.len(),
+            content_type,
+            last_modified: meta.modified().ok().and_then(http_date),
+            identity: CachedVariant {
+                encoding: None,
+                bytes: Bytes::from(bytes),
+                etag: etag_from_metadata(meta)?,
+            },
+            encoded,
+        })
     }
+
+    /// Answer a request from a cached entry, honouring conditional and range headers
+    fn serve_from_memory(
+        file: &CachedFile,
+        method: &hyper::Method,
+        headers: &hyper::HeaderMap,
+        options: &StaticOptions,
+    ) -> FileSystemResult<Response<AxumBody>> {
+        let cache_control = Self::cache_control_for(&file.uri_path, options);
+        let variant = options
+            .precompressed
+            .iter()
+            .filter(|encoding| accepts_encoding(headers, encoding.as_str()))
+            .find_map(|encoding| {
+                file.encoded
+                    .iter()
+                    .find(|variant| variant.encoding == Some(encoding.as_str()))
+            })
+            .unwrap_or(&file.identity);
+
+        let not_modified = match headers.get(header::IF_NONE_MATCH) {
+            Some(if_none_match) => etag_matches(if_none_match, &variant.etag),
+            None => headers
+                .get(header::IF_MODIFIED_SINCE)
+                .is_some_and(|since| not_modified_since(since, file.modified)),
+        };
+        if not_modified {
+            return Self::not_modified(
+                &variant.etag,
+                file.last_modified.as_ref(),
+                cache_control,
+                options,
+            );
+        }
+
+        let len = variant.bytes.len() as u64;
+        let range = headers
+            .get(header::RANGE)
+            .filter(|_| method == hyper::Method::GET)
+            .filter(|_| {
+                headers
+                    .get(header::IF_RANGE)
+                    .is_none_or(|if_range| if_range_matches(if_range, &variant.etag, file.modified))
+            })
+            .map(|range| parse_range(range, len))
+            .unwrap_or(RangeOutcome::Full);
+
+        let mut builder = Response::builder()
+            .header(header::CONTENT_TYPE, file.content_type.clone())
+            .header(header::ACCEPT_RANGES, "bytes")
+            .header(header::ETAG, variant.etag.clone());
+        if let Some(last_modified) = &file.last_modified {
+            builder = builder.header(header::LAST_MODIFIED, last_modified.clone());
+        }
+        if let Some(cache_control) = cache_control {
+            builder = builder.header(header::CACHE_CONTROL, cache_control);
+        }
+        if let Some(encoding) = variant.encoding {
+            builder = builder.header(header::CONTENT_ENCODING, encoding);
+        }
+        if !options.precompressed.is_empty() {
+            builder = builder.header(header::VARY, "Accept-Encoding");
+        }
+
+        let (status, body) = match range {
+            RangeOutcome::Full => (StatusCode::OK, variant.bytes.clone()),
+            RangeOutcome::Single(start, end) => {
+                builder =
+                    builder.header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{len}"));
+                (
+                    StatusCode::PARTIAL_CONTENT,
+                    variant.bytes.slice(start as usize..=end as usize),
+                )
+            }
+            RangeOutcome::Unsatisfiable => {
+                builder = builder.header(header::CONTENT_RANGE, format!("bytes */{len}"));
+                (StatusCode::RANGE_NOT_SATISFIABLE, Bytes::new())
+            }
+        };
+
+        builder = builder
+            .status(status)
+            .header(header::CONTENT_LENGTH, body.len());
+        let body = if method == hyper::Method::HEAD {
+            AxumBody::empty()
+        } else {
+            AxumBody::from(body)
+        };
+        builder
+            .body(body)
+            .map_err(|e| FileSystemError::IoError(std::io::Error::other(e)))
+    }
+
+    /// Apply the dotfile and index-file policies to find what the request should serve
+    async fn resolve(
+        root: &str,
+        path: &str,
+        uri: &hyper::Uri,
+        options: &StaticOptions,
+    ) -> Resolved {
+        let Some(fs_path) = Self::resolve_path(root, path) else {
+            return Resolved::NotFound;
+        };
+        if options.deny_dotfiles && Self::has_denied_dotfile(path, options) {
+            return Resolved::NotFound;
+        }
+        let Ok(meta) = tokio::fs::metadata(&fs_path).await else {
+            return Resolved::NotFound;
+        };
+        let relative = path.trim_start_matches('/');
+        if meta.is_file() {
+            return Resolved::File {
+                uri_path: format!("/{relative}"),
+                fs_path,
+                meta,
+            };
+        }
+        if !meta.is_dir() {
+            return Resolved::NotFound;
+        }
+
+        // Relative links and index files only work from a URL ending in '/'
+        let request_path = uri.path();
+        if !request_path.ends_with('/') {
+            return Resolved::Redirect(match uri.query() {
+                Some(query) => format!("{request_path}/?{query}"),
+                None => format!("{request_path}/"),
+            });
+        }
+
+        for index in &options.index_files {
+            let index_path = fs_path.join(index);
+            if let Ok(index_meta) = tokio::fs::metadata(&index_path).await
+                && index_meta.is_file()
+            {
+                return Resolved::File {
+                    uri_path: format!("/{relative}{}", utf8_percent_encode(index, PATH_SEGMENT)),
+                    fs_path: index_path,
+                    meta: index_meta,
+                };
+            }
+        }
+        Resolved::Directory(fs_path)
+    }
+
+    /// Whether any segment of the path is a dotfile that isn't explicitly allowed
+    fn has_denied_dotfile(path: &str, options: &StaticOptions) -> bool {
+        let decoded = percent_decode_str(path).decode_utf8_lossy();
+        decoded
+            .split('/')
+            .filter(|segment| segment.starts_with('.') && *segment != ".")
+            .any(|segment| {
+                !options
+                    .allowed_dotfiles
+                    .iter()
+                    .any(|allowed| allowed == segment)
+            })
+    }
+
+    /// Compute the ETag and Last-Modified of the file being served
+    async fn file_validators(
+        fs_path: &Path,
+        meta: &std::fs::Metadata,
+        headers: &hyper::HeaderMap,
+        options: &StaticOptions,
+    ) -> Option<FileValidators> {
+        let etag = etag_from_metadata(meta)?;
+        let last_modified = meta.modified().ok().and_then(http_date);
+
+        let mut encoded_etags = Vec::new();
+        for encoding in &options.precompressed {
+            if !accepts_encoding(headers, encoding.as_str()) {
+                continue;
+            }
+            let mut sidecar = fs_path.as_os_str().to_owned();
+            sidecar.push(".");
+            sidecar.push(encoding.extension());
+            if let Ok(sidecar_meta) = tokio::fs::metadata(&sidecar).await
+                && let Some(sidecar_etag) = etag_from_metadata(&sidecar_meta)
+            {
+                encoded_etags.push((encoding.as_str(), sidecar_etag));
+            }
+        }
+
+        Some(FileValidators {
+            etag,
+            last_modified,
+            encoded_etags,
+        })
+    }
+
+    /// Pick the Cache-Control value of the first rule matching the requested file
+    fn cache_control_for(path: &str, options: &StaticOptions) -> Option<HeaderValue> {
+        let relative = percent_decode_str(path.trim_start_matches('/')).decode_utf8_lossy();
+        let file_name = relative.rsplit('/').next().unwrap_or_default();
+        options
+            .cache_control
+            .iter()
+            .find(|rule| {
+                if rule.pattern.contains('/') {
+                    glob_matches(rule.pattern.trim_start_matches('/'), &relative)
+                } else {
+                    glob_matches(&rule.pattern, file_name)
+                }
+            })
+            .and_then(|rule| HeaderValue::from_str(&rule.value).ok())
+    }
+
+    fn not_modified(
+        etag: &HeaderValue,
+        last_modified: Option<&HeaderValue>,
+        cache_control: Option<HeaderValue>,
+        options: &StaticOptions,
+    ) -> FileSystemResult<Response<AxumBody>> {
+        let mut builder = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, etag.clone());
+        if !options.precompressed.is_empty() {
+            builder = builder.header(header::VARY, "Accept-Encoding");
+        }
+        if let Some(last_modified) = last_modified {
+            builder = builder.header(header::LAST_MODIFIED, last_modified.clone());
+        }
+        if let Some(cache_control) = cache_control {
+            builder = builder.header(header::CACHE_CONTROL, cache_control);
+        }
+        builder
+            .body(AxumBody::empty())
+            .map_err(|e| FileSystemError::IoError(std::io::Error::other(e)))
+    }
+
+    /// Map a request path onto the static root, refusing anything that would escape it
+    fn resolve_path(root: &str, path: &str) -> Option<PathBuf> {
+        let decoded = percent_decode_str(path).decode_utf8().ok()?;
+        let mut resolved = PathBuf::from(root);
+        for component in Path::new(decoded.trim_start_matches('/')).components() {
+            match component {
+                Component::Normal(segment) => resolved.push(segment),
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        Some(resolved)
+    }
+
+    /// Render an HTML or JSON (per Accept) listing of a directory
+    async fn directory_listing(
+        dir: &Path,
+        uri: &hyper::Uri,
+        headers: &hyper::HeaderMap,
+        options: &StaticOptions,
+    ) -> FileSystemResult<Response<AxumBody>> {
+        let request_path = uri.path();
+        let entries = Self::read_entries(dir, options.show_hidden).await?;
+        let wants_json = headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|accept| accept.contains("application/json"));
+
+        let (content_type, body) = if wants_json {
+            let body = serde_json::to_string(&entries)
+                .map_err(|e| FileSystemError::IoError(std::io::Error::other(e)))?;
+            ("application/json", body)
+        } else {
+            (
+                "text/html; charset=utf-8",
+                Self::render_listing_html(request_path, &entries),
+            )
+        };
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::VARY, "Accept")
+            .body(AxumBody::from(body))
+            .map_err(|e| FileSystemError::IoError(std::io::Error::other(e)))
+    }
+
+    async fn read_entries(dir: &Path, show_hidden: bool) -> FileSystemResult<Vec<DirEntryInfo>> {
+        let mut entries = Vec::new();
+        let mut read_dir = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !show_hidden && name.starts_with('.') {
+                continue;
+            }
+            let Ok(meta) = entry.metadata().await else {
+                continue;
+            };
+            entries.push(DirEntryInfo {
+                name,
+                is_dir: meta.is_dir(),
+                size: if meta.is_dir() { 0 } else { meta.len() },
+                modified: meta.modified().ok().map(DateTime::<Utc>::from),
+            });
+        }
+        // Directories first, then alphabetical
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+        Ok(entries)
+    }
+
+    fn render_listing_html(request_path: &str, entries: &[DirEntryInfo]) -> String {
+        let title = escape_html(&percent_decode_str(request_path).decode_utf8_lossy());
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {title}</title></head>\n<body>\n<h1>Index of {title}</h1>\n<table>\n<tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n"
+        );
+        for entry in entries {
+            let suffix = if entry.is_dir { "/" } else { "" };
+            let href = utf8_percent_encode(&entry.name, PATH_SEGMENT).to_string();
+            let size = if entry.is_dir {
+                "-".to_string()
+            } else {
+                entry.size.to_string()
+            };
+            let modified = entry
+                .modified
+                .map(|m| m.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_default();
+            html.push_str(&format!(
+                "<tr><td><a href=\"{href}{suffix}\">{name}{suffix}</a></td><td>{size}</td><td>{modified}</td></tr>\n",
+                href = escape_html(&href),
+                name = escape_html(&entry.name),
+            ));
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+}
+
+/// Strong validator derived from size and modification time, like most file servers
+fn etag_from_metadata(meta: &std::fs::Metadata) -> Option<HeaderValue> {
+    let mtime_nanos = meta
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    HeaderValue::from_str(&format!("\"{:x}-{:x}\"", meta.len(), mtime_nanos)).ok()
+}
+
+/// Format a timestamp as an HTTP-date (RFC 9110 5.6.7)
+fn http_date(time: SystemTime) -> Option<HeaderValue> {
+    let formatted = DateTime::<Utc>::from(time).format("%a, %d %b %Y %H:%M:%S GMT");
+    HeaderValue::from_str(&formatted.to_string()).ok()
+}
+
+fn parse_http_date(value: &HeaderValue) -> Option<i64> {
+    DateTime::parse_from_rfc2822(value.to_str().ok()?)
+        .ok()
+        .map(|date| date.timestamp())
+}
+
+fn unix_seconds(time: SystemTime) -> Option<i64> {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .and_then(|d| i64::try_from(d.as_secs()).ok())
+}
+
+/// If-Modified-Since check at the one-second resolution of HTTP dates
+fn not_modified_since(since: &HeaderValue, modified: Option<SystemTime>) -> bool {
+    match (parse_http_date(since), modified.and_then(unix_seconds)) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// If-Range holds either a strong ETag or the exact Last-Modified date
+fn if_range_matches(
+    if_range: &HeaderValue,
+    etag: &HeaderValue,
+    modified: Option<SystemTime>,
+) -> bool {
+    let Ok(value) = if_range.to_str() else {
+        return false;
+    };
+    let value = value.trim();
+    if value.starts_with('"') {
+        return etag.to_str().is_ok_and(|etag| etag == value);
+    }
+    if value.starts_with("W/") {
+        // Weak validators never match for If-Range
+        return false;
+    }
+    match (parse_http_date(if_range), modified.and_then(unix_seconds)) {
+        (Some(date), Some(modified)) => date == modified,
+        _ => false,
+    }
+}
+
+/// Interpret a `Range: bytes=...` header against a body of `len` bytes
+fn parse_range(range: &HeaderValue, len: u64) -> RangeOutcome {
+    let Some(specs) = range
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().strip_prefix("bytes="))
+    else {
+        // Unknown range units are ignored
+        return RangeOutcome::Full;
+    };
+
+    let mut satisfiable = Vec::new();
+    let mut count = 0;
+    for spec in specs.split(',').map(str::trim) {
+        count += 1;
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeOutcome::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+        let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+            // "-N": the last N bytes
+            (Err(_), Ok(suffix)) if start.is_empty() => {
+                (suffix > 0 && len > 0).then(|| (len - suffix.min(len), len - 1))
+            }
+            // "N-": from N to the end
+            (Ok(first), Err(_)) if end.is_empty() => (first < len).then(|| (first, len - 1)),
+            (Ok(first), Ok(last)) if first <= last => {
+                (first < len).then(|| (first, last.min(len - 1)))
+            }
+            _ => return RangeOutcome::Full,
+        };
+        satisfiable.extend(range);
+    }
+
+    match (count, satisfiable.as_slice()) {
+        (_, []) => RangeOutcome::Unsatisfiable,
+        (1, [(start, end)]) => RangeOutcome::Single(*start, *end),
+        // Multipart/byteranges isn't supported; the full body is a valid answer
+        _ => RangeOutcome::Full,
+    }
+}
+
+/// Whether Accept-Encoding lists the coding with a non-zero quality
+fn accepts_encoding(headers: &hyper::HeaderMap, coding: &str) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let mut params = item.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let rejected = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            name.eq_ignore_ascii_case(coding) && !rejected
+        })
+}
+
+/// Check an If-None-Match header value against the current ETag
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(candidates) = if_none_match.to_str() else {
+        return false;
+    };
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+    // Weak comparison, as required for If-None-Match
+    let etag = etag.trim_start_matches("W/");
+    candidates
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// Minimal glob matching: `*` and `?` stay within a path segment, `**` spans segments
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    fn matches(p: &[u8], t: &[u8]) -> bool {
+        match p.first() {
+            None => t.is_empty(),
+            Some(b'*') if p.get(1) == Some(&b'*') => {
+                let rest = p[2..].strip_prefix(b"/").unwrap_or(&p[2..]);
+                (0..=t.len()).any(|i| matches(rest, &t[i..]))
+            }
+            Some(b'*') => (0..=t.len())
+                .take_while(|&i| i == 0 || t[i - 1] != b'/')
+                .any(|i| matches(&p[1..], &t[i..])),
+            Some(b'?') => t.first().is_some_and(|&c| c != b'/') && matches(&p[1..], &t[1..]),
+            Some(&c) => t.first() == Some(&c) && matches(&p[1..], &t[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), text.as_bytes())
+}
+
+fn escape_html(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
 }
 
 impl FileSystem for TowerFileSystem {
@@ -22,32 +695,140 @@ impl FileSystem for TowerFileSystem {
         root: &str,
         path: &str,
         req: Request<AxumBody>,
+        options: &StaticOptions,
     ) -> FileSystemResult<Response<AxumBody>> {
+        let cache = options
+            .cache
+            .as_ref()
+            .map(|config| self.cache_for(root, config));
+        if let Some(cache) = &cache {
+            let cached = Self::cached_file(cache, path).await;
+            increment_static_cache_request(root, cached.is_some());
+            if let Some(file) = cached {
+                return Self::serve_from_memory(&file, req.method(), req.headers(), options);
+            }
+        }
+
+        let (uri_path, fs_path, meta) = match Self::resolve(root, path, req.uri(), options).await {
+            Resolved::File {
+                uri_path,
+                fs_path,
+                meta,
+            } => (uri_path, fs_path, meta),
+            Resolved::Directory(dir) if options.directory_listing => {
+                return Self::directory_listing(&dir, req.uri(), req.headers(), options).await;
+            }
+            Resolved::Redirect(location) => {
+                return Response::builder()
+                    .status(StatusCode::MOVED_PERMANENTLY)
+                    .header(header::LOCATION, location)
+                    .body(AxumBody::empty())
+                    .map_err(|e| FileSystemError::IoError(std::io::Error::other(e)));
+            }
+            Resolved::Directory(_) | Resolved::NotFound => {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(AxumBody::empty())
+                    .map_err(|e| FileSystemError::IoError(std::io::Error::other(e)));
+            }
+        };
+
+        if let Some(cache) = &cache
+            && cache.accepts(meta.len())
+            && let Some(file) =
+                Self::load_cached_file(&uri_path, &fs_path, &meta, cache, options).await
+        {
+            let file = cache.insert(path.to_string(), file);
+            return Self::serve_from_memory(&file, req.method(), req.headers(), options);
+        }
+
+        let validators = Self::file_validators(&fs_path, &meta, req.headers(), options).await;
+        let cache_control = Self::cache_control_for(&uri_path, options);
+
+        let (mut parts, body) = req.into_parts();
+        if let Some(validators) = &validators
+            && let Some(if_none_match) = parts.headers.get(header::IF_NONE_MATCH)
+        {
+            // Any representation the client can accept is a valid match
+            let matched = std::iter::once(&validators.etag)
+                .chain(validators.encoded_etags.iter().map(|(_, etag)| etag))
+                .find(|etag| etag_matches(if_none_match, etag));
+            if let Some(etag) = matched {
+                return Self::not_modified(
+                    etag,
+                    validators.last_modified.as_ref(),
+                    cache_control,
+                    options,
+                );
+            }
+            // If-None-Match takes precedence over If-Modified-Since (RFC 9110 13.2.2)
+            parts.headers.remove(header::IF_MODIFIED_SINCE);
+        }
+        if let Some(range) = parts.headers.get(header::RANGE) {
+            let if_range_holds = parts.headers.get(header::IF_RANGE).is_none_or(|if_range| {
+                validators.as_ref().is_some_and(|validators| {
+                    std::iter::once(&validators.etag)
+                        .chain(validators.encoded_etags.iter().map(|(_, etag)| etag))
+                        .any(|etag| if_range_matches(if_range, etag, meta.modified().ok()))
+                })
+            });
+            // ServeDir ignores If-Range and answers multi-range or malformed ranges with 416;
+            // drop the header in those cases so the client gets the full body instead
+            if !if_range_holds || parse_range(range, meta.len()) == RangeOutcome::Full {
+                parts.headers.remove(header::RANGE);
+            }
+        }
+        let req = Request::from_parts(parts, body);
+
         let root = root.to_string();
-        let path = path.to_string();
 
         // Create a new request with the path adjusted for ServeDir
-        let uri_string = format!("/{path}", path = path.trim_start_matches('/'));
-        let uri = hyper::Uri::try_from(uri_string)
+        let uri = hyper::Uri::try_from(uri_path)
             .map_err(|e| FileSystemError::InvalidPath(e.to_string()))?;
 
         let (parts, body) = req.into_parts();
         let mut new_req = Request::from_parts(parts, body);
         *new_req.uri_mut() = uri;
 
-        // Use ServeDir from tower-http
-        let serve_dir = ServeDir::new(&root);
+        // Use ServeDir from tower-http; it falls back to the plain file when a sidecar is missing
+        let mut serve_dir = ServeDir::new(&root).append_index_html_on_directories(false);
+        for encoding in &options.precompressed {
+            serve_dir = match encoding {
+                PrecompressedEncoding::Br => serve_dir.precompressed_br(),
+                PrecompressedEncoding::Gzip => serve_dir.precompressed_gzip(),
+                PrecompressedEncoding::Zstd => serve_dir.precompressed_zstd(),
+                PrecompressedEncoding::Deflate => serve_dir.precompressed_deflate(),
+            };
+        }
         let response = serve_dir.oneshot(new_req).await.map_err(|e| {
             FileSystemError::IoError(std::io::Error::other(format!("ServeDir error: {e}")))
         })?;
 
-        let (parts, tower_body) = response.into_parts();
+        let (mut parts, tower_body) = response.into_parts();
         let axum_body = AxumBody::new(tower_body.map_err(|e| {
             tracing::error!("Error reading static file body: {}", e);
             // Convert Infallible to a type compatible with AxumBody's error
             axum::Error::new(e)
         }));
 
+        if matches!(
+            parts.status,
+            StatusCode::OK | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+        ) {
+            if let Some(validators) = &validators {
+                let etag = validators.etag_for(parts.headers.get(header::CONTENT_ENCODING));
+                parts.headers.insert(header::ETAG, etag.clone());
+            }
+            if !options.precompressed.is_empty() {
+                parts
+                    .headers
+                    .append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+            }
+            if let Some(cache_control) = cache_control {
+                parts.headers.insert(header::CACHE_CONTROL, cache_control);
+            }
+        }
+
         Ok(Response::from_parts(parts, axum_body))
     }
 }
@@ -55,10 +836,522 @@ impl FileSystem for TowerFileSystem {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::CacheControlRule;
 
     #[test]
     fn test_default_construction() {
         let _fs1 = TowerFileSystem::new();
-        let _fs2 = TowerFileSystem {};
+        let _fs2 = TowerFileSystem::default();
+    }
+
+    fn listing_options(show_hidden: bool) -> StaticOptions {
+        StaticOptions {
+            directory_listing: true,
+            show_hidden,
+            ..Default::default()
+        }
+    }
+
+    async fn body_string(response: Response<AxumBody>) -> String {
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_directory_listing_html_escapes_and_hides_dotfiles() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("<script>.txt"), b"hello").unwrap();
+        std::fs::write(dir.path().join(".env"), b"secret").unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let fs = TowerFileSystem::new();
+        let req = Request::get("/files/").body(AxumBody::empty()).unwrap();
+        let response = fs
+            .serve_file(root, "/", req, &listing_options(false))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let html = body_string(response).await;
+        assert!(html.contains("&lt;script&gt;.txt"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("href=\"sub/\""));
+        assert!(!html.contains(".env"));
+
+        let req = Request::get("/files/").body(AxumBody::empty()).unwrap();
+        let response = fs
+            .serve_file(root, "/", req, &listing_options(true))
+            .await
+            .unwrap();
+        assert!(body_string(response).await.contains(".env"));
+    }
+
+    #[tokio::test]
+    async fn test_directory_listing_json_and_redirect() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/a.bin"), b"12345").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let fs = TowerFileSystem::new();
+
+        let req = Request::get("/files/sub").body(AxumBody::empty()).unwrap();
+        let response = fs
+            .serve_file(root, "/sub", req, &listing_options(false))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[header::LOCATION], "/files/sub/");
+
+        let req = Request::get("/files/sub/")
+            .header(header::ACCEPT, "application/json")
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs
+            .serve_file(root, "/sub/", req, &listing_options(false))
+            .await
+            .unwrap();
+        let entries: serde_json::Value =
+            serde_json::from_str(&body_string(response).await).unwrap();
+        assert_eq!(entries[0]["name"], "a.bin");
+        assert_eq!(entries[0]["size"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_directory_listing_disabled_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let req = Request::get("/").body(AxumBody::empty()).unwrap();
+        let response = TowerFileSystem::new()
+            .serve_file(root, "/", req, &StaticOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_resolve_path_rejects_traversal() {
+        assert!(TowerFileSystem::resolve_path("/srv", "/../etc/passwd").is_none());
+        assert!(TowerFileSystem::resolve_path("/srv", "/%2e%2e/etc").is_none());
+        assert_eq!(
+            TowerFileSystem::resolve_path("/srv", "/a%20b/c").unwrap(),
+            PathBuf::from("/srv/a b/c")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests_return_304() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.js"), b"console.log(1);").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let fs = TowerFileSystem::new();
+        let options = StaticOptions {
+            cache_control: vec![CacheControlRule {
+                pattern: "*.js".to_string(),
+                value: "public, max-age=3600".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let req = Request::get("/app.js").body(AxumBody::empty()).unwrap();
+        let response = fs.serve_file(root, "/app.js", req, &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=3600"
+        );
+        let etag = response.headers()[header::ETAG].clone();
+        let last_modified = response.headers()[header::LAST_MODIFIED].clone();
+        assert!(!etag.to_str().unwrap().starts_with("W/"));
+
+        let req = Request::get("/app.js")
+            .header(header::IF_NONE_MATCH, etag.clone())
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs.serve_file(root, "/app.js", req, &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+        assert!(body_string(response).await.is_empty());
+
+        let req = Request::get("/app.js")
+            .header(header::IF_MODIFIED_SINCE, last_modified)
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs.serve_file(root, "/app.js", req, &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(body_string(response).await.is_empty());
+
+        // A stale ETag wins over a matching If-Modified-Since
+        let req = Request::get("/app.js")
+            .header(header::IF_NONE_MATCH, "\"stale\"")
+            .header(header::IF_MODIFIED_SINCE, "Fri, 31 Dec 2100 23:59:59 GMT")
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs.serve_file(root, "/app.js", req, &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_glob_matching() {
+        assert!(glob_matches("*.js", "app.js"));
+        assert!(!glob_matches("*.js", "app.json"));
+        assert!(glob_matches("assets/**", "assets/img/logo.png"));
+        assert!(!glob_matches("assets/*", "assets/img/logo.png"));
+        assert!(glob_matches("**/*.css", "a/b/site.css"));
+        assert!(glob_matches("v?.txt", "v1.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_precompressed_sidecar_and_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.js"), b"plain").unwrap();
+        std::fs::write(dir.path().join("app.js.br"), b"brotli").unwrap();
+        std::fs::write(dir.path().join("style.css"), b"plain css").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let fs = TowerFileSystem::new();
+        let options = StaticOptions {
+            precompressed: vec![PrecompressedEncoding::Br, PrecompressedEncoding::Gzip],
+            ..Default::default()
+        };
+
+        let req = Request::get("/app.js")
+            .header(header::ACCEPT_ENCODING, "gzip, br")
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs.serve_file(root, "/app.js", req, &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+        assert_eq!(response.headers()[header::VARY], "Accept-Encoding");
+        let br_etag = response.headers()[header::ETAG].clone();
+        assert_eq!(body_string(response).await, "brotli");
+
+        // The sidecar's ETag revalidates the encoded representation
+        let req = Request::get("/app.js")
+            .header(header::ACCEPT_ENCODING, "br")
+            .header(header::IF_NONE_MATCH, br_etag)
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs.serve_file(root, "/app.js", req, &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // No sidecar: plain file, still varying on Accept-Encoding
+        let req = Request::get("/style.css")
+            .header(header::ACCEPT_ENCODING, "br")
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs
+            .serve_file(root, "/style.css", req, &options)
+            .await
+            .unwrap();
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers()[header::VARY], "Accept-Encoding");
+        assert_eq!(body_string(response).await, "plain css");
+
+        // Client that doesn't accept br gets the plain file
+        let req = Request::get("/app.js")
+            .header(header::ACCEPT_ENCODING, "br;q=0, gzip")
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs.serve_file(root, "/app.js", req, &options).await.unwrap();
+        assert_eq!(body_string(response).await, "plain");
+    }
+
+    #[tokio::test]
+    async fn test_index_files_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/index.htm"), b"htm").unwrap();
+        std::fs::write(dir.path().join("docs/default.html"), b"default").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let fs = TowerFileSystem::new();
+
+        let options = StaticOptions {
+            index_files: vec!["index.html".to_string(), "index.htm".to_string()],
+            ..Default::default()
+        };
+        let req = Request::get("/site/docs/").body(AxumBody::empty()).unwrap();
+        let response = fs.serve_file(root, "/docs/", req, &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "htm");
+
+        // Only the configured names are considered
+        let req = Request::get("/site/docs/").body(AxumBody::empty()).unwrap();
+        let response = fs
+            .serve_file(root, "/docs/", req, &StaticOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // The redirect keeps the route prefix
+        let req = Request::get("/site/docs").body(AxumBody::empty()).unwrap();
+        let response = fs.serve_file(root, "/docs", req, &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[header::LOCATION], "/site/docs/");
+    }
+
+    #[tokio::test]
+    async fn test_dotfiles_denied_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".env"), b"SECRET=1").unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join(".git/config"), b"[core]").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let fs = TowerFileSystem::new();
+
+        for path in ["/.env", "/.git/config", "/%2eenv"] {
+            let req = Request::get(path).body(AxumBody::empty()).unwrap();
+            let response = fs
+                .serve_file(root, path, req, &StaticOptions::default())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{path}");
+        }
+
+        let options = StaticOptions {
+            deny_dotfiles: false,
+            ..Default::default()
+        };
+        let req = Request::get("/.env").body(AxumBody::empty()).unwrap();
+        let response = fs.serve_file(root, "/.env", req, &options).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_well_known_is_exempt_from_dotfile_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let challenge_dir = dir.path().join(".well-known/acme-challenge");
+        std::fs::create_dir_all(&challenge_dir).unwrap();
+        std::fs::write(challenge_dir.join("token"), b"token.key").unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let path = "/.well-known/acme-challenge/token";
+        let req = Request::get(path).body(AxumBody::empty()).unwrap();
+        let response = TowerFileSystem::new()
+            .serve_file(root, path, req, &StaticOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "token.key");
+    }
+
+    fn cached_options() -> StaticOptions {
+        StaticOptions {
+            cache: Some(StaticCacheConfig {
+                ttl: "1h".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_skips_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("hello.txt");
+        std::fs::write(&file, b"cached body").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let fs = TowerFileSystem::new();
+        let options = cached_options();
+
+        let req = Request::get("/hello.txt").body(AxumBody::empty()).unwrap();
+        let response = fs
+            .serve_file(root, "/hello.txt", req, &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "cached body");
+
+        // Within the TTL the entry is served without looking at the disk
+        std::fs::remove_file(&file).unwrap();
+        let req = Request::get("/hello.txt").body(AxumBody::empty()).unwrap();
+        let response = fs
+            .serve_file(root, "/hello.txt", req, &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(body_string(response).await, "cached body");
+    }
+
+    #[tokio::test]
+    async fn test_cache_revalidates_stale_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.txt");
+        std::fs::write(&file, b"first").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let fs = TowerFileSystem::new();
+        let options = StaticOptions {
+            cache: Some(StaticCacheConfig {
+                ttl: "0s".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let req = Request::get("/data.txt").body(AxumBody::empty()).unwrap();
+        let response = fs
+            .serve_file(root, "/data.txt", req, &options)
+            .await
+            .unwrap();
+        assert_eq!(body_string(response).await, "first");
+
+        std::fs::write(&file, b"second version").unwrap();
+        let req = Request::get("/data.txt").body(AxumBody::empty()).unwrap();
+        let response = fs
+            .serve_file(root, "/data.txt", req, &options)
+            .await
+            .unwrap();
+        assert_eq!(body_string(response).await, "second version");
+    }
+
+    #[tokio::test]
+    async fn test_cached_entries_support_ranges_and_conditionals() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("digits.txt"), b"0123456789").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let fs = TowerFileSystem::new();
+        let options = cached_options();
+
+        let req = Request::get("/digits.txt").body(AxumBody::empty()).unwrap();
+        let response = fs
+            .serve_file(root, "/digits.txt", req, &options)
+            .await
+            .unwrap();
+        let etag = response.headers()[header::ETAG].clone();
+
+        let req = Request::get("/digits.txt")
+            .header(header::RANGE, "bytes=2-4")
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs
+            .serve_file(root, "/digits.txt", req, &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 2-4/10");
+        assert_eq!(body_string(response).await, "234");
+
+        let req = Request::get("/digits.txt")
+            .header(header::RANGE, "bytes=20-")
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs
+            .serve_file(root, "/digits.txt", req, &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+
+        let req = Request::get("/digits.txt")
+            .header(header::IF_NONE_MATCH, etag)
+            .body(AxumBody::empty())
+            .unwrap();
+        let response = fs
+            .serve_file(root, "/digits.txt", req, &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let range = |value: &str| parse_range(&HeaderValue::from_str(value).unwrap(), 10);
+        assert_eq!(range("bytes=0-0"), RangeOutcome::Single(0, 0));
+        assert_eq!(range("bytes=5-"), RangeOutcome::Single(5, 9));
+        assert_eq!(range("bytes=-3"), RangeOutcome::Single(7, 9));
+        assert_eq!(range("bytes=8-100"), RangeOutcome::Single(8, 9));
+        assert_eq!(range("bytes=10-"), RangeOutcome::Unsatisfiable);
+        assert_eq!(range("bytes=0-1,4-5"), RangeOutcome::Full);
+        assert_eq!(range("bytes=5-2"), RangeOutcome::Full);
+        assert_eq!(range("items=0-1"), RangeOutcome::Full);
+    }
+
+    #[tokio::test]
+    async fn test_byte_ranges_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("video.bin"), b"0123456789").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let fs = TowerFileSystem::new();
+        let options = StaticOptions::default();
+        let get = |range: &str, if_range: Option<&HeaderValue>| {
+            let mut builder = Request::get("/video.bin").header(header::RANGE, range);
+            if let Some(if_range) = if_range {
+                builder = builder.header(header::IF_RANGE, if_range.clone());
+            }
+            builder.body(AxumBody::empty()).unwrap()
+        };
+
+        let response = fs
+            .serve_file(root, "/video.bin", get("bytes=2-5", None), &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 2-5/10");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "4");
+        let etag = response.headers()[header::ETAG].clone();
+        let last_modified = response.headers()[header::LAST_MODIFIED].clone();
+        assert_eq!(body_string(response).await, "2345");
+
+        let response = fs
+            .serve_file(root, "/video.bin", get("bytes=-3", None), &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body_string(response).await, "789");
+
+        let response = fs
+            .serve_file(root, "/video.bin", get("bytes=10-20", None), &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */10");
+
+        // Multipart responses aren't supported; multi-range requests get the full body
+        let response = fs
+            .serve_file(root, "/video.bin", get("bytes=0-1,4-5", None), &options)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "0123456789");
+
+        // If-Range: the range applies only while the validator still matches
+        for validator in [&etag, &last_modified] {
+            let response = fs
+                .serve_file(
+                    root,
+                    "/video.bin",
+                    get("bytes=0-1", Some(validator)),
+                    &options,
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        }
+        for stale in ["\"stale\"", "Thu, 01 Jan 1970 00:00:00 GMT"] {
+            let stale = HeaderValue::from_static(stale);
+            let response = fs
+                .serve_file(root, "/video.bin", get("bytes=0-1", Some(&stale)), &options)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(body_string(response).await, "0123456789");
+        }
+    }
+
+    #[test]
+    fn test_if_range_matching() {
+        let etag = HeaderValue::from_static("\"a-1\"");
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(784111777);
+        let matches = |value: &str| {
+            if_range_matches(
+                &HeaderValue::from_str(value).unwrap(),
+                &etag,
+                Some(modified),
+            )
+        };
+        assert!(matches("\"a-1\""));
+        assert!(!matches("W/\"a-1\""));
+        assert!(!matches("\"b-2\""));
+        assert!(matches("Sun, 06 Nov 1994 08:49:37 GMT"));
+        assert!(!matches("Sun, 06 Nov 1994 08:49:38 GMT"));
     }
 }
@@ -2,24 +2,40 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
-use tokio::time::sleep;
+use futures_util::future::join_all;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{HeaderMap, Method};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::time::{Instant, sleep_until};
+use url::Url;
 
 use crate::adapters::http_client::HyperHttpClient;
-use crate::config::{HealthCheckConfig, HealthStatus};
+use crate::adapters::websocket;
+use crate::config::{HealthCheckConfig, HealthCheckType};
 use crate::core::ProxyService;
-use crate::core::backend::BackendHealth;
-use crate::ports::http_client::HttpClient;
+use crate::core::backend::{BackendHealth, HealthSource};
+use crate::metrics::record_health_check;
+use crate::ports::http_client::{HealthCheckResponse, HttpClient};
+
+/// Each backend's interval is stretched or shrunk by up to this fraction, so checks
+/// don't all fire at once
+const INTERVAL_JITTER: f64 = 0.2;
 
 pub struct HealthChecker {
     proxy_service: Arc<ProxyService>,
     http_client: Arc<HyperHttpClient>,
+    /// Bounds the checks in flight at once
+    permits: Semaphore,
 }
 
 impl HealthChecker {
     pub fn new(proxy_service: Arc<ProxyService>, http_client: Arc<HyperHttpClient>) -> Self {
+        let permits = Semaphore::new(proxy_service.health_config().max_concurrent_checks.max(1));
         Self {
             proxy_service,
             http_client,
+            permits,
         }
     }
 
@@ -32,88 +48,112 @@ impl HealthChecker {
             return Ok(());
         }
 
-        let interval = Duration::from_secs(health_config.interval_secs);
-        let timeout = Duration::from_secs(health_config.timeout_secs);
-
         tracing::info!(
-            "Starting health checker with interval: {}s, timeout: {}s, default path: {}",
+            "Starting health checker with interval: {}s, timeout: {}s, default path: {}, grace period: {}s, at most {} checks at once",
             health_config.interval_secs,
             health_config.timeout_secs,
-            health_config.path
+            health_config.path,
+            health_config.grace_period_secs,
+            health_config.max_concurrent_checks
         );
 
-        loop {
-            // Sleep at the beginning to allow the server to start up
-            sleep(interval).await;
+        // Each backend on its own timer, so its interval and timeout don't hold up the others
+        let targets: Vec<String> = self
+            .proxy_service
+            .backend_health()
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        join_all(targets.into_iter().map(|target| self.run_backend(target))).await;
+        Ok(())
+    }
 
-            tracing::info!("Running health checks on all backends...");
+    /// Check `target` every interval, with its route and backend overrides applied
+    async fn run_backend(&self, target: String) {
+        let config = self.proxy_service.backend_health_check(&target);
+        let interval = Duration::from_secs(config.interval_secs);
 
-            // Check each backend using the getter method instead of direct field access
-            for backend_entry in self.proxy_service.backend_health().iter() {
-                let target = backend_entry.key().clone();
-                let backend_health = backend_entry.value();
+        let health_check_url = probe_url(&target, &config);
+        // Validated with the config
+        let method = Method::from_bytes(config.method().to_ascii_uppercase().as_bytes())
+            .unwrap_or(Method::HEAD);
+        let headers: HeaderMap = config
+            .headers
+            .0
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::try_from(name.as_str()).ok()?,
+                    HeaderValue::try_from(value.as_str()).ok()?,
+                ))
+            })
+            .collect();
 
-                // Get backend-specific health check path or use default
-                let backend_path = self.proxy_service.get_backend_health_path(&target);
+        let probe = match config.check_type {
+            HealthCheckType::Http => format!("{method} {health_check_url}"),
+            HealthCheckType::Tcp => format!("a TCP connection to {health_check_url}"),
+        };
+        tracing::info!(
+            "Health checking {} with {} every {}s (timeout: {}s)",
+            target,
+            probe,
+            config.interval_secs,
+            config.timeout_secs
+        );
 
-                // Construct health check URL
-                let health_check_url = format!("{target}{backend_path}");
+        // Scheduled from the previous due time, not from when the last check finished
+        let mut next_check = Instant::now();
+        loop {
+            // Sleep at the beginning to allow the server to start up
+            next_check = (next_check + jittered(interval)).max(Instant::now());
+            sleep_until(next_check).await;
+            let Ok(_permit) = self.permits.acquire().await else {
+                return;
+            };
 
-                tracing::info!("Health checking: {}", health_check_url);
+            tracing::debug!("Health checking {} with {}", target, probe);
 
-                // Perform the health check with timeout
-                match self
+            // Perform the health check with timeout
+            let started = Instant::now();
+            let verdict = match config.check_type {
+                HealthCheckType::Http => match self
                     .http_client
-                    .health_check(&health_check_url, timeout.as_secs())
+                    .health_check(
+                        &health_check_url,
+                        method.clone(),
+                        &headers,
+                        config.timeout_secs,
+                    )
                     .await
                 {
-                    Ok(is_healthy) => {
-                        if is_healthy {
-                            // Increment success counter
-                            let successes = backend_health
-                                .consecutive_successes
-                                .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-                                + 1;
-
-                            // Log every successful health check
-                            tracing::info!(
-                                "Health check for {} succeeded ({} consecutive successes)",
-                                target,
-                                successes
-                            );
-
-                            // If we've reached the threshold, mark as healthy
-                            if successes >= health_config.healthy_threshold
-                                && backend_health.status() == HealthStatus::Unhealthy
-                            {
-                                tracing::info!(
-                                    "Backend {} is now HEALTHY (after {} consecutive successes)",
-                                    target,
-                                    successes
-                                );
-                                backend_health.mark_healthy();
-                            }
-                        } else {
-                            self.handle_health_check_failure(
-                                &target,
-                                backend_health,
-                                health_config,
-                                "Backend returned unhealthy status",
-                            );
-                        }
-                    }
-                    Err(err) => {
-                        self.handle_health_check_failure(
-                            &target,
-                            backend_health,
-                            health_config,
-                            &format!("Health check error: {err}"),
-                        );
-                    }
+                    Ok(response) => check_response(&config, &response),
+                    Err(err) => Err(format!("Health check error: {err}")),
+                },
+                HealthCheckType::Tcp => {
+                    tcp_check(&health_check_url, Duration::from_secs(config.timeout_secs)).await
                 }
-            }
+            };
+
+            record_health_check(&target, verdict.is_ok(), started.elapsed());
 
-            tracing::info!("Health check cycle completed");
+            let Some(backend_health) = self.proxy_service.backend_health().get(&target) else {
+                return;
+            };
+            match verdict {
+                Ok(()) => {
+                    backend_health.record_success(config.healthy_threshold, HealthSource::Active);
+
+                    // Log every successful health check
+                    tracing::info!(
+                        "Health check for {} succeeded ({} consecutive successes)",
+                        target,
+                        backend_health.consecutive_successes()
+                    );
+                }
+                Err(reason) => {
+                    self.handle_health_check_failure(&target, &backend_health, &config, &reason);
+                }
+            }
         }
     }
 
@@ -124,37 +164,215 @@ impl HealthChecker {
         health_config: &HealthCheckConfig,
         reason: &str,
     ) {
-        // Atomically increment failure counter and get new value
-        let failures = backend_health
-            .consecutive_failures
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-            + 1;
+        if backend_health.in_grace_period(health_config.grace_period()) {
+            tracing::info!(
+                "Health check failed for {} within its {}s grace period, not counted: {}",
+                target,
+                health_config.grace_period_secs,
+                reason
+            );
+            return;
+        }
 
-        // Reset success counter atomically
-        backend_health
-            .consecutive_successes
-            .store(0, std::sync::atomic::Ordering::Relaxed);
+        backend_health.record_failure(
+            health_config.unhealthy_threshold,
+            reason,
+            HealthSource::Active,
+        );
 
         // Log all failures at the INFO level for better visibility
         tracing::info!(
             "Health check failed for {}: {} (failures: {}/{})",
             target,
             reason,
-            failures,
+            backend_health.consecutive_failures(),
             health_config.unhealthy_threshold
         );
+    }
+}
 
-        // Mark as unhealthy if threshold reached and current status is healthy
-        if failures >= health_config.unhealthy_threshold
-            && backend_health.status() == HealthStatus::Healthy
-        {
-            tracing::warn!(
-                "Backend {} is now UNHEALTHY (after {} consecutive failures): {}",
-                target,
-                failures,
-                reason
-            );
-            backend_health.mark_unhealthy();
+/// URL probed for `target`: WebSocket backends are checked over HTTP(S), and the
+/// check's `scheme` and `port` replace the backend's
+fn probe_url(target: &str, config: &HealthCheckConfig) -> String {
+    let url = websocket::backend_url(target, &config.path);
+    if config.scheme.is_none() && config.port.is_none() {
+        return url;
+    }
+    let Ok(mut parsed) = Url::parse(&url) else {
+        return url;
+    };
+    // Both are validated with the config, and http(s) URLs accept either change
+    if let Some(scheme) = &config.scheme {
+        let _ = parsed.set_scheme(scheme);
+    }
+    if let Some(port) = config.port {
+        let _ = parsed.set_port(Some(port));
+    }
+    parsed.into()
+}
+
+/// `interval` scaled by a random factor within ±`INTERVAL_JITTER`
+fn jittered(interval: Duration) -> Duration {
+    interval.mul_f64(1.0 + rand::random_range(-INTERVAL_JITTER..=INTERVAL_JITTER))
+}
+
+/// Whether a TCP connection to `target`'s host and port opens within `timeout`
+async fn tcp_check(target: &str, timeout: Duration) -> std::result::Result<(), String> {
+    let url = Url::parse(target).map_err(|e| format!("Invalid backend URL: {e}"))?;
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Err(format!("No host and port to connect to in {target}"));
+    };
+    // IPv6 literals come bracketed
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    match tokio::time::timeout(timeout, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(format!("TCP connect error: {e}")),
+        Err(_) => Err(format!("TCP connect timeout after {timeout:?}")),
+    }
+}
+
+/// Whether `response` counts as healthy under `config`, and if not, why
+fn check_response(
+    config: &HealthCheckConfig,
+    response: &HealthCheckResponse,
+) -> std::result::Result<(), String> {
+    let status = response.status;
+    let expected = if config.expected_statuses.is_empty() {
+        status.is_success()
+    } else {
+        config.expected_statuses.contains(&status.as_u16())
+    };
+    if !expected {
+        return Err(format!("Backend returned unexpected status {status}"));
+    }
+
+    if let Some(text) = &config.body_contains
+        && !String::from_utf8_lossy(&response.body).contains(text.as_str())
+    {
+        return Err(format!("Response body does not contain {text:?}"));
+    }
+
+    if let Some(json_match) = &config.body_json_path {
+        let document: serde_json::Value = serde_json::from_slice(&response.body)
+            .map_err(|e| format!("Response body is not JSON: {e}"))?;
+        match json_match.lookup(&document) {
+            Some(value) if *value == json_match.equals => {}
+            Some(value) => {
+                return Err(format!(
+                    "Response body has {} = {} instead of {}",
+                    json_match.path, value, json_match.equals
+                ));
+            }
+            None => return Err(format!("Response body has no {}", json_match.path)),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use hyper::StatusCode;
+
+    use super::*;
+    use crate::config::JsonPathMatch;
+
+    fn response(status: u16, body: &str) -> HealthCheckResponse {
+        HealthCheckResponse {
+            status: StatusCode::from_u16(status).unwrap(),
+            body: Bytes::from(body.to_string()),
         }
     }
+
+    #[test]
+    fn test_check_response() {
+        let mut config = HealthCheckConfig::default();
+        assert_eq!(config.method(), "HEAD");
+        assert!(check_response(&config, &response(204, "")).is_ok());
+        assert!(check_response(&config, &response(503, "")).is_err());
+
+        config.expected_statuses = vec![200, 503];
+        assert!(check_response(&config, &response(503, "")).is_ok());
+        assert!(check_response(&config, &response(204, "")).is_err());
+
+        // Degraded backends still answer 200; the body tells
+        config.expected_statuses = vec![200];
+        config.body_json_path = Some(JsonPathMatch {
+            path: "checks.0.status".to_string(),
+            equals: serde_json::json!("ok"),
+        });
+        assert_eq!(config.method(), "GET");
+        let healthy = r#"{"checks":[{"name":"db","status":"ok"}]}"#;
+        assert!(check_response(&config, &response(200, healthy)).is_ok());
+        let degraded = r#"{"checks":[{"name":"db","status":"degraded"}]}"#;
+        assert_eq!(
+            check_response(&config, &response(200, degraded)),
+            Err(r#"Response body has checks.0.status = "degraded" instead of "ok""#.to_string())
+        );
+        assert!(check_response(&config, &response(200, "<html>")).is_err());
+        assert!(check_response(&config, &response(200, "{}")).is_err());
+
+        config.body_json_path = None;
+        config.body_contains = Some("UP".to_string());
+        assert!(check_response(&config, &response(200, "status: UP")).is_ok());
+        assert!(check_response(&config, &response(200, "status: DOWN")).is_err());
+    }
+
+    #[test]
+    fn test_probe_url() {
+        let mut config = HealthCheckConfig::default();
+        assert_eq!(
+            probe_url("ws://chat:9000", &config),
+            "http://chat:9000/health"
+        );
+
+        config.port = Some(8081);
+        assert_eq!(
+            probe_url("http://api:8080/", &config),
+            "http://api:8081/health"
+        );
+        config.scheme = Some("https".to_string());
+        assert_eq!(
+            probe_url("http://api:8080", &config),
+            "https://api:8081/health"
+        );
+
+        config
+            .headers
+            .0
+            .insert("Authorization".to_string(), "Bearer s3cret".to_string());
+        let logged = format!("{config:?}");
+        assert!(logged.contains("Authorization") && !logged.contains("s3cret"));
+    }
+
+    #[test]
+    fn test_jittered_interval() {
+        let interval = Duration::from_secs(10);
+        for _ in 0..100 {
+            let jittered = jittered(interval);
+            assert!(jittered >= Duration::from_secs(8) && jittered <= Duration::from_secs(12));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_check() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let timeout = Duration::from_secs(1);
+
+        assert!(tcp_check(&format!("http://{addr}"), timeout).await.is_ok());
+        assert!(
+            tcp_check(&format!("ws://{addr}/socket"), timeout)
+                .await
+                .is_ok()
+        );
+
+        drop(listener);
+        let error = tcp_check(&format!("http://{addr}"), timeout)
+            .await
+            .unwrap_err();
+        assert!(error.starts_with("TCP connect error"), "{error}");
+    }
 }
@@ -0,0 +1,346 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::ConnectInfo;
+use axum_server::accept::Accept;
+use futures_util::future::BoxFuture;
+use hyper::body::{Body, Incoming};
+use hyper::header::{CONNECTION, HeaderValue};
+use hyper::{Request, Response, StatusCode, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use rand::Rng;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tower::{Layer, Service};
+
+use crate::config::models::{ConnectionLimitsConfig, ServerConfig};
+use crate::utils::connection_tracker::{
+    CloseReason, ConnectionGuard, ConnectionInfo, ConnectionTracker,
+};
+
+/// The service each connection starts with: the app and the client's address
+pub type ConnectedRouter = axum::middleware::AddExtension<Router, ConnectInfo<SocketAddr>>;
+
+/// Asks the connections of a listener started by [`serve`] to close
+#[derive(Clone)]
+pub struct ListenerHandle {
+    /// How long requests in flight get to finish, once retired
+    retired: watch::Sender<Option<Duration>>,
+}
+
+impl ListenerHandle {
+    pub fn new() -> Self {
+        Self {
+            retired: watch::Sender::new(None),
+        }
+    }
+
+    /// Close every connection once its requests are done, or after `grace`
+    pub fn retire(&self, grace: Duration) {
+        self.retired.send_replace(Some(grace));
+    }
+}
+
+impl Default for ListenerHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serve `app` on `listener` through `acceptor` until the task running it is aborted.
+/// Each TCP connection is registered with `tracker` from accept until it closes, TLS
+/// handshake included, and is closed gracefully when it reaches the `connection_limits`
+/// read from `config_holder` as it is accepted.
+pub async fn serve<A>(
+    listener: TcpListener,
+    acceptor: A,
+    app: Router,
+    tracker: ConnectionTracker,
+    config_holder: Arc<RwLock<Arc<ServerConfig>>>,
+    handle: ListenerHandle,
+) -> io::Result<()>
+where
+    A: Accept<TcpStream, ConnectedRouter> + Clone + Send + Sync + 'static,
+    A::Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    A::Service:
+        Service<Request<Incoming>, Response = Response<axum::body::Body>> + Clone + Send + 'static,
+    <A::Service as Service<Request<Incoming>>>::Error:
+        Into<Box<dyn std::error::Error + Send + Sync>>,
+    <A::Service as Service<Request<Incoming>>>::Future: Send + 'static,
+    A::Future: Send,
+{
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Usually out of file descriptors; give connections a moment to close
+                tracing::debug!("Failed to accept a connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                continue;
+            }
+        };
+        let limits = config_holder
+            .read()
+            .map(|config| config.connection_limits.clone())
+            .unwrap_or_default();
+        let connection = Arc::new(ConnectionGuard::new(tracker.clone(), addr));
+        let service = axum::Extension(ConnectInfo(addr)).layer(app.clone());
+        let acceptor = acceptor.clone();
+        let retired = handle.retired.subscribe();
+        tokio::spawn(async move {
+            // A failed TLS handshake just drops the connection, as with axum-server
+            let Ok((stream, service)) = acceptor.accept(stream, service).await else {
+                return;
+            };
+            let service = WithConnection {
+                inner: service,
+                connection: connection.clone(),
+                max_requests: limits.max_requests_per_connection,
+            };
+            serve_connection(
+                stream,
+                service,
+                connection.connection_info(),
+                &limits,
+                retired,
+            )
+            .await;
+        });
+    }
+}
+
+/// Serve HTTP/1.1 or HTTP/2 on `stream` until it closes, or until it is asked to close and
+/// its requests are done
+async fn serve_connection<I, S>(
+    stream: I,
+    service: S,
+    info: &ConnectionInfo,
+    limits: &ConnectionLimitsConfig,
+    mut retired: watch::Receiver<Option<Duration>>,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Service<Request<Incoming>, Response = Response<axum::body::Body>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    S::Future: Send + 'static,
+{
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let connection = builder
+        .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service));
+    tokio::pin!(connection);
+
+    let max_age = limits.max_connection_age();
+    let expired = async {
+        match max_age {
+            // Spread out, so connections opened together aren't all closed together
+            Some(age) => {
+                let jitter = rand::rng().random_range(0.9..1.1);
+                tokio::time::sleep(age.mul_f64(jitter)).await
+            }
+            None => std::future::pending().await,
+        }
+    };
+    let retired = async {
+        retired
+            .wait_for(Option::is_some)
+            .await
+            .ok()
+            .and_then(|grace| *grace)
+    };
+    let closing = async {
+        tokio::select! {
+            _ = info.closing() => limits.max_connection_age_grace(),
+            () = expired => {
+                info.request_close(CloseReason::MaxAge);
+                limits.max_connection_age_grace()
+            }
+            Some(grace) = retired => {
+                info.request_close(CloseReason::ListenerRestart);
+                grace
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = connection.as_mut() => {}
+        grace = closing => {
+            // Sends HTTP/2 a GOAWAY; HTTP/1.1 closes after the response in progress
+            connection.as_mut().graceful_shutdown();
+            if tokio::time::timeout(grace, connection.as_mut()).await.is_err() {
+                tracing::debug!(
+                    "Connection from {} still busy after {:?}, closing it",
+                    info.remote_addr,
+                    grace
+                );
+            }
+        }
+    }
+}
+
+/// Adds the connection's `Arc<ConnectionGuard>` to each request's extensions, so the guard
+/// lives as long as the connection or a request still holding it, such as an upgraded
+/// WebSocket. Counts the connection's requests towards `max_requests_per_connection`.
+#[derive(Clone)]
+pub struct WithConnection<S> {
+    inner: S,
+    connection: Arc<ConnectionGuard>,
+    max_requests: Option<u64>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for WithConnection<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Body,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let info = self.connection.connection_info().clone();
+        let served = info.record_request();
+        if self.max_requests.is_some_and(|max| served >= max) {
+            info.request_close(CloseReason::MaxRequests);
+        }
+        let http1 = req.version() < Version::HTTP_2;
+        req.extensions_mut().insert(self.connection.clone());
+        let response = self.inner.call(req);
+        Box::pin(async move {
+            let mut response = response.await?;
+            // Tell HTTP/1.1 clients, rather than closing the connection under them
+            if http1
+                && info.close_reason().is_some()
+                && response.status() != StatusCode::SWITCHING_PROTOCOLS
+            {
+                response
+                    .headers_mut()
+                    .insert(CONNECTION, HeaderValue::from_static("close"));
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::Extension;
+    use axum_server::accept::DefaultAcceptor;
+    use http_body_util::{BodyExt, Empty};
+    use hyper::body::Bytes;
+    use hyper::client::conn::{http1, http2};
+
+    use super::*;
+
+    /// Serve an app answering with its connection's id, returning the address
+    async fn serve_app(tracker: &ConnectionTracker, limits: &str) -> SocketAddr {
+        let config: ServerConfig = serde_yaml::from_str(&format!(
+            "listen_addr: \"127.0.0.1:0\"\nconnection_limits: {limits}\n"
+        ))
+        .unwrap();
+        let app = Router::new().route(
+            "/",
+            axum::routing::get(
+                |Extension(connection): Extension<Arc<ConnectionGuard>>| async move {
+                    let _request = connection.request_guard();
+                    connection.connection_id().to_string()
+                },
+            ),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(
+            listener,
+            DefaultAcceptor::new(),
+            app,
+            tracker.clone(),
+            Arc::new(RwLock::new(Arc::new(config))),
+            ListenerHandle::new(),
+        ));
+        addr
+    }
+
+    async fn wait_for_no_connections(tracker: &ConnectionTracker) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while tracker.active_connection_count() > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the connection is unregistered once closed");
+    }
+
+    #[tokio::test]
+    async fn test_one_connection_spans_its_requests() {
+        let tracker = ConnectionTracker::new();
+        let addr = serve_app(&tracker, "{}").await;
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = http1::handshake(TokioIo::new(stream)).await.unwrap();
+        let conn = tokio::spawn(conn);
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+            let response = sender.send_request(req).await.unwrap();
+            assert!(!response.headers().contains_key(CONNECTION));
+            ids.push(response.into_body().collect().await.unwrap().to_bytes());
+        }
+        assert_eq!(ids[0], ids[1]);
+        assert_eq!(tracker.active_connection_count(), 1);
+        assert_eq!(tracker.total_active_requests(), 0);
+
+        drop(sender);
+        conn.await.unwrap().unwrap();
+        wait_for_no_connections(&tracker).await;
+    }
+
+    #[tokio::test]
+    async fn test_connections_close_after_max_requests() {
+        let tracker = ConnectionTracker::new();
+        let addr = serve_app(&tracker, "{ max_requests_per_connection: 2 }").await;
+
+        // HTTP/1.1 is told with the last response
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = http1::handshake(TokioIo::new(stream)).await.unwrap();
+        let conn = tokio::spawn(conn);
+        let mut closes = Vec::new();
+        for _ in 0..2 {
+            let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+            let response = sender.send_request(req).await.unwrap();
+            closes.push(response.headers().get(CONNECTION).cloned());
+        }
+        assert_eq!(closes, [None, Some(HeaderValue::from_static("close"))]);
+        conn.await.unwrap().unwrap();
+        wait_for_no_connections(&tracker).await;
+
+        // HTTP/2 gets a GOAWAY, so the connection ends once the last stream is done
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .unwrap();
+        let conn = tokio::spawn(conn);
+        for _ in 0..2 {
+            let req = Request::get(format!("http://{addr}/"))
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            let response = sender.send_request(req).await.unwrap();
+            assert!(response.status().is_success());
+        }
+        tokio::time::timeout(Duration::from_secs(5), conn)
+            .await
+            .expect("the server ends the connection")
+            .unwrap()
+            .unwrap();
+        wait_for_no_connections(&tracker).await;
+    }
+}
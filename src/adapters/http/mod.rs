@@ -1 +1,3 @@
+pub mod connection;
+pub mod protocol;
 pub mod server;
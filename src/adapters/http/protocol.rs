@@ -0,0 +1,254 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use axum_server::accept::Accept;
+use futures_util::future::BoxFuture;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Connection preface every HTTP/2 client sends first (RFC 9113, section 3.4)
+const HTTP2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Wraps the listener's acceptor to enforce `http2_enabled: false`. ALPN already keeps
+/// TLS clients on HTTP/1.1, but hyper serves HTTP/2 to any connection opening with the
+/// HTTP/2 preface (prior knowledge over TLS, or h2c), so those connections are closed.
+#[derive(Debug, Clone)]
+pub struct ProtocolAcceptor<A> {
+    inner: A,
+    http2_enabled: bool,
+}
+
+impl<A> ProtocolAcceptor<A> {
+    pub fn new(inner: A, http2_enabled: bool) -> Self {
+        Self {
+            inner,
+            http2_enabled,
+        }
+    }
+}
+
+impl<A, I, S> Accept<I, S> for ProtocolAcceptor<A>
+where
+    A: Accept<I, S>,
+    A::Future: Send + 'static,
+    A::Stream: Send + 'static,
+    A::Service: Send + 'static,
+{
+    type Stream = PrefaceGuard<A::Stream>;
+    type Service = A::Service;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let accept = self.inner.accept(stream, service);
+        let http2_enabled = self.http2_enabled;
+        Box::pin(async move {
+            let (stream, service) = accept.await?;
+            let guard = PrefaceGuard {
+                inner: stream,
+                matched: (!http2_enabled).then_some(0),
+            };
+            Ok((guard, service))
+        })
+    }
+}
+
+/// Stream that fails reads once the peer has sent the whole HTTP/2 preface
+#[derive(Debug)]
+pub struct PrefaceGuard<S> {
+    inner: S,
+    /// Preface bytes received so far, or `None` once HTTP/2 is ruled out or allowed
+    matched: Option<usize>,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PrefaceGuard<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let start = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        if let Some(matched) = self.matched {
+            let read = &buf.filled()[start..];
+            let expected = &HTTP2_PREFACE[matched..];
+            let len = read.len().min(expected.len());
+            if read[..len] != expected[..len] {
+                self.matched = None;
+            } else if matched + len == HTTP2_PREFACE.len() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "HTTP/2 is disabled on this listener",
+                )));
+            } else {
+                self.matched = Some(matched + len);
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PrefaceGuard<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use axum_server::tls_rustls::RustlsConfig;
+    use http_body_util::Empty;
+    use hyper::Request;
+    use hyper::body::Bytes;
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::adapters::tls;
+    use crate::config::models::ProtocolConfig;
+
+    /// Serve a TLS listener for app.example.com, returning its address and the CA to trust
+    async fn serve(http2_enabled: bool) -> (SocketAddr, rustls::RootCertStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let mut ca_params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["app.example.com".to_string()])
+            .unwrap()
+            .signed_by(&key, &ca, &ca_key)
+            .unwrap();
+        let cert_path = dir.path().join("site.crt").to_string_lossy().into_owned();
+        let key_path = dir.path().join("site.key").to_string_lossy().into_owned();
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key.serialize_pem()).unwrap();
+
+        let protocols = ProtocolConfig {
+            http2_enabled,
+            ..Default::default()
+        };
+        let config = tls::server_config(Some((&cert_path, &key_path)), &[], &protocols).unwrap();
+        let handle = axum_server::Handle::new();
+        let app = axum::Router::new().route("/", axum::routing::get(|| async { "ok" }));
+        tokio::spawn(
+            axum_server::bind_rustls(
+                ([127, 0, 0, 1], 0).into(),
+                RustlsConfig::from_config(Arc::new(config)),
+            )
+            .map(|acceptor| ProtocolAcceptor::new(acceptor, http2_enabled))
+            .handle(handle.clone())
+            .serve(app.into_make_service()),
+        );
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        (handle.listening().await.unwrap(), roots)
+    }
+
+    /// TLS connection offering exactly `alpn`, with the protocol the server picked
+    async fn connect(
+        addr: SocketAddr,
+        roots: &rustls::RootCertStore,
+        alpn: &[&[u8]],
+    ) -> io::Result<(tokio_rustls::client::TlsStream<TcpStream>, Option<Vec<u8>>)> {
+        let mut config = rustls::ClientConfig::builder_with_provider(tls::crypto_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots.clone())
+            .with_no_client_auth();
+        config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+        let stream = TcpStream::connect(addr).await?;
+        let tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect("app.example.com".try_into().unwrap(), stream)
+            .await?;
+        let negotiated = tls.get_ref().1.alpn_protocol().map(<[u8]>::to_vec);
+        Ok((tls, negotiated))
+    }
+
+    /// Send a GET over HTTP/2 without negotiating it, returning whether it was answered
+    async fn http2_request(tls: tokio_rustls::client::TlsStream<TcpStream>) -> bool {
+        let Ok((mut sender, conn)) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(tls)).await
+        else {
+            return false;
+        };
+        tokio::spawn(conn);
+        let request = Request::get("https://app.example.com/")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        sender
+            .send_request(request)
+            .await
+            .is_ok_and(|response| response.status().is_success())
+    }
+
+    #[tokio::test]
+    async fn test_http2_disabled() {
+        let (addr, roots) = serve(false).await;
+
+        // A client insisting on h2 finds no common protocol
+        assert!(connect(addr, &roots, &[b"h2"]).await.is_err());
+
+        let (tls, negotiated) = connect(addr, &roots, &[b"h2", b"http/1.1"]).await.unwrap();
+        assert_eq!(negotiated.as_deref(), Some(&b"http/1.1"[..]));
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(tls))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let request = Request::get("/")
+            .header("host", "app.example.com")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        assert!(
+            sender
+                .send_request(request)
+                .await
+                .unwrap()
+                .status()
+                .is_success()
+        );
+
+        // HTTP/2 with prior knowledge is refused too
+        let (tls, negotiated) = connect(addr, &roots, &[]).await.unwrap();
+        assert_eq!(negotiated, None);
+        assert!(!http2_request(tls).await);
+    }
+
+    #[tokio::test]
+    async fn test_http2_enabled() {
+        let (addr, roots) = serve(true).await;
+
+        let (tls, negotiated) = connect(addr, &roots, &[b"h2", b"http/1.1"]).await.unwrap();
+        assert_eq!(negotiated.as_deref(), Some(&b"h2"[..]));
+        assert!(http2_request(tls).await);
+
+        let (_, negotiated) = connect(addr, &roots, &[b"http/1.1"]).await.unwrap();
+        assert_eq!(negotiated.as_deref(), Some(&b"http/1.1"[..]));
+    }
+}
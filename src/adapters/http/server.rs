@@ -1,47 +1,61 @@
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, anyhow};
-use axum::Json;
+use aws_lc_rs::digest;
 use axum::body::Body as AxumBody;
 use axum::extract::{ConnectInfo, State};
+use axum::middleware::Next;
 use axum::routing::{get, post};
+use axum::{Extension, Json};
 use axum::{
     Router,
     http::Request,
     response::{IntoResponse, Response as AxumResponse},
 };
-use axum_prometheus::PrometheusMetricLayer;
-use axum_server::tls_rustls::RustlsConfig;
+use axum_prometheus::{PrometheusMetricLayer, PrometheusMetricLayerBuilder};
+use axum_server::accept::DefaultAcceptor;
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use chrono::{DateTime, Utc};
 use http_body_util::BodyExt;
-use hyper::StatusCode;
-use metrics_exporter_prometheus::PrometheusHandle;
-use tokio::sync::Mutex as TokioMutex;
+use hyper::{HeaderMap, StatusCode, header};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex as TokioMutex, broadcast};
 use tower_http::trace::TraceLayer;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-use crate::adapters::acme::AcmeService;
+use crate::adapters::access_log::{AccessLog, AccessLogEntry};
+use crate::adapters::acme::{AcmeService, CertificateInfo, Http01Challenges};
+use crate::adapters::admin_auth::{self, AdminIdentity};
+use crate::adapters::debug_capture::{CaptureReport, CaptureRequest, DebugCapture};
 use crate::adapters::file_system::TowerFileSystem;
+use crate::adapters::http::connection::{self, ListenerHandle};
+use crate::adapters::http::protocol::ProtocolAcceptor;
 use crate::adapters::http_client::HyperHttpClient;
 use crate::adapters::http_handler::HyperHandler;
 use crate::adapters::middleware;
-use crate::config::models::ServerConfig;
+use crate::adapters::ocsp;
+use crate::adapters::tls;
+use crate::config::loader::ConfigFormat;
+use crate::config::models::{
+    HealthStatus, MetricsConfig, ProtocolConfig, ServerConfig, TlsConfig, TracingMode,
+};
+use crate::config::patch::ConfigPatch;
+use crate::config::validation::{ConfigValidator, ValidationIssue};
 use crate::core::ProxyService;
-use crate::metrics::{RequestTimer, increment_request_total};
+use crate::core::backend::{BackendHealth, HealthOverride};
+use crate::core::config_history::ConfigSource;
+use crate::core::router::MatchedRoute;
+use crate::metrics::{RequestTimer, increment_request_total, protocol_label, set_server_stats};
 use crate::ports::http_server::{HandlerError, HttpHandler, HttpServer};
-use crate::utils::connection_tracker::{ConnectionInfo, ConnectionTracker};
+use crate::tracing_setup;
+use crate::utils::config_reload::ConfigReloader;
+use crate::utils::connection_tracker::{ConnectionGuard, ConnectionTracker};
 use crate::utils::graceful_shutdown::{GracefulShutdown, ShutdownToken};
-use crate::utils::health_checker_utils::spawn_health_checker_task;
-
-struct ConnectionRequestGuard {
-    connection_info: Arc<ConnectionInfo>,
-}
-
-impl Drop for ConnectionRequestGuard {
-    fn drop(&mut self) {
-        self.connection_info.decrement_requests();
-    }
-}
 
 #[derive(Clone)]
 struct AppState {
@@ -49,16 +63,59 @@ struct AppState {
     config_holder: Arc<RwLock<Arc<ServerConfig>>>,
     http_client: Arc<HyperHttpClient>,
     file_system: Arc<TowerFileSystem>,
-    health_checker_handle: Arc<TokioMutex<Option<tokio::task::JoinHandle<()>>>>,
     connection_tracker: ConnectionTracker,
     shutdown_token: ShutdownToken,
+    /// HTTP-01 challenges published by the ACME service and answered by the handler
+    acme_challenges: Http01Challenges,
+    access_log: Option<AccessLog>,
+    started_at: Instant,
+    /// Requests captured for `/-/debug/capture`
+    debug_capture: DebugCapture,
+    /// Applies config updates and reloads
+    config_reloader: ConfigReloader,
 }
 
 pub struct HyperServer {
     app_state: AppState,
-    prometheus_layer: PrometheusMetricLayer<'static>,
+    /// `None` with `metrics.builtin_layer: false`
+    prometheus_layer: Option<PrometheusMetricLayer<'static>>,
     prometheus_handle: PrometheusHandle,
     graceful_shutdown: Arc<GracefulShutdown>,
+    /// Certificates renewed by ACME, for every listener that has to swap them in
+    certificate_renewals: broadcast::Sender<CertificateInfo>,
+}
+
+/// Install the global Prometheus recorder, with the built-in HTTP layer unless disabled
+fn prometheus_recorder(
+    config: &MetricsConfig,
+) -> (Option<PrometheusMetricLayer<'static>>, PrometheusHandle) {
+    let install = || {
+        let builder = PrometheusBuilder::new().upkeep_timeout(Duration::from_secs(5));
+        // Validated with the config
+        let builder = match &config.buckets {
+            Some(buckets) => builder.set_buckets(buckets),
+            None => builder.set_buckets_for_metric(
+                Matcher::Full(axum_prometheus::utils::requests_duration_name().to_string()),
+                axum_prometheus::utils::SECONDS_DURATION_BUCKETS,
+            ),
+        }
+        .expect("histogram buckets must not be empty");
+        let recorder = builder.build_recorder();
+        let handle = recorder.handle();
+        metrics::set_global_recorder(recorder).expect("Failed to set global recorder");
+        handle
+    };
+
+    if !config.builtin_layer {
+        return (None, install());
+    }
+    let mut builder = PrometheusMetricLayerBuilder::new();
+    if let Some(prefix) = &config.prefix {
+        builder = builder.with_prefix(prefix.clone());
+    }
+    // The prefix is set before `install` runs, so it names the right duration metric
+    let (layer, handle) = builder.with_metrics_from_fn(install).build_pair();
+    (Some(layer), handle)
 }
 
 impl HyperServer {
@@ -70,9 +127,19 @@ impl HyperServer {
         health_checker_handle: Arc<TokioMutex<Option<tokio::task::JoinHandle<()>>>>,
         graceful_shutdown: Arc<GracefulShutdown>,
     ) -> Self {
-        let (prometheus_layer, prometheus_handle) = PrometheusMetricLayer::pair();
+        let metrics_config = config_holder
+            .read()
+            .map(|config| config.metrics.clone())
+            .unwrap_or_default();
+        let (prometheus_layer, prometheus_handle) = prometheus_recorder(&metrics_config);
         let connection_tracker = ConnectionTracker::new();
         let shutdown_token = graceful_shutdown.shutdown_token();
+        let config_reloader = ConfigReloader::new(
+            config_holder.clone(),
+            proxy_service_holder.clone(),
+            http_client.clone(),
+            health_checker_handle,
+        );
 
         Self {
             app_state: AppState {
@@ -80,353 +147,1311 @@ impl HyperServer {
                 config_holder,
                 http_client,
                 file_system,
-                health_checker_handle,
                 connection_tracker,
                 shutdown_token,
+                acme_challenges: Http01Challenges::default(),
+                access_log: None,
+                started_at: Instant::now(),
+                debug_capture: DebugCapture::default(),
+                config_reloader,
             },
             prometheus_layer,
             prometheus_handle,
             graceful_shutdown,
+            certificate_renewals: broadcast::channel(4).0,
         }
     }
 
-    async fn build_app(&self) -> Router {
-        let general_handler = HyperHandler::new(
+    /// Write a line for every request served to `access_log`
+    pub fn with_access_log(mut self, access_log: AccessLog) -> Self {
+        self.app_state.access_log = Some(access_log);
+        self
+    }
+
+    /// Apply config updates through `config_reloader`, which also knows the config file for
+    /// `POST /-/config/reload`
+    pub fn with_config_reloader(mut self, config_reloader: ConfigReloader) -> Self {
+        self.app_state.config_reloader = config_reloader;
+        self
+    }
+
+    /// Receive each certificate the ACME renewal task obtains
+    pub fn subscribe_certificate_renewals(&self) -> broadcast::Receiver<CertificateInfo> {
+        self.certificate_renewals.subscribe()
+    }
+
+    /// The request handler behind the listener, shared with the HTTP/3 server
+    pub fn handler(&self) -> HyperHandler {
+        HyperHandler::new(
             self.app_state.proxy_service_holder.clone(),
             self.app_state.http_client.clone(),
             self.app_state.file_system.clone(),
-        );
+        )
+        .with_acme_challenges(self.app_state.acme_challenges.clone())
+    }
+
+    /// `admin.listen_addr`, when the admin endpoints have a listener of their own
+    fn admin_listen_addr(&self) -> Result<Option<SocketAddr>> {
+        let config = self
+            .app_state
+            .config_holder
+            .read()
+            .map_err(|e| anyhow!("Failed to acquire config read lock: {}", e))?;
+        config
+            .admin
+            .listen_addr
+            .as_deref()
+            .map(|addr| {
+                addr.parse::<SocketAddr>()
+                    .with_context(|| format!("Failed to parse admin listen address: {addr}"))
+            })
+            .transpose()
+    }
 
+    /// `/metrics` and the `/-/` endpoints, each request checked against the `admin` section
+    fn admin_routes(&self) -> Router<AppState> {
         let metrics_handle_for_route = self.prometheus_handle.clone();
-        let app_state_for_fallback = self.app_state.clone();
+        let app_state_for_metrics = self.app_state.clone();
 
         Router::new()
-            .route("/-/config", post(update_config_handler))
+            .route(
+                "/-/config",
+                get(get_config_handler)
+                    .post(update_config_handler)
+                    .patch(patch_config_handler),
+            )
+            .route("/-/config/history", get(config_history_handler))
+            .route("/-/config/validate", post(validate_config_handler))
+            .route("/-/config/rollback", post(rollback_config_handler))
+            .route("/-/config/reload", post(reload_config_handler))
+            .route("/-/health/backends", get(backend_health_handler))
+            .route("/-/logs/reopen", post(reopen_logs_handler))
+            .route("/-/stats", get(stats_handler))
+            .route(
+                "/-/debug/capture",
+                get(debug_capture_handler)
+                    .post(arm_debug_capture_handler)
+                    .delete(disarm_debug_capture_handler),
+            )
+            .route(
+                "/-/health/backends/override",
+                post(backend_health_override_handler),
+            )
             .route(
                 "/metrics",
-                get(move || async move { metrics_handle_for_route.render() }),
+                get(move || async move {
+                    app_state_for_metrics.record_server_stats();
+                    metrics_handle_for_route.render()
+                }),
             )
+            .route_layer(axum::middleware::from_fn_with_state(
+                self.app_state.clone(),
+                admin_guard,
+            ))
+    }
+
+    /// Serve the admin endpoints on `addr` until shutdown, over HTTPS with `admin.tls`
+    async fn spawn_admin_listener(&self, addr: SocketAddr) -> Result<()> {
+        let app = self
+            .admin_routes()
+            .with_state(self.app_state.clone())
+            .layer(TraceLayer::new_for_http());
+        let tls = self
+            .app_state
+            .config_holder
+            .read()
+            .map_err(|e| anyhow!("Failed to acquire config read lock: {}", e))?
+            .admin
+            .tls
+            .clone();
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind admin listener on {addr}"))?;
+        // Stay up through the drain, so `/metrics` shows how it is going
+        let mut shutdown_receiver = self.graceful_shutdown.subscribe();
+        let drain_period =
+            self.graceful_shutdown.grace_period() + self.graceful_shutdown.drain_timeout();
+        let shutdown = async move {
+            let _ = shutdown_receiver.recv().await;
+            tokio::time::sleep(drain_period).await;
+        };
+
+        if let Some(tls) = tls {
+            let rustls_config = RustlsConfig::from_config(Arc::new(
+                tls::admin_server_config(&tls).context("Failed to set up admin TLS")?,
+            ));
+            tracing::info!(
+                "Admin endpoints listening on {} over HTTPS{}",
+                addr,
+                if tls.client_ca_path.is_some() {
+                    ", client certificates required"
+                } else {
+                    ""
+                }
+            );
+            let handle = axum_server::Handle::new();
+            let shutdown_handle = handle.clone();
+            tokio::spawn(async move {
+                shutdown.await;
+                shutdown_handle.graceful_shutdown(Some(Duration::from_secs(5)));
+            });
+            let listener = listener.into_std()?;
+            tokio::spawn(async move {
+                let server = axum_server::from_tcp_rustls(listener, rustls_config)
+                    .map(admin_auth::ClientCertAcceptor::new)
+                    .handle(handle)
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+                if let Err(e) = server.await {
+                    tracing::error!("Admin listener error: {}", e);
+                }
+            });
+            return Ok(());
+        }
+
+        tracing::info!("Admin endpoints listening on {}", addr);
+        tokio::spawn(async move {
+            let server = axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown);
+            if let Err(e) = server.await {
+                tracing::error!("Admin listener error: {}", e);
+            }
+        });
+        Ok(())
+    }
+
+    async fn build_app(&self, with_admin_routes: bool) -> Router {
+        let general_handler = self.handler();
+        let app_state_for_fallback = self.app_state.clone();
+
+        let router = if with_admin_routes {
+            self.admin_routes()
+        } else {
+            Router::new()
+        };
+        let app = router
             .fallback(
-                move |ConnectInfo(addr): ConnectInfo<SocketAddr>, req: Request<AxumBody>| {
+                move |ConnectInfo(addr): ConnectInfo<SocketAddr>, mut req: Request<AxumBody>| {
                     let handler = general_handler.clone();
                     let app_state = app_state_for_fallback.clone();
                     async move {
                         let path = req.uri().path().to_string();
                         let method = req.method().to_string();
+                        let protocol = protocol_label(req.version());
+                        let observability = handler.route_observability(&req);
+                        req.extensions_mut().insert(observability.tracing);
+                        let access_log_entry = app_state
+                            .access_log
+                            .as_ref()
+                            .filter(|_| observability.access_log)
+                            .map(|_| AccessLogEntry::new(&req, addr));
+                        let span = request_span(&req, addr, observability.tracing);
+
+                        async move {
+                            // Counted on its connection until the response is ready
+                            let _request_guard = req
+                                .extensions()
+                                .get::<Arc<ConnectionGuard>>()
+                                .map(|connection| connection.request_guard());
+
+                            // Timer will record duration when dropped
+                            let mut timer = RequestTimer::new(&path, &method, protocol);
 
-                        // Create a tracing span for the request
-                        let span = tracing::info_span!(
-                            "http_request",
-                            http.method = %method,
-                            http.path = %path,
-                            http.status_code = tracing::field::Empty,
-                            connection.remote_addr = %addr,
-                        );
-
-                        let _enter = span.enter();
-
-                        // Create connection guard for tracking
-                        let connection_info =
-                            app_state.connection_tracker.register_connection(addr);
-                        let _request_guard = {
-                            connection_info.increment_requests();
-                            // Use a custom guard that decrements on drop
-                            ConnectionRequestGuard {
-                                connection_info: connection_info.clone(),
+                            // Refuse new requests once the shutdown grace period is over
+                            if app_state.shutdown_token.is_draining() {
+                                tracing::warn!("Rejecting new request due to shutdown in progress");
+                                let response =
+                                    (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down")
+                                        .into_response();
+                                increment_request_total(
+                                    &path,
+                                    &method,
+                                    response.status().as_u16(),
+                                    protocol,
+                                );
+                                timer.set_outcome(None, response.status().as_u16());
+                                if let (Some(access_log), Some(entry)) =
+                                    (&app_state.access_log, &access_log_entry)
+                                {
+                                    access_log.log(entry, &response);
+                                }
+                                return response;
                             }
-                        };
-
-                        // Timer will record duration when dropped
-                        let _timer = RequestTimer::new(&path, &method);
-
-                        // Check if shutdown is requested
-                        if app_state.shutdown_token.is_shutdown_requested() {
-                            tracing::warn!("Rejecting new request due to shutdown in progress");
-                            let response =
-                                (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down")
-                                    .into_response();
-                            increment_request_total(&path, &method, response.status().as_u16());
-                            return response;
-                        }
 
-                        // Await the actual response. Since the error type is Infallible,
-                        // we can safely unwrap the Result.
-                        let response = handle_request(handler, req, addr).await.unwrap();
+                            // Await the actual response. Since the error type is Infallible,
+                            // we can safely unwrap the Result.
+                            let (req, capture) = app_state.debug_capture.begin(req, addr);
+                            let response = handle_request(handler, req, addr).await.unwrap();
 
-                        // Record the status code in the span
-                        tracing::Span::current()
-                            .record("http.status_code", response.status().as_u16());
+                            // Record the status code in the span
+                            tracing::Span::current()
+                                .record("http.status_code", response.status().as_u16());
 
-                        // Now 'response' is of type AxumResponse (http::Response<axum::body::Body>)
-                        increment_request_total(&path, &method, response.status().as_u16());
+                            // Now 'response' is of type AxumResponse (http::Response<axum::body::Body>)
+                            increment_request_total(
+                                &path,
+                                &method,
+                                response.status().as_u16(),
+                                protocol,
+                            );
+                            let route = response.extensions().get::<MatchedRoute>();
+                            timer.set_outcome(
+                                route.map(|route| route.0.as_str()),
+                                response.status().as_u16(),
+                            );
+                            if let (Some(access_log), Some(entry)) =
+                                (&app_state.access_log, &access_log_entry)
+                            {
+                                access_log.log(entry, &response);
+                            }
+                            let response = match capture {
+                                Some(capture) => capture.finish(response),
+                                None => response,
+                            };
 
-                        // Return the response. AxumResponse implements IntoResponse.
-                        // The request guard will automatically decrement the request count when dropped
-                        response
+                            // Return the response. AxumResponse implements IntoResponse.
+                            // The request guard will automatically decrement the request count when dropped
+                            response
+                        }
+                        .instrument(span)
+                        .await
                     }
                 },
             )
             .with_state(self.app_state.clone())
             .layer(axum::middleware::from_fn(
                 middleware::create_alt_svc_middleware(self.app_state.config_holder.clone()),
-            ))
-            .layer(self.prometheus_layer.clone())
-            .layer(TraceLayer::new_for_http())
+            ));
+        let app = match &self.prometheus_layer {
+            Some(prometheus_layer) => app.layer(prometheus_layer.clone()),
+            None => app,
+        };
+        app.layer(TraceLayer::new_for_http())
     }
 }
 
-async fn update_config_handler(
+/// Turn away admin requests from outside `admin.allowed_ips` or without `admin.auth`'s
+/// credentials
+async fn admin_guard(
     State(app_state): State<AppState>,
-    Json(new_config_payload): Json<ServerConfig>,
-) -> Result<AxumResponse, AxumResponse> {
-    tracing::info!("Received API request to update configuration.");
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut req: Request<AxumBody>,
+    next: Next,
+) -> AxumResponse {
+    let admin = match app_state.config_holder.read() {
+        Ok(config) => config.admin.clone(),
+        Err(e) => {
+            tracing::error!("Failed to acquire config read lock: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response();
+        }
+    };
+    let certificate = req.extensions().get::<admin_auth::ClientCertificate>();
+    let authorized =
+        admin_auth::authorize(&admin, addr.ip(), req.headers(), certificate).and_then(|identity| {
+            // Swapping the config hands over the whole proxy, so it is never anonymous
+            if is_config_update(&req) && !identity.is_authenticated() {
+                Err(admin_auth::AdminRejection::AuthenticationRequired)
+            } else {
+                Ok(identity)
+            }
+        });
+    let identity = match authorized {
+        Ok(identity) => identity,
+        Err(rejection) => {
+            if is_config_update(&req) {
+                tracing::warn!(
+                    "Rejected config update from {}: {}",
+                    addr,
+                    rejection.reason()
+                );
+            } else {
+                tracing::warn!(
+                    "Rejected admin request {} {} from {}: {}",
+                    req.method(),
+                    req.uri().path(),
+                    addr,
+                    rejection.reason()
+                );
+            }
+            return rejection.into_response();
+        }
+    };
+    req.extensions_mut().insert(identity);
+    next.run(req).await
+}
 
-    // Validate the incoming configuration payload using ServerConfigBuilder.
-    let mut builder = ServerConfig::builder()
-        .listen_addr(new_config_payload.listen_addr.clone()) // Clone to avoid moving from new_config_payload
-        .health_check(new_config_payload.health_check.clone())
-        .protocols(new_config_payload.protocols.clone());
+fn is_config_update(req: &Request<AxumBody>) -> bool {
+    matches!(*req.method(), hyper::Method::POST | hyper::Method::PATCH)
+        && matches!(
+            req.uri().path(),
+            "/-/config" | "/-/config/rollback" | "/-/config/reload"
+        )
+}
 
-    for (prefix, route_config) in new_config_payload.routes.iter() {
-        builder = builder.route(prefix.clone(), route_config.clone());
-    }
+/// What prox currently thinks of one backend
+#[derive(Serialize)]
+struct BackendHealthReport {
+    target: String,
+    /// Status used for routing, with any override applied
+    status: HealthStatus,
+    /// Status according to the checks alone
+    checked_status: HealthStatus,
+    #[serde(rename = "override")]
+    health_override: HealthOverride,
+    consecutive_successes: u32,
+    consecutive_failures: u32,
+    last_checked: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    routes: Vec<String>,
+}
+
+async fn backend_health_handler(
+    State(app_state): State<AppState>,
+) -> Result<Json<Vec<BackendHealthReport>>, AxumResponse> {
+    let proxy_service = app_state
+        .proxy_service_holder
+        .read()
+        .map_err(|e| {
+            tracing::error!("Failed to acquire proxy service read lock: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read backend health",
+            )
+                .into_response()
+        })?
+        .clone();
+
+    let mut reports: Vec<BackendHealthReport> = proxy_service
+        .backend_health()
+        .iter()
+        .map(|entry| BackendHealthReport::new(&proxy_service, entry.key(), entry.value()))
+        .collect();
+    reports.sort_by(|a, b| a.target.cmp(&b.target));
+    Ok(Json(reports))
+}
 
-    if let Some(tls_config) = &new_config_payload.tls {
-        if let (Some(cert_path), Some(key_path)) = (&tls_config.cert_path, &tls_config.key_path) {
-            builder = builder.tls(cert_path.clone(), key_path.clone());
-        } else if let Some(acme_config) = &tls_config.acme {
-            builder = builder.acme(acme_config.clone());
+impl BackendHealthReport {
+    fn new(proxy_service: &ProxyService, target: &str, health: &BackendHealth) -> Self {
+        Self {
+            target: target.to_string(),
+            status: health.status(),
+            checked_status: health.checked_status(),
+            health_override: health.health_override(),
+            consecutive_successes: health.consecutive_successes(),
+            consecutive_failures: health.consecutive_failures(),
+            last_checked: health.last_checked(),
+            last_error: health.last_error(),
+            routes: proxy_service.backend_routes(target),
         }
     }
+}
 
-    for (backend, path) in new_config_payload.backend_health_paths.iter() {
-        builder = builder.backend_health_path(backend.clone(), path.clone());
-    }
+#[derive(Deserialize)]
+struct BackendHealthOverrideRequest {
+    target: String,
+    state: HealthOverride,
+}
 
-    if let Err(validation_err) = builder.build() {
-        tracing::warn!("Validation failed: {}", validation_err);
+/// Force a backend healthy or unhealthy regardless of its checks, or hand it back to
+/// them with "auto". Overrides last until cleared or restart, across config reloads.
+async fn backend_health_override_handler(
+    State(app_state): State<AppState>,
+    Json(request): Json<BackendHealthOverrideRequest>,
+) -> Result<Json<BackendHealthReport>, AxumResponse> {
+    let proxy_service = app_state
+        .proxy_service_holder
+        .read()
+        .map_err(|e| {
+            tracing::error!("Failed to acquire proxy service read lock: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read backend health",
+            )
+                .into_response()
+        })?
+        .clone();
+    let Some(health) = proxy_service.backend_health().get(&request.target) else {
         return Err((
-            StatusCode::BAD_REQUEST,
-            format!("Invalid config payload: {validation_err}"),
+            StatusCode::NOT_FOUND,
+            format!("Unknown backend '{}'", request.target),
         )
             .into_response());
-    }
+    };
 
-    // If validation passes, proceed with the validated config (new_config_payload can be used directly
-    // as its structure matches ServerConfig, and builder was primarily for validation here)
-    let new_config_arc = Arc::new(new_config_payload);
+    tracing::warn!(
+        "(API) Health override of backend {} set to {:?} (was {:?})",
+        request.target,
+        request.state,
+        health.health_override()
+    );
+    health.set_override(request.state);
+    Ok(Json(BackendHealthReport::new(
+        &proxy_service,
+        &request.target,
+        &health,
+    )))
+}
 
-    // 1. Update Config Holder
-    {
-        let mut config_w = app_state.config_holder.write().map_err(|e| {
-            tracing::error!("Failed to acquire config write lock: {}", e);
+/// Reopen the access log, for use after logrotate has moved it away
+async fn reopen_logs_handler(State(app_state): State<AppState>) -> AxumResponse {
+    let Some(access_log) = &app_state.access_log else {
+        return (StatusCode::NOT_FOUND, "No access log is configured").into_response();
+    };
+    match access_log.reopen().await {
+        Ok(()) => (StatusCode::OK, "Access log reopened").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to reopen access log: {}", e);
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to update configuration",
+                format!("Failed to reopen access log: {e}"),
             )
                 .into_response()
-        })?;
-        *config_w = new_config_arc.clone();
-        tracing::info!("(API Reload) Global ServerConfig Arc updated.");
+        }
     }
+}
 
-    // 2. Update ProxyService Holder
-    let new_proxy_service = Arc::new(ProxyService::new(new_config_arc.clone()));
-    {
-        let mut proxy_s_w = app_state.proxy_service_holder.write().map_err(|e| {
-            tracing::error!("Failed to acquire proxy service write lock: {}", e);
+async fn debug_capture_handler(State(app_state): State<AppState>) -> Json<CaptureReport> {
+    Json(app_state.debug_capture.report())
+}
+
+async fn arm_debug_capture_handler(
+    State(app_state): State<AppState>,
+    Json(request): Json<CaptureRequest>,
+) -> AxumResponse {
+    match app_state.debug_capture.arm(request) {
+        Ok(armed) => {
+            tracing::info!(
+                "Debug capture armed for {} request(s) under {}",
+                armed.remaining,
+                armed.path_prefix
+            );
+            Json(armed).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+async fn disarm_debug_capture_handler(State(app_state): State<AppState>) -> StatusCode {
+    app_state.debug_capture.disarm();
+    StatusCode::NO_CONTENT
+}
+
+impl AppState {
+    fn current_proxy_service(&self) -> Option<Arc<ProxyService>> {
+        match self.proxy_service_holder.read() {
+            Ok(service) => Some(service.clone()),
+            Err(e) => {
+                tracing::error!("Failed to acquire proxy service read lock: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Refresh the gauges mirroring `/-/stats`
+    fn record_server_stats(&self) {
+        let config_version = self
+            .current_proxy_service()
+            .map_or(0, |service| service.config_version());
+        set_server_stats(
+            &self.connection_tracker.get_stats(),
+            self.started_at.elapsed(),
+            config_version,
+        );
+    }
+}
+
+/// Connection, drain and process state, for checking a deploy or spotting leaks
+#[derive(Serialize)]
+struct StatsReport {
+    version: &'static str,
+    uptime_secs: u64,
+    config_version: u64,
+    /// SHA-256 of the running config
+    config_hash: String,
+    shutting_down: bool,
+    connections: ConnectionStatsReport,
+}
+
+#[derive(Serialize)]
+struct ConnectionStatsReport {
+    total: usize,
+    idle: usize,
+    busy: usize,
+    active_requests: u64,
+    oldest_age_secs: Option<f64>,
+}
+
+async fn stats_handler(
+    State(app_state): State<AppState>,
+) -> Result<Json<StatsReport>, AxumResponse> {
+    let unavailable = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read server state",
+        )
+            .into_response()
+    };
+    let proxy_service = app_state.current_proxy_service().ok_or_else(unavailable)?;
+    let config = app_state
+        .config_holder
+        .read()
+        .map_err(|e| {
+            tracing::error!("Failed to acquire config read lock: {}", e);
+            unavailable()
+        })?
+        .clone();
+    let stats = app_state.connection_tracker.get_stats();
+
+    Ok(Json(StatsReport {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: app_state.started_at.elapsed().as_secs(),
+        config_version: proxy_service.config_version(),
+        config_hash: config_hash(&config),
+        shutting_down: app_state.shutdown_token.is_shutdown_requested(),
+        connections: ConnectionStatsReport {
+            total: stats.total_connections,
+            idle: stats.idle_connections,
+            busy: stats.busy_connections,
+            active_requests: stats.total_active_requests,
+            oldest_age_secs: stats.oldest_connection_age.map(|age| age.as_secs_f64()),
+        },
+    }))
+}
+
+/// Hex SHA-256 of `config`, stable across runs; JSON objects serialize with sorted keys
+fn config_hash(config: &ServerConfig) -> String {
+    let canonical = serde_json::to_value(config)
+        .and_then(|value| serde_json::to_vec(&value))
+        .unwrap_or_default();
+    digest::digest(&digest::SHA256, &canonical)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The running config as returned by `GET /-/config`, with its secrets redacted
+#[derive(Serialize)]
+struct ConfigReport {
+    config_version: u64,
+    /// SHA-256 of the running config, before redaction
+    config_hash: String,
+    loaded_at: DateTime<Utc>,
+    config: ServerConfig,
+}
+
+impl ConfigReport {
+    fn new(proxy_service: &ProxyService, config: &ServerConfig) -> Self {
+        Self {
+            config_version: proxy_service.config_version(),
+            config_hash: config_hash(config),
+            loaded_at: proxy_service.loaded_at(),
+            config: config.redacted(),
+        }
+    }
+}
+
+/// The running config as JSON, or YAML when the `Accept` header asks for it
+async fn get_config_handler(
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<AxumResponse, AxumResponse> {
+    let unavailable = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read server state",
+        )
+            .into_response()
+    };
+    let proxy_service = app_state.current_proxy_service().ok_or_else(unavailable)?;
+    let config = app_state
+        .config_holder
+        .read()
+        .map_err(|e| {
+            tracing::error!("Failed to acquire config read lock: {}", e);
+            unavailable()
+        })?
+        .clone();
+    let report = ConfigReport::new(&proxy_service, &config);
+
+    let wants_yaml = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("yaml"));
+    if !wants_yaml {
+        return Ok(Json(report).into_response());
+    }
+    let yaml = serde_yaml::to_string(&report).map_err(|e| {
+        tracing::error!("Failed to serialize config as YAML: {}", e);
+        unavailable()
+    })?;
+    Ok(([(header::CONTENT_TYPE, "application/yaml")], yaml).into_response())
+}
+
+async fn update_config_handler(
+    State(app_state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(identity): Extension<AdminIdentity>,
+    Json(new_config_payload): Json<ServerConfig>,
+) -> Result<AxumResponse, AxumResponse> {
+    tracing::info!(
+        "Received API request to update configuration from {} ({}).",
+        addr,
+        identity
+    );
+    apply_config_update(
+        &app_state,
+        new_config_payload,
+        ConfigSource::Api,
+        addr,
+        &identity,
+    )
+    .await?;
+    Ok((StatusCode::OK, "Configuration updated successfully").into_response())
+}
+
+/// Change part of the running config: an `application/merge-patch+json` body is a JSON
+/// merge patch, a plain JSON one lists `routes` to add or replace and `remove_routes`.
+/// Responds with the resulting config, redacted.
+async fn patch_config_handler(
+    State(app_state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(identity): Extension<AdminIdentity>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<AxumResponse, AxumResponse> {
+    tracing::info!(
+        "Received API request to patch configuration from {} ({}).",
+        addr,
+        identity
+    );
+    let rejected = |message: String| {
+        tracing::warn!(
+            "Rejected config update from {} ({}): {}",
+            addr,
+            identity,
+            message
+        );
+        (StatusCode::BAD_REQUEST, message).into_response()
+    };
+
+    let is_merge_patch = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/merge-patch+json"));
+    let patch = if is_merge_patch {
+        serde_json::from_slice(&body).map(ConfigPatch::Merge)
+    } else {
+        serde_json::from_slice(&body).map(ConfigPatch::Operations)
+    }
+    .map_err(|e| rejected(format!("Invalid config patch: {e}")))?;
+
+    let current_config = app_state
+        .config_holder
+        .read()
+        .map_err(|e| {
+            tracing::error!("Failed to acquire config read lock: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read config").into_response()
+        })?
+        .clone();
+    let new_config = patch
+        .apply(&current_config)
+        .map_err(|e| rejected(format!("Invalid config patch: {e:#}")))?;
+
+    let proxy_service =
+        apply_config_update(&app_state, new_config, ConfigSource::Api, addr, &identity).await?;
+    Ok(Json(ConfigReport::new(&proxy_service, proxy_service.config())).into_response())
+}
+
+/// The configs remembered for rollback, newest last
+#[derive(Serialize)]
+struct ConfigHistoryReport {
+    current_version: u64,
+    entries: Vec<ConfigHistoryItem>,
+}
+
+#[derive(Serialize)]
+struct ConfigHistoryItem {
+    version: u64,
+    source: ConfigSource,
+    applied_at: DateTime<Utc>,
+    config_hash: String,
+}
+
+async fn config_history_handler(
+    State(app_state): State<AppState>,
+) -> Result<Json<ConfigHistoryReport>, AxumResponse> {
+    let proxy_service = app_state.current_proxy_service().ok_or_else(|| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read server state",
+        )
+            .into_response()
+    })?;
+    let entries = proxy_service
+        .config_history()
+        .entries()
+        .into_iter()
+        .map(|entry| ConfigHistoryItem {
+            version: entry.version,
+            source: entry.source,
+            applied_at: entry.applied_at,
+            config_hash: config_hash(&entry.config),
+        })
+        .collect();
+    Ok(Json(ConfigHistoryReport {
+        current_version: proxy_service.config_version(),
+        entries,
+    }))
+}
+
+#[derive(Deserialize)]
+struct RollbackRequest {
+    version: u64,
+}
+
+/// Re-apply a config from the history as a new version
+async fn rollback_config_handler(
+    State(app_state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(identity): Extension<AdminIdentity>,
+    Json(RollbackRequest { version }): Json<RollbackRequest>,
+) -> Result<AxumResponse, AxumResponse> {
+    tracing::info!(
+        "Received API request to roll back to config version {} from {} ({}).",
+        version,
+        addr,
+        identity
+    );
+    let previous_config = app_state
+        .current_proxy_service()
+        .and_then(|service| service.config_history().get(version))
+        .ok_or_else(|| {
+            tracing::warn!(
+                "Rejected config update from {} ({}): version {} is not in the history",
+                addr,
+                identity,
+                version
+            );
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to update proxy service",
+                StatusCode::NOT_FOUND,
+                format!("Config version {version} is not in the history"),
             )
                 .into_response()
         })?;
-        *proxy_s_w = new_proxy_service.clone();
-        tracing::info!("(API Reload) Global ProxyService Arc updated.");
-    }
 
-    // 3. Restart HealthChecker
-    let mut handle_guard = app_state.health_checker_handle.lock().await;
-    if let Some(old_handle) = handle_guard.take() {
-        tracing::info!("(API Reload) Aborting previous health checker task...");
-        old_handle.abort();
+    let proxy_service = apply_config_update(
+        &app_state,
+        previous_config,
+        ConfigSource::Rollback { version },
+        addr,
+        &identity,
+    )
+    .await?;
+    Ok(Json(ConfigReport::new(&proxy_service, proxy_service.config())).into_response())
+}
+
+/// Re-read the config file the server was started with and apply it, like a change
+/// picked up by the file watcher or SIGHUP
+async fn reload_config_handler(
+    State(app_state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<AxumResponse, AxumResponse> {
+    let reloader = &app_state.config_reloader;
+    let Some(config_path) = reloader.config_path() else {
+        return Err((
+            StatusCode::CONFLICT,
+            "The server was not started from a config file",
+        )
+            .into_response());
+    };
+    tracing::info!(
+        "Received API request to reload configuration from {} by {} ({}).",
+        config_path,
+        addr,
+        identity
+    );
+    match reloader.reload_from_file().await {
+        Ok(proxy_service) => {
+            tracing::info!("Config update from {} ({}) accepted", addr, identity);
+            Ok(Json(ConfigReport::new(&proxy_service, proxy_service.config())).into_response())
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Rejected config update from {} ({}): {:#}",
+                addr,
+                identity,
+                e
+            );
+            Err((
+                StatusCode::BAD_REQUEST,
+                format!("Failed to reload configuration: {e:#}"),
+            )
+                .into_response())
+        }
     }
+}
 
-    if new_config_arc.health_check.enabled {
-        tracing::info!(
-            "(API Reload) Starting new health checker task with updated configuration..."
-        );
-        *handle_guard = Some(spawn_health_checker_task(
-            new_proxy_service.clone(),
-            app_state.http_client.clone(),
-            new_config_arc.clone(),
-            "API Reload".to_string(),
-        ));
+/// Result of `POST /-/config/validate`
+#[derive(Serialize)]
+struct ValidateConfigReport {
+    valid: bool,
+    errors: Vec<ValidationIssue>,
+    warnings: Vec<ValidationIssue>,
+}
+
+/// Check a proposed config, JSON, YAML or TOML by `Content-Type`, without applying it:
+/// 200 when it is valid, 422 with the errors otherwise
+async fn validate_config_handler(headers: HeaderMap, body: axum::body::Bytes) -> AxumResponse {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let format = if content_type.contains("yaml") {
+        ConfigFormat::Yaml
+    } else if content_type.contains("toml") {
+        ConfigFormat::Toml
     } else {
-        tracing::info!(
-            "(API Reload) Health checking is disabled in the new configuration. Not starting health checker task."
+        ConfigFormat::Json
+    };
+    let parsed = std::str::from_utf8(&body)
+        .map_err(|e| e.to_string())
+        .and_then(|content| format.parse(content).map_err(|e| e.to_string()));
+    let report = match parsed {
+        Ok(config) => {
+            let report = ConfigValidator::check(&config);
+            ValidateConfigReport {
+                valid: report.is_valid(),
+                errors: report.errors.iter().map(ValidationIssue::from).collect(),
+                warnings: report.warnings,
+            }
+        }
+        Err(message) => ValidateConfigReport {
+            valid: false,
+            errors: vec![ValidationIssue {
+                field: None,
+                message,
+            }],
+            warnings: Vec::new(),
+        },
+    };
+    let status = if report.valid {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    (status, Json(report)).into_response()
+}
+
+/// Validate `new_config_payload` and swap it in, returning the new service and config.
+/// Shared by full and partial updates and rollbacks.
+async fn apply_config_update(
+    app_state: &AppState,
+    new_config_payload: ServerConfig,
+    source: ConfigSource,
+    addr: SocketAddr,
+    identity: &AdminIdentity,
+) -> Result<Arc<ProxyService>, AxumResponse> {
+    if let Err(validation_err) = ConfigValidator::validate(&new_config_payload) {
+        tracing::warn!(
+            "Rejected config update from {} ({}): {}",
+            addr,
+            identity,
+            validation_err
         );
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid config payload: {validation_err}"),
+        )
+            .into_response());
     }
 
-    tracing::info!("(API Reload) Configuration updated and health checker managed successfully.");
-    Ok((StatusCode::OK, "Configuration updated successfully").into_response())
+    let new_proxy_service = app_state
+        .config_reloader
+        .apply(new_config_payload, source)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to apply config update: {:#}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update configuration",
+            )
+                .into_response()
+        })?;
+    tracing::info!("Config update from {} ({}) accepted", addr, identity);
+    Ok(new_proxy_service)
 }
 
 impl HttpServer for HyperServer {
     async fn run(&self) -> Result<()> {
-        let app = self.build_app().await;
+        let admin_listen_addr = self.admin_listen_addr()?;
+        if let Some(addr) = admin_listen_addr {
+            self.spawn_admin_listener(addr).await?;
+        }
+        let app = self.build_app(admin_listen_addr.is_none()).await;
 
-        // Read values from config_guard and then drop it
-        let (listen_addr_str, tls_config_opt_owned, protocols_config) = {
-            let config_guard = self
+        let settings = ListenerSettings::of(
+            &self
                 .app_state
                 .config_holder
                 .read()
-                .map_err(|e| anyhow::anyhow!("Failed to acquire config read lock: {}", e))?;
-            let addr_str = config_guard.listen_addr.clone();
-            let tls_opt = config_guard.tls.clone(); // Clone the Option<TlsConfig>
-            let protocols = config_guard.protocols.clone(); // Clone the ProtocolConfig
-            (addr_str, tls_opt, protocols)
-        }; // config_guard is dropped here
-
-        let addr = listen_addr_str.parse::<SocketAddr>().with_context(|| {
-            format!("Failed to parse listen address: \\\"{listen_addr_str}\\\"")
-        })?;
-
-        tracing::info!("Server listening on {}", addr);
+                .map_err(|e| anyhow::anyhow!("Failed to acquire config read lock: {}", e))?
+                .clone(),
+        );
         tracing::info!(
             "Protocol configuration: HTTP/2 enabled: {}, WebSocket enabled: {}",
-            protocols_config.http2_enabled,
-            protocols_config.websocket_enabled
+            settings.protocols.http2_enabled,
+            settings.protocols.websocket_enabled
         );
 
-        // Create shutdown signal receiver
+        // Subscribe before the TLS setup, which can take a while with ACME
         let mut shutdown_receiver = self.graceful_shutdown.subscribe();
-        let connection_tracker = self.app_state.connection_tracker.clone();
-
-        if let Some(tls_config_data) = tls_config_opt_owned {
-            // Handle both manual certificates and ACME
-            let (cert_path, key_path) = if let Some(acme_config) = &tls_config_data.acme {
-                if acme_config.enabled {
-                    tracing::info!(
-                        "ACME is enabled. Requesting certificate for domains: {:?}",
-                        acme_config.domains
-                    );
-
-                    let acme_service = AcmeService::new(acme_config.clone())
-                        .context("Failed to create ACME service")?;
-
-                    let cert_info = acme_service
-                        .get_certificate()
-                        .await
-                        .context("Failed to get ACME certificate")?;
-
-                    // Start renewal task
-                    acme_service.start_renewal_task();
-
-                    tracing::info!(
-                        "ACME certificate obtained: cert={}, key={}",
-                        cert_info.cert_path,
-                        cert_info.key_path
-                    );
-                    (cert_info.cert_path, cert_info.key_path)
-                } else {
-                    return Err(anyhow!("ACME is configured but not enabled"));
-                }
-            } else if let (Some(cert_path), Some(key_path)) =
-                (&tls_config_data.cert_path, &tls_config_data.key_path)
-            {
-                tracing::info!(
-                    "Using manual TLS certificates: cert={}, key={}",
-                    cert_path,
-                    key_path
-                );
-                (cert_path.clone(), key_path.clone())
-            } else {
-                return Err(anyhow!(
-                    "TLS is configured but neither manual certificates nor ACME configuration is provided"
-                ));
-            };
-
-            tracing::info!(
-                "TLS is ENABLED. Certificate: {}, Key: {}",
-                cert_path,
-                key_path
-            );
-            let rustls_config = RustlsConfig::from_pem_file(&cert_path, &key_path)
-                .await
-                .with_context(|| {
-                    format!("Failed to load TLS certificate/key from paths: cert='{cert_path}', key='{key_path}'")
-                })?;
-
-            // Run server with graceful shutdown
-            let server_future = axum_server::bind_rustls(addr, rustls_config)
-                .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let mut applied_configs = self.app_state.config_reloader.subscribe();
+        let mut listener = self.start_listener(app.clone(), settings, None).await?;
 
+        loop {
             tokio::select! {
-                result = server_future => {
-                    result.context("TLS server error")?;
+                result = &mut listener.server => {
+                    return result.context("Server task failed")?.context("Server error");
                 }
                 shutdown_reason = shutdown_receiver.recv() => {
                     match shutdown_reason {
-                        Ok(reason) => {
-                            tracing::info!("Server shutdown initiated: {:?}", reason);
-                            // Signal connection tracker to start draining
-                            connection_tracker.initiate_shutdown();
-
-                            // Wait for connections to drain (with timeout)
-                            let drain_timeout = std::time::Duration::from_secs(30);
-                            if connection_tracker.drain_connections(drain_timeout).await {
-                                tracing::info!("All connections drained successfully");
-                            } else {
-                                tracing::warn!("Connection drain timeout exceeded, forcing shutdown");
-                            }
-                        }
+                        Ok(reason) => tracing::info!("Server shutdown initiated: {:?}", reason),
                         Err(e) => {
                             tracing::error!("Error receiving shutdown signal: {}", e);
+                            return Ok(());
                         }
                     }
+                    break;
+                }
+                Ok(()) = applied_configs.changed() => {
+                    let settings = ListenerSettings::of(&applied_configs.borrow_and_update());
+                    let changes = listener.settings.changes(&settings);
+                    if changes.is_empty() {
+                        continue;
+                    }
+                    tracing::warn!(
+                        "Restarting the listener to apply changes to {}",
+                        changes.join(", ")
+                    );
+                    match self.start_listener(app.clone(), settings, Some(&listener)).await {
+                        Ok(new_listener) => {
+                            let previous = std::mem::replace(&mut listener, new_listener);
+                            tracing::warn!(
+                                "Now serving on {}; connections to the previous listener on {} get {:?} to finish",
+                                listener.settings.listen_addr,
+                                previous.settings.listen_addr,
+                                self.graceful_shutdown.drain_timeout()
+                            );
+                            previous.retire(self.graceful_shutdown.drain_timeout());
+                        }
+                        Err(e) => tracing::error!(
+                            "Failed to apply changes to {}, still serving on {} with the previous settings: {:#}",
+                            changes.join(", "),
+                            listener.settings.listen_addr,
+                            e
+                        ),
+                    }
                 }
             }
-        } else {
-            tracing::info!("TLS is DISABLED.");
-
-            // Run server with graceful shutdown
-            let server_future = axum_server::bind(addr)
-                .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        }
 
+        let grace_period = self.graceful_shutdown.grace_period();
+        if !grace_period.is_zero() {
+            tracing::info!("Serving for {:?} before draining connections", grace_period);
             tokio::select! {
-                result = server_future => {
-                    result.context("Server error")?;
-                }
-                shutdown_reason = shutdown_receiver.recv() => {
-                    match shutdown_reason {
-                        Ok(reason) => {
-                            tracing::info!("Server shutdown initiated: {:?}", reason);
-                            // Signal connection tracker to start draining
-                            connection_tracker.initiate_shutdown();
-
-                            // Wait for connections to drain (with timeout)
-                            let drain_timeout = std::time::Duration::from_secs(30);
-                            if connection_tracker.drain_connections(drain_timeout).await {
-                                tracing::info!("All connections drained successfully");
-                            } else {
-                                tracing::warn!("Connection drain timeout exceeded, forcing shutdown");
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!("Error receiving shutdown signal: {}", e);
-                        }
-                    }
+                result = &mut listener.server => {
+                    return result.context("Server task failed")?.context("Server error");
                 }
+                _ = tokio::time::sleep(grace_period) => {}
             }
         }
+        listener.stop_accepting();
 
+        // Signal connection tracker to start draining
+        let connection_tracker = &self.app_state.connection_tracker;
+        connection_tracker.initiate_shutdown();
+        if connection_tracker
+            .drain_connections(self.graceful_shutdown.drain_timeout())
+            .await
+        {
+            tracing::info!("All connections drained successfully");
+        } else {
+            tracing::warn!("Connection drain timeout exceeded, forcing shutdown");
+        }
         Ok(())
     }
 }
 
-async fn handle_request(
+/// What the TCP listener is started with; a reload changing it restarts the listener
+#[derive(Clone)]
+struct ListenerSettings {
+    listen_addr: String,
+    tls: Option<TlsConfig>,
+    protocols: ProtocolConfig,
+}
+
+impl ListenerSettings {
+    fn of(config: &ServerConfig) -> Self {
+        Self {
+            listen_addr: config.listen_addr.clone(),
+            tls: config.tls.clone(),
+            protocols: config.protocols.clone(),
+        }
+    }
+
+    /// The settings that differ in `other`. HTTP/3 settings aren't the TCP listener's.
+    fn changes(&self, other: &Self) -> Vec<&'static str> {
+        let mut changes = Vec::new();
+        if self.listen_addr != other.listen_addr {
+            changes.push("listen_addr");
+        }
+        if serde_json::to_value(&self.tls).ok() != serde_json::to_value(&other.tls).ok() {
+            changes.push("tls");
+        }
+        if self.protocols.http2_enabled != other.protocols.http2_enabled {
+            changes.push("protocols.http2_enabled");
+        }
+        changes
+    }
+}
+
+/// A running TCP listener and the tasks keeping its certificates current
+struct Listener {
+    settings: ListenerSettings,
+    socket: std::net::TcpListener,
+    handle: ListenerHandle,
+    server: tokio::task::JoinHandle<std::io::Result<()>>,
+    tasks: Vec<tokio::task::JoinHandle<()>>,
+}
+
+impl Listener {
+    /// Stop accepting connections; open ones keep being served
+    fn stop_accepting(self) {
+        self.server.abort();
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+
+    /// Stop accepting connections and give open ones `drain_timeout` to finish their
+    /// requests before closing them
+    fn retire(self, drain_timeout: Duration) {
+        self.server.abort();
+        self.handle.retire(drain_timeout);
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl HyperServer {
+    /// Serve `app` as `settings` say, on the socket of `previous` when its address is the
+    /// same, so that no connection is refused while switching over
+    async fn start_listener(
+        &self,
+        app: Router,
+        settings: ListenerSettings,
+        previous: Option<&Listener>,
+    ) -> Result<Listener> {
+        let addr = settings
+            .listen_addr
+            .parse::<SocketAddr>()
+            .with_context(|| {
+                format!(
+                    "Failed to parse listen address: \\\"{}\\\"",
+                    settings.listen_addr
+                )
+            })?;
+        let socket = match previous
+            .filter(|previous| previous.settings.listen_addr == settings.listen_addr)
+        {
+            Some(previous) => previous
+                .socket
+                .try_clone()
+                .context("Failed to share the listening socket")?,
+            None => tokio::net::TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to bind {addr}"))?
+                .into_std()?,
+        };
+        tracing::info!("Server listening on {}", addr);
+
+        let mut tasks = Vec::new();
+        let handle = ListenerHandle::new();
+        let listener = {
+            let socket = socket.try_clone()?;
+            socket.set_nonblocking(true)?;
+            tokio::net::TcpListener::from_std(socket)?
+        };
+        let config_holder = self.app_state.config_holder.clone();
+        let http2_enabled = settings.protocols.http2_enabled;
+        let tracker = self.app_state.connection_tracker.clone();
+        let server = match &settings.tls {
+            Some(tls_config) => {
+                let rustls_config = match self
+                    .rustls_config(tls_config, &settings.protocols, &mut tasks)
+                    .await
+                {
+                    Ok(rustls_config) => rustls_config,
+                    Err(e) => {
+                        tasks.iter().for_each(tokio::task::JoinHandle::abort);
+                        return Err(e);
+                    }
+                };
+                let acceptor =
+                    ProtocolAcceptor::new(RustlsAcceptor::new(rustls_config), http2_enabled);
+                tokio::spawn(connection::serve(
+                    listener,
+                    acceptor,
+                    app,
+                    tracker,
+                    config_holder,
+                    handle.clone(),
+                ))
+            }
+            None => {
+                tracing::info!("TLS is DISABLED.");
+                let acceptor = ProtocolAcceptor::new(DefaultAcceptor::new(), http2_enabled);
+                tokio::spawn(connection::serve(
+                    listener,
+                    acceptor,
+                    app,
+                    tracker,
+                    config_holder,
+                    handle.clone(),
+                ))
+            }
+        };
+
+        Ok(Listener {
+            settings,
+            socket,
+            handle,
+            server,
+            tasks,
+        })
+    }
+
+    /// Load the certificates `tls_config` names, obtaining them over ACME if need be, and
+    /// start the tasks that renew, reload and staple them, adding them to `tasks`
+    async fn rustls_config(
+        &self,
+        tls_config_data: &TlsConfig,
+        protocols_config: &ProtocolConfig,
+        tasks: &mut Vec<tokio::task::JoinHandle<()>>,
+    ) -> Result<RustlsConfig> {
+        // Handle both manual certificates and ACME
+        let mut renewals = None;
+        let default_pair = if let Some(acme_config) = &tls_config_data.acme {
+            if acme_config.enabled {
+                tracing::info!(
+                    "ACME is enabled. Requesting certificate for domains: {:?}",
+                    acme_config.domains
+                );
+
+                let acme_service = AcmeService::new(acme_config.clone())
+                    .context("Failed to create ACME service")?
+                    .with_http01_challenges(self.app_state.acme_challenges.clone());
+
+                let cert_info = acme_service
+                    .get_certificate()
+                    .await
+                    .context("Failed to get ACME certificate")?;
+
+                // Start renewal task, reloading the listeners after each renewal
+                renewals = Some(self.certificate_renewals.subscribe());
+                tasks
+                    .push(acme_service.start_renewal_task(Some(self.certificate_renewals.clone())));
+
+                tracing::info!(
+                    "ACME certificate obtained: cert={}, key={}",
+                    cert_info.cert_path,
+                    cert_info.key_path
+                );
+                Some((cert_info.cert_path, cert_info.key_path))
+            } else {
+                return Err(anyhow!("ACME is configured but not enabled"));
+            }
+        } else if let (Some(cert_path), Some(key_path)) =
+            (&tls_config_data.cert_path, &tls_config_data.key_path)
+        {
+            tracing::info!(
+                "Using manual TLS certificates: cert={}, key={}",
+                cert_path,
+                key_path
+            );
+            Some((cert_path.clone(), key_path.clone()))
+        } else if !tls_config_data.certificates.is_empty() {
+            None
+        } else {
+            return Err(anyhow!(
+                "TLS is configured but neither manual certificates nor ACME configuration is provided"
+            ));
+        };
+
+        match &default_pair {
+            Some((cert_path, key_path)) => tracing::info!(
+                "TLS is ENABLED. Certificate: {}, Key: {}",
+                cert_path,
+                key_path
+            ),
+            None => tracing::info!("TLS is ENABLED with SNI certificates only"),
+        }
+        let server_config = tls::server_config(
+            default_pair
+                .as_ref()
+                .map(|(cert_path, key_path)| (cert_path.as_str(), key_path.as_str())),
+            &tls_config_data.certificates,
+            protocols_config,
+        )
+        .context("Failed to load TLS certificates")?;
+        let rustls_config = RustlsConfig::from_config(Arc::new(server_config));
+        let reloader = tls::CertificateReloader::new(
+            rustls_config.clone(),
+            default_pair,
+            tls_config_data.certificates.clone(),
+        );
+        if tls_config_data.ocsp_stapling {
+            match ocsp::OcspStapler::new(reloader.clone(), tls_config_data.ocsp_responder.clone()) {
+                Ok(stapler) => tasks.push(stapler.spawn()),
+                Err(e) => tracing::error!("OCSP stapling disabled: {e:#}"),
+            }
+        }
+        match reloader.spawn_watcher(renewals) {
+            Ok(watcher) => tasks.push(watcher),
+            Err(e) => tracing::error!("Certificate hot reload disabled: {e:#}"),
+        }
+        Ok(rustls_config)
+    }
+}
+
+/// The `http_request` span a request runs in on every listener, joined to the caller's trace
+/// so backend requests continue it too; none for routes with `tracing: off`
+pub(crate) fn request_span(
+    req: &Request<AxumBody>,
+    remote_addr: SocketAddr,
+    mode: TracingMode,
+) -> tracing::Span {
+    if mode == TracingMode::Off {
+        return tracing::Span::none();
+    }
+    let span = tracing::info_span!(
+        "http_request",
+        http.method = %req.method(),
+        http.path = %req.uri().path(),
+        http.status_code = tracing::field::Empty,
+        network.protocol = protocol_label(req.version()),
+        connection.remote_addr = %remote_addr,
+    );
+    let _ = span.set_parent(tracing_setup::extract_trace_context(req.headers()));
+    span
+}
+
+pub(crate) async fn handle_request(
     handler: HyperHandler, // This handler is created with a snapshot of ProxyService
     req: Request<AxumBody>,
     _remote_addr: SocketAddr,
@@ -3,6 +3,9 @@ use quiche::{Config, CongestionControlAlgorithm};
 
 use crate::config::models::{Http3Config, Http3CongestionControl};
 
+/// Unidirectional streams each peer may open
+const UNI_STREAMS: u64 = 100;
+
 pub struct QuicheConfig {
     config: Config,
 }
@@ -28,6 +31,10 @@ impl QuicheConfig {
         config.set_initial_max_stream_data_bidi_local(http3_config.max_stream_data);
         config.set_initial_max_stream_data_bidi_remote(http3_config.max_stream_data);
         config.set_initial_max_streams_bidi(http3_config.max_streams_bidi);
+        // HTTP/3 needs unidirectional streams for its control and QPACK streams, and
+        // clients may open reserved "grease" ones too
+        config.set_initial_max_stream_data_uni(http3_config.max_stream_data);
+        config.set_initial_max_streams_uni(UNI_STREAMS);
 
         let cc_algorithm = match http3_config.congestion_control {
             Http3CongestionControl::Cubic => CongestionControlAlgorithm::CUBIC,
@@ -76,6 +83,10 @@ mod tests {
             congestion_control: Http3CongestionControl::Cubic,
             enable_0rtt: false,
             max_packet_size: Some(1452),
+            max_request_body_size: 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         }
     }
 
@@ -1,14 +1,61 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use bytes::Bytes;
-use quiche::{Connection, ConnectionId};
-use tokio::sync::Mutex;
+use aws_lc_rs::hmac;
+use aws_lc_rs::rand::SystemRandom;
+use quiche::{Connection, ConnectionId, RecvInfo, SendInfo};
+use tokio::sync::{mpsc, watch};
 
 use crate::adapters::http3::QuicheConfig;
+use crate::adapters::http3::qlog::QlogFiles;
+use crate::adapters::http3::retry::RetryTokens;
 use crate::config::models::Http3Config;
+use crate::metrics::set_http3_connections;
+
+/// Largest UDP payload quiche is asked to build
+const MAX_DATAGRAM_SIZE: usize = 1350;
+/// How long connections closed at the end of a drain get to send their CONNECTION_CLOSE
+const CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+/// A datagram quiche produced and where to send it
+pub type OutgoingPacket = (Vec<u8>, SendInfo);
+
+/// A datagram received for a connection and the addresses it travelled between
+pub type Datagram = (Vec<u8>, RecvInfo);
+
+/// Where an incoming datagram goes
+pub enum PacketRoute {
+    /// To the task of an existing connection
+    Connection(mpsc::Sender<Datagram>),
+    /// To a connection just accepted for it, which needs a task of its own
+    Accepted {
+        conn_id: Vec<u8>,
+        connection: Box<QuicConnection>,
+    },
+    /// Answered directly with this packet, without any connection state
+    Reply(Vec<u8>),
+    /// Nowhere: a packet for an unknown connection that can't open one
+    Drop,
+}
+
+/// Whether connections keep serving, as the server shuts down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainState {
+    Serving,
+    /// GOAWAY sent: in-flight requests finish, new ones are refused
+    Draining,
+    /// The drain timed out: close whatever is left
+    Closing,
+}
+
+/// Connection ID as lowercase hex, for logs and file names
+pub fn conn_id_hex(conn_id: &[u8]) -> String {
+    conn_id.iter().map(|byte| format!("{byte:02x}")).collect()
+}
 
 pub struct QuicConnection {
     connection: Connection,
@@ -36,8 +83,25 @@ impl QuicConnection {
         &mut self.connection
     }
 
-    pub fn h3_connection(&mut self) -> Option<&mut quiche::h3::Connection> {
-        self.h3_connection.as_mut()
+    /// Every packet the connection has ready: handshake, ACKs, stream data, closes
+    pub fn drain_packets(&mut self) -> Vec<OutgoingPacket> {
+        let mut packets = Vec::new();
+        loop {
+            let mut packet = vec![0; MAX_DATAGRAM_SIZE];
+            match self.connection.send(&mut packet) {
+                Ok((len, send_info)) => {
+                    packet.truncate(len);
+                    packets.push((packet, send_info));
+                }
+                Err(quiche::Error::Done) => break,
+                Err(e) => {
+                    tracing::warn!("Failed to build QUIC packet, closing connection: {}", e);
+                    let _ = self.connection.close(false, 0x1, b"internal error");
+                    break;
+                }
+            }
+        }
+        packets
     }
 
     pub fn establish_h3(&mut self, h3_config: &quiche::h3::Config) -> Result<()> {
@@ -65,119 +129,389 @@ impl QuicConnection {
         Ok(events)
     }
 
-    pub fn send_response(
+    /// Send GOAWAY, telling the client requests on `stream_id` and later won't be processed
+    pub fn send_goaway(&mut self, stream_id: u64) -> Result<()> {
+        if let Some(ref mut h3_conn) = self.h3_connection {
+            h3_conn
+                .send_goaway(&mut self.connection, stream_id)
+                .context("Failed to send GOAWAY")
+        } else {
+            Err(anyhow::anyhow!("HTTP/3 connection not established"))
+        }
+    }
+
+    /// Whether quiche is done with `stream_id`: both sides finished and the client
+    /// acknowledged everything sent, or the client stopped it
+    pub fn stream_complete(&mut self, stream_id: u64) -> bool {
+        self.connection.stream_capacity(stream_id).is_err()
+    }
+
+    /// Write response headers, returning false when the stream can't take them yet
+    pub fn send_headers(
         &mut self,
         stream_id: u64,
         headers: &[quiche::h3::Header],
-        body: Option<Bytes>,
         fin: bool,
-    ) -> Result<()> {
+    ) -> Result<bool> {
         if let Some(ref mut h3_conn) = self.h3_connection {
-            h3_conn
-                .send_response(
-                    &mut self.connection,
-                    stream_id,
-                    headers,
-                    fin && body.is_none(),
-                )
-                .map_err(|e| anyhow::anyhow!("Failed to send response headers: {}", e))?;
-
-            if let Some(body_data) = body {
-                h3_conn
-                    .send_body(&mut self.connection, stream_id, &body_data, fin)
-                    .map_err(|e| anyhow::anyhow!("Failed to send response body: {}", e))?;
+            match h3_conn.send_response(&mut self.connection, stream_id, headers, fin) {
+                Ok(()) => Ok(true),
+                Err(quiche::h3::Error::StreamBlocked | quiche::h3::Error::Done) => Ok(false),
+                Err(e) => Err(anyhow::anyhow!("Failed to send response headers: {}", e)),
             }
+        } else {
+            Err(anyhow::anyhow!("HTTP/3 connection not established"))
+        }
+    }
+
+    /// Read request body bytes buffered on `stream_id`, or `None` once there are none left
+    pub fn recv_body(&mut self, stream_id: u64, out: &mut [u8]) -> Result<Option<usize>> {
+        if let Some(ref mut h3_conn) = self.h3_connection {
+            match h3_conn.recv_body(&mut self.connection, stream_id, out) {
+                Ok(read) => Ok(Some(read)),
+                Err(quiche::h3::Error::Done) => Ok(None),
+                Err(e) => Err(anyhow::anyhow!("Failed to read request body: {}", e)),
+            }
+        } else {
+            Err(anyhow::anyhow!("HTTP/3 connection not established"))
+        }
+    }
 
-            Ok(())
+    /// Write as much of `body` as flow control allows, returning the bytes written or
+    /// `None` when the stream can't take any right now. `fin` only applies once everything
+    /// is written.
+    pub fn send_body(&mut self, stream_id: u64, body: &[u8], fin: bool) -> Result<Option<usize>> {
+        if let Some(ref mut h3_conn) = self.h3_connection {
+            match h3_conn.send_body(&mut self.connection, stream_id, body, fin) {
+                Ok(written) => Ok(Some(written)),
+                Err(quiche::h3::Error::Done) => Ok(None),
+                Err(e) => Err(anyhow::anyhow!("Failed to send response body: {}", e)),
+            }
         } else {
             Err(anyhow::anyhow!("HTTP/3 connection not established"))
         }
     }
 }
 
+/// Hands each datagram to the task owning its connection and accepts new connections. The
+/// connections themselves live in their tasks; only the route table is shared.
 pub struct ConnectionManager {
-    connections: Arc<Mutex<HashMap<Vec<u8>, QuicConnection>>>,
+    /// Packet queue of each connection task, by every connection ID it answers to
+    routes: Mutex<HashMap<Vec<u8>, mpsc::Sender<Datagram>>>,
     http3_config: Http3Config,
-    cert_path: String,
-    key_path: String,
+    /// Certificate and key paths loaded into each new connection's QUIC config
+    certificate: RwLock<(String, String)>,
+    /// QUIC config shared by the connections, built on first use and again for each new
+    /// certificate. Sharing it keeps one session ticket key, so clients can resume
+    /// connections and send 0-RTT early data.
+    quiche_config: Mutex<Option<quiche::Config>>,
     h3_config: quiche::h3::Config,
+    /// Key deriving the connection IDs we pick from the ones clients pick
+    conn_id_key: hmac::Key,
+    /// Address validation tokens, when `retry` is enabled
+    retry_tokens: Option<RetryTokens>,
+    /// Per-connection qlog files, when `qlog_dir` is set
+    qlog_files: Option<QlogFiles>,
+    /// Watched by the connection tasks to send GOAWAY and close during shutdown
+    drain: watch::Sender<DrainState>,
+    /// Requests in flight across all connections
+    active_requests: AtomicUsize,
 }
 
 impl ConnectionManager {
     pub fn new(http3_config: Http3Config, cert_path: &str, key_path: &str) -> Result<Self> {
         let h3_config = quiche::h3::Config::new().context("Failed to create HTTP/3 config")?;
+        let retry_tokens = http3_config.retry.then(RetryTokens::new).transpose()?;
+        let qlog_files = http3_config
+            .qlog_dir
+            .as_deref()
+            .map(|dir| QlogFiles::new(dir, http3_config.qlog_max_files))
+            .transpose()?;
 
         Ok(Self {
-            connections: Arc::new(Mutex::new(HashMap::new())),
+            routes: Mutex::new(HashMap::new()),
             http3_config,
-            cert_path: cert_path.to_string(),
-            key_path: key_path.to_string(),
+            certificate: RwLock::new((cert_path.to_string(), key_path.to_string())),
+            quiche_config: Mutex::new(None),
             h3_config,
+            conn_id_key: hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+                .map_err(|_| anyhow::anyhow!("Failed to generate connection ID key"))?,
+            retry_tokens,
+            qlog_files,
+            drain: watch::channel(DrainState::Serving).0,
+            active_requests: AtomicUsize::new(0),
         })
     }
 
-    fn create_quiche_config(&self) -> Result<QuicheConfig> {
-        QuicheConfig::new(&self.http3_config, &self.cert_path, &self.key_path)
+    fn routes(&self) -> MutexGuard<'_, HashMap<Vec<u8>, mpsc::Sender<Datagram>>> {
+        self.routes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Our connection ID for a client's initial destination ID. Deriving it lets a
+    /// retransmitted Initial find the connection its first copy created.
+    fn derive_conn_id(&self, dcid: &[u8]) -> Vec<u8> {
+        hmac::sign(&self.conn_id_key, dcid).as_ref()[..quiche::MAX_CONN_ID_LEN].to_vec()
     }
 
-    pub async fn get_or_create_connection(
+    /// Find the connection a packet belongs to, accepting a new one for a client Initial
+    pub fn route_packet(
         &self,
-        conn_id: &ConnectionId<'_>,
-        odcid: Option<&ConnectionId<'_>>,
+        header: &quiche::Header<'_>,
         local_addr: SocketAddr,
         peer_addr: SocketAddr,
-    ) -> Result<()> {
-        let mut connections = self.connections.lock().await;
-        let conn_id_vec = conn_id.to_vec();
-
-        if let std::collections::hash_map::Entry::Vacant(e) = connections.entry(conn_id_vec) {
-            let quiche_config = self.create_quiche_config()?;
-            let mut config = quiche_config.into_inner();
+    ) -> Result<PacketRoute> {
+        let derived = self.derive_conn_id(&header.dcid);
+        {
+            let routes = self.routes();
+            if let Some(packets) = routes
+                .get(header.dcid.as_ref())
+                .or_else(|| routes.get(&derived))
+            {
+                return Ok(PacketRoute::Connection(packets.clone()));
+            }
+        }
 
-            let mut quic_conn =
-                QuicConnection::new(conn_id, odcid, local_addr, peer_addr, &mut config)?;
+        // No new connections once the server drains
+        if header.ty != quiche::Type::Initial || *self.drain.borrow() != DrainState::Serving {
+            return Ok(PacketRoute::Drop);
+        }
+        if !quiche::version_is_supported(header.version) {
+            let mut reply = vec![0; MAX_DATAGRAM_SIZE];
+            let len = quiche::negotiate_version(&header.scid, &header.dcid, &mut reply)
+                .context("Failed to build version negotiation packet")?;
+            reply.truncate(len);
+            return Ok(PacketRoute::Reply(reply));
+        }
 
-            if quic_conn.connection().is_established() {
-                quic_conn.establish_h3(&self.h3_config)?;
-            }
+        let Some(retry_tokens) = &self.retry_tokens else {
+            let connection = self.accept(&derived, None, local_addr, peer_addr)?;
+            return Ok(PacketRoute::Accepted {
+                conn_id: derived,
+                connection: Box::new(connection),
+            });
+        };
 
-            e.insert(quic_conn);
+        // Without a token, answer with a Retry and keep nothing. The client comes back
+        // addressing the connection ID picked here, with the token.
+        let token = header.token.as_deref().unwrap_or_default();
+        if token.is_empty() {
+            let token = retry_tokens.mint(peer_addr, &header.dcid);
+            let mut reply = vec![0; MAX_DATAGRAM_SIZE];
+            let len = quiche::retry(
+                &header.scid,
+                &header.dcid,
+                &ConnectionId::from_ref(&derived),
+                &token,
+                header.version,
+                &mut reply,
+            )
+            .context("Failed to build retry packet")?;
+            reply.truncate(len);
+            return Ok(PacketRoute::Reply(reply));
         }
 
-        Ok(())
+        let Some(odcid) = retry_tokens.validate(token, peer_addr) else {
+            tracing::debug!(
+                "Dropping Initial with an invalid retry token from {}",
+                peer_addr
+            );
+            return Ok(PacketRoute::Drop);
+        };
+        let connection = self.accept(&header.dcid, Some(&odcid), local_addr, peer_addr)?;
+        Ok(PacketRoute::Accepted {
+            conn_id: header.dcid.to_vec(),
+            connection: Box::new(connection),
+        })
     }
 
-    pub async fn process_connection_events(
+    fn accept(
         &self,
         conn_id: &[u8],
-    ) -> Result<Vec<(u64, quiche::h3::Event)>> {
-        let mut connections = self.connections.lock().await;
+        odcid: Option<&[u8]>,
+        local_addr: SocketAddr,
+        peer_addr: SocketAddr,
+    ) -> Result<QuicConnection> {
+        let mut quiche_config = self.quiche_config.lock().unwrap_or_else(|e| e.into_inner());
+        let config = match quiche_config.as_mut() {
+            Some(config) => config,
+            None => quiche_config.insert(self.create_quiche_config()?.into_inner()),
+        };
+        let mut quic_conn = QuicConnection::new(
+            &ConnectionId::from_ref(conn_id),
+            odcid.map(ConnectionId::from_ref).as_ref(),
+            local_addr,
+            peer_addr,
+            config,
+        )?;
+        drop(quiche_config);
 
-        if let Some(quic_conn) = connections.get_mut(conn_id) {
-            if quic_conn.h3_connection().is_none() && quic_conn.connection().is_established() {
-                quic_conn.establish_h3(&self.h3_config)?;
+        if let Some(qlog_files) = &self.qlog_files {
+            // A connection without its qlog still gets served
+            match qlog_files.create(conn_id) {
+                Ok(writer) => quic_conn.connection().set_qlog(
+                    Box::new(writer),
+                    "prox HTTP/3 connection".to_string(),
+                    format!("Connection {} from {}", conn_id_hex(conn_id), peer_addr),
+                ),
+                Err(e) => tracing::warn!("{:#}", e),
             }
+        }
+
+        Ok(quic_conn)
+    }
+
+    /// Send the packets for `conn_id` to the task owning that connection
+    pub fn register(&self, conn_id: Vec<u8>, packets: mpsc::Sender<Datagram>) {
+        let mut routes = self.routes();
+        routes.insert(conn_id, packets);
+        set_http3_connections(routes.len());
+    }
 
-            quic_conn.poll_h3_events()
+    /// Forget a connection whose task finished
+    pub fn remove(&self, conn_id: &[u8]) {
+        let mut routes = self.routes();
+        routes.remove(conn_id);
+        set_http3_connections(routes.len());
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.routes().len()
+    }
+
+    /// Follow the server's shutdown
+    pub fn drain_state(&self) -> watch::Receiver<DrainState> {
+        self.drain.subscribe()
+    }
+
+    /// A connection's in-flight request count went from `before` to `after`
+    pub fn update_active_requests(&self, before: usize, after: usize) {
+        if after > before {
+            self.active_requests
+                .fetch_add(after - before, Ordering::Relaxed);
         } else {
-            Ok(Vec::new())
+            self.active_requests
+                .fetch_sub(before - after, Ordering::Relaxed);
         }
     }
 
-    pub async fn send_response(
-        &self,
-        conn_id: &[u8],
-        stream_id: u64,
-        headers: &[quiche::h3::Header],
-        body: Option<Bytes>,
-        fin: bool,
-    ) -> Result<()> {
-        let mut connections = self.connections.lock().await;
+    pub fn active_request_count(&self) -> usize {
+        self.active_requests.load(Ordering::Relaxed)
+    }
 
-        if let Some(quic_conn) = connections.get_mut(conn_id) {
-            quic_conn.send_response(stream_id, headers, body, fin)
-        } else {
-            Err(anyhow::anyhow!("Connection not found"))
+    /// Send GOAWAY on every connection and wait for them to finish their in-flight
+    /// requests and close, closing the ones left after `timeout`. Returns whether every
+    /// connection drained in time.
+    pub async fn drain_connections(&self, timeout: Duration) -> bool {
+        let start = Instant::now();
+        let mut log_interval = tokio::time::interval(Duration::from_secs(5));
+
+        tracing::info!(
+            "Starting HTTP/3 connection drain: {} active connections, {} total active requests, timeout={:?}",
+            self.connection_count(),
+            self.active_request_count(),
+            timeout
+        );
+        self.drain.send_replace(DrainState::Draining);
+
+        while start.elapsed() < timeout {
+            if self.connection_count() == 0 {
+                tracing::info!(
+                    "All HTTP/3 connections drained successfully in {:?}",
+                    start.elapsed()
+                );
+                return true;
+            }
+
+            tokio::select! {
+                _ = log_interval.tick() => {
+                    tracing::info!(
+                        "HTTP/3 connection drain in progress: {} active requests across {} connections (elapsed: {:?})",
+                        self.active_request_count(),
+                        self.connection_count(),
+                        start.elapsed()
+                    );
+                }
+                _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+            }
+        }
+
+        tracing::warn!(
+            "HTTP/3 connection drain timeout exceeded: {} requests still active across {} connections after {:?}",
+            self.active_request_count(),
+            self.connection_count(),
+            timeout
+        );
+        self.drain.send_replace(DrainState::Closing);
+        let closing = Instant::now();
+        while self.connection_count() > 0 && closing.elapsed() < CLOSE_GRACE {
+            tokio::time::sleep(Duration::from_millis(20)).await;
         }
+        false
+    }
+
+    pub fn h3_config(&self) -> &quiche::h3::Config {
+        &self.h3_config
+    }
+
+    fn create_quiche_config(&self) -> Result<QuicheConfig> {
+        let certificate = self
+            .certificate
+            .read()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire certificate read lock: {}", e))?;
+        QuicheConfig::new(&self.http3_config, &certificate.0, &certificate.1)
+    }
+
+    /// Serve a new certificate to connections accepted from now on. The pair is loaded
+    /// once up front, so a broken renewal leaves the current certificate in place.
+    pub fn set_certificate(&self, cert_path: &str, key_path: &str) -> Result<()> {
+        let config = QuicheConfig::new(&self.http3_config, cert_path, key_path)?;
+        *self.quiche_config.lock().unwrap_or_else(|e| e.into_inner()) = Some(config.into_inner());
+        let mut certificate = self
+            .certificate
+            .write()
+            .map_err(|e| anyhow::anyhow!("Failed to acquire certificate write lock: {}", e))?;
+        *certificate = (cert_path.to_string(), key_path.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_certificate(dir: &std::path::Path, name: &str) -> (String, String) {
+        let generated =
+            rcgen::generate_simple_self_signed(vec!["app.example.com".to_string()]).unwrap();
+        let cert_path = dir.join(format!("{name}.crt"));
+        let key_path = dir.join(format!("{name}.key"));
+        std::fs::write(&cert_path, generated.cert.pem()).unwrap();
+        std::fs::write(&key_path, generated.key_pair.serialize_pem()).unwrap();
+        (
+            cert_path.to_string_lossy().into_owned(),
+            key_path.to_string_lossy().into_owned(),
+        )
+    }
+
+    #[test]
+    fn test_set_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_path, key_path) = write_certificate(dir.path(), "site");
+        let manager =
+            ConnectionManager::new(Http3Config::default(), &cert_path, &key_path).unwrap();
+        let served = || manager.certificate.read().unwrap().clone();
+
+        // Missing files are refused and the current certificate stays
+        let missing = dir
+            .path()
+            .join("missing.crt")
+            .to_string_lossy()
+            .into_owned();
+        assert!(manager.set_certificate(&missing, &key_path).is_err());
+        assert_eq!(served(), (cert_path.clone(), key_path.clone()));
+
+        let renewed = write_certificate(dir.path(), "renewed");
+        manager.set_certificate(&renewed.0, &renewed.1).unwrap();
+        assert_eq!(served(), renewed);
+        assert!(manager.create_quiche_config().is_ok());
     }
 }
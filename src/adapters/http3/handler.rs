@@ -1,47 +1,155 @@
-use std::sync::{Arc, RwLock};
+use std::net::SocketAddr;
 
 use anyhow::{Context, Result};
+use axum::body::Body as AxumBody;
+use axum::extract::ConnectInfo;
+use axum::response::{IntoResponse, Response as AxumResponse};
 use bytes::Bytes;
-use http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode, Uri, Version, header};
+use http_body_util::BodyExt;
 use quiche::h3::{Header as H3Header, NameValue};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+
+use crate::adapters::early_data::EarlyData;
+use crate::adapters::http::server::{handle_request, request_span};
+use crate::adapters::http_handler::HyperHandler;
+use crate::core::router::MatchedRoute;
+use crate::metrics::{RequestTimer, increment_request_total, protocol_label};
+
+/// Connection-specific headers HTTP/3 forbids (RFC 9114, section 4.2)
+const CONNECTION_HEADERS: [HeaderName; 5] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    HeaderName::from_static("proxy-connection"),
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Response data for the connection task to write to a stream. Writes queue up until the
+/// stream's flow control lets them through, in the order they were sent.
+#[derive(Debug)]
+pub enum StreamWrite {
+    Headers {
+        stream_id: u64,
+        headers: Vec<H3Header>,
+        fin: bool,
+    },
+    Body {
+        stream_id: u64,
+        data: Bytes,
+        fin: bool,
+    },
+}
 
-use crate::adapters::http3::ConnectionManager;
-use crate::core::ProxyService;
+impl StreamWrite {
+    pub fn stream_id(&self) -> u64 {
+        match self {
+            Self::Headers { stream_id, .. } | Self::Body { stream_id, .. } => *stream_id,
+        }
+    }
 
+    /// Body bytes still to be written
+    pub fn pending_bytes(&self) -> usize {
+        match self {
+            Self::Headers { .. } => 0,
+            Self::Body { data, .. } => data.len(),
+        }
+    }
+}
+
+/// Channel to the task owning the connection a response goes out on
+pub type ResponseWriter = mpsc::Sender<StreamWrite>;
+
+/// Runs HTTP/3 requests through the same pipeline as the TCP listener, so routing, rate
+/// limiting, header and body actions, load balancing and static files behave identically
 pub struct Http3Handler {
-    proxy_service_holder: Arc<RwLock<Arc<ProxyService>>>,
-    connection_manager: Arc<ConnectionManager>,
+    handler: HyperHandler,
 }
 
 impl Http3Handler {
-    pub fn new(
-        proxy_service_holder: Arc<RwLock<Arc<ProxyService>>>,
-        connection_manager: Arc<ConnectionManager>,
-    ) -> Self {
-        Self {
-            proxy_service_holder,
-            connection_manager,
-        }
+    pub fn new(handler: HyperHandler) -> Self {
+        Self { handler }
     }
 
     pub async fn handle_h3_request(
         &self,
-        conn_id: &[u8],
+        writer: &ResponseWriter,
         stream_id: u64,
+        peer_addr: SocketAddr,
         headers: Vec<H3Header>,
-        _body: Option<Bytes>,
+        body: Option<Bytes>,
+        early_data: Option<EarlyData>,
     ) -> Result<()> {
         tracing::debug!("Handling HTTP/3 request on stream {}", stream_id);
 
-        let (_method, uri, _http_headers) = self.convert_h3_headers(headers)?;
-
-        let request_info = Http3RequestInfo { uri };
+        let mut request = match self.build_request(headers, body, peer_addr) {
+            Ok(mut request) => {
+                if let Some(early_data) = early_data {
+                    request.extensions_mut().insert(early_data);
+                }
+                request
+            }
+            Err(e) => {
+                tracing::warn!("Malformed HTTP/3 request on stream {}: {:#}", stream_id, e);
+                return self
+                    .send_error(writer, stream_id, StatusCode::BAD_REQUEST)
+                    .await;
+            }
+        };
+        let path = request.uri().path().to_string();
+        let method = request.method().to_string();
+        let protocol = protocol_label(request.version());
+        let tracing_mode = self.handler.route_observability(&request).tracing;
+        request.extensions_mut().insert(tracing_mode);
+        let span = request_span(&request, peer_addr, tracing_mode);
+        // Same span and request metrics as the TCP listener, told apart by `protocol`
+        let response = async {
+            let mut timer = RequestTimer::new(&path, &method, protocol);
+            let response = self.process_request(request, peer_addr).await;
+            let status = response.status().as_u16();
+            tracing::Span::current().record("http.status_code", status);
+            increment_request_total(&path, &method, status, protocol);
+            let route = response.extensions().get::<MatchedRoute>();
+            timer.set_outcome(route.map(|route| route.0.as_str()), status);
+            response
+        }
+        .instrument(span)
+        .await;
 
-        let response = self.process_request(request_info).await?;
+        self.send_h3_response(writer, stream_id, response).await
+    }
 
-        self.send_h3_response(conn_id, stream_id, response).await?;
+    /// Answer `stream_id` with a bare status, for requests refused before reaching routing
+    pub async fn send_error(
+        &self,
+        writer: &ResponseWriter,
+        stream_id: u64,
+        status: StatusCode,
+    ) -> Result<()> {
+        let response = (status, status.canonical_reason().unwrap_or_default()).into_response();
+        self.send_h3_response(writer, stream_id, response).await
+    }
 
-        Ok(())
+    /// The H3 request as the `Request` the TCP listener hands to the handler
+    fn build_request(
+        &self,
+        headers: Vec<H3Header>,
+        body: Option<Bytes>,
+        peer_addr: SocketAddr,
+    ) -> Result<Request<AxumBody>> {
+        let (method, uri, headers) = self.convert_h3_headers(headers)?;
+
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .version(Version::HTTP_3)
+            .body(body.map_or_else(AxumBody::empty, AxumBody::from))
+            .context("Failed to build request")?;
+        *request.headers_mut() = headers;
+        // Client IP for rate limiting, IP conditions and X-Forwarded-For
+        request.extensions_mut().insert(ConnectInfo(peer_addr));
+        Ok(request)
     }
 
     fn convert_h3_headers(&self, headers: Vec<H3Header>) -> Result<(Method, Uri, HeaderMap)> {
@@ -76,7 +184,7 @@ impl Http3Handler {
                         HeaderName::from_bytes(name.as_bytes()).context("Invalid header name")?;
                     let header_value =
                         HeaderValue::from_str(value).context("Invalid header value")?;
-                    header_map.insert(header_name, header_value);
+                    header_map.append(header_name, header_value);
                 }
             }
         }
@@ -93,102 +201,117 @@ impl Http3Handler {
         Ok((method, uri, header_map))
     }
 
-    async fn process_request(&self, request_info: Http3RequestInfo) -> Result<Http3Response> {
-        // This is a simplified version - in a real implementation, you'd need to:
-        // 1. Create a proper HTTP request from the H3 request
-        // 2. Use the existing proxy service to handle routing
-        // 3. Convert the response back to H3 format
-
-        // For now, let's create a basic response
-        let proxy_service = match self.proxy_service_holder.read() {
-            Ok(service) => service,
-            Err(e) => {
-                tracing::error!(
-                    "Failed to acquire proxy service read lock in HTTP/3 handler: {}",
-                    e
-                );
-                return Ok(Http3Response {
-                    status: StatusCode::INTERNAL_SERVER_ERROR,
-                    headers: HeaderMap::new(),
-                    body: Some(Bytes::from("Internal server error")),
-                });
-            }
-        };
-
-        let path = request_info.uri.path();
-
-        let route_config = proxy_service.find_matching_route(path);
-
-        if route_config.is_some() {
-            Ok(Http3Response {
-                status: StatusCode::OK,
-                headers: HeaderMap::new(),
-                body: Some(Bytes::from("HTTP/3 response from proxy")),
-            })
-        } else {
-            Ok(Http3Response {
-                status: StatusCode::NOT_FOUND,
-                headers: HeaderMap::new(),
-                body: Some(Bytes::from("Not Found")),
-            })
-        }
+    async fn process_request(
+        &self,
+        request: Request<AxumBody>,
+        peer_addr: SocketAddr,
+    ) -> AxumResponse {
+        let Ok(response) = handle_request(self.handler.clone(), request, peer_addr).await;
+        response
     }
 
+    /// Send the response headers, then stream the body in chunks as the backend produces it
     async fn send_h3_response(
         &self,
-        conn_id: &[u8],
+        writer: &ResponseWriter,
         stream_id: u64,
-        response: Http3Response,
+        response: AxumResponse,
     ) -> Result<()> {
-        // Convert HTTP headers to HTTP/3 headers
-        let mut h3_headers = Vec::new();
+        let (parts, mut body) = response.into_parts();
 
-        // Add status header
-        h3_headers.push(H3Header::new(
-            b":status",
-            response.status.as_str().as_bytes(),
-        ));
-
-        // Add regular headers
-        for (name, value) in response.headers.iter() {
-            h3_headers.push(H3Header::new(name.as_str().as_bytes(), value.as_bytes()));
+        let mut h3_headers = vec![H3Header::new(b":status", parts.status.as_str().as_bytes())];
+        for (name, value) in parts.headers.iter() {
+            if !CONNECTION_HEADERS.contains(name) {
+                h3_headers.push(H3Header::new(name.as_str().as_bytes(), value.as_bytes()));
+            }
         }
 
-        // Add Alt-Svc header to advertise HTTP/3 support
-        h3_headers.push(H3Header::new(b"alt-svc", b"h3=\":443\"; ma=3600"));
-
-        // Send the response
-        self.connection_manager
-            .send_response(
-                conn_id,
+        let empty = http_body::Body::is_end_stream(&body);
+        Self::write(
+            writer,
+            StreamWrite::Headers {
                 stream_id,
-                &h3_headers,
-                response.body,
-                true, // fin = true for complete response
-            )
-            .await?;
+                headers: h3_headers,
+                fin: empty,
+            },
+        )
+        .await?;
+        if empty {
+            return Ok(());
+        }
 
-        Ok(())
+        while let Some(frame) = body.frame().await {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(e) => {
+                    // Headers are gone already, so the client sees a truncated body
+                    tracing::error!("Error streaming HTTP/3 response body: {}", e);
+                    break;
+                }
+            };
+            if let Ok(data) = frame.into_data()
+                && !data.is_empty()
+            {
+                let write = StreamWrite::Body {
+                    stream_id,
+                    data,
+                    fin: false,
+                };
+                Self::write(writer, write).await?;
+            }
+        }
+        let end = StreamWrite::Body {
+            stream_id,
+            data: Bytes::new(),
+            fin: true,
+        };
+        Self::write(writer, end).await
     }
-}
-
-#[derive(Debug)]
-struct Http3RequestInfo {
-    uri: Uri,
-}
 
-#[derive(Debug)]
-struct Http3Response {
-    status: StatusCode,
-    headers: HeaderMap,
-    body: Option<Bytes>,
+    /// Queue a write on the connection, waiting while the connection has too much queued
+    async fn write(writer: &ResponseWriter, write: StreamWrite) -> Result<()> {
+        writer
+            .send(write)
+            .await
+            .map_err(|_| anyhow::anyhow!("HTTP/3 connection closed"))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{Arc, RwLock};
+
     use quiche::h3::Header as H3Header;
 
+    use crate::adapters::file_system::TowerFileSystem;
+    use crate::adapters::http_client::HyperHttpClient;
+    use crate::core::ProxyService;
+
+    /// Handler over `routes`
+    fn handler(routes: &str) -> Http3Handler {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        let config: crate::config::ServerConfig =
+            serde_yaml::from_str(&format!("listen_addr: \"127.0.0.1:0\"\nroutes: {routes}\n"))
+                .unwrap();
+        let handler = HyperHandler::new(
+            Arc::new(RwLock::new(Arc::new(ProxyService::new(Arc::new(config))))),
+            Arc::new(HyperHttpClient::new()),
+            Arc::new(TowerFileSystem::new()),
+        );
+        Http3Handler::new(handler)
+    }
+
+    fn request_headers(method: &str, path: &str) -> Vec<H3Header> {
+        vec![
+            H3Header::new(b":method", method.as_bytes()),
+            H3Header::new(b":scheme", b"https"),
+            H3Header::new(b":authority", b"app.example.com"),
+            H3Header::new(b":path", path.as_bytes()),
+            H3Header::new(b"user-agent", b"test-client"),
+        ]
+    }
+
     #[test]
     fn test_h3_header_conversion() {
         // Test basic HTTP header conversion functionality
@@ -208,24 +331,136 @@ mod tests {
     }
 
     #[test]
-    fn test_http3_response_creation() {
-        let response = Http3Response {
-            status: StatusCode::OK,
-            headers: HeaderMap::new(),
-            body: Some(Bytes::from("test response")),
+    fn test_build_request() {
+        let handler = handler("{}");
+        let peer: SocketAddr = "192.0.2.7:4433".parse().unwrap();
+
+        let mut headers = request_headers("POST", "/api/items?page=2");
+        headers.push(H3Header::new(b"cookie", b"a=1"));
+        headers.push(H3Header::new(b"cookie", b"b=2"));
+        let request = handler
+            .build_request(headers, Some(Bytes::from("{}")), peer)
+            .unwrap();
+
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.version(), Version::HTTP_3);
+        assert_eq!(
+            request.uri().to_string(),
+            "https://app.example.com/api/items?page=2"
+        );
+        assert_eq!(request.headers().get_all(header::COOKIE).iter().count(), 2);
+        assert_eq!(
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .unwrap()
+                .0,
+            peer
+        );
+
+        let mut headers = request_headers("GET", "/");
+        headers.retain(|header| header.name() != b":method");
+        assert!(handler.build_request(headers, None, peer).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_process_request_uses_routes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("public");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("hello.txt"), "hello over h3").unwrap();
+        let routes = format!(
+            "{{\"/files\": {{type: static, root: \"{}\"}}}}",
+            root.display()
+        );
+        let handler = handler(&routes);
+        let peer: SocketAddr = "192.0.2.7:4433".parse().unwrap();
+        let fetch = |path: &'static str| {
+            let request = handler
+                .build_request(request_headers("GET", path), None, peer)
+                .unwrap();
+            handler.process_request(request, peer)
         };
 
-        assert_eq!(response.status, StatusCode::OK);
-        assert_eq!(response.body.unwrap(), Bytes::from("test response"));
+        let response = fetch("/files/hello.txt").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello over h3");
+
+        assert_eq!(fetch("/missing").await.status(), StatusCode::NOT_FOUND);
     }
 
     #[test]
-    fn test_http3_request_info_creation() {
-        let uri = Uri::from_static("https://example.com/test");
+    fn test_h3_requests_recorded_like_tcp_ones() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let metrics = recorder.handle();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let handler = handler("{\"/missing\": {type: redirect, target: \"/elsewhere\"}}");
+        let peer: SocketAddr = "192.0.2.7:4433".parse().unwrap();
+        let (writer, mut writes) = mpsc::channel(8);
+
+        metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                let headers = request_headers("GET", "/missing");
+                handler
+                    .handle_h3_request(&writer, 0, peer, headers, None, None)
+                    .await
+                    .unwrap();
+            })
+        });
+        assert!(matches!(
+            writes.try_recv(),
+            Ok(StreamWrite::Headers { stream_id: 0, .. })
+        ));
 
-        let request_info = Http3RequestInfo { uri: uri.clone() };
+        let rendered = metrics.render();
+        let counted = rendered.lines().any(|line| {
+            line.starts_with("prox_requests_total{")
+                && line.contains("path=\"/missing\"")
+                && line.contains("protocol=\"h3\"")
+        });
+        assert!(counted, "{rendered}");
+        assert!(
+            rendered.contains("prox_route_request_duration_seconds_count{route=\"/missing\""),
+            "{rendered}"
+        );
+    }
 
-        assert_eq!(request_info.uri, uri);
-        assert_eq!(request_info.uri.path(), "/test");
+    #[tokio::test]
+    async fn test_request_body_reaches_backend() {
+        let received = Arc::new(tokio::sync::Mutex::new(None));
+        let backend = axum::Router::new().route(
+            "/items",
+            axum::routing::post({
+                let received = received.clone();
+                move |body: Bytes| async move {
+                    *received.lock().await = Some(body.clone());
+                    body
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, backend).await });
+
+        let routes = format!("{{\"/api\": {{type: proxy, target: \"http://{backend_addr}\"}}}}");
+        let handler = handler(&routes);
+        let peer: SocketAddr = "192.0.2.7:4433".parse().unwrap();
+        let payload = Bytes::from_static(br#"{"name":"widget","qty":3}"#);
+
+        let mut headers = request_headers("POST", "/api/items");
+        headers.push(H3Header::new(b"content-type", b"application/json"));
+        let request = handler
+            .build_request(headers, Some(payload.clone()), peer)
+            .unwrap();
+        let response = handler.process_request(request, peer).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, payload);
+        assert_eq!(received.lock().await.as_ref(), Some(&payload));
     }
 }
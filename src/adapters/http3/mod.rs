@@ -1,7 +1,10 @@
 pub mod config;
 pub mod connection;
 pub mod handler;
+pub mod qlog;
+pub mod retry;
 pub mod server;
+pub mod task;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,95 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+
+use crate::adapters::http3::connection::conn_id_hex;
+
+/// Extension of qlog's JSON-SEQ serialization, the one quiche writes
+const QLOG_EXTENSION: &str = "sqlog";
+
+/// Opens a qlog file per connection in `http3_config.qlog_dir`, named by connection ID.
+/// The oldest files go once there are `max_files`, so a busy server can't fill the disk.
+pub struct QlogFiles {
+    dir: PathBuf,
+    max_files: usize,
+}
+
+impl QlogFiles {
+    pub fn new(dir: &str, max_files: usize) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create qlog dir {dir}"))?;
+        Ok(Self {
+            dir: PathBuf::from(dir),
+            max_files,
+        })
+    }
+
+    /// Writer for the qlog of connection `conn_id`
+    pub fn create(&self, conn_id: &[u8]) -> Result<BufWriter<File>> {
+        self.prune(self.max_files.saturating_sub(1));
+        let path = self
+            .dir
+            .join(format!("{}.{QLOG_EXTENSION}", conn_id_hex(conn_id)));
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create qlog file {}", path.display()))?;
+        Ok(BufWriter::new(file))
+    }
+
+    /// Delete the oldest qlog files until at most `keep` are left
+    fn prune(&self, keep: usize) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut files: Vec<(SystemTime, PathBuf)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let path = entry.path();
+                if path.extension()? != QLOG_EXTENSION {
+                    return None;
+                }
+                Some((entry.metadata().ok()?.modified().ok()?, path))
+            })
+            .collect();
+        if files.len() <= keep {
+            return;
+        }
+
+        files.sort();
+        for (_, path) in &files[..files.len() - keep] {
+            if let Err(e) = fs::remove_file(path) {
+                tracing::warn!("Failed to remove qlog file {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_qlog_files_limited() {
+        let dir = tempfile::tempdir().unwrap();
+        let qlog_dir = dir.path().join("qlog");
+        let files = QlogFiles::new(qlog_dir.to_str().unwrap(), 2).unwrap();
+        std::fs::write(qlog_dir.join("notes.txt"), "kept").unwrap();
+
+        for id in 1..=3u8 {
+            files.create(&[id; 4]).unwrap().write_all(b"{}").unwrap();
+            // Distinct modification times
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let mut names: Vec<String> = std::fs::read_dir(&qlog_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["02020202.sqlog", "03030303.sqlog", "notes.txt"]);
+    }
+}
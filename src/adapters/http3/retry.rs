@@ -0,0 +1,101 @@
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use aws_lc_rs::hmac;
+use aws_lc_rs::rand::SystemRandom;
+
+/// How long a client has to come back with the token of a Retry
+const TOKEN_LIFETIME: Duration = Duration::from_secs(10);
+/// Length of the HMAC-SHA256 tag ending each token
+const TAG_LEN: usize = 32;
+
+/// Mints and checks the address validation tokens sent in QUIC Retry packets (RFC 9000,
+/// section 8.1.2). A token binds the client's address and the connection ID it first used,
+/// so only a client that can receive packets at that address gets connection state.
+pub struct RetryTokens {
+    key: hmac::Key,
+}
+
+impl RetryTokens {
+    pub fn new() -> Result<Self> {
+        let key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .map_err(|_| anyhow::anyhow!("Failed to generate retry token key"))?;
+        Ok(Self { key })
+    }
+
+    /// Token for a client at `peer` whose Initial was sent to `odcid`
+    pub fn mint(&self, peer: SocketAddr, odcid: &[u8]) -> Vec<u8> {
+        let issued = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut token = issued.to_be_bytes().to_vec();
+        token.extend_from_slice(odcid);
+        let tag = hmac::sign(&self.key, &Self::signed_data(&token, peer));
+        token.extend_from_slice(tag.as_ref());
+        token
+    }
+
+    /// The original destination connection ID of a valid, fresh token presented from `peer`
+    pub fn validate(&self, token: &[u8], peer: SocketAddr) -> Option<Vec<u8>> {
+        if token.len() < 8 + TAG_LEN {
+            return None;
+        }
+        let (payload, tag) = token.split_at(token.len() - TAG_LEN);
+        hmac::verify(&self.key, &Self::signed_data(payload, peer), tag).ok()?;
+
+        let (issued, odcid) = payload.split_at(8);
+        let issued = UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(issued.try_into().ok()?));
+        let age = SystemTime::now().duration_since(issued).unwrap_or_default();
+        (age <= TOKEN_LIFETIME).then(|| odcid.to_vec())
+    }
+
+    fn signed_data(payload: &[u8], peer: SocketAddr) -> Vec<u8> {
+        let mut data = peer.to_string().into_bytes();
+        data.push(0);
+        data.extend_from_slice(payload);
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_tokens() {
+        let tokens = RetryTokens::new().unwrap();
+        let peer: SocketAddr = "192.0.2.7:4433".parse().unwrap();
+        let odcid = [7; 16];
+
+        let token = tokens.mint(peer, &odcid);
+        assert_eq!(tokens.validate(&token, peer), Some(odcid.to_vec()));
+
+        // Replayed from another address, even another port
+        assert!(
+            tokens
+                .validate(&token, "198.51.100.1:4433".parse().unwrap())
+                .is_none()
+        );
+        assert!(
+            tokens
+                .validate(&token, "192.0.2.7:4434".parse().unwrap())
+                .is_none()
+        );
+
+        // Tampered with, or minted by another server
+        let mut tampered = token.clone();
+        tampered[9] ^= 1;
+        assert!(tokens.validate(&tampered, peer).is_none());
+        assert!(RetryTokens::new().unwrap().validate(&token, peer).is_none());
+        assert!(tokens.validate(&token[..20], peer).is_none());
+
+        // Expired
+        let mut stale = 0u64.to_be_bytes().to_vec();
+        stale.extend_from_slice(&odcid);
+        let tag = hmac::sign(&tokens.key, &RetryTokens::signed_data(&stale, peer));
+        stale.extend_from_slice(tag.as_ref());
+        assert!(tokens.validate(&stale, peer).is_none());
+    }
+}
@@ -1,18 +1,24 @@
 use std::net::SocketAddr;
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
-use quiche::h3::Event as H3Event;
 use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, mpsc};
 
+use crate::adapters::acme::CertificateInfo;
+use crate::adapters::http_handler::HyperHandler;
+use crate::adapters::http3::connection::PacketRoute;
+use crate::adapters::http3::task::{ConnectionTask, PACKET_QUEUE};
 use crate::adapters::http3::{ConnectionManager, Http3Handler};
 use crate::config::models::Http3Config;
-use crate::core::ProxyService;
+use crate::metrics::{increment_http3_packets_received, increment_http3_packets_sent};
+use crate::utils::graceful_shutdown::ShutdownToken;
 
 pub struct Http3Server {
-    socket: UdpSocket,
+    socket: Arc<UdpSocket>,
     connection_manager: Arc<ConnectionManager>,
-    handler: Http3Handler,
+    handler: Arc<Http3Handler>,
+    max_request_body_size: usize,
     local_addr: SocketAddr,
 }
 
@@ -22,13 +28,15 @@ impl Http3Server {
         http3_config: &Http3Config,
         cert_path: &str,
         key_path: &str,
-        proxy_service_holder: Arc<RwLock<Arc<ProxyService>>>,
+        handler: HyperHandler,
     ) -> Result<Self> {
         let socket = UdpSocket::bind(bind_addr)
             .await
             .with_context(|| format!("Failed to bind UDP socket to {bind_addr}"))?;
+        // The actual port when binding to port 0
+        let local_addr = socket.local_addr()?;
 
-        tracing::info!("HTTP/3 server bound to UDP {}", bind_addr);
+        tracing::info!("HTTP/3 server bound to UDP {}", local_addr);
 
         let connection_manager = Arc::new(ConnectionManager::new(
             http3_config.clone(),
@@ -36,19 +44,72 @@ impl Http3Server {
             key_path,
         )?);
 
-        let handler = Http3Handler::new(proxy_service_holder, connection_manager.clone());
-
         Ok(Self {
-            socket,
+            socket: Arc::new(socket),
             connection_manager,
-            handler,
-            local_addr: bind_addr,
+            handler: Arc::new(Http3Handler::new(handler)),
+            max_request_body_size: http3_config.max_request_body_size,
+            local_addr,
+        })
+    }
+
+    /// Swap in each certificate the ACME renewal task announces on `renewals`
+    pub fn spawn_certificate_updates(
+        &self,
+        mut renewals: broadcast::Receiver<CertificateInfo>,
+    ) -> tokio::task::JoinHandle<()> {
+        let connection_manager = self.connection_manager.clone();
+        tokio::spawn(async move {
+            loop {
+                match renewals.recv().await {
+                    Ok(renewed) => {
+                        match connection_manager
+                            .set_certificate(&renewed.cert_path, &renewed.key_path)
+                        {
+                            Ok(()) => tracing::info!(
+                                "HTTP/3 now serving renewed certificate {}",
+                                renewed.cert_path
+                            ),
+                            Err(e) => tracing::error!(
+                                "Failed to load renewed certificate for HTTP/3, keeping the current one: {e:#}"
+                            ),
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("HTTP/3 missed {} certificate renewals", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
         })
     }
 
-    pub async fn run(&self) -> Result<()> {
+    /// Serve until `shutdown` and through its grace period, then send GOAWAY on every
+    /// connection and drain them
+    pub async fn run(&self, mut shutdown: ShutdownToken) -> Result<()> {
         tracing::info!("Starting HTTP/3 server on {}", self.local_addr);
 
+        tokio::select! {
+            result = self.receive_packets() => return result,
+            reason = shutdown.cancelled() => {
+                tracing::info!("HTTP/3 server shutdown initiated: {:?}", reason);
+            }
+        }
+        tokio::select! {
+            result = self.receive_packets() => return result,
+            _ = tokio::time::sleep(shutdown.grace_period()) => {}
+        }
+
+        // Draining connections still need their ACKs and request bodies
+        tokio::select! {
+            result = self.receive_packets() => result,
+            _ = self.connection_manager.drain_connections(shutdown.drain_timeout()) => Ok(()),
+        }
+    }
+
+    /// Read datagrams and hand each to the task of its connection. Nothing here waits on
+    /// a connection, so one busy connection doesn't hold up the others.
+    async fn receive_packets(&self) -> Result<()> {
         let mut buffer = vec![0; 65536];
 
         loop {
@@ -58,77 +119,62 @@ impl Http3Server {
                 .await
                 .context("Failed to receive UDP packet")?;
 
-            let packet = &buffer[..len];
-
             tracing::debug!("Received {} bytes from {}", len, peer_addr);
+            increment_http3_packets_received(1);
 
-            if let Err(e) = self.process_packet(packet, peer_addr).await {
+            if let Err(e) = self.dispatch_packet(&buffer[..len], peer_addr).await {
                 tracing::error!("Error processing packet from {}: {}", peer_addr, e);
             }
         }
     }
 
-    async fn process_packet(&self, packet: &[u8], peer_addr: SocketAddr) -> Result<()> {
-        let mut packet_buf = packet.to_vec();
-        let hdr = quiche::Header::from_slice(&mut packet_buf, quiche::MAX_CONN_ID_LEN)
+    async fn dispatch_packet(&self, packet: &[u8], peer_addr: SocketAddr) -> Result<()> {
+        let mut packet = packet.to_vec();
+        let hdr = quiche::Header::from_slice(&mut packet, quiche::MAX_CONN_ID_LEN)
             .context("Failed to parse QUIC header")?;
-
-        let conn_id = hdr.dcid.clone();
-
-        self.connection_manager
-            .get_or_create_connection(&conn_id, None, self.local_addr, peer_addr)
-            .await?;
-
-        let events = self
+        let route = self
             .connection_manager
-            .process_connection_events(&conn_id)
-            .await?;
-
-        for (stream_id, event) in events {
-            if let Err(e) = self.handle_h3_event(&conn_id, stream_id, event).await {
-                tracing::error!("Error handling HTTP/3 event: {}", e);
-            }
-        }
-
-        Ok(())
-    }
-
-    async fn handle_h3_event(&self, conn_id: &[u8], stream_id: u64, event: H3Event) -> Result<()> {
-        match event {
-            H3Event::Headers { list, more_frames } => {
-                tracing::debug!(
-                    "Received headers on stream {}, more_frames: {}",
-                    stream_id,
-                    more_frames
-                );
-
-                let mut body = None;
-                if more_frames {
-                    let body_data = Vec::new();
-                    body = Some(bytes::Bytes::from(body_data));
+            .route_packet(&hdr, self.local_addr, peer_addr)?;
+
+        let recv_info = quiche::RecvInfo {
+            from: peer_addr,
+            to: self.local_addr,
+        };
+        match route {
+            PacketRoute::Connection(packets) => {
+                if packets.try_send((packet, recv_info)).is_err() {
+                    tracing::debug!("Dropping packet for busy connection from {}", peer_addr);
                 }
-
-                self.handler
-                    .handle_h3_request(conn_id, stream_id, list, body)
-                    .await?;
-            }
-            H3Event::Data => {
-                tracing::debug!("Received data on stream {}", stream_id);
-            }
-            H3Event::Finished => {
-                tracing::debug!("Stream {} finished", stream_id);
             }
-            H3Event::Reset(error_code) => {
-                tracing::warn!("Stream {} reset with error code: {}", stream_id, error_code);
+            PacketRoute::Accepted {
+                conn_id,
+                connection,
+            } => {
+                let (packets, receiver) = mpsc::channel(PACKET_QUEUE);
+                let _ = packets.try_send((packet, recv_info));
+                self.connection_manager.register(conn_id.clone(), packets);
+                let task = ConnectionTask::new(
+                    conn_id,
+                    connection,
+                    peer_addr,
+                    self.socket.clone(),
+                    self.connection_manager.clone(),
+                    self.handler.clone(),
+                    self.max_request_body_size,
+                );
+                tokio::spawn(task.run(receiver));
             }
-            H3Event::PriorityUpdate => {
-                tracing::debug!("Received priority update on stream {}", stream_id);
+            PacketRoute::Reply(reply) => {
+                increment_http3_packets_sent(1);
+                self.socket
+                    .send_to(&reply, peer_addr)
+                    .await
+                    .context("Failed to send stateless reply")?;
             }
-            H3Event::GoAway => {
-                tracing::info!("Received GOAWAY");
+            PacketRoute::Drop => {
+                tracing::debug!("Dropping packet for unknown connection from {}", peer_addr);
             }
         }
-
         Ok(())
     }
 
@@ -139,7 +185,18 @@ impl Http3Server {
 
 #[cfg(test)]
 mod tests {
-    use crate::config::models::{Http3Config, Http3CongestionControl};
+    use std::time::Duration;
+
+    use axum::body::Bytes as AxumBytes;
+    use quiche::ConnectionId;
+    use quiche::h3::{Event as H3Event, Header as H3Header, NameValue};
+
+    use super::*;
+    use crate::adapters::file_system::TowerFileSystem;
+    use crate::adapters::http_client::HyperHttpClient;
+    use crate::config::models::Http3CongestionControl;
+    use crate::core::ProxyService;
+    use crate::utils::graceful_shutdown::{GracefulShutdown, ShutdownReason};
 
     fn create_test_config() -> Http3Config {
         Http3Config {
@@ -150,6 +207,10 @@ mod tests {
             congestion_control: Http3CongestionControl::Cubic,
             enable_0rtt: false,
             max_packet_size: Some(1452),
+            max_request_body_size: 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         }
     }
 
@@ -186,6 +247,473 @@ mod tests {
         }
     }
 
+    /// HTTP/3 server on a random port serving `routes`, until the returned shutdown fires
+    async fn start_server(
+        dir: &std::path::Path,
+        routes: &str,
+        http3_config: &Http3Config,
+    ) -> (Arc<Http3Server>, GracefulShutdown) {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        let generated =
+            rcgen::generate_simple_self_signed(vec!["app.example.com".to_string()]).unwrap();
+        let cert_path = dir.join("site.crt").to_string_lossy().into_owned();
+        let key_path = dir.join("site.key").to_string_lossy().into_owned();
+        std::fs::write(&cert_path, generated.cert.pem()).unwrap();
+        std::fs::write(&key_path, generated.key_pair.serialize_pem()).unwrap();
+
+        let config: crate::config::ServerConfig =
+            serde_yaml::from_str(&format!("listen_addr: \"127.0.0.1:0\"\nroutes: {routes}\n"))
+                .unwrap();
+        let handler = HyperHandler::new(
+            Arc::new(std::sync::RwLock::new(Arc::new(ProxyService::new(
+                Arc::new(config),
+            )))),
+            Arc::new(HyperHttpClient::new()),
+            Arc::new(TowerFileSystem::new()),
+        );
+        let server = Http3Server::new(
+            "127.0.0.1:0".parse().unwrap(),
+            http3_config,
+            &cert_path,
+            &key_path,
+            handler,
+        )
+        .await
+        .unwrap();
+        let server = Arc::new(server);
+        let shutdown = GracefulShutdown::new();
+        tokio::spawn({
+            let server = server.clone();
+            let shutdown = shutdown.shutdown_token();
+            async move { server.run(shutdown).await }
+        });
+        (server, shutdown)
+    }
+
+    /// Minimal quiche HTTP/3 client
+    struct TestClient {
+        socket: UdpSocket,
+        conn: quiche::Connection,
+        h3: Option<quiche::h3::Connection>,
+    }
+
+    impl TestClient {
+        async fn connect(server: SocketAddr) -> Self {
+            Self::connect_with(server, None).await
+        }
+
+        /// Connect, resuming `session` and returning while still in 0-RTT when given one
+        async fn connect_with(server: SocketAddr, session: Option<&[u8]>) -> Self {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION).unwrap();
+            config
+                .set_application_protos(quiche::h3::APPLICATION_PROTOCOL)
+                .unwrap();
+            config.verify_peer(false);
+            config.set_max_idle_timeout(5_000);
+            config.set_initial_max_data(10_000_000);
+            config.set_initial_max_stream_data_bidi_local(1_000_000);
+            config.set_initial_max_stream_data_bidi_remote(1_000_000);
+            config.set_initial_max_stream_data_uni(1_000_000);
+            config.set_initial_max_streams_bidi(100);
+            config.set_initial_max_streams_uni(100);
+            config.enable_early_data();
+            let scid = ConnectionId::from_vec(rand::random::<[u8; 16]>().to_vec());
+            let mut conn = quiche::connect(
+                Some("app.example.com"),
+                &scid,
+                socket.local_addr().unwrap(),
+                server,
+                &mut config,
+            )
+            .unwrap();
+            if let Some(session) = session {
+                conn.set_session(session).unwrap();
+            }
+
+            let mut client = Self {
+                socket,
+                conn,
+                h3: None,
+            };
+            // Early data becomes available once the ClientHello is out
+            client.send_pending().await;
+            while !client.conn.is_established() && !client.conn.is_in_early_data() {
+                client.exchange().await;
+            }
+            let h3_config = quiche::h3::Config::new().unwrap();
+            client.h3 =
+                Some(quiche::h3::Connection::with_transport(&mut client.conn, &h3_config).unwrap());
+            client
+        }
+
+        async fn send_pending(&mut self) {
+            let mut out = [0; 1350];
+            loop {
+                match self.conn.send(&mut out) {
+                    Ok((len, send_info)) => {
+                        self.socket
+                            .send_to(&out[..len], send_info.to)
+                            .await
+                            .unwrap();
+                    }
+                    Err(quiche::Error::Done) => break,
+                    Err(e) => panic!("Client failed to build a packet: {e}"),
+                }
+            }
+        }
+
+        /// Send everything pending, then process one datagram or a timeout
+        async fn exchange(&mut self) {
+            self.send_pending().await;
+
+            let mut buf = vec![0; 65535];
+            let wait = self.conn.timeout().unwrap_or(Duration::from_secs(1));
+            match tokio::time::timeout(wait, self.socket.recv_from(&mut buf)).await {
+                Ok(Ok((len, from))) => {
+                    let recv_info = quiche::RecvInfo {
+                        from,
+                        to: self.socket.local_addr().unwrap(),
+                    };
+                    let _ = self.conn.recv(&mut buf[..len], recv_info);
+                }
+                _ => self.conn.on_timeout(),
+            }
+            assert!(
+                !self.conn.is_closed(),
+                "connection closed: {:?}",
+                self.conn.peer_error()
+            );
+        }
+
+        /// Close the connection and send the CONNECTION_CLOSE
+        async fn close(&mut self) {
+            self.conn.close(true, 0, b"done").unwrap();
+            self.send_pending().await;
+        }
+
+        /// Process packets until the server closes the connection, returning whether it
+        /// closed with an application error code and which
+        async fn wait_for_close(&mut self) -> (bool, u64) {
+            loop {
+                if let Some(error) = self.conn.peer_error() {
+                    return (error.is_app, error.error_code);
+                }
+                self.exchange().await;
+            }
+        }
+
+        async fn request(
+            &mut self,
+            method: &str,
+            path: &str,
+            body: Option<&[u8]>,
+        ) -> (u16, Vec<u8>) {
+            let headers = [
+                H3Header::new(b":method", method.as_bytes()),
+                H3Header::new(b":scheme", b"https"),
+                H3Header::new(b":authority", b"app.example.com"),
+                H3Header::new(b":path", path.as_bytes()),
+            ];
+            let h3 = self.h3.as_mut().unwrap();
+            let stream_id = h3
+                .send_request(&mut self.conn, &headers, body.is_none())
+                .unwrap();
+            if let Some(body) = body {
+                h3.send_body(&mut self.conn, stream_id, body, true).unwrap();
+            }
+
+            let mut status = 0;
+            let mut response = Vec::new();
+            loop {
+                self.exchange().await;
+                let h3 = self.h3.as_mut().unwrap();
+                loop {
+                    match h3.poll(&mut self.conn) {
+                        Ok((id, H3Event::Headers { list, .. })) if id == stream_id => {
+                            let value = list.iter().find(|h| h.name() == b":status").unwrap();
+                            status = std::str::from_utf8(value.value()).unwrap().parse().unwrap();
+                        }
+                        Ok((id, H3Event::Data)) if id == stream_id => {
+                            let mut buf = [0; 4096];
+                            while let Ok(read) = h3.recv_body(&mut self.conn, id, &mut buf) {
+                                response.extend_from_slice(&buf[..read]);
+                            }
+                        }
+                        Ok((id, H3Event::Finished)) if id == stream_id => {
+                            return (status, response);
+                        }
+                        Ok(_) => {}
+                        Err(quiche::h3::Error::Done) => break,
+                        Err(e) => panic!("HTTP/3 client error: {e}"),
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http3_requests_end_to_end() {
+        let backend = axum::Router::new().route(
+            "/items",
+            axum::routing::post(|body: AxumBytes| async move { body }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, backend).await });
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("public");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("hello.txt"), "hello over h3").unwrap();
+        let routes = format!(
+            "{{\"/files\": {{type: static, root: \"{}\"}}, \"/api\": {{type: proxy, target: \"http://{backend_addr}\"}}}}",
+            root.display()
+        );
+        let (server, _shutdown) = start_server(dir.path(), &routes, &Http3Config::default()).await;
+        let server = server.local_addr();
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let mut client = TestClient::connect(server).await;
+
+            let (status, body) = client.request("GET", "/files/hello.txt", None).await;
+            assert_eq!(status, 200);
+            assert_eq!(body, b"hello over h3");
+
+            let payload = br#"{"name":"widget","qty":3}"#;
+            let (status, body) = client.request("POST", "/api/items", Some(payload)).await;
+            assert_eq!(status, 200);
+            assert_eq!(body, payload);
+
+            let (status, _) = client.request("GET", "/missing", None).await;
+            assert_eq!(status, 404);
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_early_data_policy() {
+        let backend = axum::Router::new()
+            .route(
+                "/early",
+                axum::routing::get(|headers: http::HeaderMap| async move {
+                    match headers.get("early-data") {
+                        Some(value) => value.to_str().unwrap().to_string(),
+                        None => "none".to_string(),
+                    }
+                }),
+            )
+            .route(
+                "/items",
+                axum::routing::post(|body: AxumBytes| async move { body }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, backend).await });
+
+        let dir = tempfile::tempdir().unwrap();
+        let routes = format!(
+            "{{\"/api\": {{type: proxy, target: \"http://{backend_addr}\"}}, \"/deferred\": {{type: proxy, target: \"http://{backend_addr}\", early_data: defer}}}}"
+        );
+        let (server, _shutdown) = start_server(dir.path(), &routes, &Http3Config::default()).await;
+        let server = server.local_addr();
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let mut client = TestClient::connect(server).await;
+            // After the handshake nothing is marked or refused
+            assert_eq!(
+                client.request("GET", "/api/early", None).await,
+                (200, b"none".to_vec())
+            );
+            let session = client.conn.session().unwrap().to_vec();
+
+            let mut client = TestClient::connect_with(server, Some(&session)).await;
+            assert!(client.conn.is_in_early_data());
+            assert_eq!(
+                client.request("GET", "/api/early", None).await,
+                (200, b"1".to_vec())
+            );
+
+            let mut client = TestClient::connect_with(server, Some(&session)).await;
+            let (status, _) = client.request("POST", "/api/items", Some(b"{}")).await;
+            assert_eq!(status, 425);
+
+            let mut client = TestClient::connect_with(server, Some(&session)).await;
+            assert_eq!(
+                client.request("POST", "/deferred/items", Some(b"{}")).await,
+                (200, b"{}".to_vec())
+            );
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retry_end_to_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("public");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("hello.txt"), "validated").unwrap();
+        let routes = format!(
+            "{{\"/files\": {{type: static, root: \"{}\"}}}}",
+            root.display()
+        );
+        let http3_config = Http3Config {
+            retry: true,
+            qlog_dir: None,
+            qlog_max_files: 100,
+            ..Http3Config::default()
+        };
+        let (server, _shutdown) = start_server(dir.path(), &routes, &http3_config).await;
+        let server = server.local_addr();
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let mut client = TestClient::connect(server).await;
+            let (status, body) = client.request("GET", "/files/hello.txt", None).await;
+            assert_eq!(status, 200);
+            assert_eq!(body, b"validated");
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_slow_request_does_not_block_other_connections() {
+        let backend = axum::Router::new()
+            .route(
+                "/slow",
+                axum::routing::get(|| async {
+                    tokio::time::sleep(Duration::from_secs(3)).await;
+                    "slow"
+                }),
+            )
+            .route("/fast", axum::routing::get(|| async { "fast" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, backend).await });
+
+        let dir = tempfile::tempdir().unwrap();
+        let routes = format!("{{\"/api\": {{type: proxy, target: \"http://{backend_addr}\"}}}}");
+        let (server, _shutdown) = start_server(dir.path(), &routes, &Http3Config::default()).await;
+        let server = server.local_addr();
+
+        let slow = tokio::spawn(async move {
+            let mut client = TestClient::connect(server).await;
+            client.request("GET", "/api/slow", None).await
+        });
+        // Give the slow request time to reach the backend
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let (status, body) = tokio::time::timeout(Duration::from_secs(2), async {
+            let mut client = TestClient::connect(server).await;
+            client.request("GET", "/api/fast", None).await
+        })
+        .await
+        .expect("second connection waited on the first one's request");
+        assert_eq!(status, 200);
+        assert_eq!(body, b"fast");
+
+        let (status, body) = slow.await.unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, b"slow");
+    }
+
+    #[tokio::test]
+    async fn test_closed_connections_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let (server, _shutdown) = start_server(dir.path(), "{}", &Http3Config::default()).await;
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let mut client = TestClient::connect(server.local_addr()).await;
+            let (status, _) = client.request("GET", "/missing", None).await;
+            assert_eq!(status, 404);
+            assert_eq!(server.connection_manager.connection_count(), 1);
+
+            client.close().await;
+
+            // The server's task ends once the connection has drained
+            while server.connection_manager.connection_count() > 0 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_requests() {
+        let backend = axum::Router::new().route(
+            "/slow",
+            axum::routing::get(|| async {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                "slow"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, backend).await });
+
+        let dir = tempfile::tempdir().unwrap();
+        let routes = format!("{{\"/api\": {{type: proxy, target: \"http://{backend_addr}\"}}}}");
+        let (server, shutdown) = start_server(dir.path(), &routes, &Http3Config::default()).await;
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let mut client = TestClient::connect(server.local_addr()).await;
+            let request = tokio::spawn(async move {
+                let response = client.request("GET", "/api/slow", None).await;
+                (client, response)
+            });
+            // Shut down while the request waits on the backend
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            shutdown.trigger_shutdown(ShutdownReason::Graceful).unwrap();
+
+            let (mut client, response) = request.await.unwrap();
+            assert_eq!(response, (200, b"slow".to_vec()));
+            assert_eq!(
+                client.wait_for_close().await,
+                (true, quiche::h3::WireErrorCode::NoError as u64)
+            );
+            while server.connection_manager.connection_count() > 0 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            assert_eq!(server.connection_manager.active_request_count(), 0);
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_qlog_written_per_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let qlog_dir = dir.path().join("qlog");
+        let http3_config = Http3Config {
+            qlog_dir: Some(qlog_dir.to_string_lossy().into_owned()),
+            ..Http3Config::default()
+        };
+        let (server, _shutdown) = start_server(dir.path(), "{}", &http3_config).await;
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            let mut client = TestClient::connect(server.local_addr()).await;
+            client.request("GET", "/missing", None).await;
+            client.close().await;
+            while server.connection_manager.connection_count() > 0 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&qlog_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "sqlog");
+        let qlog = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(qlog.contains("\"vantage_point\""));
+    }
+
     #[test]
     fn test_http3_config_congestion_control() {
         let config = create_test_config();
@@ -0,0 +1,486 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::{Buf, Bytes, BytesMut};
+use http::StatusCode;
+use quiche::h3::{Event as H3Event, Header as H3Header, WireErrorCode};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, watch};
+
+use crate::adapters::early_data::EarlyData;
+use crate::adapters::http3::connection::{Datagram, DrainState, QuicConnection, conn_id_hex};
+use crate::adapters::http3::handler::{ResponseWriter, StreamWrite};
+use crate::adapters::http3::{ConnectionManager, Http3Handler};
+use crate::metrics::{
+    increment_http3_handshake, increment_http3_packets_sent, increment_http3_streams,
+};
+
+/// Size of the buffer request body bytes are read into
+const BODY_READ_CHUNK: usize = 16 * 1024;
+/// Datagrams queued for a connection before further ones are dropped, as the network would
+pub const PACKET_QUEUE: usize = 256;
+/// Response writes in flight from request tasks to their connection
+const WRITE_QUEUE: usize = 64;
+/// Response bytes a connection holds back for flow control before request tasks must wait
+const MAX_PENDING_WRITE_BYTES: usize = 1024 * 1024;
+/// Application error code closing a connection that shut down cleanly
+const H3_NO_ERROR: u64 = WireErrorCode::NoError as u64;
+/// Stream error code for requests refused after GOAWAY, which clients may safely retry
+const H3_REQUEST_REJECTED: u64 = WireErrorCode::RequestRejected as u64;
+
+/// Request whose body is still arriving in DATA frames
+struct PendingRequest {
+    headers: Vec<H3Header>,
+    body: BytesMut,
+}
+
+/// Buffers a connection's request bodies per stream until the stream finishes
+struct RequestBodies {
+    max_size: usize,
+    pending: HashMap<u64, PendingRequest>,
+}
+
+impl RequestBodies {
+    fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            pending: HashMap::new(),
+        }
+    }
+
+    fn start(&mut self, stream_id: u64, headers: Vec<H3Header>) {
+        let request = PendingRequest {
+            headers,
+            body: BytesMut::new(),
+        };
+        self.pending.insert(stream_id, request);
+    }
+
+    /// Append a chunk of the body. Returns false, dropping the request, when the chunk takes
+    /// the body over `max_size`. Chunks of streams not being buffered are ignored.
+    fn extend(&mut self, stream_id: u64, chunk: &[u8]) -> bool {
+        let Some(request) = self.pending.get_mut(&stream_id) else {
+            return true;
+        };
+        if request.body.len() + chunk.len() > self.max_size {
+            self.pending.remove(&stream_id);
+            return false;
+        }
+        request.body.extend_from_slice(chunk);
+        true
+    }
+
+    /// The headers and complete body of a request whose stream finished
+    fn finish(&mut self, stream_id: u64) -> Option<(Vec<H3Header>, Bytes)> {
+        let request = self.pending.remove(&stream_id)?;
+        Some((request.headers, request.body.freeze()))
+    }
+
+    fn discard(&mut self, stream_id: u64) {
+        self.pending.remove(&stream_id);
+    }
+}
+
+/// Owns one QUIC connection: feeds it the datagrams routed to it, fires its timers, runs
+/// each request in a task of its own and writes their responses back. Connections make
+/// progress independently, so a slow backend only holds up its own streams.
+pub struct ConnectionTask {
+    conn_id: Vec<u8>,
+    quic_conn: Box<QuicConnection>,
+    peer_addr: SocketAddr,
+    socket: Arc<UdpSocket>,
+    connection_manager: Arc<ConnectionManager>,
+    handler: Arc<Http3Handler>,
+    request_bodies: RequestBodies,
+    /// Response writes per stream, waiting for flow control to let them through
+    pending_writes: HashMap<u64, VecDeque<StreamWrite>>,
+    pending_bytes: usize,
+    /// Turns true once the handshake completes, releasing requests deferred from early data
+    handshake: watch::Sender<bool>,
+    /// Streams whose request hasn't been answered and acknowledged yet
+    active_streams: HashSet<u64>,
+    /// Number of `active_streams` last reported to the connection manager
+    reported_active: usize,
+    /// Stream ID following the highest request accepted so far
+    next_stream_id: u64,
+    /// First stream ID refused, once GOAWAY is sent
+    goaway: Option<u64>,
+}
+
+impl ConnectionTask {
+    pub fn new(
+        conn_id: Vec<u8>,
+        quic_conn: Box<QuicConnection>,
+        peer_addr: SocketAddr,
+        socket: Arc<UdpSocket>,
+        connection_manager: Arc<ConnectionManager>,
+        handler: Arc<Http3Handler>,
+        max_request_body_size: usize,
+    ) -> Self {
+        Self {
+            conn_id,
+            quic_conn,
+            peer_addr,
+            socket,
+            connection_manager,
+            handler,
+            request_bodies: RequestBodies::new(max_request_body_size),
+            pending_writes: HashMap::new(),
+            pending_bytes: 0,
+            handshake: watch::channel(false).0,
+            active_streams: HashSet::new(),
+            reported_active: 0,
+            next_stream_id: 0,
+            goaway: None,
+        }
+    }
+
+    /// Drive the connection until it closes, then drop its route
+    pub async fn run(mut self, mut packets: mpsc::Receiver<Datagram>) {
+        let (writer, mut writes) = mpsc::channel(WRITE_QUEUE);
+        let mut drain = self.connection_manager.drain_state();
+
+        loop {
+            let timeout = self.quic_conn.connection().timeout();
+            tokio::select! {
+                packet = packets.recv() => match packet {
+                    Some(datagram) => {
+                        self.recv(datagram);
+                        // Take whatever else arrived meanwhile before answering
+                        while let Ok(datagram) = packets.try_recv() {
+                            self.recv(datagram);
+                        }
+                    }
+                    None => break,
+                },
+                // Loss recovery, idle timeout and draining
+                _ = tokio::time::sleep(timeout.unwrap_or(Duration::MAX)), if timeout.is_some() => {
+                    self.quic_conn.connection().on_timeout();
+                }
+                Some(write) = writes.recv(), if self.pending_bytes < MAX_PENDING_WRITE_BYTES => {
+                    self.queue_write(write);
+                    while self.pending_bytes < MAX_PENDING_WRITE_BYTES
+                        && let Ok(write) = writes.try_recv()
+                    {
+                        self.queue_write(write);
+                    }
+                }
+                Ok(()) = drain.changed() => {}
+            }
+
+            if !*self.handshake.borrow() && self.quic_conn.connection().is_established() {
+                self.handshake.send_replace(true);
+                increment_http3_handshake(true);
+            }
+
+            self.handle_events(&writer);
+            self.flush_writes();
+            self.update_active_streams();
+            let drain_state = *drain.borrow();
+            self.drain(drain_state);
+            // Handshake data, ACKs, responses and the CONNECTION_CLOSE for a packet quiche
+            // rejected all go out here
+            self.send_packets().await;
+
+            if self.quic_conn.connection().is_closed() {
+                break;
+            }
+        }
+
+        if !*self.handshake.borrow() {
+            increment_http3_handshake(false);
+        }
+        self.connection_manager
+            .update_active_requests(self.reported_active, 0);
+        let connection = self.quic_conn.connection();
+        tracing::debug!(
+            "Evicting closed QUIC connection {} (timed out: {}, peer error: {:?})",
+            conn_id_hex(&self.conn_id),
+            connection.is_timed_out(),
+            connection.peer_error()
+        );
+        self.connection_manager.remove(&self.conn_id);
+    }
+
+    fn recv(&mut self, (mut packet, recv_info): Datagram) {
+        if let Err(e) = self.quic_conn.connection().recv(&mut packet, recv_info) {
+            tracing::debug!(
+                "Failed to process QUIC packet from {}: {}",
+                self.peer_addr,
+                e
+            );
+        }
+    }
+
+    fn handle_events(&mut self, writer: &ResponseWriter) {
+        let connection = self.quic_conn.connection();
+        if connection.is_established() || connection.is_in_early_data() {
+            let h3_config = self.connection_manager.h3_config();
+            if let Err(e) = self.quic_conn.establish_h3(h3_config) {
+                tracing::error!("{:#}", e);
+                let _ = self
+                    .quic_conn
+                    .connection()
+                    .close(true, 0x1, b"internal error");
+                return;
+            }
+        }
+
+        // Reading a body can surface further events, such as the stream finishing, so poll
+        // until there are none left
+        loop {
+            let events = match self.quic_conn.poll_h3_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    tracing::warn!("Closing HTTP/3 connection from {}: {}", self.peer_addr, e);
+                    let _ = self.quic_conn.connection().close(true, 0x1, b"");
+                    return;
+                }
+            };
+            if events.is_empty() {
+                return;
+            }
+
+            for (stream_id, event) in events {
+                self.handle_event(stream_id, event, writer);
+            }
+        }
+    }
+
+    fn handle_event(&mut self, stream_id: u64, event: H3Event, writer: &ResponseWriter) {
+        match event {
+            H3Event::Headers { list, more_frames } => {
+                tracing::debug!(
+                    "Received headers on stream {}, more_frames: {}",
+                    stream_id,
+                    more_frames
+                );
+                increment_http3_streams();
+
+                if self.goaway.is_some_and(|goaway| stream_id >= goaway) {
+                    // Opened before the client saw our GOAWAY; it may retry elsewhere
+                    self.reject_stream(stream_id);
+                    return;
+                }
+                self.next_stream_id = self.next_stream_id.max(stream_id + 4);
+                self.active_streams.insert(stream_id);
+
+                if more_frames {
+                    // The body follows in DATA frames; the request goes out once it's complete
+                    self.request_bodies.start(stream_id, list);
+                } else {
+                    self.spawn_request(stream_id, list, None, writer);
+                }
+            }
+            H3Event::Data => {
+                tracing::debug!("Received data on stream {}", stream_id);
+
+                let mut buffer = vec![0; BODY_READ_CHUNK];
+                loop {
+                    let read = match self.quic_conn.recv_body(stream_id, &mut buffer) {
+                        Ok(Some(read)) => read,
+                        Ok(None) => break,
+                        Err(e) => {
+                            tracing::error!("Error handling HTTP/3 event: {}", e);
+                            break;
+                        }
+                    };
+                    if !self.request_bodies.extend(stream_id, &buffer[..read]) {
+                        tracing::warn!(
+                            "Request body on stream {} exceeds max_request_body_size ({} bytes)",
+                            stream_id,
+                            self.request_bodies.max_size
+                        );
+                        let handler = self.handler.clone();
+                        let writer = writer.clone();
+                        tokio::spawn(async move {
+                            let status = StatusCode::PAYLOAD_TOO_LARGE;
+                            if let Err(e) = handler.send_error(&writer, stream_id, status).await {
+                                tracing::debug!("Failed to refuse HTTP/3 request: {}", e);
+                            }
+                        });
+                    }
+                }
+            }
+            H3Event::Finished => {
+                tracing::debug!("Stream {} finished", stream_id);
+
+                if let Some((headers, body)) = self.request_bodies.finish(stream_id) {
+                    self.spawn_request(stream_id, headers, Some(body), writer);
+                }
+            }
+            H3Event::Reset(error_code) => {
+                tracing::warn!("Stream {} reset with error code: {}", stream_id, error_code);
+                self.request_bodies.discard(stream_id);
+                self.active_streams.remove(&stream_id);
+                self.pending_writes.remove(&stream_id);
+                self.update_pending_bytes();
+            }
+            H3Event::PriorityUpdate => {
+                tracing::debug!("Received priority update on stream {}", stream_id);
+            }
+            H3Event::GoAway => {
+                tracing::info!("Received GOAWAY");
+            }
+        }
+    }
+
+    fn reject_stream(&mut self, stream_id: u64) {
+        let connection = self.quic_conn.connection();
+        for direction in [quiche::Shutdown::Read, quiche::Shutdown::Write] {
+            let _ = connection.stream_shutdown(stream_id, direction, H3_REQUEST_REJECTED);
+        }
+    }
+
+    /// Forget requests quiche is done with and report the in-flight count
+    fn update_active_streams(&mut self) {
+        let quic_conn = &mut self.quic_conn;
+        self.active_streams
+            .retain(|&stream_id| !quic_conn.stream_complete(stream_id));
+
+        let active = self.active_streams.len();
+        self.connection_manager
+            .update_active_requests(self.reported_active, active);
+        self.reported_active = active;
+    }
+
+    /// Follow the server's shutdown: send GOAWAY, then close once the requests already
+    /// accepted are answered, or right away when the drain timed out
+    fn drain(&mut self, state: DrainState) {
+        let connection = self.quic_conn.connection();
+        if state == DrainState::Serving || connection.is_draining() || connection.is_closed() {
+            return;
+        }
+
+        if self.goaway.is_none() {
+            match self.quic_conn.send_goaway(self.next_stream_id) {
+                Ok(()) => self.goaway = Some(self.next_stream_id),
+                // Nothing to wait for before HTTP/3 is up
+                Err(e) => tracing::debug!("Closing connection from {}: {:#}", self.peer_addr, e),
+            }
+        }
+
+        if state == DrainState::Closing || self.goaway.is_none() || self.active_streams.is_empty() {
+            let _ = self.quic_conn.connection().close(true, H3_NO_ERROR, b"");
+        }
+    }
+
+    /// Run a request in its own task; its response comes back through `writer`
+    fn spawn_request(
+        &mut self,
+        stream_id: u64,
+        headers: Vec<H3Header>,
+        body: Option<Bytes>,
+        writer: &ResponseWriter,
+    ) {
+        let handler = self.handler.clone();
+        let writer = writer.clone();
+        let peer_addr = self.peer_addr;
+        // Sent before the handshake completed, so possibly a replay
+        let early_data = self
+            .quic_conn
+            .connection()
+            .is_in_early_data()
+            .then(|| EarlyData::new(self.handshake.subscribe()));
+        tokio::spawn(async move {
+            if let Err(e) = handler
+                .handle_h3_request(&writer, stream_id, peer_addr, headers, body, early_data)
+                .await
+            {
+                tracing::error!("Error handling HTTP/3 request: {}", e);
+            }
+        });
+    }
+
+    fn queue_write(&mut self, write: StreamWrite) {
+        self.pending_bytes += write.pending_bytes();
+        self.pending_writes
+            .entry(write.stream_id())
+            .or_default()
+            .push_back(write);
+    }
+
+    /// Write queued responses, stream by stream in order, as far as flow control allows
+    fn flush_writes(&mut self) {
+        for (&stream_id, queue) in &mut self.pending_writes {
+            while let Some(write) = queue.front_mut() {
+                let written = match write {
+                    StreamWrite::Headers { headers, fin, .. } => {
+                        self.quic_conn.send_headers(stream_id, headers, *fin)
+                    }
+                    StreamWrite::Body { data, fin, .. } => {
+                        match self.quic_conn.send_body(stream_id, data, *fin) {
+                            Ok(Some(written)) if written == data.len() => Ok(true),
+                            Ok(Some(written)) => {
+                                data.advance(written);
+                                Ok(false)
+                            }
+                            Ok(None) => Ok(false),
+                            Err(e) => Err(e),
+                        }
+                    }
+                };
+                match written {
+                    Ok(true) => {
+                        queue.pop_front();
+                    }
+                    Ok(false) => break,
+                    Err(e) => {
+                        // The stream is gone, e.g. stopped by the client
+                        tracing::debug!("Dropping response on stream {}: {}", stream_id, e);
+                        queue.clear();
+                    }
+                }
+            }
+        }
+        self.pending_writes.retain(|_, queue| !queue.is_empty());
+        self.update_pending_bytes();
+    }
+
+    fn update_pending_bytes(&mut self) {
+        self.pending_bytes = self
+            .pending_writes
+            .values()
+            .flatten()
+            .map(StreamWrite::pending_bytes)
+            .sum();
+    }
+
+    async fn send_packets(&mut self) {
+        let packets = self.quic_conn.drain_packets();
+        increment_http3_packets_sent(packets.len() as u64);
+        for (packet, send_info) in packets {
+            if let Err(e) = self.socket.send_to(&packet, send_info.to).await {
+                tracing::warn!("Failed to send QUIC packet to {}: {}", send_info.to, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_bodies() {
+        let mut bodies = RequestBodies::new(8);
+        let headers = vec![quiche::h3::Header::new(b":method", b"POST")];
+
+        bodies.start(0, headers.clone());
+        assert!(bodies.extend(0, b"{\"a\":"));
+        assert!(bodies.extend(0, b"1}"));
+        let (finished_headers, body) = bodies.finish(0).unwrap();
+        assert_eq!(finished_headers, headers);
+        assert_eq!(body, "{\"a\":1}");
+        assert!(bodies.finish(0).is_none());
+
+        // Going over the limit drops the request; the rest of its body is ignored
+        bodies.start(4, headers);
+        assert!(bodies.extend(4, b"12345"));
+        assert!(!bodies.extend(4, b"6789"));
+        assert!(bodies.extend(4, b"more"));
+        assert!(bodies.finish(4).is_none());
+    }
+}
@@ -15,6 +15,10 @@ mod http3_tests {
             congestion_control: Http3CongestionControl::Cubic,
             enable_0rtt: true,
             max_packet_size: Some(1452),
+            max_request_body_size: 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         };
 
         assert_eq!(http3_config.max_data, 10_000_000);
@@ -48,6 +52,10 @@ mod http3_tests {
             congestion_control: Http3CongestionControl::Cubic,
             enable_0rtt: false,
             max_packet_size: Some(1200),
+            max_request_body_size: 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         };
 
         assert!(min_config.max_data >= 1024);
@@ -63,6 +71,10 @@ mod http3_tests {
             congestion_control: Http3CongestionControl::Reno,
             enable_0rtt: true,
             max_packet_size: Some(65535),
+            max_request_body_size: 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         };
 
         assert!(max_config.max_data <= 1_000_000_000);
@@ -115,6 +127,10 @@ mod http3_tests {
                 congestion_control: algorithm,
                 enable_0rtt: true,
                 max_packet_size: Some(1452),
+                max_request_body_size: 1024 * 1024,
+                retry: false,
+                qlog_dir: None,
+                qlog_max_files: 100,
             };
 
             match config.congestion_control {
@@ -134,6 +150,10 @@ mod http3_tests {
             congestion_control: Http3CongestionControl::Cubic,
             enable_0rtt: false,
             max_packet_size: None,
+            max_request_body_size: 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         };
 
         assert!(config_without_max_packet.max_packet_size.is_none());
@@ -147,6 +167,10 @@ mod http3_tests {
             congestion_control: Http3CongestionControl::Cubic,
             enable_0rtt: true,
             max_packet_size: Some(1500),
+            max_request_body_size: 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         };
 
         assert!(config_with_max_packet.max_packet_size.is_some());
@@ -167,6 +191,10 @@ mod http3_tests {
                 congestion_control: Http3CongestionControl::Cubic,
                 enable_0rtt: true,
                 max_packet_size: Some(1452),
+                max_request_body_size: 1024 * 1024,
+                retry: false,
+                qlog_dir: None,
+                qlog_max_files: 100,
             }
         }
 
@@ -188,6 +216,10 @@ mod http3_tests {
                 congestion_control: Http3CongestionControl::Reno,
                 enable_0rtt: false,
                 max_packet_size: None,
+                max_request_body_size: 1024 * 1024,
+                retry: false,
+                qlog_dir: None,
+                qlog_max_files: 100,
             }
         }
     }
@@ -217,6 +249,10 @@ mod http3_tests {
             congestion_control: Http3CongestionControl::Cubic,
             enable_0rtt: true,
             max_packet_size: Some(1452),
+            max_request_body_size: 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         };
 
         assert_eq!(zero_timeout_config.max_idle_timeout, 0);
@@ -230,6 +266,10 @@ mod http3_tests {
             congestion_control: Http3CongestionControl::Cubic,
             enable_0rtt: true,
             max_packet_size: Some(65535), // Maximum UDP packet size
+            max_request_body_size: 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         };
 
         assert_eq!(large_packet_config.max_packet_size.unwrap(), 65535);
@@ -1,7 +1,8 @@
 use axum::body::Body as AxumBody;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use http_body_util::{BodyExt, Full};
-use hyper::{Request, Response, Version, header, header::HeaderValue};
+use hyper::body::Incoming;
+use hyper::{HeaderMap, Method, Request, Response, Version, header, header::HeaderValue};
 use hyper_util::client::legacy::Client;
 use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::rt::TokioExecutor;
@@ -12,8 +13,21 @@ use tokio::time::timeout;
 use hyper_rustls::HttpsConnector;
 use rustls_native_certs::load_native_certs;
 
+use crate::config::TracingMode;
+use crate::core::router::MatchedRoute;
 use crate::metrics::{BackendRequestTimer, increment_backend_request_total};
-use crate::ports::http_client::{HttpClient, HttpClientError, HttpClientResult}; // Added
+use crate::ports::http_client::{
+    HealthCheckResponse, HttpClient, HttpClientError, HttpClientResult,
+};
+use crate::tracing_setup;
+use crate::utils::log_redaction::RedactedHeaders;
+
+/// How long a backend may take to answer a WebSocket handshake
+const UPGRADE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Bytes of a health check response body kept for matching
+const MAX_HEALTH_CHECK_BODY: usize = 64 * 1024;
+/// Most of a health check response read before giving up on reusing its connection
+const MAX_HEALTH_CHECK_DRAIN: usize = 1024 * 1024;
 
 /// Custom error type for HTTP client operations
 #[derive(Error, Debug)]
@@ -21,6 +35,12 @@ pub enum HyperClientError {
     #[error("HTTP request error: {0}")]
     RequestError(String),
 
+    #[error("Connection refused: {0}")]
+    ConnectionRefused(String),
+
+    #[error("TLS handshake failed: {0}")]
+    Tls(String),
+
     #[error("Request timeout after {0} seconds")]
     Timeout(u64),
 
@@ -41,6 +61,8 @@ impl From<HyperClientError> for HttpClientError {
     fn from(err: HyperClientError) -> Self {
         match err {
             HyperClientError::RequestError(e) => HttpClientError::ConnectionError(e.to_string()),
+            HyperClientError::ConnectionRefused(e) => HttpClientError::ConnectionRefused(e),
+            HyperClientError::Tls(e) => HttpClientError::TlsError(e),
             HyperClientError::Timeout(secs) => HttpClientError::TimeoutError(secs),
             HyperClientError::InvalidRequest(e) => {
                 HttpClientError::InvalidRequestError(e.to_string())
@@ -49,12 +71,34 @@ impl From<HyperClientError> for HttpClientError {
                 HttpClientError::BackendError { url, status }
             }
             HyperClientError::TlsConfigError(e) => {
-                HttpClientError::ConnectionError(format!("TLS Config error: {e}"))
+                HttpClientError::TlsError(format!("TLS Config error: {e}"))
             }
         }
     }
 }
 
+/// Tell refused connections and TLS failures apart from other transport errors
+fn classify_request_error(
+    error: &hyper_util::client::legacy::Error,
+    message: String,
+) -> HyperClientError {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(err) = source {
+        if err.is::<rustls::Error>() {
+            return HyperClientError::Tls(message);
+        }
+        source = match err.downcast_ref::<std::io::Error>() {
+            Some(io_err) if io_err.kind() == std::io::ErrorKind::ConnectionRefused => {
+                return HyperClientError::ConnectionRefused(message);
+            }
+            // An io::Error's source skips the error it wraps, which is where rustls' is
+            Some(io_err) => io_err.get_ref().map(|inner| inner as _),
+            None => err.source(),
+        };
+    }
+    HyperClientError::RequestError(message)
+}
+
 pub struct HyperHttpClient {
     // Updated client type for HTTP/2 support
     client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
@@ -101,6 +145,49 @@ impl HyperHttpClient {
         Self { client }
     }
 
+    /// Send a WebSocket upgrade handshake. The response body is left unread so a
+    /// `101 Switching Protocols` can be taken over with `hyper::upgrade::on`.
+    pub async fn send_upgrade(
+        &self,
+        mut req: Request<Full<Bytes>>,
+    ) -> Result<Response<Incoming>, HyperClientError> {
+        tracing_setup::inject_trace_context(&tracing::Span::current(), req.headers_mut());
+        let backend_identifier = format!(
+            "{}://{}",
+            req.uri().scheme_str().unwrap_or("http"),
+            req.uri()
+                .authority()
+                .map_or_else(|| "unknown".to_string(), |a| a.to_string())
+        );
+        let request_path = req.uri().path().to_string();
+        let request_method = req.method().to_string();
+
+        match timeout(UPGRADE_TIMEOUT, self.client.request(req)).await {
+            Ok(Ok(response)) => {
+                increment_backend_request_total(
+                    &backend_identifier,
+                    &request_path,
+                    &request_method,
+                    response.status().as_u16(),
+                );
+                Ok(response)
+            }
+            Ok(Err(e)) => {
+                increment_backend_request_total(
+                    &backend_identifier,
+                    &request_path,
+                    &request_method,
+                    599,
+                );
+                Err(classify_request_error(
+                    &e,
+                    format!("Upgrade request to {backend_identifier}{request_path} failed: {e}"),
+                ))
+            }
+            Err(_) => Err(HyperClientError::Timeout(UPGRADE_TIMEOUT.as_secs())),
+        }
+    }
+
     fn add_common_headers(req: &mut Request<AxumBody>) {
         let headers = req.headers_mut();
         if !headers.contains_key(header::USER_AGENT) {
@@ -152,19 +239,25 @@ impl HttpClient for HyperHttpClient {
         let request_path = req.uri().path().to_string();
         let request_method = req.method().to_string();
 
-        // Create a tracing span for the backend request
-        let span = tracing::info_span!(
-            "backend_request",
-            backend.url = %backend_identifier,
-            http.method = %request_method,
-            http.path = %request_path,
-            http.status_code = tracing::field::Empty,
-        );
+        // Create a tracing span for the backend request, unless the route keeps tracing
+        // to its `http_request` span or turns it off
+        let span = match req.extensions().get::<TracingMode>() {
+            Some(TracingMode::Off | TracingMode::Minimal) => tracing::Span::current(),
+            _ => tracing::info_span!(
+                "backend_request",
+                backend.url = %backend_identifier,
+                http.method = %request_method,
+                http.path = %request_path,
+                http.status_code = tracing::field::Empty,
+            ),
+        };
         let _enter = span.enter();
+        // The backend continues the client's trace as a child of this span
+        tracing_setup::inject_trace_context(&span, req.headers_mut());
 
         // Start timer for backend request duration
-        let _backend_timer =
-            BackendRequestTimer::new(&backend_identifier, &request_path, &request_method);
+        let route = req.extensions().get::<MatchedRoute>().map(|r| r.0.clone());
+        let _backend_timer = BackendRequestTimer::new(&backend_identifier, route.as_deref());
 
         if let Some(host_str) = req.uri().host() {
             let host_header_val = if let Some(port) = req.uri().port() {
@@ -192,7 +285,10 @@ impl HttpClient for HyperHttpClient {
             parts.method,
             parts.uri
         );
-        tracing::debug!("Outgoing request headers: {:?}", parts.headers);
+        tracing::debug!(
+            "Outgoing request headers: {:?}",
+            RedactedHeaders(&parts.headers)
+        );
 
         let bytes = match axum_body.collect().await {
             Ok(collected) => collected.to_bytes(),
@@ -271,42 +367,56 @@ impl HttpClient for HyperHttpClient {
                     &request_method,
                     599, // Custom status code for client errors
                 );
-                Err(HyperClientError::RequestError(format!(
-                    "Request to {method_for_error_log} {uri_for_error_log} failed: {e}"
-                ))
+                Err(classify_request_error(
+                    &e,
+                    format!("Request to {method_for_error_log} {uri_for_error_log} failed: {e}"),
+                )
                 .into())
             }
         }
     }
 
-    async fn health_check(&self, url: &str, timeout_secs: u64) -> HttpClientResult<bool> {
+    async fn health_check(
+        &self,
+        url: &str,
+        method: Method,
+        headers: &HeaderMap,
+        timeout_secs: u64,
+    ) -> HttpClientResult<HealthCheckResponse> {
         let client = self.client.clone();
 
-        let request = Request::builder()
-            .method("HEAD")
+        let mut request = Request::builder()
+            .method(method)
             .uri(url)
             .version(Version::HTTP_11)
             .body(Full::new(Bytes::new()))
             .map_err(HyperClientError::InvalidRequest)?;
+        request.headers_mut().extend(headers.clone());
 
         tracing::debug!("Health checking URL: {} (Version set to HTTP/1.1)", url);
         let timeout_duration = Duration::from_secs(timeout_secs);
 
-        match timeout(timeout_duration, client.request(request)).await {
-            Ok(result) => match result {
-                Ok(response) => {
-                    let is_healthy = response.status().is_success();
-                    // Consume the body to prevent resource leaks
-                    let _ = response.into_body().collect().await;
-                    tracing::debug!("Health check for {} result: {}", url, is_healthy);
-                    Ok(is_healthy)
-                }
-                Err(err) => {
-                    tracing::debug!("Health check error for {}: {}", url, err);
-                    // Return Ok(false) for connection errors during health check, consistent with original logic.
-                    Ok(false)
-                }
-            },
+        let check = async {
+            let response = client
+                .request(request)
+                .await
+                .map_err(|e| HyperClientError::RequestError(e.to_string()))?;
+            let status = response.status();
+            let body = read_body_prefix(response.into_body(), MAX_HEALTH_CHECK_BODY)
+                .await
+                .map_err(|e| HyperClientError::RequestError(e.to_string()))?;
+            Ok::<_, HyperClientError>(HealthCheckResponse { status, body })
+        };
+
+        match timeout(timeout_duration, check).await {
+            Ok(Ok(response)) => {
+                tracing::debug!("Health check for {} answered {}", url, response.status);
+                Ok(response)
+            }
+            Ok(Err(err)) => {
+                tracing::debug!("Health check error for {}: {}", url, err);
+                Err(err.into())
+            }
             Err(_) => {
                 tracing::debug!("Health check timeout for {}", url);
                 Err(HttpClientError::from(HyperClientError::Timeout(
@@ -317,8 +427,55 @@ impl HttpClient for HyperHttpClient {
     }
 }
 
+/// Keep the first `limit` bytes of `body` and discard the rest, so the connection can go
+/// back to the pool. Bodies longer than `MAX_HEALTH_CHECK_DRAIN` are dropped unread
+/// instead, closing their connection.
+async fn read_body_prefix(mut body: Incoming, limit: usize) -> Result<Bytes, hyper::Error> {
+    let mut prefix = BytesMut::new();
+    let mut read = 0;
+    while read < MAX_HEALTH_CHECK_DRAIN {
+        let Some(frame) = body.frame().await else {
+            break;
+        };
+        if let Ok(data) = frame?.into_data() {
+            read += data.len();
+            let take = data.len().min(limit.saturating_sub(prefix.len()));
+            prefix.extend_from_slice(&data[..take]);
+        }
+    }
+    Ok(prefix.freeze())
+}
+
 impl Default for HyperHttpClient {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_health_check_reads_body_prefix() {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        let app = axum::Router::new().route(
+            "/health",
+            axum::routing::get(|| async { "x".repeat(200 * 1024) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = HyperHttpClient::new();
+        let url = format!("http://{addr}/health");
+        for _ in 0..2 {
+            let response = client
+                .health_check(&url, Method::GET, &HeaderMap::new(), 2)
+                .await
+                .unwrap();
+            assert_eq!(response.status, hyper::StatusCode::OK);
+            assert_eq!(response.body.len(), MAX_HEALTH_CHECK_BODY);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use axum::body::Body as AxumBody;
+use axum::response::Response as AxumResponse;
+use hyper::StatusCode;
+use hyper::header::{self, HeaderValue};
+use std::net::IpAddr;
+
+use crate::config::MaintenanceConfig;
+use crate::core::ip_range::IpRange;
+
+const DEFAULT_BODY: &str = "Service temporarily unavailable for maintenance";
+
+/// Whether requests from this client should bypass the maintenance page
+pub fn is_allowed(config: &MaintenanceConfig, client_ip: Option<IpAddr>) -> bool {
+    let Some(client_ip) = client_ip else {
+        return false;
+    };
+    config
+        .allow_ips
+        .iter()
+        .filter_map(|entry| entry.parse::<IpRange>().ok())
+        .any(|range| range.contains(client_ip))
+}
+
+/// The maintenance page for a request, or `None` if the route should be served normally
+pub async fn maintenance_response(
+    config: &MaintenanceConfig,
+    client_ip: Option<IpAddr>,
+) -> Option<AxumResponse> {
+    if !config.enabled || is_allowed(config, client_ip) {
+        return None;
+    }
+
+    let (body, content_type) = match (&config.body_file, &config.body) {
+        (Some(file), _) => match tokio::fs::read(file).await {
+            Ok(bytes) => {
+                let mime = mime_guess::from_path(file).first_or_octet_stream();
+                let content_type = if mime.type_() == mime_guess::mime::TEXT {
+                    format!("{mime}; charset=utf-8")
+                } else {
+                    mime.to_string()
+                };
+                (bytes, content_type)
+            }
+            Err(e) => {
+                tracing::error!("Failed to read maintenance page '{}': {}", file, e);
+                (
+                    DEFAULT_BODY.as_bytes().to_vec(),
+                    "text/plain; charset=utf-8".to_string(),
+                )
+            }
+        },
+        (None, Some(body)) => (
+            body.clone().into_bytes(),
+            "text/html; charset=utf-8".to_string(),
+        ),
+        (None, None) => (
+            DEFAULT_BODY.as_bytes().to_vec(),
+            "text/plain; charset=utf-8".to_string(),
+        ),
+    };
+    let content_type = config.content_type.clone().unwrap_or(content_type);
+
+    let mut response = AxumResponse::new(AxumBody::from(body));
+    *response.status_mut() =
+        StatusCode::from_u16(config.status).unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&content_type) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    if let Some(retry_after) = &config.retry_after {
+        match humantime::parse_duration(retry_after) {
+            Ok(duration) => {
+                headers.insert(header::RETRY_AFTER, HeaderValue::from(duration.as_secs()));
+            }
+            Err(e) => tracing::warn!("Invalid maintenance retry_after '{}': {}", retry_after, e),
+        }
+    }
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn test_maintenance_page_and_allowlist() {
+        let dir = tempfile::tempdir().unwrap();
+        let page = dir.path().join("maintenance.html");
+        std::fs::write(&page, "<h1>Back soon</h1>").unwrap();
+        let config = MaintenanceConfig {
+            body_file: Some(page.to_string_lossy().into_owned()),
+            retry_after: Some("10m".to_string()),
+            allow_ips: vec!["10.0.0.0/8".to_string(), "192.168.1.5".to_string()],
+            ..Default::default()
+        };
+
+        let response = maintenance_response(&config, "203.0.113.9".parse().ok())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "600");
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"<h1>Back soon</h1>");
+
+        assert!(
+            maintenance_response(&config, "10.20.30.40".parse().ok())
+                .await
+                .is_none()
+        );
+        assert!(
+            maintenance_response(&config, "192.168.1.5".parse().ok())
+                .await
+                .is_none()
+        );
+
+        let disabled = MaintenanceConfig {
+            enabled: false,
+            ..config
+        };
+        assert!(maintenance_response(&disabled, None).await.is_none());
+    }
+}
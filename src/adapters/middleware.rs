@@ -12,23 +12,27 @@ pub async fn add_alt_svc_header(
 ) -> Response {
     let mut response = next.run(req).await;
 
-    // Check if HTTP/3 is enabled in the configuration
-    let should_add_alt_svc = {
+    // Check if HTTP/3 is enabled in the configuration, and on which UDP port
+    let http3_port = {
         match config_holder.read() {
-            Ok(config) => config.protocols.http3_enabled && config.tls.is_some(),
+            Ok(config) if config.protocols.http3_enabled && config.tls.is_some() => {
+                config.http3_listen_addr().ok().map(|addr| addr.port())
+            }
+            Ok(_) => None,
             Err(e) => {
                 tracing::warn!(
                     "Failed to acquire config read lock for Alt-Svc header: {}",
                     e
                 );
-                false
+                None
             }
         }
     };
 
-    if should_add_alt_svc {
+    if let Some(port) = http3_port
+        && let Ok(header_value) = HeaderValue::from_str(&format!("h3=\":{port}\"; ma=3600"))
+    {
         // Add Alt-Svc header to advertise HTTP/3 support
-        let header_value = HeaderValue::from_static("h3=\":443\"; ma=3600");
         response.headers_mut().insert("alt-svc", header_value);
     }
 
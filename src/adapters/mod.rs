@@ -1,9 +1,20 @@
+pub mod access_log;
 pub mod acme;
+pub mod admin_auth;
+pub mod debug_capture;
+pub mod dns;
+pub mod early_data;
+pub mod error_pages;
 pub mod file_system;
 pub mod health_checker;
 pub mod http;
 pub mod http3;
 pub mod http_client;
 pub mod http_handler;
+pub mod maintenance;
 pub mod middleware;
+pub mod ocsp;
+pub mod static_cache;
+pub mod tls;
 pub mod unified_server;
+pub mod websocket;
@@ -0,0 +1,457 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use aws_lc_rs::digest;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use http_body_util::{BodyExt, Full};
+use hyper::{Request, StatusCode, header};
+use tracing::{info, warn};
+
+use crate::adapters::tls::{self, CertificateReloader};
+use crate::metrics;
+use crate::utils::x509;
+
+/// How often the staples are checked for a refresh
+const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Lifetime assumed for responses without a nextUpdate
+const DEFAULT_REFRESH: chrono::Duration = chrono::Duration::hours(12);
+/// Time allowed for one request to the responder
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const ENUMERATED: u8 = 0x0a;
+/// id-sha1 (1.3.14.3.2.26), the hash responders expect in a CertID
+const SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+/// id-pkix-ocsp-basic (1.3.6.1.5.5.7.48.1.1)
+const OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+
+/// An OCSP response about one certificate, sent to clients during the handshake
+#[derive(Debug, Clone)]
+pub struct OcspStaple {
+    /// Serial number of the certificate the response is about
+    pub serial_number: Vec<u8>,
+    /// The DER-encoded OCSPResponse
+    pub response: Vec<u8>,
+    pub this_update: DateTime<Utc>,
+    pub next_update: Option<DateTime<Utc>>,
+}
+
+impl OcspStaple {
+    /// Whether the response is about `cert` (DER) and still current
+    pub fn is_valid_for(&self, cert: &[u8]) -> bool {
+        !self.is_expired()
+            && x509::parse(cert).is_some_and(|cert| cert.serial_number == self.serial_number)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.next_update.is_some_and(|next| next <= Utc::now())
+    }
+
+    /// Half way through the validity window, leaving time to retry failed fetches
+    fn refresh_at(&self) -> DateTime<Utc> {
+        match self.next_update {
+            Some(next) => self.this_update + (next - self.this_update) / 2,
+            None => self.this_update + DEFAULT_REFRESH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertStatus {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+/// The entry of an OCSP response about one certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OcspResponse {
+    pub status: CertStatus,
+    pub this_update: DateTime<Utc>,
+    pub next_update: Option<DateTime<Utc>>,
+}
+
+/// Encode one DER element
+pub(crate) fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+    let len = contents.len();
+    if len < 0x80 {
+        element.push(len as u8);
+    } else {
+        let octets = len.to_be_bytes();
+        let octets = &octets[octets.iter().take_while(|octet| **octet == 0).count()..];
+        element.push(0x80 | octets.len() as u8);
+        element.extend_from_slice(octets);
+    }
+    element.extend_from_slice(contents);
+    element
+}
+
+/// CertID of `cert` issued by `issuer` (both DER)
+fn cert_id(cert: &[u8], issuer: &[u8]) -> Option<Vec<u8>> {
+    let sha1 = |data: &[u8]| digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, data);
+    let serial_number = x509::parse(cert)?.serial_number;
+    let algorithm = der(
+        SEQUENCE,
+        &[der(OBJECT_IDENTIFIER, SHA1), der(NULL, &[])].concat(),
+    );
+    Some(der(
+        SEQUENCE,
+        &[
+            algorithm,
+            der(OCTET_STRING, sha1(x509::issuer_name(cert)?).as_ref()),
+            der(OCTET_STRING, sha1(x509::public_key(issuer)?).as_ref()),
+            der(INTEGER, &serial_number),
+        ]
+        .concat(),
+    ))
+}
+
+/// DER-encoded OCSPRequest asking about `cert` issued by `issuer`
+pub fn request(cert: &[u8], issuer: &[u8]) -> Option<Vec<u8>> {
+    let request = der(SEQUENCE, &cert_id(cert, issuer)?);
+    let request_list = der(SEQUENCE, &request);
+    let tbs_request = der(SEQUENCE, &request_list);
+    Some(der(SEQUENCE, &tbs_request))
+}
+
+/// The responseStatus and, for a successful response, the entry about `serial_number`
+fn read_response(der: &[u8], serial_number: &[u8]) -> Option<(u8, Option<OcspResponse>)> {
+    let (response, _) = x509::expect(der, SEQUENCE)?;
+    let (status, rest) = x509::expect(response, ENUMERATED)?;
+    let status = *status.first()?;
+    if status != 0 {
+        return Some((status, None));
+    }
+    let (response_bytes, _) = x509::expect(rest, 0xa0)?;
+    let (response_bytes, _) = x509::expect(response_bytes, SEQUENCE)?;
+    let (response_type, rest) = x509::expect(response_bytes, OBJECT_IDENTIFIER)?;
+    if response_type != OCSP_BASIC {
+        return None;
+    }
+    let (basic, _) = x509::expect(rest, OCTET_STRING)?;
+    let (basic, _) = x509::expect(basic, SEQUENCE)?;
+    let (data, _) = x509::expect(basic, SEQUENCE)?;
+    let mut rest = data;
+    // Optional version, then responderID and producedAt
+    if let (0xa0, _, remaining) = x509::read_element(rest)? {
+        rest = remaining;
+    }
+    let (_, _, rest) = x509::read_element(rest)?;
+    let (_, _, rest) = x509::read_element(rest)?;
+    let (mut responses, _) = x509::expect(rest, SEQUENCE)?;
+
+    while !responses.is_empty() {
+        let (single, remaining) = x509::expect(responses, SEQUENCE)?;
+        responses = remaining;
+        let (cert_id, rest) = x509::expect(single, SEQUENCE)?;
+        // hashAlgorithm, issuerNameHash and issuerKeyHash come before the serial
+        let (_, _, id_fields) = x509::read_element(cert_id)?;
+        let (_, _, id_fields) = x509::read_element(id_fields)?;
+        let (_, _, id_fields) = x509::read_element(id_fields)?;
+        let (serial, _) = x509::expect(id_fields, INTEGER)?;
+        if serial != serial_number {
+            continue;
+        }
+
+        let (tag, _, rest) = x509::read_element(rest)?;
+        let status = match tag {
+            0x80 => CertStatus::Good,
+            0xa1 => CertStatus::Revoked,
+            _ => CertStatus::Unknown,
+        };
+        let (tag, this_update, rest) = x509::read_element(rest)?;
+        let this_update = x509::parse_time(tag, this_update)?;
+        let next_update = match x509::read_element(rest) {
+            Some((0xa0, next_update, _)) => {
+                let (tag, next_update, _) = x509::read_element(next_update)?;
+                Some(x509::parse_time(tag, next_update)?)
+            }
+            _ => None,
+        };
+        return Some((
+            0,
+            Some(OcspResponse {
+                status,
+                this_update,
+                next_update,
+            }),
+        ));
+    }
+    Some((0, None))
+}
+
+/// Parse a DER OCSPResponse and return its entry about `serial_number`. The signature is
+/// not checked: clients verify the staple themselves.
+pub fn parse_response(der: &[u8], serial_number: &[u8]) -> Result<OcspResponse> {
+    match read_response(der, serial_number) {
+        Some((0, Some(response))) => Ok(response),
+        Some((0, None)) => bail!("OCSP response does not cover the certificate"),
+        Some((status, _)) => {
+            let reason = match status {
+                1 => "malformedRequest",
+                2 => "internalError",
+                3 => "tryLater",
+                5 => "sigRequired",
+                6 => "unauthorized",
+                _ => "unknown status",
+            };
+            bail!("OCSP responder returned {reason} ({status})")
+        }
+        None => bail!("Malformed OCSP response"),
+    }
+}
+
+/// Keeps an OCSP staple for every certificate of a listener, refreshing them in the
+/// background. A certificate whose staple can't be fetched is served without one.
+pub struct OcspStapler {
+    reloader: CertificateReloader,
+    /// Responder used instead of the one named in each certificate
+    responder: Option<String>,
+    client: tls::HttpsClient,
+}
+
+impl OcspStapler {
+    pub fn new(reloader: CertificateReloader, responder: Option<String>) -> Result<Self> {
+        Ok(Self {
+            reloader,
+            responder,
+            client: tls::https_client()?,
+        })
+    }
+
+    /// POST an OCSP request and return the response body
+    async fn post(&self, url: &str, body: Vec<u8>) -> Result<Vec<u8>> {
+        let request = Request::post(url)
+            .header(header::CONTENT_TYPE, "application/ocsp-request")
+            .body(Full::new(Bytes::from(body)))?;
+        let response = self
+            .client
+            .request(request)
+            .await
+            .with_context(|| format!("OCSP request to {url} failed"))?;
+        if response.status() != StatusCode::OK {
+            bail!("OCSP responder {url} returned {}", response.status());
+        }
+        Ok(response.into_body().collect().await?.to_bytes().to_vec())
+    }
+
+    /// Fetch a staple for the leaf certificate of `cert_path`, which must be followed by
+    /// its issuer in the same file
+    async fn fetch(&self, cert_path: &str) -> Result<OcspStaple> {
+        let file = File::open(cert_path)
+            .with_context(|| format!("Failed to open certificate {cert_path}"))?;
+        let chain = rustls_pemfile::certs(&mut BufReader::new(file))
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Failed to parse certificate {cert_path}"))?;
+        let (leaf, intermediates) = chain
+            .split_first()
+            .ok_or_else(|| anyhow!("No certificate found in {cert_path}"))?;
+        let certificate =
+            x509::parse(leaf).ok_or_else(|| anyhow!("Failed to parse certificate {cert_path}"))?;
+        let issuer_name = x509::issuer_name(leaf);
+        let issuer = intermediates
+            .iter()
+            .find(|cert| issuer_name.is_some() && x509::subject_name(cert) == issuer_name)
+            .ok_or_else(|| anyhow!("{cert_path} does not include its issuer certificate"))?;
+        let url = self
+            .responder
+            .clone()
+            .or(certificate.ocsp_url)
+            .ok_or_else(|| anyhow!("{cert_path} names no OCSP responder"))?;
+        let body = request(leaf, issuer)
+            .ok_or_else(|| anyhow!("Failed to build the OCSP request for {cert_path}"))?;
+
+        let response = tokio::time::timeout(FETCH_TIMEOUT, self.post(&url, body))
+            .await
+            .map_err(|_| anyhow!("OCSP responder {url} timed out"))??;
+        let parsed = parse_response(&response, &certificate.serial_number)?;
+        if parsed.status != CertStatus::Good {
+            bail!(
+                "OCSP responder {url} reports {cert_path} as {:?}",
+                parsed.status
+            );
+        }
+        let staple = OcspStaple {
+            serial_number: certificate.serial_number,
+            response,
+            this_update: parsed.this_update,
+            next_update: parsed.next_update,
+        };
+        if staple.is_expired() {
+            bail!("OCSP responder {url} sent an outdated response");
+        }
+        Ok(staple)
+    }
+
+    /// Fetch the staples that are missing, due for a refresh or about a replaced
+    /// certificate, then reload the listener. Returns whether the served staples changed.
+    pub async fn refresh(&self) -> bool {
+        let mut changed = false;
+        for cert_path in self.reloader.certificate_paths() {
+            let current = self.reloader.staple(&cert_path);
+            let serial_number = x509::parse_pem_file(&cert_path).map(|c| c.serial_number);
+            let outdated = |staple: &OcspStaple| {
+                staple.is_expired() || Some(&staple.serial_number) != serial_number.as_ref()
+            };
+            let due = current
+                .as_ref()
+                .is_none_or(|staple| outdated(staple) || Utc::now() >= staple.refresh_at());
+
+            if due {
+                match self.fetch(&cert_path).await {
+                    Ok(staple) => {
+                        info!(
+                            "Fetched OCSP staple for {}, next update {}",
+                            cert_path,
+                            staple
+                                .next_update
+                                .map_or("unknown".to_string(), |t| t.to_rfc3339())
+                        );
+                        self.reloader.set_staple(&cert_path, Some(staple));
+                        changed = true;
+                    }
+                    Err(e) => {
+                        warn!("Failed to refresh OCSP staple for {cert_path}: {e:#}");
+                        // Better no staple than one clients would reject
+                        if current.as_ref().is_some_and(outdated) {
+                            self.reloader.set_staple(&cert_path, None);
+                            changed = true;
+                        }
+                    }
+                }
+            }
+
+            if let Some(staple) = self.reloader.staple(&cert_path) {
+                let age = (Utc::now() - staple.this_update)
+                    .to_std()
+                    .unwrap_or_default();
+                metrics::set_ocsp_staple_age(&cert_path, age);
+            }
+        }
+
+        if changed {
+            self.reloader.reload_or_log();
+        }
+        changed
+    }
+
+    /// Refresh the staples now and then every few minutes
+    pub fn spawn(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                self.refresh().await;
+                tokio::time::sleep(CHECK_INTERVAL).await;
+            }
+        })
+    }
+}
+
+/// A successful OCSP response reporting `serial_number` as good, with a dummy signature
+#[cfg(test)]
+pub(crate) fn good_response(
+    serial_number: &[u8],
+    this_update: DateTime<Utc>,
+    next_update: DateTime<Utc>,
+) -> Vec<u8> {
+    let time = |t: DateTime<Utc>| der(0x18, t.format("%Y%m%d%H%M%SZ").to_string().as_bytes());
+    let algorithm = der(
+        SEQUENCE,
+        &[der(OBJECT_IDENTIFIER, SHA1), der(NULL, &[])].concat(),
+    );
+    let cert_id = der(
+        SEQUENCE,
+        &[
+            algorithm.clone(),
+            der(OCTET_STRING, &[0; 20]),
+            der(OCTET_STRING, &[0; 20]),
+            der(INTEGER, serial_number),
+        ]
+        .concat(),
+    );
+    let single = der(
+        SEQUENCE,
+        &[
+            cert_id,
+            der(0x80, &[]),
+            time(this_update),
+            der(0xa0, &time(next_update)),
+        ]
+        .concat(),
+    );
+    let data = der(
+        SEQUENCE,
+        &[
+            der(0xa2, &der(OCTET_STRING, &[0; 20])),
+            time(this_update),
+            der(SEQUENCE, &single),
+        ]
+        .concat(),
+    );
+    let basic = der(SEQUENCE, &[data, algorithm, der(0x03, &[0, 0])].concat());
+    let response_bytes = der(
+        SEQUENCE,
+        &[
+            der(OBJECT_IDENTIFIER, OCSP_BASIC),
+            der(OCTET_STRING, &basic),
+        ]
+        .concat(),
+    );
+    der(
+        SEQUENCE,
+        &[der(ENUMERATED, &[0]), der(0xa0, &response_bytes)].concat(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_ocsp_request_and_response() {
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let mut ca_params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let leaf_key = rcgen::KeyPair::generate().unwrap();
+        let leaf = rcgen::CertificateParams::new(vec!["app.example.com".to_string()])
+            .unwrap()
+            .signed_by(&leaf_key, &ca, &ca_key)
+            .unwrap();
+        let serial_number = x509::parse(leaf.der()).unwrap().serial_number;
+
+        // The CertID carries the leaf serial and the hash of the issuer's key
+        let encoded = request(leaf.der(), ca.der()).unwrap();
+        let key_hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, ca_key.public_key_raw());
+        let contains = |part: &[u8]| encoded.windows(part.len()).any(|w| w == part);
+        assert!(contains(&der(OCTET_STRING, key_hash.as_ref())));
+        assert!(contains(&der(INTEGER, &serial_number)));
+        assert!(request(leaf.der(), b"not a certificate").is_none());
+
+        let this_update = Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap();
+        let next_update = Utc.with_ymd_and_hms(2026, 3, 8, 0, 0, 0).unwrap();
+        let response = good_response(&serial_number, this_update, next_update);
+        assert_eq!(
+            parse_response(&response, &serial_number).unwrap(),
+            OcspResponse {
+                status: CertStatus::Good,
+                this_update,
+                next_update: Some(next_update),
+            }
+        );
+        assert!(parse_response(&response, &[1]).is_err());
+        // tryLater
+        let message = parse_response(&der(SEQUENCE, &der(ENUMERATED, &[3])), &serial_number)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("tryLater"));
+    }
+}
@@ -0,0 +1,235 @@
+use bytes::Bytes;
+use hyper::header::HeaderValue;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::StaticCacheConfig;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(30);
+
+/// One representation of a cached file (identity or a precompressed sidecar)
+#[derive(Debug)]
+pub struct CachedVariant {
+    /// Content coding, `None` for the plain file
+    pub encoding: Option<&'static str>,
+    pub bytes: Bytes,
+    pub etag: HeaderValue,
+}
+
+/// A static file held in memory together with the metadata needed to serve it
+#[derive(Debug)]
+pub struct CachedFile {
+    /// Path relative to the route root, used to pick Cache-Control rules
+    pub uri_path: String,
+    /// Location on disk, re-checked when the entry's TTL expires
+    pub fs_path: PathBuf,
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+    pub content_type: HeaderValue,
+    pub last_modified: Option<HeaderValue>,
+    pub identity: CachedVariant,
+    pub encoded: Vec<CachedVariant>,
+}
+
+impl CachedFile {
+    fn size(&self) -> u64 {
+        self.identity.bytes.len() as u64
+            + self
+                .encoded
+                .iter()
+                .map(|variant| variant.bytes.len() as u64)
+                .sum::<u64>()
+    }
+}
+
+/// Result of looking up a path in the cache
+pub enum CacheLookup {
+    /// Within its TTL; serve without touching the filesystem
+    Fresh(Arc<CachedFile>),
+    /// TTL expired; serve only if the file's mtime and size are unchanged
+    Stale(Arc<CachedFile>),
+    Miss,
+}
+
+#[derive(Debug)]
+struct Slot {
+    file: Arc<CachedFile>,
+    expires_at: Instant,
+    last_access: Instant,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<String, Slot>,
+    total_bytes: u64,
+}
+
+/// Size-bounded, TTL-based cache of small static files for a single route
+#[derive(Debug)]
+pub struct StaticFileCache {
+    config: StaticCacheConfig,
+    ttl: Duration,
+    state: Mutex<CacheState>,
+}
+
+impl StaticFileCache {
+    pub fn new(config: StaticCacheConfig) -> Self {
+        let ttl = humantime::parse_duration(&config.ttl).unwrap_or_else(|e| {
+            tracing::warn!(
+                "Invalid static cache ttl '{}': {}. Using {:?}",
+                config.ttl,
+                e,
+                DEFAULT_TTL
+            );
+            DEFAULT_TTL
+        });
+        Self {
+            config,
+            ttl,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Whether a file of this size may be cached at all
+    pub fn accepts(&self, len: u64) -> bool {
+        len <= self.config.max_entry_size && len <= self.config.max_total_bytes
+    }
+
+    pub fn get(&self, key: &str) -> CacheLookup {
+        let Ok(mut state) = self.state.lock() else {
+            return CacheLookup::Miss;
+        };
+        let now = Instant::now();
+        match state.entries.get_mut(key) {
+            Some(slot) => {
+                slot.last_access = now;
+                if slot.expires_at > now {
+                    CacheLookup::Fresh(slot.file.clone())
+                } else {
+                    CacheLookup::Stale(slot.file.clone())
+                }
+            }
+            None => CacheLookup::Miss,
+        }
+    }
+
+    /// Give a stale entry a new TTL after confirming the file is unchanged
+    pub fn refresh(&self, key: &str) {
+        if let Ok(mut state) = self.state.lock()
+            && let Some(slot) = state.entries.get_mut(key)
+        {
+            slot.expires_at = Instant::now() + self.ttl;
+        }
+    }
+
+    pub fn remove(&self, key: &str) {
+        if let Ok(mut state) = self.state.lock()
+            && let Some(slot) = state.entries.remove(key)
+        {
+            state.total_bytes -= slot.file.size();
+        }
+    }
+
+    /// Insert an entry, evicting least recently used ones to stay under `max_total_bytes`
+    pub fn insert(&self, key: String, file: CachedFile) -> Arc<CachedFile> {
+        let file = Arc::new(file);
+        let size = file.size();
+        if size > self.config.max_total_bytes {
+            return file;
+        }
+        let Ok(mut state) = self.state.lock() else {
+            return file;
+        };
+
+        if let Some(previous) = state.entries.remove(&key) {
+            state.total_bytes -= previous.file.size();
+        }
+        while state.total_bytes + size > self.config.max_total_bytes {
+            let Some(oldest) = state
+                .entries
+                .iter()
+                .min_by_key(|(_, slot)| slot.last_access)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(evicted) = state.entries.remove(&oldest) {
+                state.total_bytes -= evicted.file.size();
+            }
+        }
+
+        let now = Instant::now();
+        state.total_bytes += size;
+        state.entries.insert(
+            key,
+            Slot {
+                file: file.clone(),
+                expires_at: now + self.ttl,
+                last_access: now,
+            },
+        );
+        file
+    }
+
+    #[cfg(test)]
+    pub fn total_bytes(&self) -> u64 {
+        self.state.lock().map(|s| s.total_bytes).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(len: usize) -> CachedFile {
+        CachedFile {
+            uri_path: "/f".to_string(),
+            fs_path: PathBuf::from("/f"),
+            modified: None,
+            len: len as u64,
+            content_type: HeaderValue::from_static("text/plain"),
+            last_modified: None,
+            identity: CachedVariant {
+                encoding: None,
+                bytes: Bytes::from(vec![0u8; len]),
+                etag: HeaderValue::from_static("\"e\""),
+            },
+            encoded: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_eviction_keeps_total_under_limit() {
+        let cache = StaticFileCache::new(StaticCacheConfig {
+            max_entry_size: 100,
+            max_total_bytes: 250,
+            ttl: "1m".to_string(),
+        });
+        cache.insert("a".to_string(), file(100));
+        cache.insert("b".to_string(), file(100));
+        // Touch "a" so "b" becomes the least recently used entry
+        assert!(matches!(cache.get("a"), CacheLookup::Fresh(_)));
+        cache.insert("c".to_string(), file(100));
+
+        assert_eq!(cache.total_bytes(), 200);
+        assert!(matches!(cache.get("a"), CacheLookup::Fresh(_)));
+        assert!(matches!(cache.get("b"), CacheLookup::Miss));
+        assert!(matches!(cache.get("c"), CacheLookup::Fresh(_)));
+        assert!(!cache.accepts(101));
+    }
+
+    #[test]
+    fn test_expired_entries_are_stale_until_refreshed() {
+        let cache = StaticFileCache::new(StaticCacheConfig {
+            ttl: "0s".to_string(),
+            ..Default::default()
+        });
+        cache.insert("a".to_string(), file(10));
+        assert!(matches!(cache.get("a"), CacheLookup::Stale(_)));
+        cache.remove("a");
+        assert!(matches!(cache.get("a"), CacheLookup::Miss));
+        assert_eq!(cache.total_bytes(), 0);
+    }
+}
@@ -0,0 +1,739 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use axum_server::tls_rustls::RustlsConfig;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use http_body_util::Full;
+use hyper_rustls::HttpsConnector;
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use hyper_util::rt::TokioExecutor;
+use notify::{RecursiveMode, Watcher};
+use rustls::crypto::CryptoProvider;
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+
+use tokio::sync::{broadcast, mpsc};
+
+use crate::adapters::acme::CertificateInfo;
+use crate::adapters::ocsp::OcspStaple;
+use crate::config::models::{AdminTlsConfig, ProtocolConfig, TlsCertificate};
+use crate::utils::x509;
+
+/// The process-wide crypto provider, or aws-lc-rs when none was installed
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()))
+}
+
+pub type HttpsClient = Client<HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+/// HTTP/1.1 client trusting the system roots, for the CA, DNS provider and OCSP APIs
+pub fn https_client() -> Result<HttpsClient> {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_provider_and_native_roots(crypto_provider())
+        .context("Failed to load native root certificates")?
+        .https_or_http()
+        .enable_http1()
+        .build();
+    Ok(Client::builder(TokioExecutor::new()).build(connector))
+}
+
+/// Load a PEM certificate chain and its private key, checking that they belong together
+pub fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey> {
+    let cert_file =
+        File::open(cert_path).with_context(|| format!("Failed to open certificate {cert_path}"))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(cert_file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificate {cert_path}"))?;
+    if certs.is_empty() {
+        bail!("No certificate found in {cert_path}");
+    }
+
+    let key_file =
+        File::open(key_path).with_context(|| format!("Failed to open private key {key_path}"))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(key_file))
+        .with_context(|| format!("Failed to parse private key {key_path}"))?
+        .ok_or_else(|| anyhow!("No private key found in {key_path}"))?;
+
+    CertifiedKey::from_der(certs, key, &crypto_provider())
+        .with_context(|| format!("Certificate {cert_path} does not match key {key_path}"))
+}
+
+/// TLS for the admin listener, requiring a client certificate issued by `client_ca_path`
+/// when one is given
+pub fn admin_server_config(tls: &AdminTlsConfig) -> Result<rustls::ServerConfig> {
+    let key = load_certified_key(&tls.cert_path, &tls.key_path)?;
+    let builder = rustls::ServerConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS protocol versions")?;
+    let builder = match &tls.client_ca_path {
+        Some(ca_path) => {
+            let ca_file =
+                File::open(ca_path).with_context(|| format!("Failed to open CA {ca_path}"))?;
+            let mut roots = rustls::RootCertStore::empty();
+            for cert in rustls_pemfile::certs(&mut BufReader::new(ca_file)) {
+                let cert = cert.with_context(|| format!("Failed to parse CA {ca_path}"))?;
+                roots
+                    .add(cert)
+                    .with_context(|| format!("Invalid CA certificate in {ca_path}"))?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), crypto_provider())
+                    .build()
+                    .with_context(|| format!("Failed to use {ca_path} for client certificates"))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder.with_cert_resolver(Arc::new(SniResolver::new(Some(key))));
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Picks the certificate for the SNI name of each handshake: exact names first, then
+/// wildcards (`*.example.com` covers one extra label), then the default certificate.
+/// rustls' `ResolvesServerCertUsingSni` supports neither wildcards nor a default.
+#[derive(Debug, Default)]
+pub struct SniResolver {
+    exact: HashMap<String, Arc<CertifiedKey>>,
+    /// Keyed by the parent domain of the wildcard, e.g. "example.com" for "*.example.com"
+    wildcard: HashMap<String, Arc<CertifiedKey>>,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+impl SniResolver {
+    pub fn new(default: Option<CertifiedKey>) -> Self {
+        Self {
+            default: default.map(Arc::new),
+            ..Default::default()
+        }
+    }
+
+    /// Serve `key` for each of `domains`
+    pub fn add(&mut self, domains: &[String], key: CertifiedKey) {
+        let key = Arc::new(key);
+        for domain in domains {
+            let domain = domain.to_ascii_lowercase();
+            match domain.strip_prefix("*.") {
+                Some(parent) => self.wildcard.insert(parent.to_string(), key.clone()),
+                None => self.exact.insert(domain, key.clone()),
+            };
+        }
+    }
+
+    fn lookup(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let name = server_name.map(|name| name.trim_end_matches('.').to_ascii_lowercase());
+        name.and_then(|name| {
+            self.exact.get(&name).or_else(|| {
+                name.split_once('.')
+                    .and_then(|(_, parent)| self.wildcard.get(parent))
+            })
+        })
+        .or(self.default.as_ref())
+        .cloned()
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.lookup(client_hello.server_name())
+    }
+}
+
+/// Load a certificate and attach its OCSP staple, if one is held for this certificate
+fn load_stapled_key(
+    cert_path: &str,
+    key_path: &str,
+    staples: &HashMap<String, OcspStaple>,
+) -> Result<CertifiedKey> {
+    let mut key = load_certified_key(cert_path, key_path)?;
+    if let Some(staple) = staples.get(cert_path)
+        && staple.is_valid_for(&key.cert[0])
+    {
+        key.ocsp = Some(staple.response.clone());
+    }
+    Ok(key)
+}
+
+/// ALPN protocols the listener offers, most preferred first. HTTP/1.1 is always served.
+pub fn alpn_protocols(protocols: &ProtocolConfig) -> Vec<Vec<u8>> {
+    let mut alpn = Vec::new();
+    if protocols.http2_enabled {
+        alpn.push(b"h2".to_vec());
+    }
+    alpn.push(b"http/1.1".to_vec());
+    alpn
+}
+
+/// Listener TLS configuration serving the default pair (if any) plus the SNI certificates,
+/// with ALPN offering the protocols enabled in `protocols`
+pub fn server_config(
+    default: Option<(&str, &str)>,
+    certificates: &[TlsCertificate],
+    protocols: &ProtocolConfig,
+) -> Result<rustls::ServerConfig> {
+    stapled_server_config(
+        default,
+        certificates,
+        alpn_protocols(protocols),
+        &HashMap::new(),
+    )
+}
+
+/// Like [`server_config`], stapling the OCSP responses in `staples` (keyed by certificate
+/// path) to the certificates they were issued for
+fn stapled_server_config(
+    default: Option<(&str, &str)>,
+    certificates: &[TlsCertificate],
+    alpn_protocols: Vec<Vec<u8>>,
+    staples: &HashMap<String, OcspStaple>,
+) -> Result<rustls::ServerConfig> {
+    let default = default
+        .map(|(cert_path, key_path)| load_stapled_key(cert_path, key_path, staples))
+        .transpose()?;
+    let mut resolver = SniResolver::new(default);
+    for certificate in certificates {
+        let key = load_stapled_key(&certificate.cert_path, &certificate.key_path, staples)?;
+        tracing::info!(
+            "Serving certificate {} for {:?}",
+            certificate.cert_path,
+            certificate.domains
+        );
+        resolver.add(&certificate.domains, key);
+    }
+
+    let mut config = rustls::ServerConfig::builder_with_provider(crypto_provider())
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS protocol versions")?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver));
+    config.alpn_protocols = alpn_protocols;
+    Ok(config)
+}
+
+/// Swaps the listener's TLS configuration when its certificate files change, so renewed
+/// certificates are served without a restart. Handshakes in flight keep the old config.
+#[derive(Clone)]
+pub struct CertificateReloader {
+    rustls_config: RustlsConfig,
+    /// Shared by all clones, since ACME renewals may move the default certificate
+    default_pair: Arc<Mutex<Option<(String, String)>>>,
+    certificates: Vec<TlsCertificate>,
+    /// Kept from the initial configuration, as protocol changes need a restart
+    alpn_protocols: Vec<Vec<u8>>,
+    /// Expiry of each loaded certificate file, for logging what a reload changed
+    expiries: Arc<Mutex<HashMap<String, Option<DateTime<Utc>>>>>,
+    /// OCSP responses to staple, keyed by certificate path
+    staples: Arc<Mutex<HashMap<String, OcspStaple>>>,
+}
+
+impl CertificateReloader {
+    pub fn new(
+        rustls_config: RustlsConfig,
+        default_pair: Option<(String, String)>,
+        certificates: Vec<TlsCertificate>,
+    ) -> Self {
+        let alpn_protocols = rustls_config.get_inner().alpn_protocols.clone();
+        let reloader = Self {
+            rustls_config,
+            default_pair: Arc::new(Mutex::new(default_pair)),
+            certificates,
+            alpn_protocols,
+            expiries: Arc::default(),
+            staples: Arc::default(),
+        };
+        *reloader.expiries.lock().unwrap() = reloader.read_expiries();
+        reloader
+    }
+
+    fn default_pair(&self) -> Option<(String, String)> {
+        self.default_pair.lock().unwrap().clone()
+    }
+
+    /// Every certificate and key pair the configuration is built from, default first
+    fn pairs(&self) -> Vec<(String, String)> {
+        self.default_pair()
+            .into_iter()
+            .chain(
+                self.certificates
+                    .iter()
+                    .map(|c| (c.cert_path.clone(), c.key_path.clone())),
+            )
+            .collect()
+    }
+
+    /// Every certificate and key file the configuration is built from
+    fn paths(&self) -> Vec<PathBuf> {
+        self.pairs()
+            .into_iter()
+            .flat_map(|(cert_path, key_path)| [PathBuf::from(cert_path), PathBuf::from(key_path)])
+            .collect()
+    }
+
+    /// Every certificate file currently served
+    pub fn certificate_paths(&self) -> Vec<String> {
+        self.pairs()
+            .into_iter()
+            .map(|(cert_path, _)| cert_path)
+            .collect()
+    }
+
+    fn read_expiries(&self) -> HashMap<String, Option<DateTime<Utc>>> {
+        self.certificate_paths()
+            .into_iter()
+            .map(|cert_path| {
+                let expiry = x509::parse_pem_file(&cert_path).map(|c| c.not_after);
+                (cert_path, expiry)
+            })
+            .collect()
+    }
+
+    /// The OCSP staple held for `cert_path`
+    pub fn staple(&self, cert_path: &str) -> Option<OcspStaple> {
+        self.staples.lock().unwrap().get(cert_path).cloned()
+    }
+
+    /// Replace or drop the OCSP staple of `cert_path`; takes effect on the next reload
+    pub fn set_staple(&self, cert_path: &str, staple: Option<OcspStaple>) {
+        let mut staples = self.staples.lock().unwrap();
+        match staple {
+            Some(staple) => staples.insert(cert_path.to_string(), staple),
+            None => staples.remove(cert_path),
+        };
+    }
+
+    /// Rebuild the TLS configuration from disk and swap it in. On failure (e.g. a key
+    /// written before its certificate) the current configuration keeps serving.
+    pub fn reload(&self) -> Result<()> {
+        let default_pair = self.default_pair();
+        let staples = self.staples.lock().unwrap().clone();
+        let config = stapled_server_config(
+            default_pair
+                .as_ref()
+                .map(|(cert_path, key_path)| (cert_path.as_str(), key_path.as_str())),
+            &self.certificates,
+            self.alpn_protocols.clone(),
+            &staples,
+        )?;
+        self.rustls_config.reload_from_config(Arc::new(config));
+
+        let expiries = self.read_expiries();
+        let mut previous = self.expiries.lock().unwrap();
+        for (cert_path, expiry) in &expiries {
+            let old = previous.get(cert_path).copied().flatten();
+            if old != *expiry {
+                tracing::info!(
+                    "Reloaded certificate {}: expiry {} -> {}",
+                    cert_path,
+                    old.map_or("unknown".to_string(), |t| t.to_rfc3339()),
+                    expiry.map_or("unknown".to_string(), |t| t.to_rfc3339())
+                );
+            }
+        }
+        *previous = expiries;
+        tracing::info!("TLS certificates reloaded");
+        Ok(())
+    }
+
+    pub fn reload_or_log(&self) {
+        if let Err(e) = self.reload() {
+            tracing::error!("Failed to reload TLS certificates, keeping the current ones: {e:#}");
+        }
+    }
+
+    /// Serve a renewed certificate as the default one. The previous pair is restored when
+    /// the new files can't be loaded.
+    pub fn apply_renewal(&self, renewed: &CertificateInfo) -> Result<()> {
+        let pair = (renewed.cert_path.clone(), renewed.key_path.clone());
+        let previous = self.default_pair.lock().unwrap().replace(pair);
+        self.reload()
+            .inspect_err(|_| *self.default_pair.lock().unwrap() = previous)
+    }
+
+    /// Watch the certificate files and reload when they change, and whenever `renewals`
+    /// delivers a certificate from the ACME renewal task.
+    pub fn spawn_watcher(
+        self,
+        mut renewals: Option<broadcast::Receiver<CertificateInfo>>,
+    ) -> Result<tokio::task::JoinHandle<()>> {
+        let paths = self.paths();
+        let file_names: Vec<_> = paths
+            .iter()
+            .filter_map(|path| path.file_name().map(|name| name.to_os_string()))
+            .collect();
+        let mut directories: Vec<PathBuf> = paths
+            .iter()
+            .map(|path| {
+                path.parent()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new("."))
+                    .to_path_buf()
+            })
+            .collect();
+        directories.sort();
+        directories.dedup();
+
+        let (notify_tx, mut notify_rx) = mpsc::channel::<()>(10);
+        let mut watcher =
+            notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
+                match res {
+                    Ok(event) => {
+                        if (event.kind.is_modify()
+                            || event.kind.is_create()
+                            || event.kind.is_remove())
+                            && event.paths.iter().any(|p| {
+                                p.file_name()
+                                    .is_some_and(|name| file_names.iter().any(|f| f == name))
+                            })
+                        {
+                            // A full channel already has a reload pending
+                            let _ = notify_tx.try_send(());
+                        }
+                    }
+                    Err(e) => tracing::error!("Certificate watch error: {:?}", e),
+                }
+            })
+            .context("Failed to create certificate watcher")?;
+        for directory in &directories {
+            watcher
+                .watch(directory, RecursiveMode::NonRecursive)
+                .with_context(|| format!("Failed to watch certificate directory {directory:?}"))?;
+            tracing::info!("Watching certificate directory {:?} for changes", directory);
+        }
+
+        Ok(tokio::spawn(async move {
+            // The watcher stops when dropped, so the task owns it
+            let _watcher = watcher;
+            let debounce = Duration::from_millis(500);
+            loop {
+                tokio::select! {
+                    Some(()) = notify_rx.recv() => {
+                        // Cert and key are usually written back to back; reload once both landed
+                        tokio::time::sleep(debounce).await;
+                        while notify_rx.try_recv().is_ok() {}
+                        tracing::info!("Certificate files changed, reloading TLS configuration");
+                        self.reload_or_log();
+                    }
+                    renewed = async {
+                        match renewals.as_mut() {
+                            Some(renewals) => renewals.recv().await,
+                            None => std::future::pending().await,
+                        }
+                    } => match renewed {
+                        Ok(renewed) => {
+                            tracing::info!(
+                                "Certificate {} renewed, reloading TLS configuration",
+                                renewed.cert_path
+                            );
+                            if let Err(e) = self.apply_renewal(&renewed) {
+                                tracing::error!(
+                                    "Failed to load renewed certificate, keeping the current one: {e:#}"
+                                );
+                            }
+                        }
+                        // Missed renewals wrote files we already know about
+                        Err(broadcast::error::RecvError::Lagged(_)) => self.reload_or_log(),
+                        Err(broadcast::error::RecvError::Closed) => renewals = None,
+                    },
+                    else => break,
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a self-signed certificate for `domains` and return its cert and key paths
+    fn self_signed(dir: &std::path::Path, name: &str, domains: &[&str]) -> (String, String) {
+        let generated = rcgen::generate_simple_self_signed(
+            domains.iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let cert_path = dir.join(format!("{name}.crt"));
+        let key_path = dir.join(format!("{name}.key"));
+        std::fs::write(&cert_path, generated.cert.pem()).unwrap();
+        std::fs::write(&key_path, generated.key_pair.serialize_pem()).unwrap();
+        (
+            cert_path.to_string_lossy().into_owned(),
+            key_path.to_string_lossy().into_owned(),
+        )
+    }
+
+    #[test]
+    fn test_sni_resolution() {
+        let dir = tempfile::tempdir().unwrap();
+        let default = self_signed(dir.path(), "default", &["app.example.com"]);
+        let other = self_signed(dir.path(), "other", &["api.other.com", "*.other.com"]);
+
+        let load = |(cert, key): &(String, String)| load_certified_key(cert, key).unwrap();
+        let mut resolver = SniResolver::new(Some(load(&default)));
+        resolver.add(
+            &["api.other.com".to_string(), "*.other.com".to_string()],
+            load(&other),
+        );
+        let served = |name: Option<&str>| resolver.lookup(name).unwrap().cert[0].clone();
+        let other_cert = load(&other).cert[0].clone();
+        let default_cert = load(&default).cert[0].clone();
+
+        assert_eq!(served(Some("api.other.com")), other_cert);
+        assert_eq!(served(Some("WWW.Other.com.")), other_cert);
+        assert_eq!(served(Some("a.b.other.com")), default_cert);
+        assert_eq!(served(Some("app.example.com")), default_cert);
+        assert_eq!(served(None), default_cert);
+        assert!(SniResolver::new(None).lookup(Some("x.com")).is_none());
+
+        // A key that doesn't belong to the certificate is refused
+        assert!(load_certified_key(&default.0, &other.1).is_err());
+        let certificates = [TlsCertificate {
+            domains: vec!["api.other.com".to_string()],
+            cert_path: other.0.clone(),
+            key_path: other.1.clone(),
+        }];
+        let config = server_config(None, &certificates, &ProtocolConfig::default()).unwrap();
+        assert_eq!(config.alpn_protocols, [&b"h2"[..], b"http/1.1"]);
+        let http1_only = ProtocolConfig {
+            http2_enabled: false,
+            ..Default::default()
+        };
+        let config = server_config(None, &certificates, &http1_only).unwrap();
+        assert_eq!(config.alpn_protocols, [b"http/1.1"]);
+    }
+
+    /// Write a self-signed certificate for app.example.com expiring at the start of `year`
+    fn expiring(dir: &std::path::Path, name: &str, year: i32) -> (String, String) {
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let mut params =
+            rcgen::CertificateParams::new(vec!["app.example.com".to_string()]).unwrap();
+        params.not_after = rcgen::date_time_ymd(year, 1, 1);
+        let cert = params.self_signed(&key_pair).unwrap();
+        let cert_path = dir.join(format!("{name}.crt"));
+        let key_path = dir.join(format!("{name}.key"));
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key_pair.serialize_pem()).unwrap();
+        (
+            cert_path.to_string_lossy().into_owned(),
+            key_path.to_string_lossy().into_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_certificate_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_path, key_path) = expiring(dir.path(), "site", 2030);
+        let pair = Some((cert_path.as_str(), key_path.as_str()));
+        let rustls_config = RustlsConfig::from_config(Arc::new(
+            server_config(pair, &[], &ProtocolConfig::default()).unwrap(),
+        ));
+        let reloader = CertificateReloader::new(
+            rustls_config.clone(),
+            Some((cert_path.clone(), key_path.clone())),
+            Vec::new(),
+        );
+        let expiry = || {
+            reloader.expiries.lock().unwrap()[&cert_path]
+                .unwrap()
+                .to_rfc3339()
+        };
+        assert_eq!(expiry(), "2030-01-01T00:00:00+00:00");
+        let original = rustls_config.get_inner();
+
+        // A half-written pair (new key, old certificate) is refused and the old config stays
+        let renewed = expiring(dir.path(), "renewed", 2031);
+        std::fs::copy(&renewed.1, &key_path).unwrap();
+        assert!(reloader.reload().is_err());
+        assert!(Arc::ptr_eq(&rustls_config.get_inner(), &original));
+
+        std::fs::copy(&renewed.0, &cert_path).unwrap();
+        reloader.reload().unwrap();
+        assert!(!Arc::ptr_eq(&rustls_config.get_inner(), &original));
+        assert_eq!(expiry(), "2031-01-01T00:00:00+00:00");
+    }
+
+    /// Accepts any server certificate and records the OCSP staple sent with it
+    #[derive(Debug)]
+    struct AcceptAnyCertificate(Arc<CryptoProvider>, Mutex<Vec<u8>>);
+
+    impl rustls::client::danger::ServerCertVerifier for AcceptAnyCertificate {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &rustls::pki_types::CertificateDer<'_>,
+            _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+            _server_name: &rustls::pki_types::ServerName<'_>,
+            ocsp_response: &[u8],
+            _now: rustls::pki_types::UnixTime,
+        ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+            *self.1.lock().unwrap() = ocsp_response.to_vec();
+            Ok(rustls::client::danger::ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &rustls::pki_types::CertificateDer<'_>,
+            dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &rustls::pki_types::CertificateDer<'_>,
+            dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            rustls::crypto::verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    /// Handshake with `addr`, returning the certificate and OCSP staple it presents
+    async fn handshake(addr: std::net::SocketAddr) -> (x509::Certificate, Vec<u8>) {
+        let provider = crypto_provider();
+        let verifier = Arc::new(AcceptAnyCertificate(provider.clone(), Mutex::default()));
+        let config = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .dangerous()
+            .with_custom_certificate_verifier(verifier.clone())
+            .with_no_client_auth();
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect("app.example.com".try_into().unwrap(), stream)
+            .await
+            .unwrap();
+        let served = &tls.get_ref().1.peer_certificates().unwrap()[0];
+        let staple = verifier.1.lock().unwrap().clone();
+        (x509::parse(served).unwrap(), staple)
+    }
+
+    /// Serve `rustls_config` on a local port
+    async fn serve(rustls_config: RustlsConfig) -> std::net::SocketAddr {
+        let handle = axum_server::Handle::new();
+        tokio::spawn(
+            axum_server::bind_rustls(([127, 0, 0, 1], 0).into(), rustls_config)
+                .handle(handle.clone())
+                .serve(axum::Router::new().into_make_service()),
+        );
+        handle.listening().await.unwrap()
+    }
+
+    /// Expiry of the certificate a TLS handshake with `addr` presents
+    async fn served_expiry(addr: std::net::SocketAddr) -> DateTime<Utc> {
+        handshake(addr).await.0.not_after
+    }
+
+    #[tokio::test]
+    async fn test_renewal_swaps_served_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_path, key_path) = expiring(dir.path(), "site", 2030);
+        let pair = Some((cert_path.as_str(), key_path.as_str()));
+        let rustls_config = RustlsConfig::from_config(Arc::new(
+            server_config(pair, &[], &ProtocolConfig::default()).unwrap(),
+        ));
+        let (renewed_tx, renewed_rx) = broadcast::channel(1);
+        CertificateReloader::new(
+            rustls_config.clone(),
+            Some((cert_path.clone(), key_path.clone())),
+            Vec::new(),
+        )
+        .spawn_watcher(Some(renewed_rx))
+        .unwrap();
+
+        let addr = serve(rustls_config).await;
+        let year = |time: DateTime<Utc>| time.format("%Y").to_string();
+        assert_eq!(year(served_expiry(addr).await), "2030");
+
+        // The renewal lands in new files, as announced by the ACME renewal task
+        let (cert_path, key_path) = expiring(dir.path(), "renewed", 2031);
+        renewed_tx
+            .send(CertificateInfo::load(&cert_path, &key_path).unwrap())
+            .unwrap();
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while year(served_expiry(addr).await) != "2031" {
+            assert!(tokio::time::Instant::now() < deadline, "renewal not served");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ocsp_staple_served() {
+        let dir = tempfile::tempdir().unwrap();
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let mut ca_params = rcgen::CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let leaf_key = rcgen::KeyPair::generate().unwrap();
+        let leaf = rcgen::CertificateParams::new(vec!["app.example.com".to_string()])
+            .unwrap()
+            .signed_by(&leaf_key, &ca, &ca_key)
+            .unwrap();
+        let cert_path = dir.path().join("site.crt").to_string_lossy().into_owned();
+        let key_path = dir.path().join("site.key").to_string_lossy().into_owned();
+        std::fs::write(&cert_path, leaf.pem() + &ca.pem()).unwrap();
+        std::fs::write(&key_path, leaf_key.serialize_pem()).unwrap();
+
+        let serial_number = x509::parse(leaf.der()).unwrap().serial_number;
+        let now = Utc::now();
+        let response = crate::adapters::ocsp::good_response(
+            &serial_number,
+            now - chrono::Duration::hours(1),
+            now + chrono::Duration::days(3),
+        );
+        let responder = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let responder_url = format!("http://{}/", responder.local_addr().unwrap());
+        let body = response.clone();
+        tokio::spawn(async move {
+            let app = axum::Router::new().route("/", axum::routing::post(move || async { body }));
+            axum::serve(responder, app).await.unwrap();
+        });
+
+        let pair = Some((cert_path.as_str(), key_path.as_str()));
+        let rustls_config = RustlsConfig::from_config(Arc::new(
+            server_config(pair, &[], &ProtocolConfig::default()).unwrap(),
+        ));
+        let reloader = CertificateReloader::new(
+            rustls_config.clone(),
+            Some((cert_path.clone(), key_path.clone())),
+            Vec::new(),
+        );
+        let addr = serve(rustls_config).await;
+        assert!(handshake(addr).await.1.is_empty());
+
+        let stapler =
+            crate::adapters::ocsp::OcspStapler::new(reloader.clone(), Some(responder_url)).unwrap();
+        assert!(stapler.refresh().await);
+        assert_eq!(handshake(addr).await.1, response);
+        // Not due again until half way to the next update
+        assert!(!stapler.refresh().await);
+
+        // A staple about another certificate is never served
+        let mut staple = reloader.staple(&cert_path).unwrap();
+        staple.serial_number = vec![1];
+        reloader.set_staple(&cert_path, Some(staple));
+        reloader.reload().unwrap();
+        assert!(handshake(addr).await.1.is_empty());
+    }
+}
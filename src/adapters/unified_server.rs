@@ -3,6 +3,8 @@ use std::sync::{Arc, RwLock};
 
 use anyhow::{Context, Result, anyhow};
 
+use crate::adapters::access_log::AccessLog;
+use crate::adapters::acme::AcmeService;
 use crate::adapters::file_system::TowerFileSystem;
 use crate::adapters::http::server::HyperServer;
 use crate::adapters::http_client::HyperHttpClient;
@@ -10,6 +12,7 @@ use crate::adapters::http3::Http3Server;
 use crate::config::models::ServerConfig;
 use crate::core::ProxyService;
 use crate::ports::http_server::HttpServer;
+use crate::utils::config_reload::ConfigReloader;
 use crate::utils::graceful_shutdown::GracefulShutdown;
 
 pub struct UnifiedServer {
@@ -27,7 +30,7 @@ impl UnifiedServer {
         health_checker_handle: Arc<tokio::sync::Mutex<Option<tokio::task::JoinHandle<()>>>>,
         graceful_shutdown: Arc<GracefulShutdown>,
     ) -> Result<Self> {
-        let http_server = HyperServer::with_dependencies(
+        let mut http_server = HyperServer::with_dependencies(
             proxy_service_holder.clone(),
             config_holder.clone(),
             http_client.clone(),
@@ -36,15 +39,27 @@ impl UnifiedServer {
             graceful_shutdown.clone(),
         );
 
+        let access_log_config = config_holder
+            .read()
+            .map_err(|e| anyhow!("Failed to acquire config read lock for access log: {}", e))?
+            .access_log
+            .clone();
+        if let Some(access_log_config) = access_log_config {
+            let access_log = AccessLog::open(&access_log_config)
+                .await
+                .with_context(|| format!("Failed to open access log {}", access_log_config.path))?;
+            http_server = http_server.with_access_log(access_log);
+        }
+
         let http3_server = {
-            let (http3_enabled, tls_config, listen_addr, http3_config) = {
+            let (http3_enabled, tls_config, udp_addr, http3_config) = {
                 let config = config_holder.read().map_err(|e| {
                     anyhow::anyhow!("Failed to acquire config read lock for HTTP/3 setup: {}", e)
                 })?;
                 let result = (
                     config.protocols.http3_enabled,
                     config.tls.clone(),
-                    config.listen_addr.clone(),
+                    config.http3_listen_addr(),
                     config
                         .protocols
                         .http3_config
@@ -60,9 +75,19 @@ impl UnifiedServer {
                 if let Some(ref tls_config) = tls_config {
                     let (cert_path, key_path) = if let Some(acme_config) = &tls_config.acme {
                         if acme_config.enabled {
-                            return Err(anyhow!(
-                                "ACME with HTTP/3 requires coordination - not implemented in this example"
-                            ));
+                            // The HTTP server obtains the certificate on startup; QUIC
+                            // connections load it from the same files once it's there
+                            let primary_domain = acme_config
+                                .domains
+                                .first()
+                                .ok_or_else(|| anyhow!("No domains configured for ACME"))?;
+                            let (cert_path, key_path) = AcmeService::new(acme_config.clone())
+                                .context("Failed to create ACME service")?
+                                .get_cert_paths(primary_domain);
+                            (
+                                cert_path.to_string_lossy().into_owned(),
+                                key_path.to_string_lossy().into_owned(),
+                            )
                         } else {
                             return Err(anyhow!("ACME is configured but not enabled"));
                         }
@@ -70,23 +95,33 @@ impl UnifiedServer {
                         (&tls_config.cert_path, &tls_config.key_path)
                     {
                         (cert_path.clone(), key_path.clone())
+                    } else if let Some(first) = tls_config.certificates.first() {
+                        (first.cert_path.clone(), first.key_path.clone())
                     } else {
                         return Err(anyhow!("HTTP/3 requires TLS certificate configuration"));
                     };
 
-                    let tcp_addr: SocketAddr =
-                        listen_addr.parse().context("Invalid listen address")?;
-                    let udp_addr = SocketAddr::new(tcp_addr.ip(), tcp_addr.port());
+                    // quiche takes a single certificate, so HTTP/3 clients always get the
+                    // primary one whatever name they ask for
+                    if !tls_config.certificates.is_empty() {
+                        tracing::warn!(
+                            "HTTP/3 serves only the primary certificate ({}); SNI certificates apply to TCP connections",
+                            cert_path
+                        );
+                    }
+
+                    let udp_addr = udp_addr.context("Invalid HTTP/3 listen address")?;
 
                     let server = Http3Server::new(
                         udp_addr,
                         &http3_config,
                         &cert_path,
                         &key_path,
-                        proxy_service_holder.clone(),
+                        http_server.handler(),
                     )
                     .await
                     .context("Failed to create HTTP/3 server")?;
+                    server.spawn_certificate_updates(http_server.subscribe_certificate_renewals());
 
                     Some(server)
                 } else {
@@ -104,57 +139,34 @@ impl UnifiedServer {
         })
     }
 
-    pub async fn run(&self) -> Result<()> {
-        let mut shutdown_receiver = self.graceful_shutdown.subscribe();
+    /// Apply admin API config updates and reloads through `config_reloader`
+    pub fn with_config_reloader(mut self, config_reloader: ConfigReloader) -> Self {
+        self.http_server = self.http_server.with_config_reloader(config_reloader);
+        self
+    }
 
+    pub async fn run(&self) -> Result<()> {
         match &self.http3_server {
             Some(h3_server) => {
                 tracing::info!(
                     "Starting unified server with HTTP/1.1, HTTP/2 (TCP) and HTTP/3 (UDP) support"
                 );
 
-                // Run both HTTP and HTTP/3 servers concurrently
-                tokio::select! {
-                    result = self.http_server.run() => {
-                        result.context("HTTP server error")?;
-                    }
-                    result = h3_server.run() => {
-                        result.context("HTTP/3 server error")?;
-                    }
-                    shutdown_reason = shutdown_receiver.recv() => {
-                        match shutdown_reason {
-                            Ok(reason) => {
-                                tracing::info!("Unified server shutdown initiated: {:?}", reason);
-                                // Both servers will be dropped and cleaned up
-                            }
-                            Err(e) => {
-                                tracing::error!("Error receiving shutdown signal: {}", e);
-                            }
-                        }
-                    }
-                }
+                // Run both HTTP and HTTP/3 servers concurrently, until each has drained its
+                // connections on shutdown
+                let shutdown = self.graceful_shutdown.shutdown_token();
+                tokio::try_join!(
+                    async { self.http_server.run().await.context("HTTP server error") },
+                    async { h3_server.run(shutdown).await.context("HTTP/3 server error") },
+                )?;
             }
             None => {
                 tracing::info!(
                     "Starting HTTP server with HTTP/1.1 and HTTP/2 support (HTTP/3 disabled)"
                 );
 
-                // Run only the HTTP server
-                tokio::select! {
-                    result = self.http_server.run() => {
-                        result.context("HTTP server error")?;
-                    }
-                    shutdown_reason = shutdown_receiver.recv() => {
-                        match shutdown_reason {
-                            Ok(reason) => {
-                                tracing::info!("HTTP server shutdown initiated: {:?}", reason);
-                            }
-                            Err(e) => {
-                                tracing::error!("Error receiving shutdown signal: {}", e);
-                            }
-                        }
-                    }
-                }
+                // Run only the HTTP server, until it has drained its connections on shutdown
+                self.http_server.run().await.context("HTTP server error")?;
             }
         }
 
@@ -0,0 +1,1003 @@
+use axum::response::{IntoResponse, Response as AxumResponse};
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::upgrade::OnUpgrade;
+use hyper::{Method, Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Headers that describe a single hop and are never copied between the client and
+/// backend connections (the upgrade headers are set explicitly instead)
+const HOP_BY_HOP: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Whether a comma-separated header contains `token`, compared case-insensitively
+fn has_token(headers: &HeaderMap, name: HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+/// Why a request on a WebSocket route was not accepted as an opening handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeRejection {
+    NotUpgrade,
+    UnsupportedVersion,
+    MissingKey,
+    /// The client offered subprotocols but none is allowed on the route
+    UnsupportedSubprotocol,
+}
+
+impl HandshakeRejection {
+    /// Label used for the handshake failure metric
+    pub fn reason(self) -> &'static str {
+        match self {
+            HandshakeRejection::NotUpgrade => "not_upgrade",
+            HandshakeRejection::UnsupportedVersion => "unsupported_version",
+            HandshakeRejection::MissingKey => "missing_key",
+            HandshakeRejection::UnsupportedSubprotocol => "unsupported_subprotocol",
+        }
+    }
+}
+
+impl IntoResponse for HandshakeRejection {
+    fn into_response(self) -> AxumResponse {
+        match self {
+            HandshakeRejection::NotUpgrade => (
+                StatusCode::BAD_REQUEST,
+                "This route only supports WebSocket connections",
+            )
+                .into_response(),
+            HandshakeRejection::UnsupportedVersion => (
+                StatusCode::UPGRADE_REQUIRED,
+                [(header::SEC_WEBSOCKET_VERSION, "13")],
+                "Unsupported WebSocket version",
+            )
+                .into_response(),
+            HandshakeRejection::MissingKey => {
+                (StatusCode::BAD_REQUEST, "Missing Sec-WebSocket-Key").into_response()
+            }
+            HandshakeRejection::UnsupportedSubprotocol => (
+                StatusCode::BAD_REQUEST,
+                "None of the offered WebSocket subprotocols is supported",
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// Check that a request is a well-formed RFC 6455 opening handshake
+pub fn check_handshake<B>(req: &Request<B>) -> Result<(), HandshakeRejection> {
+    let headers = req.headers();
+    if req.method() != Method::GET
+        || !has_token(headers, header::UPGRADE, "websocket")
+        || !has_token(headers, header::CONNECTION, "upgrade")
+    {
+        return Err(HandshakeRejection::NotUpgrade);
+    }
+    if headers
+        .get(header::SEC_WEBSOCKET_VERSION)
+        .is_none_or(|version| version != "13")
+    {
+        return Err(HandshakeRejection::UnsupportedVersion);
+    }
+    if !headers.contains_key(header::SEC_WEBSOCKET_KEY) {
+        return Err(HandshakeRejection::MissingKey);
+    }
+    Ok(())
+}
+
+/// Items of a comma-separated header, trimmed
+fn list_items(headers: &HeaderMap, name: HeaderName) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Name of a `Sec-WebSocket-Extensions` item, without its parameters
+fn extension_name(item: &str) -> &str {
+    item.split(';').next().unwrap_or_default().trim()
+}
+
+/// Replace a list header with `items`, removing it when the list is empty
+fn set_list(headers: &mut HeaderMap, name: HeaderName, items: &[String]) {
+    headers.remove(&name);
+    if let Ok(value) = HeaderValue::from_str(&items.join(", "))
+        && !items.is_empty()
+    {
+        headers.insert(name, value);
+    }
+}
+
+/// What a route lets the client negotiate with the backend
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Negotiation<'a> {
+    /// Subprotocols that may be offered to the backend; empty allows any
+    pub subprotocols: &'a [String],
+    /// Remove `permessage-deflate` from the offer
+    pub strip_compression: bool,
+}
+
+impl Negotiation<'_> {
+    /// Narrow the client's subprotocol and extension offer in the handshake sent to the backend
+    pub fn apply(&self, headers: &mut HeaderMap) -> Result<(), HandshakeRejection> {
+        if !self.subprotocols.is_empty() {
+            let offered = list_items(headers, header::SEC_WEBSOCKET_PROTOCOL);
+            let allowed: Vec<String> = offered
+                .iter()
+                .filter(|protocol| self.subprotocols.contains(protocol))
+                .cloned()
+                .collect();
+            if !offered.is_empty() && allowed.is_empty() {
+                return Err(HandshakeRejection::UnsupportedSubprotocol);
+            }
+            set_list(headers, header::SEC_WEBSOCKET_PROTOCOL, &allowed);
+        }
+        if self.strip_compression {
+            let extensions: Vec<String> = list_items(headers, header::SEC_WEBSOCKET_EXTENSIONS)
+                .into_iter()
+                .filter(|item| !extension_name(item).eq_ignore_ascii_case("permessage-deflate"))
+                .collect();
+            set_list(headers, header::SEC_WEBSOCKET_EXTENSIONS, &extensions);
+        }
+        Ok(())
+    }
+}
+
+/// Check that the backend only selected a subprotocol and extensions that were offered to it,
+/// returning a description of the first violation
+pub fn check_selection(offer: &HeaderMap, answer: &HeaderMap) -> Result<(), String> {
+    let offered_protocols = list_items(offer, header::SEC_WEBSOCKET_PROTOCOL);
+    for protocol in list_items(answer, header::SEC_WEBSOCKET_PROTOCOL) {
+        if !offered_protocols.contains(&protocol) {
+            return Err(format!("subprotocol '{protocol}' was not offered"));
+        }
+    }
+    let offered_extensions = list_items(offer, header::SEC_WEBSOCKET_EXTENSIONS);
+    for extension in list_items(answer, header::SEC_WEBSOCKET_EXTENSIONS) {
+        let name = extension_name(&extension);
+        if !offered_extensions
+            .iter()
+            .any(|offered| extension_name(offered).eq_ignore_ascii_case(name))
+        {
+            return Err(format!("extension '{name}' was not offered"));
+        }
+    }
+    Ok(())
+}
+
+/// The HTTP URL of a WebSocket target: `ws://` is dialled as `http://`, `wss://` as `https://`.
+/// Secure targets therefore go through the same rustls connector (and trust store) as
+/// proxied HTTPS requests.
+pub fn backend_url(target: &str, path_and_query: &str) -> String {
+    let target = target.trim_end_matches('/');
+    let target = if let Some(rest) = target.strip_prefix("ws://") {
+        format!("http://{rest}")
+    } else if let Some(rest) = target.strip_prefix("wss://") {
+        format!("https://{rest}")
+    } else {
+        target.to_string()
+    };
+    format!("{target}{path_and_query}")
+}
+
+/// End-to-end headers of one side of the handshake, with the upgrade headers restored.
+/// `Sec-WebSocket-*` headers (key, version, protocol, extensions, accept) pass through.
+pub fn forwarded_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut forwarded = headers.clone();
+    for name in &HOP_BY_HOP {
+        forwarded.remove(name);
+    }
+    forwarded.remove(header::HOST);
+    forwarded.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+    forwarded.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    forwarded
+}
+
+/// Read buffer size for each direction of a tunnel
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// Guardrails for a proxied WebSocket connection
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TunnelLimits {
+    /// Close the connection when no bytes flow in either direction for this long
+    pub idle_timeout: Option<Duration>,
+    /// Close the connection once it has been open this long
+    pub max_duration: Option<Duration>,
+    /// Largest frame payload accepted in either direction
+    pub max_frame_size: Option<u64>,
+    /// Largest message (sum of a fragmented message's frames) accepted in either direction
+    pub max_message_size: Option<u64>,
+}
+
+impl TunnelLimits {
+    /// Limits from a route's settings; unparsable durations are ignored with a warning
+    pub fn new(
+        idle_timeout: Option<&str>,
+        max_duration: Option<&str>,
+        max_frame_size: Option<usize>,
+        max_message_size: Option<usize>,
+    ) -> Self {
+        let parse = |name: &str, value: Option<&str>| {
+            value.and_then(|value| {
+                humantime::parse_duration(value)
+                    .map_err(|e| tracing::warn!("Invalid WebSocket {} '{}': {}", name, value, e))
+                    .ok()
+            })
+        };
+        Self {
+            idle_timeout: parse("idle_timeout", idle_timeout),
+            max_duration: parse("max_connection_duration", max_duration),
+            max_frame_size: max_frame_size.map(|size| size as u64),
+            max_message_size: max_message_size.map(|size| size as u64),
+        }
+    }
+}
+
+/// Why a tunnel ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TunnelEnd {
+    /// Both sides closed their connection
+    Closed,
+    IdleTimeout,
+    MaxDuration,
+    FrameTooLarge,
+    MessageTooLarge,
+}
+
+impl TunnelEnd {
+    /// Status code and reason of the close frame prox sends to both sides
+    fn close_status(self) -> Option<(u16, &'static str)> {
+        match self {
+            TunnelEnd::Closed => None,
+            TunnelEnd::IdleTimeout => Some((1001, "idle timeout")),
+            TunnelEnd::MaxDuration => Some((1001, "connection lifetime exceeded")),
+            TunnelEnd::FrameTooLarge => Some((1009, "frame too large")),
+            TunnelEnd::MessageTooLarge => Some((1009, "message too large")),
+        }
+    }
+}
+
+/// Side that started closing a tunnel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseInitiator {
+    Client,
+    Backend,
+    /// prox itself, after a timeout or size limit
+    Proxy,
+}
+
+impl CloseInitiator {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CloseInitiator::Client => "client",
+            CloseInitiator::Backend => "backend",
+            CloseInitiator::Proxy => "proxy",
+        }
+    }
+}
+
+/// Byte counts and outcome of a finished tunnel
+#[derive(Debug)]
+pub struct TunnelStats {
+    pub from_client: u64,
+    pub from_backend: u64,
+    pub end: TunnelEnd,
+    /// Who sent the first close frame, or closed the connection without one
+    pub closed_by: Option<CloseInitiator>,
+    /// Status code of the initiator's close frame; `None` when the connection just dropped
+    pub close_code: Option<u16>,
+}
+
+/// Follows frame boundaries in one direction of the byte stream to enforce size limits.
+/// Frames are not decoded; only their headers are read.
+#[derive(Debug)]
+struct FrameInspector {
+    max_frame_size: Option<u64>,
+    max_message_size: Option<u64>,
+    /// Header bytes of the next frame, held back until the header is complete
+    header: Vec<u8>,
+    /// Payload bytes of the current frame still to pass through
+    remaining: u64,
+    /// Payload bytes of the current (possibly fragmented) data message so far
+    message_len: u64,
+    /// Status code of the first close frame sent in this direction (1005 when it has none)
+    close_code: Option<u16>,
+    /// Masking key and status code bytes collected while a close frame's payload passes
+    close_payload: Option<([u8; 4], Vec<u8>)>,
+}
+
+impl FrameInspector {
+    fn new(limits: &TunnelLimits) -> Self {
+        Self {
+            max_frame_size: limits.max_frame_size,
+            max_message_size: limits.max_message_size,
+            header: Vec::with_capacity(14),
+            remaining: 0,
+            message_len: 0,
+            close_code: None,
+            close_payload: None,
+        }
+    }
+
+    /// Whether everything forwarded so far ends on a frame boundary, so a close frame
+    /// can be sent without corrupting the stream
+    fn at_boundary(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Append the bytes of `data` that may be forwarded to `out`, stopping before the
+    /// header of a frame that breaks a limit
+    fn inspect(&mut self, mut data: &[u8], out: &mut Vec<u8>) -> Result<(), TunnelEnd> {
+        while let Some((&byte, rest)) = data.split_first() {
+            if self.remaining > 0 {
+                let len = self.remaining.min(data.len() as u64) as usize;
+                if let Some((mask, code)) = &mut self.close_payload {
+                    for &byte in data[..len].iter().take(2 - code.len()) {
+                        code.push(byte ^ mask[code.len() % 4]);
+                    }
+                    if let [high, low] = code[..] {
+                        self.close_code
+                            .get_or_insert(u16::from_be_bytes([high, low]));
+                        self.close_payload = None;
+                    }
+                }
+                out.extend_from_slice(&data[..len]);
+                self.remaining -= len as u64;
+                data = &data[len..];
+                continue;
+            }
+
+            self.header.push(byte);
+            data = rest;
+            let Some(payload_len) = frame_payload_len(&self.header) else {
+                continue;
+            };
+            if self.max_frame_size.is_some_and(|max| payload_len > max) {
+                return Err(TunnelEnd::FrameTooLarge);
+            }
+            match self.header[0] & 0x0f {
+                // Continuation frames add to the message, text and binary frames start one;
+                // control frames may be interleaved and don't count
+                0x0 => self.message_len += payload_len,
+                0x1 | 0x2 => self.message_len = payload_len,
+                _ => {}
+            }
+            if self.header[0] & 0x08 == 0
+                && self
+                    .max_message_size
+                    .is_some_and(|max| self.message_len > max)
+            {
+                return Err(TunnelEnd::MessageTooLarge);
+            }
+            if self.header[0] & 0x0f == 0x8 {
+                if payload_len < 2 {
+                    self.close_code.get_or_insert(1005);
+                } else {
+                    let mut mask = [0; 4];
+                    if self.header[1] & 0x80 != 0 {
+                        mask.copy_from_slice(&self.header[self.header.len() - 4..]);
+                    }
+                    self.close_payload = Some((mask, Vec::with_capacity(2)));
+                }
+            }
+            out.extend_from_slice(&self.header);
+            self.header.clear();
+            self.remaining = payload_len;
+        }
+        Ok(())
+    }
+}
+
+/// Payload length of a frame once `header` holds its complete header
+fn frame_payload_len(header: &[u8]) -> Option<u64> {
+    let &[_, second, ..] = header else {
+        return None;
+    };
+    let extended = match second & 0x7f {
+        126 => 2,
+        127 => 8,
+        _ => 0,
+    };
+    let mask = if second & 0x80 != 0 { 4 } else { 0 };
+    if header.len() < 2 + extended + mask {
+        return None;
+    }
+    Some(match extended {
+        0 => u64::from(second & 0x7f),
+        _ => header[2..2 + extended]
+            .iter()
+            .fold(0, |len, byte| len << 8 | u64::from(*byte)),
+    })
+}
+
+/// A close frame; frames sent by prox to the backend act as client frames and are masked
+fn close_frame(code: u16, reason: &str, masked: bool) -> Vec<u8> {
+    let mut payload = code.to_be_bytes().to_vec();
+    payload.extend_from_slice(reason.as_bytes());
+    let mut frame = vec![0x88, payload.len() as u8];
+    if masked {
+        let mask: [u8; 4] = rand::random();
+        frame[1] |= 0x80;
+        frame.extend_from_slice(&mask);
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+    frame.extend(payload);
+    frame
+}
+
+/// Wait for both sides to finish upgrading, then relay between them
+pub async fn tunnel(
+    client: OnUpgrade,
+    backend: OnUpgrade,
+    limits: TunnelLimits,
+) -> std::io::Result<TunnelStats> {
+    let (client, backend) = tokio::try_join!(
+        async { client.await.map_err(std::io::Error::other) },
+        async { backend.await.map_err(std::io::Error::other) },
+    )?;
+    relay(TokioIo::new(client), TokioIo::new(backend), limits).await
+}
+
+/// Copy bytes between client and backend until both sides close or a limit is hit, in
+/// which case both sides get a close frame (1009 for size limits, 1001 for timeouts)
+async fn relay<C, B>(client: C, backend: B, limits: TunnelLimits) -> std::io::Result<TunnelStats>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+    let (mut backend_read, mut backend_write) = tokio::io::split(backend);
+    let mut to_backend = FrameInspector::new(&limits);
+    let mut to_client = FrameInspector::new(&limits);
+    let mut client_buf = vec![0u8; BUFFER_SIZE];
+    let mut backend_buf = vec![0u8; BUFFER_SIZE];
+    let mut out = Vec::with_capacity(BUFFER_SIZE);
+    let mut stats = TunnelStats {
+        from_client: 0,
+        from_backend: 0,
+        end: TunnelEnd::Closed,
+        closed_by: None,
+        close_code: None,
+    };
+    let (mut client_open, mut backend_open) = (true, true);
+
+    // Disabled timers are never polled, so their deadline doesn't matter
+    let far_future = Duration::from_secs(86400 * 365);
+    let idle = tokio::time::sleep(limits.idle_timeout.unwrap_or(far_future));
+    let lifetime = tokio::time::sleep(limits.max_duration.unwrap_or(far_future));
+    tokio::pin!(idle, lifetime);
+
+    stats.end = loop {
+        if !client_open && !backend_open {
+            break TunnelEnd::Closed;
+        }
+        let verdict = tokio::select! {
+            read = client_read.read(&mut client_buf), if client_open => {
+                let n = read?;
+                if n == 0 {
+                    client_open = false;
+                    stats.closed_by.get_or_insert(CloseInitiator::Client);
+                    let _ = backend_write.shutdown().await;
+                    Ok(())
+                } else {
+                    stats.from_client += n as u64;
+                    out.clear();
+                    let verdict = to_backend.inspect(&client_buf[..n], &mut out);
+                    if to_backend.close_code.is_some() {
+                        stats.closed_by.get_or_insert(CloseInitiator::Client);
+                    }
+                    backend_write.write_all(&out).await?;
+                    verdict
+                }
+            }
+            read = backend_read.read(&mut backend_buf), if backend_open => {
+                let n = read?;
+                if n == 0 {
+                    backend_open = false;
+                    stats.closed_by.get_or_insert(CloseInitiator::Backend);
+                    let _ = client_write.shutdown().await;
+                    Ok(())
+                } else {
+                    stats.from_backend += n as u64;
+                    out.clear();
+                    let verdict = to_client.inspect(&backend_buf[..n], &mut out);
+                    if to_client.close_code.is_some() {
+                        stats.closed_by.get_or_insert(CloseInitiator::Backend);
+                    }
+                    client_write.write_all(&out).await?;
+                    verdict
+                }
+            }
+            _ = &mut idle, if limits.idle_timeout.is_some() => Err(TunnelEnd::IdleTimeout),
+            _ = &mut lifetime, if limits.max_duration.is_some() => Err(TunnelEnd::MaxDuration),
+        };
+        if let Err(end) = verdict {
+            break end;
+        }
+        if let Some(idle_timeout) = limits.idle_timeout {
+            idle.as_mut()
+                .reset(tokio::time::Instant::now() + idle_timeout);
+        }
+    };
+
+    if let Some((code, reason)) = stats.end.close_status() {
+        stats.closed_by.get_or_insert(CloseInitiator::Proxy);
+        if client_open && to_client.at_boundary() {
+            let _ = client_write
+                .write_all(&close_frame(code, reason, false))
+                .await;
+        }
+        if backend_open && to_backend.at_boundary() {
+            let _ = backend_write
+                .write_all(&close_frame(code, reason, true))
+                .await;
+        }
+        let _ = client_write.shutdown().await;
+        let _ = backend_write.shutdown().await;
+    }
+    stats.close_code = match stats.closed_by {
+        Some(CloseInitiator::Client) => to_backend.close_code,
+        Some(CloseInitiator::Backend) => to_client.close_code,
+        Some(CloseInitiator::Proxy) => stats.end.close_status().map(|(code, _)| code),
+        None => None,
+    };
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::file_system::TowerFileSystem;
+    use crate::adapters::http_client::HyperHttpClient;
+    use crate::adapters::http_handler::HyperHandler;
+    use crate::config::ServerConfig;
+    use crate::core::ProxyService;
+    use crate::ports::http_server::HttpHandler;
+    use axum::body::Body as AxumBody;
+    use axum::extract::ConnectInfo;
+    use futures_util::{SinkExt, StreamExt};
+    use std::net::SocketAddr;
+    use std::sync::{Arc, RwLock};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+    /// Echoes every data frame and records the path and headers of the handshake
+    async fn echo_server() -> (
+        SocketAddr,
+        tokio::sync::oneshot::Receiver<(String, HeaderMap)>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (handshake_tx, handshake_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut handshake_tx = Some(handshake_tx);
+            // The handshake error type is tungstenite's, not ours
+            #[allow(clippy::result_large_err)]
+            let callback = |req: &tokio_tungstenite::tungstenite::handshake::server::Request,
+                            res| {
+                let path = req
+                    .uri()
+                    .path_and_query()
+                    .map(|pq| pq.to_string())
+                    .unwrap_or_default();
+                if let Some(tx) = handshake_tx.take() {
+                    let _ = tx.send((path, req.headers().clone()));
+                }
+                Ok(res)
+            };
+            let mut ws = tokio_tungstenite::accept_hdr_async(stream, callback)
+                .await
+                .unwrap();
+            while let Some(Ok(message)) = ws.next().await {
+                // Close frames are answered by tungstenite itself while reading on
+                if message.is_text() || message.is_binary() {
+                    ws.send(message).await.unwrap();
+                }
+            }
+        });
+        (addr, handshake_rx)
+    }
+
+    async fn prox(config: &str) -> (SocketAddr, Arc<ProxyService>) {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        let config: ServerConfig = serde_yaml::from_str(config).unwrap();
+        let service = Arc::new(ProxyService::new(Arc::new(config)));
+        let handler = HyperHandler::new(
+            Arc::new(RwLock::new(service.clone())),
+            Arc::new(HyperHttpClient::new()),
+            Arc::new(TowerFileSystem::new()),
+        );
+        let app = axum::Router::new().fallback(
+            move |ConnectInfo(_addr): ConnectInfo<SocketAddr>, req: Request<AxumBody>| {
+                let handler = handler.clone();
+                async move { handler.handle_request(req).await.unwrap() }
+            },
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+        (addr, service)
+    }
+
+    #[tokio::test]
+    async fn test_websocket_proxy_echoes_frames() {
+        let (backend, handshake) = echo_server().await;
+        let (proxy, _) = prox(&format!(
+            r#"
+listen_addr: "127.0.0.1:0"
+routes:
+  "/ws":
+    type: websocket
+    target: "ws://{backend}"
+    path_rewrite: "/echo"
+    request_headers:
+      add:
+        X-Real-IP: "{{client_ip}}"
+      remove: ["Cookie"]
+    response_headers:
+      add:
+        X-Tenant-Routed: "true"
+      condition:
+        has_header: {{ name: "X-Tenant" }}
+"#
+        ))
+        .await;
+
+        let mut request = format!("ws://{proxy}/ws/room?id=7")
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert(header::COOKIE, HeaderValue::from_static("session=secret"));
+        request
+            .headers_mut()
+            .insert("x-tenant", HeaderValue::from_static("acme"));
+        let (mut ws, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(response.headers()["x-tenant-routed"], "true");
+
+        ws.send(Message::text("hello")).await.unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), Message::text("hello"));
+        ws.send(Message::binary(vec![0u8, 1, 2, 255]))
+            .await
+            .unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::binary(vec![0u8, 1, 2, 255])
+        );
+        ws.close(Some(CloseFrame {
+            code: CloseCode::Normal,
+            reason: "bye".into(),
+        }))
+        .await
+        .unwrap();
+        match ws.next().await.unwrap().unwrap() {
+            Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Normal),
+            other => panic!("expected a close frame, got {other:?}"),
+        }
+
+        let (path, headers) = handshake.await.unwrap();
+        assert_eq!(path, "/echo/room?id=7");
+        assert_eq!(headers["x-real-ip"], "127.0.0.1");
+        assert!(!headers.contains_key(header::COOKIE));
+        assert_eq!(headers[header::HOST], backend.to_string().as_str());
+    }
+
+    #[tokio::test]
+    async fn test_load_balanced_websocket_skips_unhealthy_targets() {
+        let (backend, _) = echo_server().await;
+        let (proxy, service) = prox(&format!(
+            r#"
+listen_addr: "127.0.0.1:0"
+routes:
+  "/ws":
+    type: websocket
+    targets: ["ws://127.0.0.1:1", "ws://{backend}"]
+    strategy: round_robin
+"#
+        ))
+        .await;
+        service
+            .backend_health()
+            .get("ws://127.0.0.1:1")
+            .unwrap()
+            .mark_unhealthy();
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{proxy}/ws"))
+            .await
+            .unwrap();
+        ws.send(Message::text("ping")).await.unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), Message::text("ping"));
+    }
+
+    /// Answers `GET /api` and WebSocket connections on `/ws` with its name
+    async fn named_backend(name: &'static str) -> SocketAddr {
+        use axum::extract::ws::{Message as AxumMessage, WebSocketUpgrade};
+        let app = axum::Router::new()
+            .route("/api", axum::routing::get(move || async move { name }))
+            .route(
+                "/ws",
+                axum::routing::get(move |upgrade: WebSocketUpgrade| async move {
+                    upgrade.on_upgrade(move |mut socket| async move {
+                        let _ = socket.send(AxumMessage::text(name)).await;
+                    })
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_websocket_follows_http_affinity_cookie() {
+        let (a, b) = (named_backend("a").await, named_backend("b").await);
+        let (proxy, _) = prox(&format!(
+            r#"
+listen_addr: "127.0.0.1:0"
+routes:
+  "/api":
+    type: load_balance
+    targets: ["http://{a}", "http://{b}"]
+    strategy: random
+    path_rewrite: "/api"
+    sticky: {{ by: cookie, name: app_affinity }}
+  "/ws":
+    type: websocket
+    targets: ["ws://{a}", "ws://{b}"]
+    strategy: random
+    path_rewrite: "/ws"
+    sticky: {{ by: cookie, name: app_affinity }}
+"#
+        ))
+        .await;
+
+        // An HTTP request picks a backend and pins it with the affinity cookie
+        let mut stream = tokio::net::TcpStream::connect(proxy).await.unwrap();
+        stream
+            .write_all(b"GET /api HTTP/1.1\r\nHost: prox\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let pinned = response.rsplit("\r\n").next().unwrap().to_string();
+        let cookie = response
+            .lines()
+            .find_map(|line| line.strip_prefix("set-cookie: "))
+            .and_then(|value| value.split(';').next())
+            .unwrap()
+            .to_string();
+        assert!(cookie.starts_with("app_affinity="), "{cookie}");
+        assert!(pinned == "a" || pinned == "b", "{response}");
+
+        // Every following WebSocket connection lands on the same backend
+        for _ in 0..10 {
+            let mut request = format!("ws://{proxy}/ws").into_client_request().unwrap();
+            request
+                .headers_mut()
+                .insert(header::COOKIE, HeaderValue::from_str(&cookie).unwrap());
+            let (mut ws, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+            assert!(!response.headers().contains_key(header::SET_COOKIE));
+            assert_eq!(
+                ws.next().await.unwrap().unwrap(),
+                Message::text(pinned.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn test_handshake_checks() {
+        let request = |version: &str| {
+            Request::get("/ws")
+                .header(header::CONNECTION, "keep-alive, Upgrade")
+                .header(header::UPGRADE, "WebSocket")
+                .header(header::SEC_WEBSOCKET_VERSION, version)
+                .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+                .body(())
+                .unwrap()
+        };
+        assert!(check_handshake(&request("13")).is_ok());
+        let rejection = check_handshake(&request("8")).unwrap_err();
+        assert_eq!(rejection, HandshakeRejection::UnsupportedVersion);
+        assert_eq!(
+            rejection.into_response().status(),
+            StatusCode::UPGRADE_REQUIRED
+        );
+        assert_eq!(
+            check_handshake(&Request::get("/ws").body(()).unwrap()),
+            Err(HandshakeRejection::NotUpgrade)
+        );
+        for (target, url) in [
+            (
+                "wss://chat.example.com/",
+                "https://chat.example.com/socket?x=1",
+            ),
+            ("ws://chat:9000", "http://chat:9000/socket?x=1"),
+            (
+                "https://chat.example.com",
+                "https://chat.example.com/socket?x=1",
+            ),
+            ("http://chat:9000/", "http://chat:9000/socket?x=1"),
+        ] {
+            assert_eq!(backend_url(target, "/socket?x=1"), url);
+        }
+    }
+
+    #[test]
+    fn test_negotiation() {
+        let mut offer = HeaderMap::new();
+        offer.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("v12.stomp, mqtt"),
+        );
+        offer.insert(
+            header::SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static("permessage-deflate; client_max_window_bits, x-custom"),
+        );
+        let allowed = ["mqtt".to_string()];
+        let negotiation = Negotiation {
+            subprotocols: &allowed,
+            strip_compression: true,
+        };
+        negotiation.apply(&mut offer).unwrap();
+        assert_eq!(offer[header::SEC_WEBSOCKET_PROTOCOL], "mqtt");
+        assert_eq!(offer[header::SEC_WEBSOCKET_EXTENSIONS], "x-custom");
+
+        let mut answer = HeaderMap::new();
+        answer.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("mqtt"),
+        );
+        assert!(check_selection(&offer, &answer).is_ok());
+        answer.insert(
+            header::SEC_WEBSOCKET_EXTENSIONS,
+            HeaderValue::from_static("permessage-deflate"),
+        );
+        assert!(check_selection(&offer, &answer).is_err());
+
+        let mut stomp_only = HeaderMap::new();
+        stomp_only.insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("v12.stomp"),
+        );
+        assert_eq!(
+            negotiation.apply(&mut stomp_only),
+            Err(HandshakeRejection::UnsupportedSubprotocol)
+        );
+        // Clients that offer nothing are unaffected by the allowlist
+        assert!(negotiation.apply(&mut HeaderMap::new()).is_ok());
+    }
+
+    /// A masked client frame with the given first byte and payload length
+    fn client_frame(first: u8, len: usize) -> Vec<u8> {
+        let mut frame = vec![first, 0x80 | len as u8, 1, 2, 3, 4];
+        frame.extend(std::iter::repeat_n(b'x', len));
+        frame
+    }
+
+    #[test]
+    fn test_frame_inspector_limits() {
+        let limits = TunnelLimits {
+            max_frame_size: Some(10),
+            max_message_size: Some(12),
+            ..Default::default()
+        };
+
+        // Frames split at arbitrary points pass through unchanged
+        let mut inspector = FrameInspector::new(&limits);
+        let stream = [client_frame(0x81, 10), client_frame(0x89, 4)].concat();
+        let mut out = Vec::new();
+        for chunk in stream.chunks(3) {
+            inspector.inspect(chunk, &mut out).unwrap();
+        }
+        assert_eq!(out, stream);
+        assert!(inspector.at_boundary());
+
+        let mut out = Vec::new();
+        assert_eq!(
+            inspector.inspect(&client_frame(0x82, 11), &mut out),
+            Err(TunnelEnd::FrameTooLarge)
+        );
+        assert!(out.is_empty());
+
+        // Fragments of one message add up; a ping in between doesn't count
+        let mut inspector = FrameInspector::new(&limits);
+        let mut out = Vec::new();
+        let fragments = [client_frame(0x01, 8), client_frame(0x89, 2)].concat();
+        inspector.inspect(&fragments, &mut out).unwrap();
+        assert_eq!(
+            inspector.inspect(&client_frame(0x80, 8), &mut out),
+            Err(TunnelEnd::MessageTooLarge)
+        );
+        assert_eq!(out, fragments);
+
+        assert_eq!(frame_payload_len(&[0x82, 126, 0x01, 0x00]), Some(256));
+        assert_eq!(frame_payload_len(&[0x82, 0xfe, 0x01, 0x00]), None);
+    }
+
+    #[tokio::test]
+    async fn test_relay_closes_on_limits() {
+        let limits = TunnelLimits {
+            max_frame_size: Some(10),
+            idle_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+
+        let (mut client, client_side) = tokio::io::duplex(1024);
+        let (mut backend, backend_side) = tokio::io::duplex(1024);
+        let handle = tokio::spawn(relay(client_side, backend_side, limits));
+        client.write_all(&client_frame(0x82, 20)).await.unwrap();
+        let stats = handle.await.unwrap().unwrap();
+        assert_eq!(stats.end, TunnelEnd::FrameTooLarge);
+        assert_eq!(stats.closed_by, Some(CloseInitiator::Proxy));
+        assert_eq!(stats.close_code, Some(1009));
+
+        let mut to_client = Vec::new();
+        client.read_to_end(&mut to_client).await.unwrap();
+        assert_eq!(&to_client[..4], &[0x88, 17, 0x03, 0xf1]);
+        let mut to_backend = Vec::new();
+        backend.read_to_end(&mut to_backend).await.unwrap();
+        assert_eq!(&to_backend[..2], &[0x88, 0x80 | 17]);
+
+        let (mut client, client_side) = tokio::io::duplex(1024);
+        let (_backend, backend_side) = tokio::io::duplex(1024);
+        let stats = relay(client_side, backend_side, limits).await.unwrap();
+        assert_eq!(stats.end, TunnelEnd::IdleTimeout);
+        let mut to_client = Vec::new();
+        client.read_to_end(&mut to_client).await.unwrap();
+        assert_eq!(&to_client[..4], &[0x88, 14, 0x03, 0xe9]);
+
+        // A client-initiated close handshake, with the close frame split across reads
+        let (mut client, client_side) = tokio::io::duplex(1024);
+        let (mut backend, backend_side) = tokio::io::duplex(1024);
+        let handle = tokio::spawn(relay(client_side, backend_side, TunnelLimits::default()));
+        let close = close_frame(1000, "bye", true);
+        client.write_all(&close[..7]).await.unwrap();
+        client.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        client.write_all(&close[7..]).await.unwrap();
+        let mut received = vec![0; close.len()];
+        backend.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, close);
+        let reply = close_frame(1000, "", false);
+        backend.write_all(&reply).await.unwrap();
+        let mut received = vec![0; reply.len()];
+        client.read_exact(&mut received).await.unwrap();
+        drop(backend);
+        drop(client);
+        let stats = handle.await.unwrap().unwrap();
+        assert_eq!(stats.end, TunnelEnd::Closed);
+        assert_eq!(stats.closed_by, Some(CloseInitiator::Client));
+        assert_eq!(stats.close_code, Some(1000));
+    }
+}
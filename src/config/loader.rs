@@ -1,8 +1,13 @@
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
 use thiserror::Error;
 use tokio::fs;
 
-use crate::config::models::ServerConfig;
+use crate::config::models::{LogFormat, RouteConfig, ServerConfig};
+use crate::config::schema;
 use crate::config::validation::{ConfigValidator, ValidationError};
 
 #[derive(Error, Debug)]
@@ -13,26 +18,659 @@ pub enum ConfigError {
     #[error("Failed to parse YAML config: {0}")]
     ParseError(#[from] serde_yaml::Error),
 
+    #[error("Failed to parse TOML config: {0}")]
+    TomlParseError(#[from] toml::de::Error),
+
+    #[error("Failed to parse JSON config: {0}")]
+    JsonParseError(#[from] serde_json::Error),
+
     #[error("Configuration validation failed: {0}")]
     ValidationError(#[from] ValidationError),
+
+    #[error("Environment variable {name} is not set and has no default (line {line})")]
+    MissingEnvVar { name: String, line: usize },
+
+    #[error(
+        "Invalid interpolation '{text}' on line {line}; write $${{...}} for a literal or \
+         turn interpolation off"
+    )]
+    InvalidInterpolation { text: String, line: usize },
+
+    #[error("Invalid include '{pattern}': {message}")]
+    InvalidInclude { pattern: String, message: String },
+
+    #[error("In included file {path}: {source}")]
+    IncludedFile {
+        path: String,
+        source: Box<ConfigError>,
+    },
+
+    #[error("Unknown configuration keys (strict mode): {}", keys.join(", "))]
+    UnknownKeys { keys: Vec<String> },
+
+    #[error("{kind} '{key}' is defined in both {first} and {second}")]
+    DuplicateKey {
+        kind: &'static str,
+        key: String,
+        first: String,
+        second: String,
+    },
 }
 
 pub type ConfigResult<T> = std::result::Result<T, ConfigError>;
 
+/// How config files are read
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Replace `${VAR}` and `${VAR:-default}` with environment variables before parsing
+    pub interpolate_env: bool,
+    /// Settings that win over the file, applied before it is validated
+    pub overrides: ConfigOverrides,
+    /// Reject unknown keys as if the file set `strict: true`
+    pub strict: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            interpolate_env: true,
+            overrides: ConfigOverrides::default(),
+            strict: false,
+        }
+    }
+}
+
+/// Settings given on the command line, which replace the file's on every load
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub listen_addr: Option<String>,
+    pub log_level: Option<String>,
+    pub log_format: Option<LogFormat>,
+    pub disable_health_checks: bool,
+}
+
+impl ConfigOverrides {
+    pub fn apply(&self, config: &mut ServerConfig) {
+        if let Some(listen_addr) = &self.listen_addr {
+            config.listen_addr = listen_addr.clone();
+        }
+        if let Some(log_level) = &self.log_level {
+            config.telemetry.log_level = Some(log_level.clone());
+        }
+        if let Some(log_format) = self.log_format {
+            config.telemetry.log_format = log_format;
+        }
+        if self.disable_health_checks {
+            config.health_check.enabled = false;
+        }
+    }
+}
+
+/// Config file syntax, picked by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// `.toml` and `.json` files by their extension, anything else as YAML
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigFormat::Yaml => "YAML",
+            ConfigFormat::Toml => "TOML",
+            ConfigFormat::Json => "JSON",
+        }
+    }
+
+    /// Deserialize `content`; parse errors carry the line and column
+    pub fn parse(self, content: &str) -> ConfigResult<ServerConfig> {
+        self.deserialize(content)
+    }
+
+    fn deserialize<T: DeserializeOwned>(self, content: &str) -> ConfigResult<T> {
+        Ok(match self {
+            ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+            ConfigFormat::Toml => toml::from_str(content)?,
+            ConfigFormat::Json => serde_json::from_str(content)?,
+        })
+    }
+}
+
 pub async fn load_config<P: AsRef<Path>>(path: P) -> ConfigResult<ServerConfig> {
-    let config_content = fs::read_to_string(path).await?;
-    let config: ServerConfig = serde_yaml::from_str(&config_content)?;
+    load_config_with(path, LoadOptions::default()).await
+}
+
+pub async fn load_config_with<P: AsRef<Path>>(
+    path: P,
+    options: LoadOptions,
+) -> ConfigResult<ServerConfig> {
+    let config = load_config_unchecked_with(path, options).await?;
     ConfigValidator::validate(&config)?;
     Ok(config)
 }
 
 pub async fn load_config_unchecked<P: AsRef<Path>>(path: P) -> ConfigResult<ServerConfig> {
-    let config_content = fs::read_to_string(path).await?;
-    let config: ServerConfig = serde_yaml::from_str(&config_content)?;
+    load_config_unchecked_with(path, LoadOptions::default()).await
+}
+
+pub async fn load_config_unchecked_with<P: AsRef<Path>>(
+    path: P,
+    options: LoadOptions,
+) -> ConfigResult<ServerConfig> {
+    let path = path.as_ref();
+    let content = read_config_text(path, &options).await?;
+    let format = ConfigFormat::from_path(path);
+    let mut config: ServerConfig = format.deserialize(&content)?;
+    let unknown = schema::unknown_keys(&format.deserialize(&content)?);
+    if !unknown.is_empty() {
+        if options.strict || config.strict {
+            return Err(ConfigError::UnknownKeys { keys: unknown });
+        }
+        for key in &unknown {
+            tracing::warn!("Ignoring unknown configuration key {}", key);
+        }
+    }
+    resolve_includes(path, &mut config, &options).await?;
+    options.overrides.apply(&mut config);
     Ok(config)
 }
 
+/// Keys in the config file at `path` that prox doesn't know, which strict mode rejects
+pub async fn unknown_config_keys<P: AsRef<Path>>(
+    path: P,
+    options: &LoadOptions,
+) -> ConfigResult<Vec<String>> {
+    let path = path.as_ref();
+    let content = read_config_text(path, options).await?;
+    Ok(schema::unknown_keys(
+        &ConfigFormat::from_path(path).deserialize(&content)?,
+    ))
+}
+
+async fn read_config<T: DeserializeOwned>(path: &Path, options: &LoadOptions) -> ConfigResult<T> {
+    let content = read_config_text(path, options).await?;
+    ConfigFormat::from_path(path).deserialize(&content)
+}
+
+async fn read_config_text(path: &Path, options: &LoadOptions) -> ConfigResult<String> {
+    let content = fs::read_to_string(path).await?;
+    if options.interpolate_env {
+        return interpolate_env(&content, |name| std::env::var(name).ok());
+    }
+    Ok(content)
+}
+
+/// What an included file may contain
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFragment {
+    #[serde(default)]
+    routes: HashMap<String, RouteConfig>,
+    #[serde(default)]
+    backend_health_paths: HashMap<String, String>,
+}
+
+/// An `include` entry, resolved against the directory of the config file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludePattern {
+    pattern: String,
+    /// Absolute, as file watchers report paths
+    dir: PathBuf,
+    file_name: String,
+}
+
+impl IncludePattern {
+    pub fn new(config_path: &Path, pattern: &str) -> ConfigResult<Self> {
+        let invalid = |message: &str| ConfigError::InvalidInclude {
+            pattern: pattern.to_string(),
+            message: message.to_string(),
+        };
+        let base_dir = config_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let path = base_dir.join(pattern);
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| invalid("it does not name a file"))?
+            .to_string();
+        let dir = path.parent().unwrap_or(base_dir);
+        if dir.to_string_lossy().contains(['*', '?']) {
+            return Err(invalid("only the file name may use * and ?"));
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            dir: std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf()),
+            file_name,
+        })
+    }
+
+    /// The directory the included files are in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether `path` is one of the included files
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        path.parent() == Some(self.dir.as_path())
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| wildcard_match(&self.file_name, name))
+    }
+
+    /// The included files, by name; a file named without wildcards must exist
+    async fn files(&self) -> ConfigResult<Vec<PathBuf>> {
+        if !self.file_name.contains(['*', '?']) {
+            return Ok(vec![self.dir.join(&self.file_name)]);
+        }
+        let unreadable = |e: std::io::Error| ConfigError::InvalidInclude {
+            pattern: self.pattern.clone(),
+            message: format!("cannot read {}: {e}", self.dir.display()),
+        };
+        let mut files = Vec::new();
+        let mut entries = fs::read_dir(&self.dir).await.map_err(unreadable)?;
+        while let Some(entry) = entries.next_entry().await.map_err(unreadable)? {
+            let path = entry.path();
+            if self.matches(&path) && fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// The `include` entries of the config loaded from `config_path`
+pub fn include_patterns(
+    config_path: &Path,
+    include: &[String],
+) -> ConfigResult<Vec<IncludePattern>> {
+    include
+        .iter()
+        .map(|pattern| IncludePattern::new(config_path, pattern))
+        .collect()
+}
+
+/// `*` matches any run of characters and `?` any one; like a shell, neither matches a
+/// leading dot
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // The last `*` seen and where in `name` it currently stops matching
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Merge the files `config.include` names, in the order listed and by file name within a
+/// pattern. A route or health path defined twice is an error naming both files.
+async fn resolve_includes(
+    path: &Path,
+    config: &mut ServerConfig,
+    options: &LoadOptions,
+) -> ConfigResult<()> {
+    if config.include.is_empty() {
+        return Ok(());
+    }
+    let main_file = path.display().to_string();
+    let mut route_sources: HashMap<String, String> = config
+        .routes
+        .keys()
+        .map(|route| (route.clone(), main_file.clone()))
+        .collect();
+    let mut health_path_sources: HashMap<String, String> = config
+        .backend_health_paths
+        .keys()
+        .map(|backend| (backend.clone(), main_file.clone()))
+        .collect();
+    // A file matched by several patterns, or the main file matching one, is read once
+    let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    let mut loaded = HashSet::from([canonical(path)]);
+
+    for pattern in include_patterns(path, &config.include)? {
+        for file in pattern.files().await? {
+            if !loaded.insert(canonical(&file)) {
+                continue;
+            }
+            let source = file.display().to_string();
+            let fragment: ConfigFragment =
+                read_config(&file, options)
+                    .await
+                    .map_err(|e| ConfigError::IncludedFile {
+                        path: source.clone(),
+                        source: Box::new(e),
+                    })?;
+            merge_unique(
+                "Route",
+                fragment.routes,
+                &mut config.routes,
+                &mut route_sources,
+                &source,
+            )?;
+            merge_unique(
+                "Backend health path",
+                fragment.backend_health_paths,
+                &mut config.backend_health_paths,
+                &mut health_path_sources,
+                &source,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn merge_unique<V>(
+    kind: &'static str,
+    entries: HashMap<String, V>,
+    into: &mut HashMap<String, V>,
+    sources: &mut HashMap<String, String>,
+    source: &str,
+) -> ConfigResult<()> {
+    let mut entries: Vec<_> = entries.into_iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (key, value) in entries {
+        if let Some(first) = sources.get(&key) {
+            return Err(ConfigError::DuplicateKey {
+                kind,
+                key,
+                first: first.clone(),
+                second: source.to_string(),
+            });
+        }
+        sources.insert(key.clone(), source.to_string());
+        into.insert(key, value);
+    }
+    Ok(())
+}
+
+/// Replace `${NAME}` with the variable `lookup` finds and `${NAME:-default}` with the
+/// default when it is unset or empty; `$${` stays a literal `${`
+pub fn interpolate_env(
+    content: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> ConfigResult<String> {
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let line = content[..content.len() - rest.len() + start]
+            .matches('\n')
+            .count()
+            + 1;
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix("${") {
+            output.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let Some(expression) = after.strip_prefix('{') else {
+            output.push('$');
+            rest = after;
+            continue;
+        };
+        let invalid = |text: &str| ConfigError::InvalidInterpolation {
+            text: text.to_string(),
+            line,
+        };
+        let end = expression
+            .find(['}', '\n'])
+            .filter(|&end| expression[end..].starts_with('}'))
+            .ok_or_else(|| invalid(&rest[start..start + 2]))?;
+        let (name, default) = match expression[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&expression[..end], None),
+        };
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(invalid(&rest[start..start + end + 3]));
+        }
+        match (lookup(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => output.push_str(default),
+            (Some(value), _) => output.push_str(&value),
+            (None, Some(default)) => output.push_str(default),
+            (None, None) => {
+                return Err(ConfigError::MissingEnvVar {
+                    name: name.to_string(),
+                    line,
+                });
+            }
+        }
+        rest = &expression[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
 pub fn validate_config(config: &ServerConfig) -> ConfigResult<()> {
     ConfigValidator::validate(config)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RouteConfig;
+
+    #[test]
+    fn test_interpolate_env() {
+        let lookup = |name: &str| match name {
+            "BACKEND_URL" => Some("http://api:8080".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        };
+        let interpolated = interpolate_env(
+            "target: \"${BACKEND_URL}\"\nemail: ${ACME_EMAIL:-ops@example.com}\n\
+             level: ${EMPTY:-info}\nbody: \"$${literal} costs $5\"\n",
+            lookup,
+        )
+        .unwrap();
+        assert_eq!(
+            interpolated,
+            "target: \"http://api:8080\"\nemail: ops@example.com\nlevel: info\n\
+             body: \"${literal} costs $5\"\n"
+        );
+
+        let missing = interpolate_env("a: 1\n${NOPE}: b\n", lookup).unwrap_err();
+        assert_eq!(
+            missing.to_string(),
+            "Environment variable NOPE is not set and has no default (line 2)"
+        );
+        assert!(matches!(
+            interpolate_env("body: \"${user.name}\"", lookup),
+            Err(ConfigError::InvalidInterpolation { line: 1, .. })
+        ));
+        assert!(matches!(
+            interpolate_env("body: \"${unclosed\"\n}", lookup),
+            Err(ConfigError::InvalidInterpolation { line: 1, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_overrides_replace_file_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prox.yaml");
+        std::fs::write(
+            &path,
+            "listen_addr: \"127.0.0.1:8080\"\nhealth_check: { enabled: true }\n\
+             routes:\n  \"/\": { type: proxy, target: \"http://web:8080\" }\n",
+        )
+        .unwrap();
+        let options = LoadOptions {
+            overrides: ConfigOverrides {
+                listen_addr: Some("127.0.0.1:9000".to_string()),
+                log_format: Some("text".parse().unwrap()),
+                disable_health_checks: true,
+                ..ConfigOverrides::default()
+            },
+            ..LoadOptions::default()
+        };
+        let config = load_config_with(&path, options).await.unwrap();
+        assert_eq!(config.listen_addr, "127.0.0.1:9000");
+        assert_eq!(config.telemetry.log_format, LogFormat::Text);
+        assert_eq!(config.telemetry.log_level, None);
+        assert!(!config.health_check.enabled);
+
+        let options = LoadOptions {
+            overrides: ConfigOverrides {
+                listen_addr: Some("nowhere".to_string()),
+                ..ConfigOverrides::default()
+            },
+            ..LoadOptions::default()
+        };
+        assert!(load_config_with(&path, options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_includes_merge_fragments() {
+        let dir = tempfile::tempdir().unwrap();
+        let routes_dir = dir.path().join("routes.d");
+        std::fs::create_dir(&routes_dir).unwrap();
+        let main = dir.path().join("prox.yaml");
+        std::fs::write(
+            &main,
+            "listen_addr: \"127.0.0.1:8080\"\ninclude: [\"routes.d/*.yaml\", \"extra.json\"]\n\
+             routes:\n  \"/\": { type: proxy, target: \"http://web:8080\" }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            routes_dir.join("api.yaml"),
+            "routes:\n  \"/api\": { type: proxy, target: \"http://api:8080\" }\n\
+             backend_health_paths:\n  \"http://api:8080\": \"/healthz\"\n",
+        )
+        .unwrap();
+        std::fs::write(routes_dir.join("notes.txt"), "not a fragment").unwrap();
+        std::fs::write(
+            dir.path().join("extra.json"),
+            r#"{"routes": {"/docs": {"type": "static", "root": "./docs"}}}"#,
+        )
+        .unwrap();
+
+        let config = load_config_unchecked(&main).await.unwrap();
+        let mut routes: Vec<_> = config.routes.keys().cloned().collect();
+        routes.sort();
+        assert_eq!(routes, ["/", "/api", "/docs"]);
+        assert_eq!(config.backend_health_paths["http://api:8080"], "/healthz");
+        let patterns = include_patterns(&main, &config.include).unwrap();
+        assert!(patterns[0].matches(&routes_dir.join("billing.yaml")));
+        assert!(!patterns[0].matches(&routes_dir.join(".billing.yaml.swp")));
+        assert!(!patterns[0].matches(&dir.path().join("billing.yaml")));
+
+        std::fs::write(
+            routes_dir.join("web.yaml"),
+            "routes:\n  \"/\": { type: proxy, target: \"http://other:8080\" }\n",
+        )
+        .unwrap();
+        let message = load_config_unchecked(&main).await.unwrap_err().to_string();
+        assert!(
+            message.starts_with("Route '/' is defined in both") && message.ends_with("web.yaml"),
+            "{message}"
+        );
+
+        std::fs::write(routes_dir.join("web.yaml"), "listen_addr: \"0.0.0.0:80\"\n").unwrap();
+        let message = load_config_unchecked(&main).await.unwrap_err().to_string();
+        assert!(message.contains("unknown field `listen_addr`"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn test_formats_by_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            (
+                "prox.yml",
+                "listen_addr: \"127.0.0.1:8080\"\nroutes:\n  \"/api\": { type: proxy, target: \"http://api:8080\" }\n",
+            ),
+            (
+                "prox.toml",
+                "listen_addr = \"127.0.0.1:8080\"\n\n[routes.\"/api\"]\ntype = \"proxy\"\ntarget = \"http://api:8080\"\n",
+            ),
+            (
+                "prox.json",
+                r#"{"listen_addr": "127.0.0.1:8080", "routes": {"/api": {"type": "proxy", "target": "http://api:8080"}}}"#,
+            ),
+        ];
+        for (name, content) in files {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            let config = load_config(&path).await.unwrap();
+            assert!(
+                matches!(&config.routes["/api"], RouteConfig::Proxy { target, .. } if target == "http://api:8080"),
+                "{name}"
+            );
+        }
+
+        let path = dir.path().join("broken.toml");
+        std::fs::write(&path, "listen_addr = \"127.0.0.1:8080\"\nroutes = [\n").unwrap();
+        let message = load_config(&path).await.unwrap_err().to_string();
+        assert!(
+            message.starts_with("Failed to parse TOML config"),
+            "{message}"
+        );
+        assert!(message.contains("line 2"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn test_strict_mode_rejects_unknown_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("prox.toml");
+        let content = "listen_addr = \"127.0.0.1:8080\"\n\n[routes.\"/api\"]\ntype = \"proxy\"\ntarget = \"http://api:8080\"\ntargest = \"http://typo:8080\"\n";
+        std::fs::write(&path, content).unwrap();
+        load_config(&path).await.unwrap();
+        let strict = LoadOptions {
+            strict: true,
+            ..LoadOptions::default()
+        };
+        let message = load_config_with(&path, strict)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(message.ends_with("routes.\"/api\".targest"), "{message}");
+
+        std::fs::write(&path, format!("strict = true\n{content}")).unwrap();
+        assert!(matches!(
+            load_config(&path).await,
+            Err(ConfigError::UnknownKeys { keys }) if keys.len() == 1
+        ));
+    }
+}
@@ -1,5 +1,7 @@
 pub mod loader;
 pub mod models;
+pub mod patch;
+pub mod schema;
 pub mod validation;
 
 pub use loader::load_config;
@@ -1,8 +1,13 @@
+use schemars::JsonSchema;
+
+use crate::config::validation::{ConfigValidator, ValidationError, ValidationResult};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 /// Protocol configuration for server capabilities
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(default)]
 pub struct ProtocolConfig {
     /// Enable HTTP/2 support (requires TLS for most clients)
@@ -17,10 +22,13 @@ pub struct ProtocolConfig {
     pub http2_max_concurrent_streams: Option<u32>,
     /// HTTP/3 specific configuration
     pub http3_config: Option<Http3Config>,
+    /// UDP address of the HTTP/3 listener, when it isn't `listen_addr`. Its port is the one
+    /// advertised in Alt-Svc.
+    pub http3_listen_addr: Option<String>,
 }
 
 /// HTTP/3 specific configuration options
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(default)]
 pub struct Http3Config {
     /// Maximum data per connection (in bytes)
@@ -37,6 +45,15 @@ pub struct Http3Config {
     pub enable_0rtt: bool,
     /// Maximum packet size (in bytes)
     pub max_packet_size: Option<u16>,
+    /// Largest request body accepted, since HTTP/3 bodies are buffered before proxying
+    pub max_request_body_size: usize,
+    /// Validate client addresses with a Retry before keeping any connection state
+    pub retry: bool,
+    /// Directory to write a qlog file per connection to, for debugging with qvis and the
+    /// like. Disabled when unset.
+    pub qlog_dir: Option<String>,
+    /// Most qlog files kept in `qlog_dir`; the oldest are deleted first
+    pub qlog_max_files: usize,
 }
 
 impl Default for Http3Config {
@@ -49,12 +66,16 @@ impl Default for Http3Config {
             congestion_control: Http3CongestionControl::Cubic,
             enable_0rtt: true,
             max_packet_size: None,
+            max_request_body_size: 10 * 1024 * 1024,
+            retry: false,
+            qlog_dir: None,
+            qlog_max_files: 100,
         }
     }
 }
 
 /// HTTP/3 congestion control algorithms
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Http3CongestionControl {
     Cubic,
@@ -70,11 +91,12 @@ impl Default for ProtocolConfig {
             http2_max_frame_size: None, // Use hyper defaults
             http2_max_concurrent_streams: None, // Use hyper defaults
             http3_config: None,   // Use defaults when HTTP/3 is enabled
+            http3_listen_addr: None, // Same address as the TCP listener
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
 pub struct HeaderActions {
     #[serde(default)]
     pub add: HashMap<String, String>,
@@ -84,7 +106,7 @@ pub struct HeaderActions {
     pub condition: Option<RequestCondition>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
 pub struct BodyActions {
     #[serde(default)]
     pub set_text: Option<String>, // Set the entire body to this text
@@ -98,7 +120,7 @@ pub struct BodyActions {
     // pub transform_script: Option<String>, // For more complex transformations
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct RequestCondition {
     #[serde(default)]
     pub path_matches: Option<String>, // Regex to match the request path
@@ -109,24 +131,435 @@ pub struct RequestCondition {
     // Potentially add more conditions: client_ip_is, query_param_is, etc.
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct HeaderCondition {
     pub name: String,
     pub value_matches: Option<String>, // Regex to match header value
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct ServerConfig {
     pub listen_addr: String,
+    /// Routes of the default virtual host, used when no entry in `virtual_hosts` matches
+    #[serde(default)]
     pub routes: HashMap<String, RouteConfig>,
+    /// Per-hostname route maps, keyed by "example.com" or "*.example.com"
+    #[serde(default)]
+    pub virtual_hosts: HashMap<String, VirtualHostConfig>,
     #[serde(default)]
     pub tls: Option<TlsConfig>,
     #[serde(default)]
     pub health_check: HealthCheckConfig,
     #[serde(default)]
     pub backend_health_paths: HashMap<String, String>,
+    /// Health check settings overridden per backend target
+    #[serde(default)]
+    pub backend_health_checks: HashMap<String, HealthCheckOverride>,
     #[serde(default)]
     pub protocols: ProtocolConfig,
+    /// Error pages applied to every route, keyed by status ("404") or class ("5xx")
+    #[serde(default)]
+    pub error_pages: HashMap<String, ErrorPageConfig>,
+    /// Canonicalize request paths (duplicate slashes, trailing slashes on prefixes)
+    #[serde(default)]
+    pub normalize_paths: PathNormalization,
+    /// Catch-all route for requests that match no route, instead of a 404
+    #[serde(default)]
+    pub default_route: Option<Box<RouteConfig>>,
+    /// Write a line per request to a file; read at startup only
+    #[serde(default)]
+    pub access_log: Option<AccessLogConfig>,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    /// Say why a backend request failed in the 502/504 body, for debugging only
+    #[serde(default)]
+    pub debug_errors: bool,
+    /// Add `X-Prox-Backend`, `X-Prox-Route` and `X-Prox-Duration-Ms` to responses; routes
+    /// can override it with their own `debug_headers`
+    #[serde(default)]
+    pub debug_headers: bool,
+    /// Headers whose values are kept out of logs
+    #[serde(default)]
+    pub log_redaction: LogRedactionConfig,
+    /// Who may reach `/metrics` and the `/-/` endpoints, and where they are served
+    #[serde(default)]
+    pub admin: AdminConfig,
+    /// How `/metrics` is recorded; read at startup only
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// How the listeners wind down on SIGTERM, SIGINT or SIGUSR1; read at startup only
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    /// When prox closes client connections that are still in use, so clients reconnect and
+    /// spread over the replicas; applies to connections accepted after a reload
+    #[serde(default)]
+    pub connection_limits: ConnectionLimitsConfig,
+    /// Files whose `routes` and `backend_health_paths` are merged into this config, relative
+    /// to it; file names may use `*` and `?`. Only resolved when loading from a file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Refuse to load a file with keys prox doesn't know, instead of warning about them
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// Prometheus recorder settings
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Histogram bucket bounds in seconds, for every histogram. Unset, only the built-in
+    /// layer's request duration is a histogram and the others are summaries.
+    pub buckets: Option<Vec<f64>>,
+    /// Name the built-in layer's metrics `{prefix}_http_requests_total` and so on,
+    /// instead of `axum_http_...`
+    pub prefix: Option<String>,
+    /// Record the `axum_http_*` metrics; off leaves only the `prox_*` ones
+    pub builtin_layer: bool,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            buckets: None,
+            prefix: None,
+            builtin_layer: true,
+        }
+    }
+}
+
+/// Durations, such as "30s", of each step between a shutdown signal and exit
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// Keep serving normally this long after the signal, while a load balancer or
+    /// Kubernetes takes the instance out of rotation
+    pub grace_period_before_drain: String,
+    /// Then refuse new requests and give in-flight ones this long to finish
+    pub drain_timeout: String,
+    /// Exit this long after the signal even if requests or listeners are still busy;
+    /// unset waits for the drain
+    pub force_close_after: Option<String>,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            grace_period_before_drain: "0s".to_string(),
+            drain_timeout: "30s".to_string(),
+            force_close_after: None,
+        }
+    }
+}
+
+impl ShutdownConfig {
+    pub fn grace_period_before_drain(&self) -> Duration {
+        humantime::parse_duration(&self.grace_period_before_drain).unwrap_or_default()
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        humantime::parse_duration(&self.drain_timeout).unwrap_or(Duration::from_secs(30))
+    }
+
+    pub fn force_close_after(&self) -> Option<Duration> {
+        self.force_close_after
+            .as_deref()
+            .and_then(|after| humantime::parse_duration(after).ok())
+    }
+}
+
+/// Limits after which a TCP connection is asked to close: HTTP/1.1 responses get
+/// `Connection: close` and HTTP/2 connections a GOAWAY
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(default)]
+pub struct ConnectionLimitsConfig {
+    /// Close connections after this long, such as "10m", give or take 10% so they don't all
+    /// close at once; unset keeps them open
+    pub max_connection_age: Option<String>,
+    /// How long requests in flight on a connection being closed get to finish
+    pub max_connection_age_grace: String,
+    /// Close connections after serving this many requests; unset has no limit
+    pub max_requests_per_connection: Option<u64>,
+}
+
+impl Default for ConnectionLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_connection_age: None,
+            max_connection_age_grace: "30s".to_string(),
+            max_requests_per_connection: None,
+        }
+    }
+}
+
+impl ConnectionLimitsConfig {
+    pub fn max_connection_age(&self) -> Option<Duration> {
+        self.max_connection_age
+            .as_deref()
+            .and_then(|age| humantime::parse_duration(age).ok())
+    }
+
+    pub fn max_connection_age_grace(&self) -> Duration {
+        humantime::parse_duration(&self.max_connection_age_grace).unwrap_or(Duration::from_secs(30))
+    }
+}
+
+/// Access to the admin endpoints (`/metrics` and everything under `/-/`). Only
+/// `listen_addr` is read at startup; the rest applies on reload.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct AdminConfig {
+    /// Serve the admin endpoints on this socket, e.g. "127.0.0.1:9901", instead of
+    /// `listen_addr`
+    #[serde(default)]
+    pub listen_addr: Option<String>,
+    /// Credentials required on every admin request
+    #[serde(default)]
+    pub auth: Option<AdminAuth>,
+    /// Client addresses or CIDR blocks allowed to reach the admin endpoints (empty allows any)
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    /// Start even though the admin endpoints are open to anyone on a non-loopback address
+    #[serde(default)]
+    pub allow_unauthenticated: bool,
+    /// Serve the admin listener over HTTPS, optionally requiring client certificates;
+    /// needs `listen_addr` and is read at startup
+    #[serde(default)]
+    pub tls: Option<AdminTlsConfig>,
+    /// Previously applied configs kept for `/-/config/history` and rollback
+    #[serde(default)]
+    pub history: ConfigHistoryConfig,
+}
+
+/// How many applied configs to remember, and where to keep them across restarts
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct ConfigHistoryConfig {
+    /// Applied configs kept, the running one included
+    pub max_entries: usize,
+    /// Also write the history to `<state_dir>/config-history.json` and pick it up again
+    /// at startup, so versions keep counting and rollback works after a restart
+    pub state_dir: Option<String>,
+}
+
+impl Default for ConfigHistoryConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10,
+            state_dir: None,
+        }
+    }
+}
+
+/// HTTPS for the separate admin listener
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct AdminTlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    /// Only accept clients presenting a certificate issued by this CA (mutual TLS)
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+}
+
+/// Credentials for the admin endpoints
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AdminAuth {
+    /// `Authorization: Bearer <token>`, with the token given inline or read from
+    /// `token_file` on each request so it can be rotated without a reload
+    Bearer {
+        #[serde(default)]
+        token: String,
+        #[serde(default)]
+        token_file: Option<String>,
+    },
+    /// `Authorization: Basic ...`
+    Basic { username: String, password: String },
+}
+
+impl std::fmt::Debug for AdminAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdminAuth::Bearer { token_file, .. } => f
+                .debug_struct("Bearer")
+                .field("token", &"<redacted>")
+                .field("token_file", token_file)
+                .finish(),
+            AdminAuth::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
+/// Header values logged as `[REDACTED]`: authorization, cookie, set-cookie, x-api-key and
+/// proxy-authorization, plus `headers`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct LogRedactionConfig {
+    /// Redacted on top of the defaults
+    #[serde(default)]
+    pub headers: Vec<String>,
+    /// Log every value as is, for local debugging
+    #[serde(default)]
+    pub redact_none: bool,
+}
+
+/// Logging, tracing and trace export settings. `log_level` and `b3_propagation` apply on
+/// reload; the rest at startup only.
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct TelemetryConfig {
+    /// `service.name` of exported spans
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Export spans to an OpenTelemetry collector
+    #[serde(default)]
+    pub otlp: Option<OtlpConfig>,
+    /// Fraction of traces started by prox that are sampled; traces joined from a caller
+    /// keep the caller's decision
+    #[serde(default = "default_sampling_ratio")]
+    pub sampling_ratio: f64,
+    /// Log filter such as "info" or "warn,prox::adapters=debug"; RUST_LOG when unset
+    #[serde(default)]
+    pub log_level: Option<String>,
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// Also read and send Zipkin B3 headers, for services that predate W3C Trace Context
+    #[serde(default)]
+    pub b3_propagation: bool,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            service_name: default_service_name(),
+            otlp: None,
+            sampling_ratio: default_sampling_ratio(),
+            log_level: None,
+            log_format: LogFormat::default(),
+            b3_propagation: false,
+        }
+    }
+}
+
+fn default_service_name() -> String {
+    "prox".to_string()
+}
+
+fn default_sampling_ratio() -> f64 {
+    1.0
+}
+
+/// OTLP/HTTP span exporter
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct OtlpConfig {
+    /// Traces URL of the collector, e.g. "http://collector:4318/v1/traces"
+    pub endpoint: String,
+    /// Sent with every export, e.g. the collector's credentials
+    #[serde(default)]
+    pub headers: SecretHeaders,
+    #[serde(default = "default_otlp_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_otlp_timeout_secs() -> u64 {
+    10
+}
+
+/// Log line format
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// One JSON object per line
+    #[default]
+    Json,
+    /// Human-readable lines
+    Text,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(LogFormat::Json),
+            "text" => Ok(LogFormat::Text),
+            _ => Err(format!(
+                "unknown log format '{s}', expected 'json' or 'text'"
+            )),
+        }
+    }
+}
+
+/// Access log file and its rotation
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct AccessLogConfig {
+    pub path: String,
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// Rotate once the file would grow past this many bytes
+    #[serde(default)]
+    pub max_size_bytes: Option<u64>,
+    /// Rotate when the UTC date changes
+    #[serde(default)]
+    pub rotate_daily: bool,
+    /// Rotated files kept as `<path>.1` (newest) to `<path>.<max_files>`
+    #[serde(default = "default_access_log_max_files")]
+    pub max_files: usize,
+    /// Lines queued for the writer before new ones are dropped rather than waited on
+    #[serde(default = "default_access_log_buffer_lines")]
+    pub buffer_lines: usize,
+}
+
+fn default_access_log_max_files() -> usize {
+    7
+}
+
+fn default_access_log_buffer_lines() -> usize {
+    8192
+}
+
+/// Access log line format
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLogFormat {
+    /// Apache/NGINX combined log format, plus the duration in milliseconds
+    #[default]
+    Combined,
+    /// One JSON object per line
+    Json,
+}
+
+/// How request paths are canonicalized before route matching
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PathNormalization {
+    /// Match the path exactly as received
+    #[default]
+    Off,
+    /// Match on the normalized path but forward the original one
+    Internal,
+    /// Redirect non-canonical paths to their normalized form
+    Redirect,
+}
+
+/// Routes served for requests whose Host matches a virtual host pattern
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct VirtualHostConfig {
+    pub routes: HashMap<String, RouteConfig>,
+}
+
+/// Replacement body for an error response, loaded from a file or given inline
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct ErrorPageConfig {
+    /// Path to the page; Content-Type is derived from its extension
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Inline body, used when no file is given
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Overrides the derived Content-Type (inline bodies default to text/html)
+    #[serde(default)]
+    pub content_type: Option<String>,
 }
 
 impl ServerConfig {
@@ -134,6 +567,123 @@ impl ServerConfig {
     pub fn builder() -> ServerConfigBuilder {
         ServerConfigBuilder::default()
     }
+
+    /// Address the HTTP/3 listener binds: `protocols.http3_listen_addr`, else `listen_addr`
+    pub fn http3_listen_addr(&self) -> Result<SocketAddr, std::net::AddrParseError> {
+        self.protocols
+            .http3_listen_addr
+            .as_deref()
+            .unwrap_or(&self.listen_addr)
+            .parse()
+    }
+
+    /// Every route: the top-level ones, each virtual host's and the default route
+    fn all_routes(&self) -> impl Iterator<Item = &RouteConfig> {
+        self.routes
+            .values()
+            .chain(
+                self.virtual_hosts
+                    .values()
+                    .flat_map(|vhost| vhost.routes.values()),
+            )
+            .chain(self.default_route.as_deref())
+    }
+
+    /// A copy safe to hand out: credentials and shared secrets are replaced by
+    /// `<redacted>`, file paths are kept
+    pub fn redacted(&self) -> ServerConfig {
+        const REDACTED: &str = "<redacted>";
+        let mut config = self.clone();
+
+        match &mut config.admin.auth {
+            Some(AdminAuth::Bearer { token, .. }) if !token.is_empty() => {
+                *token = REDACTED.to_string()
+            }
+            Some(AdminAuth::Basic { password, .. }) => *password = REDACTED.to_string(),
+            _ => {}
+        }
+        if let Some(acme) = config.tls.as_mut().and_then(|tls| tls.acme.as_mut()) {
+            if let Some(key) = acme.eab_hmac_key.as_mut() {
+                *key = REDACTED.to_string();
+            }
+            match acme.dns.as_mut().map(|dns| &mut dns.provider) {
+                Some(DnsProviderConfig::Cloudflare { api_token, .. }) => {
+                    *api_token = REDACTED.to_string()
+                }
+                Some(DnsProviderConfig::Rfc2136 {
+                    tsig_secret: Some(secret),
+                    ..
+                }) => *secret = REDACTED.to_string(),
+                _ => {}
+            }
+        }
+
+        let route_health_checks = config
+            .routes
+            .values_mut()
+            .chain(
+                config
+                    .virtual_hosts
+                    .values_mut()
+                    .flat_map(|vhost| vhost.routes.values_mut()),
+            )
+            .chain(config.default_route.as_deref_mut())
+            .filter_map(|route| match route {
+                RouteConfig::LoadBalance {
+                    health_check: Some(health_check),
+                    ..
+                } => Some(&mut health_check.headers),
+                _ => None,
+            });
+        for headers in std::iter::once(&mut config.health_check.headers)
+            .chain(
+                config
+                    .backend_health_checks
+                    .values_mut()
+                    .map(|c| &mut c.headers),
+            )
+            .chain(config.telemetry.otlp.as_mut().map(|otlp| &mut otlp.headers))
+            .chain(route_health_checks)
+        {
+            headers
+                .0
+                .values_mut()
+                .for_each(|value| *value = REDACTED.to_string());
+        }
+        config
+    }
+
+    /// `health_check` overrides of the load balance routes listing `target`
+    pub fn route_health_checks<'a>(
+        &'a self,
+        target: &'a str,
+    ) -> impl Iterator<Item = &'a HealthCheckOverride> {
+        self.all_routes().filter_map(move |route| match route {
+            RouteConfig::LoadBalance {
+                targets,
+                health_check: Some(health_check),
+                ..
+            } if targets.iter().any(|t| t == target) => Some(health_check.as_ref()),
+            _ => None,
+        })
+    }
+
+    /// The health check settings for `target`: the global ones, overridden by its
+    /// `backend_health_paths` entry, then the load balance route listing it, then its
+    /// `backend_health_checks` entry
+    pub fn backend_health_check(&self, target: &str) -> HealthCheckConfig {
+        let mut config = self.health_check.clone();
+        if let Some(path) = self.backend_health_paths.get(target) {
+            config.path = path.clone();
+        }
+        if let Some(route) = self.route_health_checks(target).next() {
+            config = config.with_override(route);
+        }
+        match self.backend_health_checks.get(target) {
+            Some(backend) => config.with_override(backend),
+            None => config,
+        }
+    }
 }
 
 /// Builder for ServerConfig to allow for cleaner configuration creation
@@ -141,10 +691,24 @@ impl ServerConfig {
 pub struct ServerConfigBuilder {
     listen_addr: Option<String>,
     routes: HashMap<String, RouteConfig>,
+    virtual_hosts: HashMap<String, VirtualHostConfig>,
     tls: Option<TlsConfig>,
     health_check: Option<HealthCheckConfig>,
     backend_health_paths: HashMap<String, String>,
+    backend_health_checks: HashMap<String, HealthCheckOverride>,
     protocols: Option<ProtocolConfig>,
+    error_pages: HashMap<String, ErrorPageConfig>,
+    normalize_paths: PathNormalization,
+    default_route: Option<RouteConfig>,
+    access_log: Option<AccessLogConfig>,
+    telemetry: TelemetryConfig,
+    debug_errors: bool,
+    debug_headers: bool,
+    log_redaction: LogRedactionConfig,
+    admin: AdminConfig,
+    metrics: MetricsConfig,
+    shutdown: ShutdownConfig,
+    connection_limits: ConnectionLimitsConfig,
 }
 
 impl ServerConfigBuilder {
@@ -160,26 +724,43 @@ impl ServerConfigBuilder {
         self
     }
 
-    /// Set TLS configuration with manual certificate paths
-    pub fn tls(mut self, cert_path: impl Into<String>, key_path: impl Into<String>) -> Self {
+    /// Add a virtual host with its own routes, e.g. "api.example.com" or "*.example.com"
+    pub fn virtual_host(mut self, host: impl Into<String>, config: VirtualHostConfig) -> Self {
+        self.virtual_hosts.insert(host.into(), config);
+        self
+    }
+
+    /// Serve TLS with a certificate and key read from files, replacing any ACME settings
+    pub fn tls_manual(mut self, cert_path: impl Into<String>, key_path: impl Into<String>) -> Self {
         self.tls = Some(TlsConfig {
             cert_path: Some(cert_path.into()),
             key_path: Some(key_path.into()),
             acme: None,
+            ..self.tls.unwrap_or_default()
         });
         self
     }
 
-    /// Set ACME configuration for automatic certificate management
-    pub fn acme(mut self, acme_config: AcmeConfig) -> Self {
+    /// Serve TLS with certificates obtained over ACME, replacing any certificate files
+    pub fn tls_acme(mut self, acme_config: AcmeConfig) -> Self {
         self.tls = Some(TlsConfig {
             cert_path: None,
             key_path: None,
             acme: Some(acme_config),
+            ..self.tls.unwrap_or_default()
         });
         self
     }
 
+    /// Add a certificate served to clients asking for one of its domains (via SNI)
+    pub fn tls_certificate(mut self, certificate: TlsCertificate) -> Self {
+        self.tls
+            .get_or_insert_with(TlsConfig::default)
+            .certificates
+            .push(certificate);
+        self
+    }
+
     /// Set health check configuration
     pub fn health_check(mut self, config: HealthCheckConfig) -> Self {
         self.health_check = Some(config);
@@ -197,34 +778,133 @@ impl ServerConfigBuilder {
         self
     }
 
+    /// Override health check settings for a backend
+    pub fn backend_health_check(
+        mut self,
+        backend: impl Into<String>,
+        config: HealthCheckOverride,
+    ) -> Self {
+        self.backend_health_checks.insert(backend.into(), config);
+        self
+    }
+
     /// Set protocol configuration
     pub fn protocols(mut self, config: ProtocolConfig) -> Self {
         self.protocols = Some(config);
         self
     }
 
-    /// Build the final ServerConfig
-    pub fn build(self) -> Result<ServerConfig, String> {
+    /// Add a global error page for a status ("404") or status class ("5xx")
+    pub fn error_page(mut self, status: impl Into<String>, page: ErrorPageConfig) -> Self {
+        self.error_pages.insert(status.into(), page);
+        self
+    }
+
+    /// Set how request paths are normalized before route matching
+    pub fn normalize_paths(mut self, mode: PathNormalization) -> Self {
+        self.normalize_paths = mode;
+        self
+    }
+
+    /// Set the catch-all route used when no other route matches
+    pub fn default_route(mut self, config: RouteConfig) -> Self {
+        self.default_route = Some(config);
+        self
+    }
+
+    /// Write an access log
+    pub fn access_log(mut self, config: AccessLogConfig) -> Self {
+        self.access_log = Some(config);
+        self
+    }
+
+    /// Set the tracing settings
+    pub fn telemetry(mut self, config: TelemetryConfig) -> Self {
+        self.telemetry = config;
+        self
+    }
+
+    /// Explain backend failures in error responses
+    pub fn debug_errors(mut self, enabled: bool) -> Self {
+        self.debug_errors = enabled;
+        self
+    }
+
+    /// Stamp responses with the backend, route and duration that produced them
+    pub fn debug_headers(mut self, enabled: bool) -> Self {
+        self.debug_headers = enabled;
+        self
+    }
+
+    /// Set which header values are kept out of logs
+    pub fn log_redaction(mut self, config: LogRedactionConfig) -> Self {
+        self.log_redaction = config;
+        self
+    }
+
+    /// Set access to the admin endpoints
+    pub fn admin(mut self, config: AdminConfig) -> Self {
+        self.admin = config;
+        self
+    }
+
+    /// Set how metrics are recorded
+    pub fn metrics(mut self, config: MetricsConfig) -> Self {
+        self.metrics = config;
+        self
+    }
+
+    /// Set how the listeners wind down on shutdown
+    pub fn shutdown(mut self, config: ShutdownConfig) -> Self {
+        self.shutdown = config;
+        self
+    }
+
+    /// Set when client connections are asked to close
+    pub fn connection_limits(mut self, config: ConnectionLimitsConfig) -> Self {
+        self.connection_limits = config;
+        self
+    }
+
+    /// Build the final ServerConfig, checked by the same `ConfigValidator` as config files
+    /// and configs sent to `/-/config`
+    pub fn build(self) -> ValidationResult<ServerConfig> {
         let listen_addr = self
             .listen_addr
-            .ok_or_else(|| "listen_addr is required".to_string())?;
+            .ok_or_else(|| ValidationError::MissingField {
+                field: "listen_addr".to_string(),
+            })?;
 
-        if self.routes.is_empty() {
-            return Err("At least one route must be configured".to_string());
-        }
-
-        Ok(ServerConfig {
+        let config = ServerConfig {
             listen_addr,
             routes: self.routes,
+            virtual_hosts: self.virtual_hosts,
             tls: self.tls,
             health_check: self.health_check.unwrap_or_default(),
             backend_health_paths: self.backend_health_paths,
+            backend_health_checks: self.backend_health_checks,
             protocols: self.protocols.unwrap_or_default(),
-        })
+            error_pages: self.error_pages,
+            normalize_paths: self.normalize_paths,
+            default_route: self.default_route.map(Box::new),
+            access_log: self.access_log,
+            telemetry: self.telemetry,
+            debug_errors: self.debug_errors,
+            debug_headers: self.debug_headers,
+            log_redaction: self.log_redaction,
+            admin: self.admin,
+            metrics: self.metrics,
+            shutdown: self.shutdown,
+            connection_limits: self.connection_limits,
+            include: Vec::new(),
+            strict: false,
+        };
+        ConfigValidator::validate(&config)?;
+        Ok(config)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
 pub struct TlsConfig {
     // Manual certificate paths (existing functionality)
     pub cert_path: Option<String>,
@@ -232,9 +912,29 @@ pub struct TlsConfig {
 
     // ACME configuration (new functionality)
     pub acme: Option<AcmeConfig>,
+
+    /// Extra certificates picked by SNI; the pair above (or ACME) is the default for
+    /// clients asking for any other name
+    #[serde(default)]
+    pub certificates: Vec<TlsCertificate>,
+
+    /// Fetch OCSP responses from each certificate's issuer and staple them to handshakes
+    #[serde(default)]
+    pub ocsp_stapling: bool,
+
+    /// OCSP responder to query instead of the one named in the certificates
+    pub ocsp_responder: Option<String>,
+}
+
+/// A certificate for a set of domains, e.g. `["api.other.com", "*.other.com"]`
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct TlsCertificate {
+    pub domains: Vec<String>,
+    pub cert_path: String,
+    pub key_path: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct AcmeConfig {
     pub enabled: bool,
     pub domains: Vec<String>,
@@ -243,28 +943,303 @@ pub struct AcmeConfig {
     pub staging: Option<bool>,  // Use Let's Encrypt staging for testing
     pub storage_path: Option<String>, // Where to store certificates and private keys
     pub renewal_days_before_expiry: Option<u64>, // How many days before expiry to renew
+    /// How domain ownership is proven; wildcard domains need "dns-01"
+    #[serde(default)]
+    pub challenge_type: AcmeChallengeType,
+    /// Where "dns-01" challenge records are published
+    #[serde(default)]
+    pub dns: Option<AcmeDnsConfig>,
+    /// External Account Binding key ID, for CAs such as ZeroSSL that require it
+    pub eab_kid: Option<String>,
+    /// External Account Binding HMAC key, base64url as handed out by the CA
+    pub eab_hmac_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub enum AcmeChallengeType {
+    /// Serve the token under /.well-known/acme-challenge/ (needs port 80)
+    #[default]
+    #[serde(rename = "http-01")]
+    Http01,
+    /// Publish the token as a `_acme-challenge` TXT record
+    #[serde(rename = "dns-01")]
+    Dns01,
+}
+
+/// DNS provider for "dns-01" challenges and how to check the record has propagated
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct AcmeDnsConfig {
+    #[serde(flatten)]
+    pub provider: DnsProviderConfig,
+    /// How long to wait for the TXT record to be visible, e.g. "2m" (the default)
+    pub propagation_timeout: Option<String>,
+    /// Resolvers ("ip" or "ip:port") that must all see the record before validation is
+    /// requested; defaults to the nameservers in /etc/resolv.conf
+    #[serde(default)]
+    pub resolvers: Vec<String>,
+}
+
+impl AcmeDnsConfig {
+    /// Parse "ip:port", or a bare IP with the DNS port
+    pub fn parse_name_server(value: &str) -> Option<SocketAddr> {
+        value
+            .parse()
+            .ok()
+            .or_else(|| value.parse().ok().map(|ip| SocketAddr::new(ip, 53)))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum DnsProviderConfig {
+    /// Cloudflare API; the token needs Zone.DNS edit permission
+    Cloudflare {
+        api_token: String,
+        /// Looked up from the record name when omitted
+        zone_id: Option<String>,
+    },
+    /// RFC 2136 dynamic update sent to the zone's primary name server
+    Rfc2136 {
+        server: String,
+        zone: String,
+        tsig_key_name: Option<String>,
+        /// Base64 shared secret, as in a BIND `key` statement
+        tsig_secret: Option<String>,
+        #[serde(default)]
+        tsig_algorithm: TsigAlgorithm,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum TsigAlgorithm {
+    #[default]
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(default)]
 pub struct HealthCheckConfig {
     pub enabled: bool,
+    /// How backends are probed
+    #[serde(rename = "type")]
+    pub check_type: HealthCheckType,
     pub interval_secs: u64,
     pub timeout_secs: u64,
     pub path: String,
     pub unhealthy_threshold: u32,
     pub healthy_threshold: u32,
+    /// Seconds after a backend is first seen during which failures are logged but don't
+    /// count toward `unhealthy_threshold`, so backends booting alongside prox aren't ejected
+    pub grace_period_secs: u64,
+    /// Request method; HEAD by default, GET when the body is matched
+    pub method: Option<String>,
+    /// Statuses counted as healthy; any 2xx when empty
+    pub expected_statuses: Vec<u16>,
+    /// Text the response body must contain
+    pub body_contains: Option<String>,
+    /// Value the response body, parsed as JSON, must hold at a path
+    pub body_json_path: Option<JsonPathMatch>,
+    /// Most checks in flight at once across all backends
+    pub max_concurrent_checks: usize,
+    /// Headers sent with each check, e.g. an auth token; values are redacted when logged
+    pub headers: SecretHeaders,
+    /// Port to check instead of the backend's traffic port
+    pub port: Option<u16>,
+    /// Scheme to check with instead of the backend's, "http" or "https"
+    pub scheme: Option<String>,
 }
 
 impl Default for HealthCheckConfig {
     fn default() -> Self {
         Self {
             enabled: true,
+            check_type: HealthCheckType::Http,
             interval_secs: 10,
             timeout_secs: 2,
             path: "/health".to_string(),
             unhealthy_threshold: 3,
             healthy_threshold: 2,
+            grace_period_secs: 0,
+            method: None,
+            expected_statuses: Vec::new(),
+            body_contains: None,
+            body_json_path: None,
+            max_concurrent_checks: 10,
+            headers: SecretHeaders::default(),
+            port: None,
+            scheme: None,
+        }
+    }
+}
+
+impl HealthCheckConfig {
+    /// How long after a backend is first seen its failures don't count
+    pub fn grace_period(&self) -> Duration {
+        Duration::from_secs(self.grace_period_secs)
+    }
+
+    /// Whether the check looks at the response body, not just its status
+    pub fn matches_body(&self) -> bool {
+        self.body_contains.is_some() || self.body_json_path.is_some()
+    }
+
+    /// Method the check is sent with
+    pub fn method(&self) -> &str {
+        match &self.method {
+            Some(method) => method,
+            None if self.matches_body() => "GET",
+            None => "HEAD",
+        }
+    }
+
+    /// This config with the settings `backend` overrides replaced
+    pub fn with_override(&self, backend: &HealthCheckOverride) -> Self {
+        let mut config = self.clone();
+        if let Some(check_type) = backend.check_type {
+            config.check_type = check_type;
+        }
+        if let Some(interval_secs) = backend.interval_secs {
+            config.interval_secs = interval_secs;
+        }
+        if let Some(timeout_secs) = backend.timeout_secs {
+            config.timeout_secs = timeout_secs;
+        }
+        if let Some(unhealthy_threshold) = backend.unhealthy_threshold {
+            config.unhealthy_threshold = unhealthy_threshold;
+        }
+        if let Some(healthy_threshold) = backend.healthy_threshold {
+            config.healthy_threshold = healthy_threshold;
+        }
+        if let Some(grace_period_secs) = backend.grace_period_secs {
+            config.grace_period_secs = grace_period_secs;
+        }
+        if let Some(path) = &backend.path {
+            config.path = path.clone();
+        }
+        if let Some(method) = &backend.method {
+            config.method = Some(method.clone());
+        }
+        if let Some(expected_statuses) = &backend.expected_statuses {
+            config.expected_statuses = expected_statuses.clone();
+        }
+        if let Some(body_contains) = &backend.body_contains {
+            config.body_contains = Some(body_contains.clone());
+        }
+        if let Some(body_json_path) = &backend.body_json_path {
+            config.body_json_path = Some(body_json_path.clone());
+        }
+        // Added to, rather than replacing, the global headers
+        config.headers.0.extend(
+            backend
+                .headers
+                .0
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        if let Some(port) = backend.port {
+            config.port = Some(port);
+        }
+        if let Some(scheme) = &backend.scheme {
+            config.scheme = Some(scheme.clone());
+        }
+        config
+    }
+}
+
+/// Header names and values that may carry credentials, so only the names are logged
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, JsonSchema)]
+#[serde(transparent)]
+pub struct SecretHeaders(pub HashMap<String, String>);
+
+impl std::fmt::Debug for SecretHeaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.0.keys().map(|name| (name, "<redacted>")))
+            .finish()
+    }
+}
+
+/// How a health check probes a backend
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthCheckType {
+    /// Request the health check path and judge the response
+    #[default]
+    Http,
+    /// Only open a TCP connection, for backends without an HTTP health endpoint
+    Tcp,
+}
+
+/// Expected value at a dotted path into a JSON document, e.g. `status` or
+/// `checks.0.state` (numbers index arrays)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct JsonPathMatch {
+    pub path: String,
+    pub equals: serde_json::Value,
+}
+
+impl JsonPathMatch {
+    /// The value at `path` in `document`, if there is one
+    pub fn lookup<'a>(&self, document: &'a serde_json::Value) -> Option<&'a serde_json::Value> {
+        self.path
+            .split('.')
+            .try_fold(document, |value, segment| match value {
+                serde_json::Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+                _ => value.get(segment),
+            })
+    }
+}
+
+/// Health check settings for a route's or a single backend's targets, each replacing the
+/// global `health_check` one when set
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct HealthCheckOverride {
+    #[serde(default, rename = "type")]
+    pub check_type: Option<HealthCheckType>,
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub unhealthy_threshold: Option<u32>,
+    #[serde(default)]
+    pub healthy_threshold: Option<u32>,
+    #[serde(default)]
+    pub grace_period_secs: Option<u64>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub expected_statuses: Option<Vec<u16>>,
+    #[serde(default)]
+    pub body_contains: Option<String>,
+    #[serde(default)]
+    pub body_json_path: Option<JsonPathMatch>,
+    #[serde(default)]
+    pub headers: SecretHeaders,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub scheme: Option<String>,
+}
+
+/// Which proxied responses count as backend failures for passive health checking
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct PassiveHealthCheck {
+    /// Backend response statuses counted as failures, in addition to connection errors
+    /// and timeouts (default 502, 503 and 504)
+    pub failure_statuses: Vec<u16>,
+}
+
+impl Default for PassiveHealthCheck {
+    fn default() -> Self {
+        Self {
+            failure_statuses: vec![502, 503, 504],
         }
     }
 }
@@ -277,7 +1252,7 @@ fn default_message() -> String {
     "Too Many Requests".to_string()
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum RateLimitBy {
     Ip,
@@ -285,7 +1260,7 @@ pub enum RateLimitBy {
     Route,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum RateLimitAlgorithm {
     TokenBucket,
@@ -294,7 +1269,7 @@ pub enum RateLimitAlgorithm {
                  // In the future, other algorithms like FixedWindow or SlidingWindow could be added here.
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum MissingKeyPolicy {
     Allow,
@@ -305,7 +1280,7 @@ fn default_on_missing_key() -> MissingKeyPolicy {
     MissingKeyPolicy::Allow
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct RateLimitConfig {
     pub by: RateLimitBy,
     #[serde(default)]
@@ -327,7 +1302,318 @@ fn default_rate_limit_algorithm() -> RateLimitAlgorithm {
     RateLimitAlgorithm::TokenBucket
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// File-serving settings for static routes, flattened into the route's YAML mapping
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(default)]
+pub struct StaticOptions {
+    /// Render an HTML/JSON index for directories that have no index file
+    pub directory_listing: bool,
+    /// Include dotfiles in directory listings
+    pub show_hidden: bool,
+    /// Cache-Control values by file pattern; the first matching rule wins
+    pub cache_control: Vec<CacheControlRule>,
+    /// Precompressed sidecar files (e.g. app.js.br) to serve when the client accepts them
+    pub precompressed: Vec<PrecompressedEncoding>,
+    /// File names tried, in order, when a directory is requested
+    pub index_files: Vec<String>,
+    /// Refuse to serve paths with a segment starting with '.' (e.g. .git, .env)
+    pub deny_dotfiles: bool,
+    /// Dot-prefixed names still served when `deny_dotfiles` is on (e.g. `.well-known`)
+    pub allowed_dotfiles: Vec<String>,
+    /// Keep small, frequently served files in memory
+    pub cache: Option<StaticCacheConfig>,
+    /// Hand requests for missing files to another route instead of answering 404
+    pub on_not_found: Option<NotFoundAction>,
+}
+
+/// Where a static route sends requests it has no file for
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct NotFoundAction {
+    /// Continue with the next (shorter) route prefix that matches the request path
+    pub fallthrough: bool,
+    /// Dispatch to the route with this exact path key, e.g. "/api"
+    pub proxy_to: Option<String>,
+}
+
+/// In-memory cache limits for a static route
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct StaticCacheConfig {
+    /// Files larger than this (in bytes) are never cached
+    pub max_entry_size: u64,
+    /// Upper bound on cached bytes for the route, least recently used entries are evicted first
+    pub max_total_bytes: u64,
+    /// How long an entry is served without touching the filesystem, e.g. "30s"
+    pub ttl: String,
+}
+
+impl Default for StaticCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entry_size: 64 * 1024,
+            max_total_bytes: 16 * 1024 * 1024,
+            ttl: "30s".to_string(),
+        }
+    }
+}
+
+impl Default for StaticOptions {
+    fn default() -> Self {
+        Self {
+            directory_listing: false,
+            show_hidden: false,
+            cache_control: Vec::new(),
+            precompressed: Vec::new(),
+            index_files: vec!["index.html".to_string()],
+            deny_dotfiles: true,
+            allowed_dotfiles: vec![".well-known".to_string()],
+            cache: None,
+            on_not_found: None,
+        }
+    }
+}
+
+/// Content codings that can be served from precompressed sidecar files
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PrecompressedEncoding {
+    Br,
+    Gzip,
+    Zstd,
+    Deflate,
+}
+
+impl PrecompressedEncoding {
+    /// The coding name used in Accept-Encoding and Content-Encoding
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrecompressedEncoding::Br => "br",
+            PrecompressedEncoding::Gzip => "gzip",
+            PrecompressedEncoding::Zstd => "zstd",
+            PrecompressedEncoding::Deflate => "deflate",
+        }
+    }
+
+    /// File extension of the sidecar file
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PrecompressedEncoding::Br => "br",
+            PrecompressedEncoding::Gzip => "gz",
+            PrecompressedEncoding::Zstd => "zst",
+            PrecompressedEncoding::Deflate => "zz",
+        }
+    }
+}
+
+/// Cache-Control value applied to static files matching a glob
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct CacheControlRule {
+    /// Glob such as "*.js" (matched against the file name) or "assets/**" (matched against the path)
+    pub pattern: String,
+    /// Header value, e.g. "public, max-age=31536000, immutable"
+    pub value: String,
+}
+
+/// Policy for state-changing requests that carry neither an `Origin` nor a `Referer` header
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingOriginPolicy {
+    /// Let the request through (typical for non-browser API clients)
+    #[default]
+    Allow,
+    /// Reject the request
+    Deny,
+}
+
+/// What a route does with HTTP/3 requests received in 0-RTT early data, which can be replayed
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EarlyDataPolicy {
+    /// Forward GET, HEAD and OPTIONS; answer others with 425 Too Early so the client
+    /// retries them after the handshake
+    #[default]
+    SafeMethods,
+    /// Forward safe methods; hold others until the handshake completes
+    Defer,
+    /// Forward everything and leave replay protection to the backend
+    Allow,
+}
+
+/// Settings shared by every route type, flattened into the route's YAML mapping
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+#[serde(default)]
+pub struct RouteOptions {
+    /// Reject POST/PUT/PATCH/DELETE requests whose Origin/Referer doesn't match the request host
+    pub require_same_origin: bool,
+    /// Extra origins (e.g. "https://app.example.com") accepted by the same-origin check
+    pub allowed_origins: Vec<String>,
+    /// How to treat state-changing requests without Origin or Referer
+    pub on_missing_origin: MissingOriginPolicy,
+    /// Error pages for this route, checked before the global `error_pages`
+    pub error_pages: HashMap<String, ErrorPageConfig>,
+    /// Also replace error responses returned by backends, not just prox-generated ones
+    pub intercept_backend_errors: bool,
+    /// How the request path is matched: by the route key as a prefix (default), exactly,
+    /// or by a regex
+    #[serde(rename = "match")]
+    pub path_match: PathMatch,
+    /// Path prefix to match instead of the route key, so several routes can share a path
+    /// and differ only by their request conditions
+    pub path: Option<String>,
+    /// Headers that must match, in addition to the path, for the route to be selected
+    pub match_headers: HashMap<String, ValueMatch>,
+    /// Methods the route accepts (e.g. ["GET", "HEAD"]); empty accepts any method
+    pub match_methods: Vec<String>,
+    /// Query parameters that must match, e.g. `engine: "beta"` or `debug: { present: true }`
+    pub match_query: HashMap<String, ValueMatch>,
+    /// Routes with a higher priority are tried first (default 0)
+    pub priority: i32,
+    /// Answer requests directly with a maintenance page instead of dispatching them
+    pub maintenance: Option<MaintenanceConfig>,
+    /// Handling of requests received in 0-RTT early data, marked with `Early-Data: 1`
+    /// when forwarded before the handshake completes
+    pub early_data: EarlyDataPolicy,
+    /// Count proxied requests toward the backend's health, ejecting it after the
+    /// health check's `unhealthy_threshold` consecutive failures (proxy and load balance
+    /// routes; active checks bring it back)
+    pub passive_health_check: Option<PassiveHealthCheck>,
+    /// Access logging and tracing of the route's requests, to quiet high-volume routes
+    pub observability: ObservabilityConfig,
+    /// Overrides the top-level `debug_headers` for this route
+    pub debug_headers: Option<bool>,
+}
+
+/// Per-route access log and tracing switches
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+pub struct ObservabilityConfig {
+    /// Write access log lines for the route's requests (default true)
+    pub access_log: bool,
+    pub tracing: TracingMode,
+}
+
+impl Default for ObservabilityConfig {
+    fn default() -> Self {
+        Self {
+            access_log: true,
+            tracing: TracingMode::Full,
+        }
+    }
+}
+
+/// Spans recorded for a route's requests
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TracingMode {
+    /// No spans; a client's trace context is still passed on to the backend untouched
+    Off,
+    /// The `http_request` span only, without a `backend_request` span per upstream call
+    Minimal,
+    #[default]
+    Full,
+}
+
+/// Maintenance page served by a route while it is switched off
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// Whether the route is currently in maintenance (default true, so the block can be
+    /// left in place and toggled)
+    pub enabled: bool,
+    /// Response status (default 503)
+    pub status: u16,
+    /// Path to the page; Content-Type is derived from its extension
+    pub body_file: Option<String>,
+    /// Inline body, used when no file is given
+    pub body: Option<String>,
+    /// Overrides the derived Content-Type (inline bodies default to text/html)
+    pub content_type: Option<String>,
+    /// Sent as `Retry-After` in seconds, e.g. "10m" (default "5m"; null omits the header)
+    pub retry_after: Option<String>,
+    /// Client addresses or CIDR blocks (e.g. "10.0.0.0/8") still routed to the backend
+    pub allow_ips: Vec<String>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            status: 503,
+            body_file: None,
+            body: None,
+            content_type: None,
+            retry_after: Some("5m".to_string()),
+            allow_ips: Vec::new(),
+        }
+    }
+}
+
+/// Condition on a request header (or query parameter) value
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(untagged)]
+pub enum ValueMatch {
+    /// The value must equal this string
+    Exact(String),
+    /// The value must match this regex
+    Regex { regex: String },
+    /// Only require the value to be present (`true`) or absent (`false`)
+    Present { present: bool },
+}
+
+/// Path matching strategy of a route, written as `match: exact` or
+/// `match: { type: regex, pattern: "..." }`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case", from = "PathMatchSyntax")]
+pub enum PathMatch {
+    /// The route key is a path prefix
+    #[default]
+    Prefix,
+    /// The route only matches its path exactly, and is tried before prefixes
+    Exact,
+    /// The pattern is matched against the request path; captures are available to
+    /// `path_rewrite` as `$1` or `${name}`, and the route key is only a name
+    Regex { pattern: String },
+}
+
+/// Accepted YAML forms of `match`: a bare name or a tagged mapping
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum PathMatchSyntax {
+    Name(PathMatchName),
+    Tagged(TaggedPathMatch),
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum PathMatchName {
+    Prefix,
+    Exact,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TaggedPathMatch {
+    Prefix,
+    Exact,
+    Regex { pattern: String },
+}
+
+impl From<PathMatchSyntax> for PathMatch {
+    fn from(syntax: PathMatchSyntax) -> Self {
+        match syntax {
+            PathMatchSyntax::Name(PathMatchName::Prefix)
+            | PathMatchSyntax::Tagged(TaggedPathMatch::Prefix) => PathMatch::Prefix,
+            PathMatchSyntax::Name(PathMatchName::Exact)
+            | PathMatchSyntax::Tagged(TaggedPathMatch::Exact) => PathMatch::Exact,
+            PathMatchSyntax::Tagged(TaggedPathMatch::Regex { pattern }) => {
+                PathMatch::Regex { pattern }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(tag = "type")] // Added: Use the 'type' field in YAML to determine the enum variant
 #[serde(rename_all = "snake_case")] // Added: Match snake_case YAML keys (e.g., "load_balance") to PascalCase enum variants (e.g., LoadBalance)
 pub enum RouteConfig {
@@ -336,6 +1622,10 @@ pub enum RouteConfig {
         root: String, // Ensure this field is present
         rate_limit: Option<RateLimitConfig>,
         // No header manipulation for static routes in this iteration
+        #[serde(flatten)]
+        static_options: StaticOptions,
+        #[serde(flatten)]
+        options: RouteOptions,
     },
     Redirect {
         // Assuming 'target: String' and 'status_code: Option<u16>' exist here
@@ -343,6 +1633,8 @@ pub enum RouteConfig {
         status_code: Option<u16>, // Ensure this field is present
         rate_limit: Option<RateLimitConfig>,
         // No header or body manipulation for redirect routes
+        #[serde(flatten)]
+        options: RouteOptions,
     },
     Proxy {
         target: String,
@@ -356,6 +1648,14 @@ pub enum RouteConfig {
         request_body: Option<BodyActions>,
         #[serde(default)]
         response_body: Option<BodyActions>,
+        /// Send a copy of the traffic to a secondary target
+        #[serde(default)]
+        mirror: Option<MirrorConfig>,
+        /// Regex rewrite of the full request path, tried before `path_rewrite`
+        #[serde(default)]
+        path_rewrite_regex: Option<PathRewriteRegex>,
+        #[serde(flatten)]
+        options: RouteOptions,
     },
     LoadBalance {
         targets: Vec<String>,
@@ -370,30 +1670,285 @@ pub enum RouteConfig {
         request_body: Option<BodyActions>,
         #[serde(default)]
         response_body: Option<BodyActions>,
+        /// Send a copy of the traffic to a secondary target
+        #[serde(default)]
+        mirror: Option<MirrorConfig>,
+        /// Regex rewrite of the full request path, tried before `path_rewrite`
+        #[serde(default)]
+        path_rewrite_regex: Option<PathRewriteRegex>,
+        /// Keep a client on the same target across requests
+        #[serde(default)]
+        sticky: Option<SplitStickiness>,
+        /// Health check settings for the targets, over the global `health_check`
+        #[serde(default)]
+        health_check: Option<Box<HealthCheckOverride>>,
+        #[serde(flatten)]
+        options: RouteOptions,
+    },
+    /// Weighted traffic split between variants, e.g. stable and canary deployments
+    Split {
+        variants: Vec<SplitVariant>,
+        /// Keep a client on the same variant across requests
+        #[serde(default)]
+        sticky: Option<SplitStickiness>,
+        path_rewrite: Option<String>,
+        rate_limit: Option<RateLimitConfig>,
+        #[serde(default)]
+        request_headers: Option<HeaderActions>,
+        #[serde(default)]
+        response_headers: Option<HeaderActions>,
+        #[serde(default)]
+        request_body: Option<BodyActions>,
+        #[serde(default)]
+        response_body: Option<BodyActions>,
+        #[serde(flatten)]
+        options: RouteOptions,
     },
     Websocket {
-        target: String,
+        /// Single backend; use `targets` instead to balance connections across several
+        #[serde(default)]
+        target: Option<String>,
+        /// Backends picked per connection at handshake time, among the healthy ones
+        #[serde(default)]
+        targets: Vec<String>,
+        /// Strategy used to pick one of `targets`
+        #[serde(default)]
+        strategy: LoadBalanceStrategy,
+        /// Keep a client on the same target across connections; a cookie shared with a
+        /// `load_balance` route pins both to the same backend
+        #[serde(default)]
+        sticky: Option<SplitStickiness>,
         path_rewrite: Option<String>,
         rate_limit: Option<RateLimitConfig>,
+        /// Header actions applied to the upgrade handshake sent to the backend
+        request_headers: Option<HeaderActions>,
+        /// Header actions applied to the 101 response returned to the client
+        response_headers: Option<HeaderActions>,
         /// Maximum WebSocket frame size (in bytes)
         #[serde(default)]
         max_frame_size: Option<usize>,
         /// Maximum message size (in bytes)
         #[serde(default)]
         max_message_size: Option<usize>,
+        /// Close the connection when no data flows in either direction for this long (e.g. "5m")
+        #[serde(default)]
+        idle_timeout: Option<String>,
+        /// Close the connection once it has been open this long (e.g. "1h")
+        #[serde(default)]
+        max_connection_duration: Option<String>,
+        /// Subprotocols clients may negotiate; others are dropped from the offer (empty allows any)
+        #[serde(default)]
+        subprotocols: Vec<String>,
+        /// Whether `permessage-deflate` negotiation reaches the backend
+        #[serde(default)]
+        compression: WebSocketCompression,
+        #[serde(flatten)]
+        options: RouteOptions,
     },
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+impl RouteConfig {
+    /// Settings shared by all route types
+    pub fn options(&self) -> &RouteOptions {
+        match self {
+            RouteConfig::Static { options, .. }
+            | RouteConfig::Redirect { options, .. }
+            | RouteConfig::Proxy { options, .. }
+            | RouteConfig::LoadBalance { options, .. }
+            | RouteConfig::Split { options, .. }
+            | RouteConfig::Websocket { options, .. } => options,
+        }
+    }
+
+    pub fn rate_limit(&self) -> Option<&RateLimitConfig> {
+        match self {
+            RouteConfig::Static { rate_limit, .. }
+            | RouteConfig::Redirect { rate_limit, .. }
+            | RouteConfig::Proxy { rate_limit, .. }
+            | RouteConfig::LoadBalance { rate_limit, .. }
+            | RouteConfig::Split { rate_limit, .. }
+            | RouteConfig::Websocket { rate_limit, .. } => rate_limit.as_ref(),
+        }
+    }
+
+    /// The path rewrite template of routes that forward requests
+    pub fn path_rewrite_mut(&mut self) -> Option<&mut String> {
+        match self {
+            RouteConfig::Proxy { path_rewrite, .. }
+            | RouteConfig::LoadBalance { path_rewrite, .. }
+            | RouteConfig::Split { path_rewrite, .. }
+            | RouteConfig::Websocket { path_rewrite, .. } => path_rewrite.as_mut(),
+            RouteConfig::Static { .. } | RouteConfig::Redirect { .. } => None,
+        }
+    }
+
+    /// Request and response header actions of routes that forward requests
+    pub fn header_actions(&self) -> (Option<&HeaderActions>, Option<&HeaderActions>) {
+        match self {
+            RouteConfig::Proxy {
+                request_headers,
+                response_headers,
+                ..
+            }
+            | RouteConfig::LoadBalance {
+                request_headers,
+                response_headers,
+                ..
+            }
+            | RouteConfig::Split {
+                request_headers,
+                response_headers,
+                ..
+            }
+            | RouteConfig::Websocket {
+                request_headers,
+                response_headers,
+                ..
+            } => (request_headers.as_ref(), response_headers.as_ref()),
+            RouteConfig::Static { .. } | RouteConfig::Redirect { .. } => (None, None),
+        }
+    }
+
+    /// Request and response body actions of routes that forward requests
+    pub fn body_actions(&self) -> (Option<&BodyActions>, Option<&BodyActions>) {
+        match self {
+            RouteConfig::Proxy {
+                request_body,
+                response_body,
+                ..
+            }
+            | RouteConfig::LoadBalance {
+                request_body,
+                response_body,
+                ..
+            }
+            | RouteConfig::Split {
+                request_body,
+                response_body,
+                ..
+            } => (request_body.as_ref(), response_body.as_ref()),
+            RouteConfig::Static { .. }
+            | RouteConfig::Redirect { .. }
+            | RouteConfig::Websocket { .. } => (None, None),
+        }
+    }
+
+    /// Mutable access to the request and response header actions
+    pub fn header_actions_mut(&mut self) -> Vec<&mut HeaderActions> {
+        match self {
+            RouteConfig::Proxy {
+                request_headers,
+                response_headers,
+                ..
+            }
+            | RouteConfig::LoadBalance {
+                request_headers,
+                response_headers,
+                ..
+            }
+            | RouteConfig::Split {
+                request_headers,
+                response_headers,
+                ..
+            }
+            | RouteConfig::Websocket {
+                request_headers,
+                response_headers,
+                ..
+            } => request_headers
+                .iter_mut()
+                .chain(response_headers.iter_mut())
+                .collect(),
+            RouteConfig::Static { .. } | RouteConfig::Redirect { .. } => Vec::new(),
+        }
+    }
+}
+
+/// Rewrite of the full original path: `/users/(\d+)/avatar` to `/v2/avatars?id=$1`.
+/// A query in the replacement is merged with the request's own query.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct PathRewriteRegex {
+    pub pattern: String,
+    /// Replacement for the matched part of the path; `$1`/`${name}` refer to captures
+    pub replacement: String,
+}
+
+/// Shadow traffic: a sample of requests is also sent to `target` and its responses are discarded
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct MirrorConfig {
+    pub target: String,
+    /// Fraction of requests to mirror, from 0.0 to 1.0
+    #[serde(default = "default_mirror_sample_rate")]
+    pub sample_rate: f64,
+    /// Requests with larger bodies are not mirrored (in bytes)
+    #[serde(default = "default_mirror_max_body_size")]
+    pub max_body_size: usize,
+}
+
+fn default_mirror_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_mirror_max_body_size() -> usize {
+    1024 * 1024 // 1 MiB
+}
+
+/// One target of a split route and its share of the traffic
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+pub struct SplitVariant {
+    /// Label used in metrics and the stickiness cookie; defaults to the target
+    #[serde(default)]
+    pub name: Option<String>,
+    pub target: String,
+    /// Relative weight; 0 takes the variant out of rotation
+    pub weight: u32,
+}
+
+impl SplitVariant {
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.target)
+    }
+}
+
+/// How a split or load-balanced route keeps a client on the same variant or target
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(tag = "by", rename_all = "snake_case")]
+pub enum SplitStickiness {
+    /// Hash of the client IP picks the variant
+    ClientIp,
+    /// The chosen variant's name (or an opaque label of the target) is stored in a cookie
+    Cookie {
+        #[serde(default = "default_split_cookie")]
+        name: String,
+    },
+}
+
+fn default_split_cookie() -> String {
+    "prox_variant".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum LoadBalanceStrategy {
+    #[default]
     #[serde(rename = "round_robin")]
     RoundRobin,
     #[serde(rename = "random")]
     Random,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Handling of the `permessage-deflate` extension on WebSocket routes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebSocketCompression {
+    /// Let client and backend negotiate compression; frames are relayed as is
+    #[default]
+    Passthrough,
+    /// Remove the extension from the client's offer so frames are never compressed
+    Strip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum HealthStatus {
     #[serde(rename = "healthy")]
     Healthy,
@@ -409,3 +1964,84 @@ impl std::fmt::Display for HealthStatus {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacted_hides_secrets_but_keeps_paths() {
+        let config: ServerConfig = serde_yaml::from_str(
+            r#"
+listen_addr: "127.0.0.1:8080"
+routes:
+  "/": { type: proxy, target: "http://backend:8080" }
+tls:
+  cert_path: "certs/server.crt"
+  key_path: "certs/server.key"
+  acme:
+    enabled: true
+    domains: ["example.com"]
+    email: "ops@example.com"
+    eab_kid: "kid"
+    eab_hmac_key: "hmac-key"
+    challenge_type: "dns-01"
+    dns: { provider: cloudflare, api_token: "cf-token" }
+health_check:
+  headers: { Authorization: "Bearer health" }
+admin:
+  auth: { type: basic, username: ops, password: "hunter2" }
+"#,
+        )
+        .unwrap();
+
+        let redacted = serde_json::to_string(&config.redacted()).unwrap();
+        for secret in ["hmac-key", "cf-token", "Bearer health", "hunter2"] {
+            assert!(!redacted.contains(secret), "{secret} leaked");
+        }
+        for kept in ["certs/server.key", "\"kid\"", "\"ops\"", "Authorization"] {
+            assert!(redacted.contains(kept), "{kept} missing");
+        }
+    }
+
+    #[test]
+    fn test_builder_validates_like_config_files() {
+        let route: RouteConfig =
+            serde_yaml::from_str("{ type: proxy, target: \"http://backend:8080\" }").unwrap();
+        let config = ServerConfigBuilder::default()
+            .listen_addr("127.0.0.1:8443")
+            .tls_acme(AcmeConfig {
+                enabled: true,
+                domains: vec!["example.com".to_string()],
+                email: "ops@example.com".to_string(),
+                ca_url: None,
+                staging: None,
+                storage_path: None,
+                renewal_days_before_expiry: None,
+                challenge_type: AcmeChallengeType::Http01,
+                dns: None,
+                eab_kid: None,
+                eab_hmac_key: None,
+            })
+            .route("/", route.clone())
+            .build()
+            .unwrap();
+        let tls = config.tls.unwrap();
+        assert!(tls.cert_path.is_none() && tls.acme.is_some());
+
+        assert!(matches!(
+            ServerConfigBuilder::default()
+                .route("/", route.clone())
+                .build(),
+            Err(ValidationError::MissingField { .. })
+        ));
+        assert!(
+            ServerConfigBuilder::default()
+                .listen_addr("127.0.0.1:8443")
+                .route("/", route)
+                .tls_manual("", "certs/server.key")
+                .build()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::models::{RouteConfig, ServerConfig};
+
+/// A change to the running config, as sent to `PATCH /-/config`
+pub enum ConfigPatch {
+    /// RFC 7386 JSON merge patch against the whole config
+    Merge(Value),
+    /// Targeted route changes, leaving everything else alone
+    Operations(ConfigOperations),
+}
+
+/// Routes to add or replace, then routes to remove
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigOperations {
+    /// Added, or replacing the route with the same prefix as a whole
+    #[serde(default)]
+    pub routes: HashMap<String, RouteConfig>,
+    /// Prefixes of routes to remove; each must exist
+    #[serde(default)]
+    pub remove_routes: Vec<String>,
+}
+
+impl ConfigPatch {
+    /// The config `config` becomes with this patch applied; the result still has to be
+    /// validated
+    pub fn apply(self, config: &ServerConfig) -> Result<ServerConfig> {
+        match self {
+            ConfigPatch::Merge(patch) => {
+                let mut document =
+                    serde_json::to_value(config).context("Failed to serialize config")?;
+                merge_patch(&mut document, &patch);
+                serde_json::from_value(document).context("Patched config is not a valid config")
+            }
+            ConfigPatch::Operations(operations) => {
+                let mut config = config.clone();
+                config.routes.extend(operations.routes);
+                for prefix in &operations.remove_routes {
+                    if config.routes.remove(prefix).is_none() {
+                        bail!("No route with prefix '{prefix}' to remove");
+                    }
+                }
+                Ok(config)
+            }
+        }
+    }
+}
+
+/// Apply an RFC 7386 merge patch: objects merge key by key, `null` removes a key and
+/// anything else replaces the target
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> ServerConfig {
+        serde_yaml::from_str(
+            r#"
+listen_addr: "127.0.0.1:8080"
+routes:
+  "/api": { type: proxy, target: "http://api:8080" }
+  "/old": { type: proxy, target: "http://old:8080" }
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut document = json!({"a": {"b": 1, "c": 2}, "d": [1, 2]});
+        merge_patch(&mut document, &json!({"a": {"b": null, "e": 3}, "d": [3]}));
+        assert_eq!(document, json!({"a": {"c": 2, "e": 3}, "d": [3]}));
+
+        let patched = ConfigPatch::Merge(json!({
+            "debug_headers": true,
+            "routes": {"/old": null, "/api": {"target": "http://api-v2:8080"}}
+        }))
+        .apply(&config())
+        .unwrap();
+        assert!(patched.debug_headers);
+        assert!(!patched.routes.contains_key("/old"));
+        assert!(matches!(
+            &patched.routes["/api"],
+            RouteConfig::Proxy { target, .. } if target == "http://api-v2:8080"
+        ));
+    }
+
+    #[test]
+    fn test_route_operations() {
+        let operations: ConfigOperations = serde_json::from_value(json!({
+            "routes": {"/new": {"type": "redirect", "target": "https://example.com"}},
+            "remove_routes": ["/old"]
+        }))
+        .unwrap();
+        let patched = ConfigPatch::Operations(operations)
+            .apply(&config())
+            .unwrap();
+        let mut prefixes = patched.routes.keys().collect::<Vec<_>>();
+        prefixes.sort();
+        assert_eq!(prefixes, ["/api", "/new"]);
+
+        let missing = ConfigOperations {
+            routes: HashMap::new(),
+            remove_routes: vec!["/missing".to_string()],
+        };
+        assert!(ConfigPatch::Operations(missing).apply(&config()).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use schemars::Schema;
+use serde_json::Value;
+
+use crate::config::models::ServerConfig;
+
+/// JSON Schema of the config file, generated from the serde models so it follows them
+pub fn config_schema() -> Schema {
+    schemars::schema_for!(ServerConfig)
+}
+
+/// Paths of the keys in a parsed config file that no model declares, such as
+/// `routes."/api".targest`; serde skips them without complaint
+pub fn unknown_keys(config: &Value) -> Vec<String> {
+    let schema = config_schema().to_value();
+    let mut unknown = Vec::new();
+    collect_unknown_keys(&schema, &schema, config, "", &mut unknown);
+    unknown
+}
+
+fn collect_unknown_keys(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+    unknown: &mut Vec<String>,
+) {
+    let schema = resolve_ref(root, schema);
+    if let Some(alternatives) = schema
+        .get("oneOf")
+        .or_else(|| schema.get("anyOf"))
+        .and_then(Value::as_array)
+    {
+        // Report against the variant that fits best: the one `type` or the shape points to.
+        // Fields next to a flattened enum are declared beside its variants.
+        let shared = schema.get("properties").and_then(Value::as_object);
+        let best = alternatives
+            .iter()
+            .map(|alternative| resolve_ref(root, alternative))
+            .filter(|alternative| accepts_shape(root, alternative, value))
+            .map(|alternative| {
+                let mut alternative = alternative.clone();
+                if let Some(shared) = shared
+                    && let Some(properties) = alternative
+                        .as_object_mut()
+                        .map(|object| {
+                            object
+                                .entry("properties")
+                                .or_insert_with(|| Value::Object(Default::default()))
+                        })
+                        .and_then(Value::as_object_mut)
+                {
+                    properties.extend(shared.clone());
+                }
+                let mut found = Vec::new();
+                collect_unknown_keys(root, &alternative, value, path, &mut found);
+                found
+            })
+            .min_by_key(Vec::len);
+        unknown.extend(best.unwrap_or_default());
+        return;
+    }
+
+    match value {
+        Value::Object(object) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let additional = schema.get("additionalProperties");
+            for (key, value) in object {
+                let key_path = join_path(path, key);
+                match (properties.and_then(|known| known.get(key)), additional) {
+                    (Some(property), _) => {
+                        collect_unknown_keys(root, property, value, &key_path, unknown)
+                    }
+                    (None, Some(additional @ Value::Object(_))) => {
+                        collect_unknown_keys(root, additional, value, &key_path, unknown)
+                    }
+                    (None, Some(Value::Bool(true))) => {}
+                    (None, _) if properties.is_some() => unknown.push(key_path),
+                    (None, _) => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    let item_path = format!("{path}[{index}]");
+                    collect_unknown_keys(root, item_schema, item, &item_path, unknown);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Follow `$ref`s into `$defs`
+fn resolve_ref<'a>(root: &'a Value, mut schema: &'a Value) -> &'a Value {
+    while let Some(pointer) = schema
+        .get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|reference| reference.strip_prefix('#'))
+    {
+        match root.pointer(pointer) {
+            Some(target) => schema = target,
+            None => break,
+        }
+    }
+    schema
+}
+
+/// Whether `value` has the JSON type `schema` asks for and the same `const` tags
+fn accepts_shape(root: &Value, schema: &Value, value: &Value) -> bool {
+    let schema = resolve_ref(root, schema);
+    let value_type = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    };
+    let type_matches = |name: &Value| {
+        name.as_str()
+            .is_some_and(|name| name == value_type || (name == "number" && value_type == "integer"))
+    };
+    let types_ok = match schema.get("type") {
+        Some(Value::Array(names)) => names.iter().any(type_matches),
+        Some(name) => type_matches(name),
+        None => true,
+    };
+    let tags_ok = match (schema.get("properties").and_then(Value::as_object), value) {
+        (Some(properties), Value::Object(object)) => {
+            properties.iter().all(|(key, property)| {
+                match (property.get("const"), object.get(key)) {
+                    (Some(tag), Some(given)) => tag == given,
+                    _ => true,
+                }
+            })
+        }
+        _ => true,
+    };
+    types_ok && tags_ok
+}
+
+/// `routes."/api".target`: keys other than plain words are quoted
+fn join_path(path: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    let key = if plain {
+        key.to_string()
+    } else {
+        format!("{key:?}")
+    };
+    if path.is_empty() {
+        key
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    /// Reject properties the models don't declare, which serde itself ignores, so a field
+    /// missing from the schema fails the test instead of passing silently
+    fn deny_unknown_properties(schema: &mut Value) {
+        match schema {
+            Value::Object(object) => {
+                if object.contains_key("properties") && !object.contains_key("additionalProperties")
+                {
+                    object.insert("additionalProperties".to_string(), Value::Bool(false));
+                }
+                object.values_mut().for_each(deny_unknown_properties);
+            }
+            Value::Array(items) => items.iter_mut().for_each(deny_unknown_properties),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_example_configs_match_schema() {
+        let mut schema = config_schema().to_value();
+        deny_unknown_properties(&mut schema);
+        let validator = jsonschema::validator_for(&schema).unwrap();
+
+        for file in [
+            "config.yaml",
+            "config-example-websocket.yaml",
+            "config-http3-example.yaml",
+        ] {
+            let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(file);
+            let content = std::fs::read_to_string(&path).unwrap();
+            let config: Value = serde_yaml::from_str(&content).unwrap();
+            let errors: Vec<String> = validator
+                .iter_errors(&config)
+                .map(|error| format!("{}: {error}", error.instance_path()))
+                .collect();
+            assert!(errors.is_empty(), "{file}:\n{}", errors.join("\n"));
+            assert_eq!(unknown_keys(&config), Vec::<String>::new(), "{file}");
+        }
+
+        let typo: Value =
+            serde_yaml::from_str("listen_addr: \"127.0.0.1:8080\"\nhealth_chek: {}\n").unwrap();
+        assert!(!validator.is_valid(&typo));
+    }
+
+    #[test]
+    fn test_unknown_keys_have_full_paths() {
+        let config: Value = serde_yaml::from_str(
+            r#"
+listen_addr: "127.0.0.1:8080"
+rate_limt: {}
+routes:
+  "/api":
+    type: proxy
+    targest: "http://typo:8080"
+    target: "http://backend:8080"
+    rate_limit: { by: ip, requests: 10, period: 1m, algoritm: token_bucket }
+    match: { type: regex, pattern: "^/api", flags: i }
+  "/app":
+    type: split
+    variants:
+      - { target: "http://a:8080", weight: 1, wieght: 2 }
+tls:
+  acme:
+    enabled: true
+    domains: ["example.com"]
+    email: "ops@example.com"
+    dns: { provider: cloudflare, api_token: "t", propagation_timeout: 2m, resolver: x }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            unknown_keys(&config),
+            [
+                "rate_limt",
+                "routes.\"/api\".targest",
+                "routes.\"/api\".rate_limit.algoritm",
+                "routes.\"/api\".match.flags",
+                "routes.\"/app\".variants[0].wieght",
+                "tls.acme.dns.resolver",
+            ]
+        );
+    }
+}
@@ -1,12 +1,24 @@
+use base64::Engine;
 use regex::Regex;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::path::Path;
 use std::time::Duration;
 use thiserror::Error;
 use url::Url;
 
-use crate::config::models::{AcmeConfig, RateLimitConfig, RouteConfig, ServerConfig, TlsConfig};
+use crate::config::models::{
+    AccessLogConfig, AcmeChallengeType, AcmeConfig, AcmeDnsConfig, AdminAuth,
+    ConnectionLimitsConfig, DnsProviderConfig, ErrorPageConfig, HealthCheckConfig,
+    LogRedactionConfig, MaintenanceConfig, MetricsConfig, PathMatch, PathRewriteRegex,
+    RateLimitConfig, RequestCondition, RouteConfig, RouteOptions, SecretHeaders, ServerConfig,
+    ShutdownConfig, SplitStickiness, SplitVariant, TelemetryConfig, TlsCertificate, TlsConfig,
+    ValueMatch, VirtualHostConfig,
+};
+use crate::core::ip_range::IpRange;
+use crate::core::router;
+use crate::core::{ProxyService, proxy};
 
 #[derive(Error, Debug)]
 pub enum ValidationError {
@@ -41,36 +53,163 @@ pub enum ValidationError {
     #[error("Route configuration conflict: {message}")]
     RouteConflict { message: String },
 
+    #[error(
+        "Route '{route}' sends requests to {target}, which is prox's own listen_addr \
+         {listen_addr}, so they would loop back"
+    )]
+    SelfReferentialTarget {
+        route: String,
+        target: String,
+        listen_addr: String,
+    },
+
+    #[error("Route '{route}' lists target {target} more than once")]
+    DuplicateTarget { route: String, target: String },
+
+    #[error(
+        "Routes '{first}' and '{second}' both match {methods} requests to the same path with \
+         the same priority and conditions; give one a higher priority or make their methods \
+         disjoint"
+    )]
+    AmbiguousRoutes {
+        first: String,
+        second: String,
+        methods: String,
+    },
+
     #[error("File not found: {path}")]
     FileNotFound { path: String },
 }
 
+impl ValidationError {
+    /// The config field at fault, when the error names one
+    pub fn field(&self) -> Option<String> {
+        match self {
+            ValidationError::InvalidField { field, .. }
+            | ValidationError::MissingField { field }
+            | ValidationError::InvalidUrl { field, .. } => Some(field.clone()),
+            ValidationError::InvalidListenAddress { .. } => Some("listen_addr".to_string()),
+            ValidationError::InvalidRateLimit { route, .. } => {
+                Some(format!("route '{route}' rate_limit"))
+            }
+            ValidationError::InvalidTls { .. } => Some("tls".to_string()),
+            ValidationError::InvalidAcme { .. } => Some("tls.acme".to_string()),
+            ValidationError::RouteConflict { .. } => Some("routes".to_string()),
+            ValidationError::SelfReferentialTarget { route, .. }
+            | ValidationError::DuplicateTarget { route, .. } => Some(format!("route '{route}'")),
+            ValidationError::AmbiguousRoutes { second, .. } => Some(format!("route '{second}'")),
+            ValidationError::ValidationFailed { .. } | ValidationError::FileNotFound { .. } => None,
+        }
+    }
+}
+
 pub type ValidationResult<T> = Result<T, ValidationError>;
 
+/// One problem found in a config, as reported by `POST /-/config/validate`
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl From<&ValidationError> for ValidationIssue {
+    fn from(error: &ValidationError) -> Self {
+        Self {
+            field: error.field(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{field}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Everything found in a config: errors make it invalid, warnings point at settings that
+/// work but are probably not intended
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<ValidationError>,
+    pub warnings: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The errors as one `ValidationFailed`, ignoring warnings
+    pub fn into_result(self) -> ValidationResult<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError::ValidationFailed {
+                message: ConfigValidator::format_multiple_errors(self.errors),
+            })
+        }
+    }
+}
+
+/// Placeholders replaced in header action values, which must be the whole value
+const HEADER_PLACEHOLDERS: [&str; 2] = ["client_ip", "timestamp"];
+
+/// Placeholders replaced anywhere in `set_text` and the strings of `set_json`
+const BODY_PLACEHOLDERS: [&str; 3] = ["uri_path", "timestamp_iso", "client_ip"];
+
 /// Configuration validator with detailed error reporting
 pub struct ConfigValidator;
 
 impl ConfigValidator {
     /// Validate a complete server configuration
     pub fn validate(config: &ServerConfig) -> ValidationResult<()> {
+        Self::check(config).into_result()
+    }
+
+    /// Every error and warning in a complete server configuration; what `validate`, the
+    /// `validate` subcommand and the config endpoints go by
+    pub fn check(config: &ServerConfig) -> ValidationReport {
         let mut errors = Vec::new();
 
         if let Err(e) = Self::validate_listen_address(&config.listen_addr) {
             errors.push(e);
         }
+        if let Some(http3_listen_addr) = &config.protocols.http3_listen_addr
+            && let Err(e) = Self::validate_listen_address(http3_listen_addr)
+        {
+            errors.push(e);
+        }
+        if let Some(http3_config) = &config.protocols.http3_config
+            && http3_config.qlog_dir.is_some()
+            && http3_config.qlog_max_files == 0
+        {
+            errors.push(ValidationError::InvalidField {
+                field: "protocols.http3_config.qlog_max_files".to_string(),
+                message: "must be at least 1 when qlog_dir is set".to_string(),
+            });
+        }
 
-        if config.routes.is_empty() {
+        if config.routes.is_empty()
+            && config.virtual_hosts.is_empty()
+            && config.default_route.is_none()
+        {
             errors.push(ValidationError::MissingField {
                 field: "routes".to_string(),
             });
         } else {
-            for (path, route_config) in &config.routes {
-                if let Err(mut route_errors) = Self::validate_single_route(path, route_config) {
-                    errors.append(&mut route_errors);
-                }
-            }
+            errors.extend(Self::validate_routes(&config.routes));
+        }
+
+        if let Some(default_route) = &config.default_route {
+            errors.extend(Self::validate_default_route(default_route));
         }
 
+        errors.extend(Self::validate_virtual_hosts(&config.virtual_hosts));
+
         // Validate TLS configuration if present
         if let Some(tls_config) = &config.tls {
             Self::validate_tls_config(tls_config)
@@ -78,17 +217,373 @@ impl ConfigValidator {
                 .ok();
         }
 
-        if let Err(conflict_error_list) = Self::check_route_conflicts(&config.routes) {
-            errors.extend(conflict_error_list);
+        errors.extend(Self::validate_error_pages(
+            "error_pages",
+            &config.error_pages,
+        ));
+
+        if let Some(access_log) = &config.access_log {
+            errors.extend(Self::validate_access_log(access_log));
+        }
+        errors.extend(Self::validate_telemetry(&config.telemetry));
+        errors.extend(Self::validate_log_redaction(&config.log_redaction));
+        errors.extend(Self::validate_admin(config));
+        errors.extend(Self::validate_metrics(&config.metrics));
+        errors.extend(Self::validate_shutdown(&config.shutdown));
+        errors.extend(Self::validate_connection_limits(&config.connection_limits));
+
+        errors.extend(Self::validate_health_check(
+            "health_check",
+            &config.health_check,
+        ));
+        if config.health_check.max_concurrent_checks == 0 {
+            errors.push(ValidationError::InvalidField {
+                field: "health_check.max_concurrent_checks".to_string(),
+                message: "Must be at least 1".to_string(),
+            });
+        }
+        for (path, route) in Self::all_routes(config) {
+            errors.extend(Self::validate_targets(path, route, &config.listen_addr));
+            if let RouteConfig::LoadBalance {
+                health_check: Some(health_check),
+                ..
+            } = route
+            {
+                errors.extend(Self::validate_health_check(
+                    &format!("route '{path}' health_check"),
+                    &config.health_check.with_override(health_check),
+                ));
+            }
+            if route.options().passive_health_check.is_some() {
+                let field = format!("route '{path}' passive_health_check");
+                if !matches!(
+                    route,
+                    RouteConfig::Proxy { .. } | RouteConfig::LoadBalance { .. }
+                ) {
+                    errors.push(ValidationError::InvalidField {
+                        field,
+                        message: "Only proxy and load balance routes support passive health checks"
+                            .to_string(),
+                    });
+                } else if !config.health_check.enabled {
+                    // Nothing would bring an ejected backend back
+                    errors.push(ValidationError::InvalidField {
+                        field,
+                        message: "Passive health checks need health_check.enabled, whose active checks restore ejected backends".to_string(),
+                    });
+                }
+            }
+        }
+        for backend in config.backend_health_checks.keys() {
+            errors.extend(Self::validate_health_check(
+                &format!("backend_health_checks '{backend}'"),
+                &config.backend_health_check(backend),
+            ));
+        }
+        for backend in ProxyService::collect_backends(config) {
+            if config.route_health_checks(&backend).count() > 1 {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("backend '{backend}'"),
+                    message: "Several load balance routes override its health check; set them once in backend_health_checks instead".to_string(),
+                });
+            }
         }
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(ValidationError::ValidationFailed {
-                message: Self::format_multiple_errors(errors),
+        ValidationReport {
+            errors,
+            warnings: Self::warnings(config),
+        }
+    }
+
+    /// Routes of the default and virtual hosts, then `default_route`
+    fn all_routes(config: &ServerConfig) -> impl Iterator<Item = (&str, &RouteConfig)> {
+        config
+            .routes
+            .iter()
+            .chain(
+                config
+                    .virtual_hosts
+                    .values()
+                    .flat_map(|vhost| vhost.routes.iter()),
+            )
+            .map(|(path, route)| (path.as_str(), route))
+            .chain(
+                config
+                    .default_route
+                    .as_deref()
+                    .map(|route| ("default_route", route)),
+            )
+    }
+
+    /// Settings that are valid but risky or likely mistakes
+    pub fn warnings(config: &ServerConfig) -> Vec<ValidationIssue> {
+        let mut warnings = Vec::new();
+        let mut warn = |field: &str, message: String| {
+            warnings.push(ValidationIssue {
+                field: Some(field.to_string()),
+                message,
             })
+        };
+        if config.debug_errors {
+            warn(
+                "debug_errors",
+                "Error responses include internal details; turn off outside development"
+                    .to_string(),
+            );
+        }
+        if config.admin.allow_unauthenticated
+            && let Some(addr) = Self::admin_open_to_anyone(config)
+        {
+            warn(
+                "admin.allow_unauthenticated",
+                format!("/metrics and the /-/ endpoints are open to anyone on {addr}"),
+            );
+        }
+        let has_load_balancing = Self::all_routes(config)
+            .any(|(_, route)| matches!(route, RouteConfig::LoadBalance { .. }));
+        let backends = ProxyService::collect_backends(config);
+        if has_load_balancing && !config.health_check.enabled {
+            warn(
+                "health_check.enabled",
+                "Load balance routes keep sending requests to failed backends without health \
+                 checks"
+                    .to_string(),
+            );
+        } else if config.health_check.enabled && backends.is_empty() {
+            warn(
+                "health_check.enabled",
+                "No proxy or load balance routes have backends to check".to_string(),
+            );
+        }
+        for (path, route) in Self::all_routes(config) {
+            if let Some(rate_limit) = route.rate_limit()
+                && Self::parse_period(&rate_limit.period)
+                    .is_ok_and(|period| period > Duration::from_secs(24 * 60 * 60))
+            {
+                warn(
+                    &format!("route '{path}' rate_limit.period"),
+                    format!(
+                        "{} is over a day; limits are kept in memory and start over on restart",
+                        rate_limit.period
+                    ),
+                );
+            }
+        }
+        if let Some(acme) = config.tls.as_ref().and_then(|tls| tls.acme.as_ref())
+            && acme.enabled
+            && acme.staging == Some(true)
+            && acme.ca_url.is_none()
+            && let Some(domain) = acme
+                .domains
+                .iter()
+                .find(|domain| Self::is_public_domain(domain))
+        {
+            warn(
+                "tls.acme.staging",
+                format!(
+                    "Staging certificates are not trusted by browsers, but {domain} looks like a \
+                     public domain"
+                ),
+            );
+        }
+        let shutdown = &config.shutdown;
+        if let Some(force_close_after) = shutdown.force_close_after()
+            && force_close_after < shutdown.grace_period_before_drain() + shutdown.drain_timeout()
+        {
+            warn(
+                "shutdown.force_close_after",
+                format!(
+                    "{} is shorter than grace_period_before_drain plus drain_timeout, so \
+                     in-flight requests may be cut off before the drain ends",
+                    humantime::format_duration(force_close_after)
+                ),
+            );
+        }
+        warnings
+    }
+
+    /// Targets that point back at prox, or that a load balancing route lists twice
+    fn validate_targets(
+        path: &str,
+        route: &RouteConfig,
+        listen_addr: &str,
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let mut seen = HashSet::new();
+        for target in proxy::route_backends(route) {
+            // Compare parsed URLs, so "http://a:80" and "http://a/" are the same backend
+            let normalized = Url::parse(&target).map_or_else(|_| target.clone(), String::from);
+            if !seen.insert(normalized) {
+                // Split variants may share a backend with different headers or weights
+                if !matches!(route, RouteConfig::Split { .. }) {
+                    errors.push(ValidationError::DuplicateTarget {
+                        route: path.to_string(),
+                        target,
+                    });
+                }
+                continue;
+            }
+            if Self::targets_listener(&target, listen_addr) {
+                errors.push(ValidationError::SelfReferentialTarget {
+                    route: path.to_string(),
+                    target,
+                    listen_addr: listen_addr.to_string(),
+                });
+            }
+        }
+        errors
+    }
+
+    /// Whether `backend` is prox's own listener, including through a loopback address
+    /// when listening on every interface
+    fn targets_listener(backend: &str, listen_addr: &str) -> bool {
+        let (Ok(url), Ok(listen_addr)) = (Url::parse(backend), listen_addr.parse::<SocketAddr>())
+        else {
+            return false;
+        };
+        if url.port_or_known_default() != Some(listen_addr.port()) {
+            return false;
+        }
+        let local = |ip: std::net::IpAddr| {
+            ip == listen_addr.ip() || (listen_addr.ip().is_unspecified() && ip.is_loopback())
+        };
+        match url.host() {
+            Some(url::Host::Ipv4(ip)) => local(ip.into()),
+            Some(url::Host::Ipv6(ip)) => local(ip.into()),
+            Some(url::Host::Domain(domain)) => {
+                domain.eq_ignore_ascii_case("localhost")
+                    && (listen_addr.ip().is_loopback() || listen_addr.ip().is_unspecified())
+            }
+            None => false,
+        }
+    }
+
+    /// Anything but IP addresses, `localhost` and the reserved test and local TLDs
+    fn is_public_domain(domain: &str) -> bool {
+        let domain = domain.trim_start_matches("*.").trim_end_matches('.');
+        let tld = domain.rsplit('.').next().unwrap_or(domain);
+        domain.contains('.')
+            && domain.parse::<std::net::IpAddr>().is_err()
+            && !matches!(
+                tld.to_ascii_lowercase().as_str(),
+                "localhost" | "test" | "example" | "invalid" | "local" | "internal" | "lan"
+            )
+    }
+
+    /// Validate one route map: every route, prefix conflicts and `on_not_found` targets
+    fn validate_routes(routes: &HashMap<String, RouteConfig>) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        for (path, route_config) in routes {
+            if let Err(route_errors) = Self::validate_single_route(path, route_config) {
+                errors.extend(route_errors);
+            }
+        }
+        if let Err(conflict_error_list) = Self::check_route_conflicts(routes) {
+            errors.extend(conflict_error_list);
+        }
+        errors.extend(Self::validate_not_found_targets(routes));
+        errors
+    }
+
+    /// The catch-all route is checked like any other route, but takes no match conditions
+    fn validate_default_route(route: &RouteConfig) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let options = route.options();
+        if options.path_match != PathMatch::Prefix
+            || options.path.is_some()
+            || !options.match_headers.is_empty()
+            || !options.match_methods.is_empty()
+            || !options.match_query.is_empty()
+        {
+            errors.push(ValidationError::InvalidField {
+                field: "default_route".to_string(),
+                message: "The default route matches every path; remove match, path and \
+                          match_* settings"
+                    .to_string(),
+            });
+        }
+        if let RouteConfig::Static { static_options, .. } = route
+            && static_options.on_not_found.is_some()
+        {
+            errors.push(ValidationError::InvalidField {
+                field: "default_route".to_string(),
+                message: "on_not_found is not supported on the default route".to_string(),
+            });
+        }
+        // Its prefix is "/", which keeps the leading-slash check happy
+        if let Err(route_errors) = Self::validate_single_route("/", route) {
+            errors.extend(
+                route_errors
+                    .into_iter()
+                    .map(|e| ValidationError::InvalidField {
+                        field: "default_route".to_string(),
+                        message: e.to_string(),
+                    }),
+            );
+        }
+        errors
+    }
+
+    /// Validate host patterns and the routes of each virtual host
+    fn validate_virtual_hosts(
+        virtual_hosts: &HashMap<String, VirtualHostConfig>,
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let mut seen: HashMap<String, &String> = HashMap::new();
+
+        for (host, vhost) in virtual_hosts {
+            let field = format!("virtual host '{host}'");
+            if let Err(message) = Self::validate_host_pattern(host) {
+                errors.push(ValidationError::InvalidField {
+                    field: field.clone(),
+                    message,
+                });
+            }
+            if let Some(other) = seen.insert(host.to_ascii_lowercase(), host) {
+                errors.push(ValidationError::RouteConflict {
+                    message: format!("Virtual hosts '{other}' and '{host}' match the same names"),
+                });
+            }
+            if vhost.routes.is_empty() {
+                errors.push(ValidationError::MissingField {
+                    field: format!("virtual_hosts '{host}' routes"),
+                });
+            }
+            // Route conflicts are per virtual host: the same prefix may exist under several hosts
+            errors.extend(Self::validate_routes(&vhost.routes).into_iter().map(|e| {
+                ValidationError::InvalidField {
+                    field: field.clone(),
+                    message: e.to_string(),
+                }
+            }));
+        }
+
+        errors
+    }
+
+    /// Host patterns are DNS names, optionally starting with a "*." wildcard label
+    fn validate_host_pattern(pattern: &str) -> Result<(), String> {
+        let name = pattern.strip_prefix("*.").unwrap_or(pattern);
+        if name.is_empty() {
+            return Err("Host pattern cannot be empty".to_string());
+        }
+        if name.contains(':') {
+            return Err("Host patterns must not include a port".to_string());
+        }
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(format!("'{pattern}' has an empty or overlong label"));
+            }
+            if label.starts_with('-')
+                || label.ends_with('-')
+                || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Err(format!(
+                    "'{pattern}' must be a host name, optionally prefixed with '*.'"
+                ));
+            }
         }
+        Ok(())
     }
 
     /// Validate listen address format
@@ -108,12 +603,23 @@ impl ConfigValidator {
     fn validate_single_route(path: &str, config: &RouteConfig) -> Result<(), Vec<ValidationError>> {
         let mut errors = Vec::new();
 
-        if !path.starts_with('/') {
+        // Regex routes and routes with an explicit `path` use their key only as a name
+        let match_path = match (&config.options().path_match, &config.options().path) {
+            (PathMatch::Prefix | PathMatch::Exact, Some(match_path)) => Some(match_path.as_str()),
+            (PathMatch::Prefix | PathMatch::Exact, None) => Some(path),
+            (PathMatch::Regex { .. }, _) => None,
+        };
+        if let Some(match_path) = match_path
+            && !match_path.starts_with('/')
+        {
             errors.push(ValidationError::InvalidField {
-                field: format!("route path: {path}"),
+                field: format!("route path: {match_path}"),
                 message: "Route paths must start with '/'".to_string(),
             });
         }
+        errors.extend(Self::validate_path_params(path, match_path, config));
+        errors.extend(Self::validate_header_actions(path, config));
+        errors.extend(Self::validate_body_actions(path, config));
 
         match config {
             RouteConfig::Proxy { target, .. } => {
@@ -122,7 +628,10 @@ impl ConfigValidator {
                     errors.push(e);
                 }
             }
-            RouteConfig::LoadBalance { targets, .. } => {
+            RouteConfig::LoadBalance {
+                targets, sticky, ..
+            } => {
+                errors.extend(Self::validate_sticky_cookie(path, sticky.as_ref()));
                 if targets.is_empty() {
                     errors.push(ValidationError::InvalidField {
                         field: format!("route '{path}' load balance targets"),
@@ -139,10 +648,57 @@ impl ConfigValidator {
                     }
                 }
             }
-            RouteConfig::Static { root, .. } => {
+            RouteConfig::Split {
+                variants, sticky, ..
+            } => errors.extend(Self::validate_split(path, variants, sticky.as_ref())),
+            RouteConfig::Static {
+                root,
+                static_options,
+                ..
+            } => {
                 if !Path::new(root).exists() {
                     errors.push(ValidationError::FileNotFound { path: root.clone() });
                 }
+
+                for index in &static_options.index_files {
+                    if index.is_empty() || index.contains('/') || index == "." || index == ".." {
+                        errors.push(ValidationError::InvalidField {
+                            field: format!("route '{path}' index_files"),
+                            message: format!("'{index}' must be a plain file name"),
+                        });
+                    }
+                }
+
+                for (i, rule) in static_options.cache_control.iter().enumerate() {
+                    if rule.pattern.is_empty() {
+                        errors.push(ValidationError::InvalidField {
+                            field: format!("route '{path}' cache_control {i} pattern"),
+                            message: "Cache-Control pattern cannot be empty".to_string(),
+                        });
+                    }
+                    if rule.value.parse::<hyper::header::HeaderValue>().is_err() {
+                        errors.push(ValidationError::InvalidField {
+                            field: format!("route '{path}' cache_control {i} value"),
+                            message: format!("'{}' is not a valid header value", rule.value),
+                        });
+                    }
+                }
+
+                if let Some(cache) = &static_options.cache {
+                    if cache.max_entry_size == 0 || cache.max_total_bytes == 0 {
+                        errors.push(ValidationError::InvalidField {
+                            field: format!("route '{path}' cache"),
+                            message: "max_entry_size and max_total_bytes must be greater than 0"
+                                .to_string(),
+                        });
+                    }
+                    if let Err(e) = humantime::parse_duration(&cache.ttl) {
+                        errors.push(ValidationError::InvalidField {
+                            field: format!("route '{path}' cache ttl"),
+                            message: format!("Invalid duration '{}': {e}", cache.ttl),
+                        });
+                    }
+                }
             }
             RouteConfig::Redirect {
                 target,
@@ -169,15 +725,40 @@ impl ConfigValidator {
             }
             RouteConfig::Websocket {
                 target,
+                targets,
                 max_frame_size,
                 max_message_size,
+                idle_timeout,
+                max_connection_duration,
+                subprotocols,
+                sticky,
                 ..
             } => {
-                if let Err(e) = Self::validate_websocket_url(
-                    target,
-                    &format!("route '{path}' websocket target"),
-                ) {
-                    errors.push(e);
+                errors.extend(Self::validate_sticky_cookie(path, sticky.as_ref()));
+                match (target, targets.is_empty()) {
+                    (Some(target), true) => {
+                        if let Err(e) = Self::validate_websocket_url(
+                            target,
+                            &format!("route '{path}' websocket target"),
+                        ) {
+                            errors.push(e);
+                        }
+                    }
+                    (None, false) => {
+                        for (i, target) in targets.iter().enumerate() {
+                            if let Err(e) = Self::validate_websocket_url(
+                                target,
+                                &format!("route '{path}' websocket target {i}"),
+                            ) {
+                                errors.push(e);
+                            }
+                        }
+                    }
+                    _ => errors.push(ValidationError::InvalidField {
+                        field: format!("route '{path}' websocket target"),
+                        message: "WebSocket routes need exactly one of 'target' or 'targets'"
+                            .to_string(),
+                    }),
                 }
 
                 // Validate frame size
@@ -195,12 +776,76 @@ impl ConfigValidator {
                         message: "WebSocket max message size must be greater than 0".to_string(),
                     });
                 }
+
+                for protocol in subprotocols {
+                    if !is_http_token(protocol) {
+                        errors.push(ValidationError::InvalidField {
+                            field: format!("route '{path}' subprotocols"),
+                            message: format!("'{protocol}' is not a valid subprotocol name"),
+                        });
+                    }
+                }
+
+                for (name, value) in [
+                    ("idle_timeout", idle_timeout),
+                    ("max_connection_duration", max_connection_duration),
+                ] {
+                    let Some(value) = value else { continue };
+                    match humantime::parse_duration(value) {
+                        Ok(duration) if duration.is_zero() => {
+                            errors.push(ValidationError::InvalidField {
+                                field: format!("route '{path}' {name}"),
+                                message: "Duration must be greater than 0".to_string(),
+                            });
+                        }
+                        Ok(_) => {}
+                        Err(e) => errors.push(ValidationError::InvalidField {
+                            field: format!("route '{path}' {name}"),
+                            message: format!("Invalid duration '{value}': {e}"),
+                        }),
+                    }
+                }
             }
         }
 
+        if let RouteConfig::Proxy {
+            mirror: Some(mirror),
+            ..
+        }
+        | RouteConfig::LoadBalance {
+            mirror: Some(mirror),
+            ..
+        } = config
+        {
+            if let Err(e) =
+                Self::validate_url(&mirror.target, &format!("route '{path}' mirror target"))
+            {
+                errors.push(e);
+            }
+            if !(0.0..=1.0).contains(&mirror.sample_rate) {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("route '{path}' mirror sample_rate"),
+                    message: format!("{} must be between 0.0 and 1.0", mirror.sample_rate),
+                });
+            }
+        }
+
+        if let RouteConfig::Proxy {
+            path_rewrite_regex: Some(rewrite),
+            ..
+        }
+        | RouteConfig::LoadBalance {
+            path_rewrite_regex: Some(rewrite),
+            ..
+        } = config
+        {
+            errors.extend(Self::validate_path_rewrite_regex(path, rewrite));
+        }
+
         let rate_limit = match config {
             RouteConfig::Proxy { rate_limit, .. } => rate_limit,
             RouteConfig::LoadBalance { rate_limit, .. } => rate_limit,
+            RouteConfig::Split { rate_limit, .. } => rate_limit,
             RouteConfig::Static { rate_limit, .. } => rate_limit,
             RouteConfig::Redirect { rate_limit, .. } => rate_limit,
             RouteConfig::Websocket { rate_limit, .. } => rate_limit,
@@ -216,6 +861,7 @@ impl ConfigValidator {
         let path_rewrite = match config {
             RouteConfig::Proxy { path_rewrite, .. } => path_rewrite,
             RouteConfig::LoadBalance { path_rewrite, .. } => path_rewrite,
+            RouteConfig::Split { path_rewrite, .. } => path_rewrite,
             RouteConfig::Static { .. } => &None,
             RouteConfig::Redirect { .. } => &None,
             RouteConfig::Websocket { path_rewrite, .. } => path_rewrite,
@@ -228,6 +874,13 @@ impl ConfigValidator {
                 .ok();
         }
 
+        errors.extend(Self::validate_path_match(
+            path,
+            &config.options().path_match,
+            path_rewrite.as_deref(),
+        ));
+        errors.extend(Self::validate_route_options(path, config.options()));
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -235,52 +888,980 @@ impl ConfigValidator {
         }
     }
 
-    /// Validate URL format
-    fn validate_url(url_str: &str, context: &str) -> ValidationResult<()> {
-        match Url::parse(url_str) {
-            Ok(url) => {
-                if url.scheme() != "http" && url.scheme() != "https" {
-                    return Err(ValidationError::InvalidUrl {
-                        field: context.to_string(),
-                        url: url_str.to_string(),
-                        reason: "URL must use http:// or https:// scheme".to_string(),
-                    });
-                }
+    /// Compile regex routes and check that `path_rewrite` only references existing groups
+    fn validate_path_match(
+        path: &str,
+        path_match: &PathMatch,
+        path_rewrite: Option<&str>,
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let references = path_rewrite.map(capture_references).unwrap_or_default();
 
-                if url.host().is_none() {
-                    return Err(ValidationError::InvalidUrl {
-                        field: context.to_string(),
-                        url: url_str.to_string(),
-                        reason: "URL must have a valid host".to_string(),
+        match path_match {
+            PathMatch::Prefix | PathMatch::Exact => {
+                if let Some(reference) = references.first() {
+                    errors.push(ValidationError::InvalidField {
+                        field: format!("route '{path}' path_rewrite"),
+                        message: format!(
+                            "'${reference}' refers to a capture group, which requires match type 'regex'"
+                        ),
                     });
                 }
-
-                Ok(())
             }
-            Err(e) => Err(ValidationError::InvalidUrl {
-                field: context.to_string(),
-                url: url_str.to_string(),
-                reason: format!("Invalid URL format: {e}"),
-            }),
+            PathMatch::Regex { pattern } => match Regex::new(pattern) {
+                Ok(regex) => {
+                    for reference in missing_captures(&regex, path_rewrite.unwrap_or_default()) {
+                        errors.push(ValidationError::InvalidField {
+                            field: format!("route '{path}' path_rewrite"),
+                            message: format!(
+                                "'${reference}' does not match a capture group of '{pattern}' (use ${{1}} when followed by text)"
+                            ),
+                        });
+                    }
+                }
+                Err(e) => errors.push(ValidationError::InvalidField {
+                    field: format!("route '{path}' match pattern"),
+                    message: format!("Invalid regex '{pattern}': {e}"),
+                }),
+            },
         }
+
+        errors
     }
 
-    /// Validate WebSocket URL format
-    fn validate_websocket_url(url_str: &str, context: &str) -> ValidationResult<()> {
-        match Url::parse(url_str) {
-            Ok(url) => {
-                if !matches!(url.scheme(), "ws" | "wss" | "http" | "https") {
-                    return Err(ValidationError::InvalidUrl {
-                        field: context.to_string(),
-                        url: url_str.to_string(),
-                        reason: "WebSocket URL must use ws://, wss://, http://, or https:// scheme"
-                            .to_string(),
-                    });
-                }
+    fn validate_path_rewrite_regex(path: &str, rewrite: &PathRewriteRegex) -> Vec<ValidationError> {
+        let regex = match Regex::new(&rewrite.pattern) {
+            Ok(regex) => regex,
+            Err(e) => {
+                return vec![ValidationError::InvalidField {
+                    field: format!("route '{path}' path_rewrite_regex pattern"),
+                    message: format!("Invalid regex '{}': {e}", rewrite.pattern),
+                }];
+            }
+        };
+        missing_captures(&regex, &rewrite.replacement)
+            .into_iter()
+            .map(|reference| ValidationError::InvalidField {
+                field: format!("route '{path}' path_rewrite_regex replacement"),
+                message: format!(
+                    "'${reference}' does not match a capture group of '{}' (use ${{1}} when followed by text)",
+                    rewrite.pattern
+                ),
+            })
+            .collect()
+    }
 
-                if url.host().is_none() {
-                    return Err(ValidationError::InvalidUrl {
-                        field: context.to_string(),
+    /// Validate `*`/`:name` segments and the `{path_param:name}` placeholders that use them
+    fn validate_path_params(
+        path: &str,
+        match_path: Option<&str>,
+        config: &RouteConfig,
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let names = match_path.map(router::path_param_names).unwrap_or_default();
+        let mut seen = HashSet::new();
+        for name in &names {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("route '{path}' path"),
+                    message: format!(
+                        "Parameter ':{name}' must be a non-empty name of letters, digits or '_'"
+                    ),
+                });
+            } else if !seen.insert(*name) {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("route '{path}' path"),
+                    message: format!("Parameter ':{name}' is used more than once"),
+                });
+            }
+        }
+
+        let (path_rewrite, request_headers, response_headers) = match config {
+            RouteConfig::Proxy {
+                path_rewrite,
+                request_headers,
+                response_headers,
+                ..
+            }
+            | RouteConfig::LoadBalance {
+                path_rewrite,
+                request_headers,
+                response_headers,
+                ..
+            }
+            | RouteConfig::Split {
+                path_rewrite,
+                request_headers,
+                response_headers,
+                ..
+            }
+            | RouteConfig::Websocket {
+                path_rewrite,
+                request_headers,
+                response_headers,
+                ..
+            } => (
+                path_rewrite.as_ref(),
+                request_headers.as_ref(),
+                response_headers.as_ref(),
+            ),
+            RouteConfig::Static { .. } | RouteConfig::Redirect { .. } => (None, None, None),
+        };
+        let templates = path_rewrite
+            .map(|rewrite| ("path_rewrite".to_string(), rewrite))
+            .into_iter()
+            .chain(
+                [
+                    ("request_headers", request_headers),
+                    ("response_headers", response_headers),
+                ]
+                .into_iter()
+                .flat_map(|(field, actions)| {
+                    actions.into_iter().flat_map(move |actions| {
+                        actions
+                            .add
+                            .iter()
+                            .map(move |(header, value)| (format!("{field} '{header}'"), value))
+                    })
+                }),
+            );
+        for (field, template) in templates {
+            for reference in router::path_param_references(template) {
+                if !names.contains(&reference) {
+                    errors.push(ValidationError::InvalidField {
+                        field: format!("route '{path}' {field}"),
+                        message: format!(
+                            "'{{path_param:{reference}}}' does not match a ':{reference}' segment of the route path"
+                        ),
+                    });
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Check header names and values, their placeholders and the condition of request and
+    /// response header actions
+    fn validate_header_actions(path: &str, config: &RouteConfig) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let (request_headers, response_headers) = config.header_actions();
+        for (field, actions) in [
+            ("request_headers", request_headers),
+            ("response_headers", response_headers),
+        ] {
+            let Some(actions) = actions else { continue };
+            let field = format!("route '{path}' {field}");
+            let names = actions.add.keys().chain(&actions.remove);
+            for name in names {
+                if hyper::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                    errors.push(ValidationError::InvalidField {
+                        field: field.clone(),
+                        message: format!("'{name}' is not a valid header name"),
+                    });
+                }
+            }
+            for (name, value) in &actions.add {
+                let mut invalid = |message: String| {
+                    errors.push(ValidationError::InvalidField {
+                        field: format!("{field} '{name}'"),
+                        message,
+                    })
+                };
+                if hyper::header::HeaderValue::from_str(value).is_err() {
+                    invalid(format!("'{value}' is not a valid header value"));
+                }
+                // `{path_param:name}` is checked against the route path separately
+                for placeholder in Self::placeholders(value) {
+                    if placeholder.starts_with("path_param:") {
+                        continue;
+                    }
+                    if !HEADER_PLACEHOLDERS.contains(&placeholder) {
+                        invalid(format!(
+                            "Unknown placeholder '{{{placeholder}}}'; header values can be \
+                             {{client_ip}} or {{timestamp}}, or use {{path_param:name}}"
+                        ));
+                    } else if *value != format!("{{{placeholder}}}") {
+                        invalid(format!(
+                            "'{{{placeholder}}}' is only replaced when it is the whole value"
+                        ));
+                    }
+                }
+            }
+            if let Some(condition) = &actions.condition {
+                errors.extend(Self::validate_condition(
+                    &format!("{field} condition"),
+                    condition,
+                ));
+            }
+        }
+        errors
+    }
+
+    /// Check the placeholders and condition of request and response body actions
+    fn validate_body_actions(path: &str, config: &RouteConfig) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let (request_body, response_body) = config.body_actions();
+        for (field, actions) in [
+            ("request_body", request_body),
+            ("response_body", response_body),
+        ] {
+            let Some(actions) = actions else { continue };
+            let field = format!("route '{path}' {field}");
+            let mut invalid = |message: String| {
+                errors.push(ValidationError::InvalidField {
+                    field: field.clone(),
+                    message,
+                })
+            };
+            if actions.set_text.is_some() && actions.set_json.is_some() {
+                invalid("Set either set_text or set_json; set_json is ignored".to_string());
+            }
+            let mut texts = Vec::new();
+            if let Some(text) = &actions.set_text {
+                texts.push(text.as_str());
+            }
+            if let Some(json) = &actions.set_json {
+                Self::json_strings(json, &mut texts, &mut invalid);
+            }
+            for placeholder in texts.into_iter().flat_map(Self::placeholders) {
+                if !BODY_PLACEHOLDERS.contains(&placeholder) {
+                    invalid(format!(
+                        "Unknown placeholder '{{{placeholder}}}'; bodies can use {{uri_path}}, \
+                         {{timestamp_iso}} and {{client_ip}}"
+                    ));
+                }
+            }
+            if let Some(condition) = &actions.condition {
+                errors.extend(Self::validate_condition(
+                    &format!("{field} condition"),
+                    condition,
+                ));
+            }
+        }
+        errors
+    }
+
+    /// The strings of `set_json` that placeholders are replaced in, reporting placeholders
+    /// that are not inside one
+    fn json_strings<'a>(
+        value: &'a serde_json::Value,
+        strings: &mut Vec<&'a str>,
+        invalid: &mut impl FnMut(String),
+    ) {
+        match value {
+            serde_json::Value::String(text) => strings.push(text),
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    Self::json_strings(item, strings, invalid);
+                }
+            }
+            serde_json::Value::Object(object) => {
+                // An unquoted `{client_ip}` in YAML is a mapping with a null value
+                if let Some((name, serde_json::Value::Null)) = object.iter().next()
+                    && object.len() == 1
+                    && BODY_PLACEHOLDERS.contains(&name.as_str())
+                {
+                    invalid(format!(
+                        "set_json has an unquoted {{{name}}}, which YAML reads as a mapping; \
+                         write \"{{{name}}}\""
+                    ));
+                    return;
+                }
+                for (key, item) in object {
+                    if !Self::placeholders(key).is_empty() {
+                        invalid(format!(
+                            "Placeholders are not replaced in set_json keys such as '{key}'"
+                        ));
+                    }
+                    Self::json_strings(item, strings, invalid);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Names of the `{name}` and `{name:argument}` placeholders in `template`
+    fn placeholders(template: &str) -> Vec<&str> {
+        Regex::new(r"\{([A-Za-z_][A-Za-z0-9_]*(?::[^{}]*)?)\}")
+            .unwrap()
+            .captures_iter(template)
+            .filter_map(|captures| captures.get(1))
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    fn validate_condition(field: &str, condition: &RequestCondition) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let patterns = [
+            condition.path_matches.as_ref(),
+            condition
+                .has_header
+                .as_ref()
+                .and_then(|header| header.value_matches.as_ref()),
+        ];
+        for pattern in patterns.into_iter().flatten() {
+            if let Err(e) = Regex::new(pattern) {
+                errors.push(ValidationError::InvalidField {
+                    field: field.to_string(),
+                    message: format!("Invalid regex '{pattern}': {e}"),
+                });
+            }
+        }
+        if let Some(method) = &condition.method_is
+            && hyper::Method::from_bytes(method.to_ascii_uppercase().as_bytes()).is_err()
+        {
+            errors.push(ValidationError::InvalidField {
+                field: field.to_string(),
+                message: format!("'{method}' is not a valid HTTP method"),
+            });
+        }
+        if let Some(header) = &condition.has_header
+            && hyper::header::HeaderName::from_bytes(header.name.as_bytes()).is_err()
+        {
+            errors.push(ValidationError::InvalidField {
+                field: field.to_string(),
+                message: format!("'{}' is not a valid header name", header.name),
+            });
+        }
+        errors
+    }
+
+    /// Validate settings shared by all route types
+    fn validate_route_options(path: &str, options: &RouteOptions) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        for method in &options.match_methods {
+            if hyper::Method::from_bytes(method.to_ascii_uppercase().as_bytes()).is_err() {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("route '{path}' match_methods"),
+                    message: format!("'{method}' is not a valid HTTP method"),
+                });
+            }
+        }
+
+        for (name, value_match) in &options.match_query {
+            if let ValueMatch::Regex { regex } = value_match
+                && let Err(e) = Regex::new(regex)
+            {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("route '{path}' match_query '{name}'"),
+                    message: format!("Invalid regex '{regex}': {e}"),
+                });
+            }
+        }
+
+        for (name, value_match) in &options.match_headers {
+            if hyper::header::HeaderName::try_from(name.as_str()).is_err() {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("route '{path}' match_headers"),
+                    message: format!("'{name}' is not a valid header name"),
+                });
+            }
+            if let ValueMatch::Regex { regex } = value_match
+                && let Err(e) = Regex::new(regex)
+            {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("route '{path}' match_headers '{name}'"),
+                    message: format!("Invalid regex '{regex}': {e}"),
+                });
+            }
+        }
+
+        for (i, origin) in options.allowed_origins.iter().enumerate() {
+            let field = format!("route '{path}' allowed_origins {i}");
+            if let Err(e) = Self::validate_url(origin, &field) {
+                errors.push(e);
+                continue;
+            }
+            // An origin is scheme://host[:port] only; anything else would never match
+            if let Ok(url) = Url::parse(origin)
+                && (url.path() != "/" || url.query().is_some() || url.fragment().is_some())
+            {
+                errors.push(ValidationError::InvalidUrl {
+                    field,
+                    url: origin.clone(),
+                    reason: "Allowed origins must not contain a path, query or fragment"
+                        .to_string(),
+                });
+            }
+        }
+
+        errors.extend(Self::validate_error_pages(
+            &format!("route '{path}' error_pages"),
+            &options.error_pages,
+        ));
+
+        if let Some(maintenance) = &options.maintenance {
+            errors.extend(Self::validate_maintenance(path, maintenance));
+        }
+
+        if let Some(passive) = &options.passive_health_check {
+            for status in &passive.failure_statuses {
+                if !(100..=599).contains(status) {
+                    errors.push(ValidationError::InvalidField {
+                        field: format!("route '{path}' passive_health_check.failure_statuses"),
+                        message: format!("{status} is not an HTTP status code"),
+                    });
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Validate a route's maintenance page, status and allowlist
+    fn validate_maintenance(path: &str, maintenance: &MaintenanceConfig) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let field = format!("route '{path}' maintenance");
+
+        if !(400..=599).contains(&maintenance.status) {
+            errors.push(ValidationError::InvalidField {
+                field: field.clone(),
+                message: format!("Status {} must be a 4xx or 5xx code", maintenance.status),
+            });
+        }
+        if maintenance.body_file.is_some() && maintenance.body.is_some() {
+            errors.push(ValidationError::InvalidField {
+                field: field.clone(),
+                message: "Use only one of 'body_file' or 'body'".to_string(),
+            });
+        }
+        if let Some(file) = &maintenance.body_file
+            && !Path::new(file).is_file()
+        {
+            errors.push(ValidationError::FileNotFound { path: file.clone() });
+        }
+        if let Some(retry_after) = &maintenance.retry_after
+            && let Err(e) = humantime::parse_duration(retry_after)
+        {
+            errors.push(ValidationError::InvalidField {
+                field: field.clone(),
+                message: format!("Invalid retry_after '{retry_after}': {e}"),
+            });
+        }
+        for entry in &maintenance.allow_ips {
+            if let Err(e) = entry.parse::<IpRange>() {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("{field} allow_ips"),
+                    message: e,
+                });
+            }
+        }
+
+        errors
+    }
+
+    /// Validate error page keys ("404", "5xx") and their sources
+    fn validate_error_pages(
+        context: &str,
+        pages: &HashMap<String, ErrorPageConfig>,
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        for (key, page) in pages {
+            let field = format!("{context} '{key}'");
+            let valid_key = match key.parse::<u16>() {
+                Ok(code) => (400..=599).contains(&code),
+                Err(_) => matches!(key.to_ascii_lowercase().as_str(), "4xx" | "5xx"),
+            };
+            if !valid_key {
+                errors.push(ValidationError::InvalidField {
+                    field: field.clone(),
+                    message: "Error page keys must be a 4xx/5xx status code or '4xx'/'5xx'"
+                        .to_string(),
+                });
+            }
+
+            match (&page.file, &page.body) {
+                (Some(_), Some(_)) | (None, None) => {
+                    errors.push(ValidationError::InvalidField {
+                        field,
+                        message: "Error pages need exactly one of 'file' or 'body'".to_string(),
+                    });
+                }
+                (Some(file), None) if !Path::new(file).is_file() => {
+                    errors.push(ValidationError::FileNotFound { path: file.clone() });
+                }
+                _ => {}
+            }
+        }
+
+        errors
+    }
+
+    /// Validate header names and values, without echoing the values
+    fn validate_secret_headers(field: &str, headers: &SecretHeaders) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        for (name, value) in &headers.0 {
+            if hyper::header::HeaderName::try_from(name.as_str()).is_err() {
+                errors.push(ValidationError::InvalidField {
+                    field: field.to_string(),
+                    message: format!("'{name}' is not a valid header name"),
+                });
+            }
+            if hyper::header::HeaderValue::try_from(value.as_str()).is_err() {
+                errors.push(ValidationError::InvalidField {
+                    field: field.to_string(),
+                    message: format!("The value of '{name}' is not a valid header value"),
+                });
+            }
+        }
+        errors
+    }
+
+    /// Validate the sampling ratio, log filter and OTLP exporter
+    fn validate_telemetry(config: &TelemetryConfig) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if !(0.0..=1.0).contains(&config.sampling_ratio) {
+            errors.push(ValidationError::InvalidField {
+                field: "telemetry.sampling_ratio".to_string(),
+                message: format!("{} is not between 0.0 and 1.0", config.sampling_ratio),
+            });
+        }
+        if let Some(level) = &config.log_level
+            && let Err(e) = tracing_subscriber::EnvFilter::try_new(level)
+        {
+            errors.push(ValidationError::InvalidField {
+                field: "telemetry.log_level".to_string(),
+                message: format!("Invalid log filter '{level}': {e}"),
+            });
+        }
+        if let Some(otlp) = &config.otlp {
+            match Url::parse(&otlp.endpoint) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+                _ => errors.push(ValidationError::InvalidField {
+                    field: "telemetry.otlp.endpoint".to_string(),
+                    message: format!("'{}' is not an http(s) URL", otlp.endpoint),
+                }),
+            }
+            if otlp.timeout_secs == 0 {
+                errors.push(ValidationError::InvalidField {
+                    field: "telemetry.otlp.timeout_secs".to_string(),
+                    message: "Must be at least 1".to_string(),
+                });
+            }
+            errors.extend(Self::validate_secret_headers(
+                "telemetry.otlp.headers",
+                &otlp.headers,
+            ));
+        }
+        errors
+    }
+
+    /// Validate the access log's rotation and buffer settings
+    /// Validate the admin section, and refuse admin endpoints open to anyone on a
+    /// non-loopback address unless `allow_unauthenticated` says that's intended
+    fn validate_admin(config: &ServerConfig) -> Vec<ValidationError> {
+        let admin = &config.admin;
+        let mut errors = Vec::new();
+        if let Some(listen_addr) = &admin.listen_addr
+            && let Err(e) = Self::validate_listen_address(listen_addr)
+        {
+            errors.push(e);
+        }
+        match &admin.auth {
+            Some(AdminAuth::Bearer { token, token_file }) => match token_file {
+                None if token.is_empty() => {
+                    errors.push(ValidationError::InvalidField {
+                        field: "admin.auth.token".to_string(),
+                        message: "Set token or token_file".to_string(),
+                    });
+                }
+                Some(_) if !token.is_empty() => {
+                    errors.push(ValidationError::InvalidField {
+                        field: "admin.auth.token".to_string(),
+                        message: "Set either token or token_file, not both".to_string(),
+                    });
+                }
+                Some(file) => match std::fs::read_to_string(file) {
+                    Ok(contents) if contents.trim().is_empty() => {
+                        errors.push(ValidationError::InvalidField {
+                            field: "admin.auth.token_file".to_string(),
+                            message: format!("{file} is empty"),
+                        });
+                    }
+                    Ok(_) => {}
+                    Err(_) => {
+                        errors.push(ValidationError::FileNotFound { path: file.clone() });
+                    }
+                },
+                None => {}
+            },
+            Some(AdminAuth::Basic { username, password })
+                if username.is_empty() || username.contains(':') || password.is_empty() =>
+            {
+                errors.push(ValidationError::InvalidField {
+                    field: "admin.auth.basic".to_string(),
+                    message: "Needs a username without ':' and a non-empty password".to_string(),
+                });
+            }
+            _ => {}
+        }
+        for entry in &admin.allowed_ips {
+            if let Err(e) = entry.parse::<IpRange>() {
+                errors.push(ValidationError::InvalidField {
+                    field: "admin.allowed_ips".to_string(),
+                    message: e,
+                });
+            }
+        }
+        if let Some(tls) = &admin.tls {
+            if admin.listen_addr.is_none() {
+                errors.push(ValidationError::InvalidField {
+                    field: "admin.tls".to_string(),
+                    message: "Needs admin.listen_addr; the main listener has its own tls"
+                        .to_string(),
+                });
+            }
+            let files = [&tls.cert_path, &tls.key_path].into_iter();
+            for path in files.chain(&tls.client_ca_path) {
+                if !Path::new(path).is_file() {
+                    errors.push(ValidationError::FileNotFound { path: path.clone() });
+                }
+            }
+        }
+        if admin.history.max_entries == 0 {
+            errors.push(ValidationError::InvalidField {
+                field: "admin.history.max_entries".to_string(),
+                message: "Must keep at least the running config".to_string(),
+            });
+        }
+        if let Some(state_dir) = &admin.history.state_dir
+            && Path::new(state_dir).is_file()
+        {
+            errors.push(ValidationError::InvalidField {
+                field: "admin.history.state_dir".to_string(),
+                message: format!("{state_dir} is a file, not a directory"),
+            });
+        }
+
+        if !admin.allow_unauthenticated
+            && let Some(addr) = Self::admin_open_to_anyone(config)
+        {
+            errors.push(ValidationError::InvalidField {
+                field: "admin".to_string(),
+                message: format!(
+                    "/metrics and the /-/ endpoints would be open to anyone on {addr}; set \
+                     admin.auth, admin.allowed_ips or admin.tls.client_ca_path, serve them \
+                     on a loopback admin.listen_addr, or set admin.allow_unauthenticated"
+                ),
+            });
+        }
+        errors
+    }
+
+    /// The non-loopback address the admin endpoints are served on without any auth, IP or
+    /// client certificate restriction
+    fn admin_open_to_anyone(config: &ServerConfig) -> Option<SocketAddr> {
+        let admin = &config.admin;
+        let client_certs_required = admin
+            .tls
+            .as_ref()
+            .is_some_and(|tls| tls.client_ca_path.is_some());
+        if admin.auth.is_some() || !admin.allowed_ips.is_empty() || client_certs_required {
+            return None;
+        }
+        let exposed_on = admin.listen_addr.as_deref().unwrap_or(&config.listen_addr);
+        exposed_on
+            .parse::<SocketAddr>()
+            .ok()
+            .filter(|addr| !addr.ip().is_loopback())
+    }
+
+    fn validate_metrics(config: &MetricsConfig) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if let Some(buckets) = &config.buckets
+            && (buckets.is_empty()
+                || buckets.iter().any(|bound| !bound.is_finite())
+                || buckets.windows(2).any(|pair| pair[0] >= pair[1]))
+        {
+            errors.push(ValidationError::InvalidField {
+                field: "metrics.buckets".to_string(),
+                message: "Must be a non-empty list of finite, increasing bounds".to_string(),
+            });
+        }
+        if let Some(prefix) = &config.prefix
+            && !Regex::new("^[a-zA-Z_][a-zA-Z0-9_]*$")
+                .unwrap()
+                .is_match(prefix)
+        {
+            errors.push(ValidationError::InvalidField {
+                field: "metrics.prefix".to_string(),
+                message: format!("'{prefix}' is not a valid Prometheus metric name prefix"),
+            });
+        }
+        errors
+    }
+
+    fn validate_shutdown(config: &ShutdownConfig) -> Vec<ValidationError> {
+        [
+            (
+                "grace_period_before_drain",
+                Some(&config.grace_period_before_drain),
+            ),
+            ("drain_timeout", Some(&config.drain_timeout)),
+            ("force_close_after", config.force_close_after.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let value = value?;
+            humantime::parse_duration(value)
+                .err()
+                .map(|e| ValidationError::InvalidField {
+                    field: format!("shutdown.{name}"),
+                    message: format!("Invalid duration '{value}': {e}"),
+                })
+        })
+        .collect()
+    }
+
+    fn validate_connection_limits(config: &ConnectionLimitsConfig) -> Vec<ValidationError> {
+        let mut errors: Vec<_> = [
+            ("max_connection_age", config.max_connection_age.as_ref()),
+            (
+                "max_connection_age_grace",
+                Some(&config.max_connection_age_grace),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let value = value?;
+            let message = match humantime::parse_duration(value) {
+                Err(e) => format!("Invalid duration '{value}': {e}"),
+                Ok(duration) if duration.is_zero() && name == "max_connection_age" => {
+                    "Must be greater than 0".to_string()
+                }
+                Ok(_) => return None,
+            };
+            Some(ValidationError::InvalidField {
+                field: format!("connection_limits.{name}"),
+                message,
+            })
+        })
+        .collect();
+        if config.max_requests_per_connection == Some(0) {
+            errors.push(ValidationError::InvalidField {
+                field: "connection_limits.max_requests_per_connection".to_string(),
+                message: "Must be greater than 0".to_string(),
+            });
+        }
+        errors
+    }
+
+    fn validate_log_redaction(config: &LogRedactionConfig) -> Vec<ValidationError> {
+        config
+            .headers
+            .iter()
+            .filter(|name| hyper::header::HeaderName::try_from(name.as_str()).is_err())
+            .map(|name| ValidationError::InvalidField {
+                field: "log_redaction.headers".to_string(),
+                message: format!("'{name}' is not a valid header name"),
+            })
+            .collect()
+    }
+
+    fn validate_access_log(config: &AccessLogConfig) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if config.path.is_empty() {
+            errors.push(ValidationError::InvalidField {
+                field: "access_log.path".to_string(),
+                message: "Must not be empty".to_string(),
+            });
+        }
+        if config.max_size_bytes == Some(0) {
+            errors.push(ValidationError::InvalidField {
+                field: "access_log.max_size_bytes".to_string(),
+                message: "Must be at least 1".to_string(),
+            });
+        }
+        if config.buffer_lines == 0 {
+            errors.push(ValidationError::InvalidField {
+                field: "access_log.buffer_lines".to_string(),
+                message: "Must be at least 1".to_string(),
+            });
+        }
+        errors
+    }
+
+    /// Validate a health check's schedule, method and expected statuses
+    fn validate_health_check(context: &str, config: &HealthCheckConfig) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        if config.interval_secs == 0 {
+            errors.push(ValidationError::InvalidField {
+                field: format!("{context}.interval_secs"),
+                message: "Must be at least 1".to_string(),
+            });
+        } else if config.interval_secs < config.timeout_secs {
+            errors.push(ValidationError::InvalidField {
+                field: format!("{context}.interval_secs"),
+                message: format!(
+                    "Interval of {}s is shorter than the {}s timeout",
+                    config.interval_secs, config.timeout_secs
+                ),
+            });
+        }
+
+        match hyper::Method::from_bytes(config.method().to_ascii_uppercase().as_bytes()) {
+            Ok(method) if method == hyper::Method::HEAD && config.matches_body() => {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("{context}.method"),
+                    message:
+                        "HEAD responses have no body for body_contains or body_json_path to match"
+                            .to_string(),
+                });
+            }
+            Ok(_) => {}
+            Err(_) => errors.push(ValidationError::InvalidField {
+                field: format!("{context}.method"),
+                message: format!("Invalid HTTP method '{}'", config.method()),
+            }),
+        }
+
+        for status in &config.expected_statuses {
+            if !(100..=599).contains(status) {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("{context}.expected_statuses"),
+                    message: format!("{status} is not an HTTP status code"),
+                });
+            }
+        }
+
+        errors.extend(Self::validate_secret_headers(
+            &format!("{context}.headers"),
+            &config.headers,
+        ));
+
+        if config.port == Some(0) {
+            errors.push(ValidationError::InvalidField {
+                field: format!("{context}.port"),
+                message: "Must be between 1 and 65535".to_string(),
+            });
+        }
+
+        if let Some(scheme) = &config.scheme
+            && scheme != "http"
+            && scheme != "https"
+        {
+            errors.push(ValidationError::InvalidField {
+                field: format!("{context}.scheme"),
+                message: format!("Scheme must be 'http' or 'https', got '{scheme}'"),
+            });
+        }
+
+        errors
+    }
+
+    /// Validate split variants and their stickiness settings
+    fn validate_split(
+        path: &str,
+        variants: &[SplitVariant],
+        sticky: Option<&SplitStickiness>,
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if variants.iter().all(|variant| variant.weight == 0) {
+            errors.push(ValidationError::InvalidField {
+                field: format!("route '{path}' split variants"),
+                message: "Split routes need at least one variant with a weight above 0".to_string(),
+            });
+        }
+
+        let mut labels = HashSet::new();
+        for (i, variant) in variants.iter().enumerate() {
+            if let Err(e) = Self::validate_url(
+                &variant.target,
+                &format!("route '{path}' split variant {i}"),
+            ) {
+                errors.push(e);
+            }
+            if !labels.insert(variant.label()) {
+                errors.push(ValidationError::InvalidField {
+                    field: format!("route '{path}' split variant {i}"),
+                    message: format!("Duplicate variant '{}'", variant.label()),
+                });
+            }
+        }
+
+        errors.extend(Self::validate_sticky_cookie(path, sticky));
+        if let Some(SplitStickiness::Cookie { .. }) = sticky {
+            // The variant name is stored as the cookie value
+            for (i, variant) in variants.iter().enumerate() {
+                if !variant.name.as_deref().is_some_and(is_cookie_token) {
+                    errors.push(ValidationError::InvalidField {
+                        field: format!("route '{path}' split variant {i} name"),
+                        message: "Cookie stickiness requires every variant to have a name \
+                                  made of letters, digits, '-', '_' or '.'"
+                            .to_string(),
+                    });
+                }
+            }
+        }
+        errors
+    }
+
+    /// Check the cookie name of cookie-based stickiness
+    fn validate_sticky_cookie(
+        path: &str,
+        sticky: Option<&SplitStickiness>,
+    ) -> Option<ValidationError> {
+        match sticky {
+            Some(SplitStickiness::Cookie { name }) if !is_cookie_token(name) => {
+                Some(ValidationError::InvalidField {
+                    field: format!("route '{path}' sticky cookie name"),
+                    message: format!("'{name}' is not a valid cookie name"),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Validate URL format
+    fn validate_url(url_str: &str, context: &str) -> ValidationResult<()> {
+        match Url::parse(url_str) {
+            Ok(url) => {
+                if url.scheme() != "http" && url.scheme() != "https" {
+                    return Err(ValidationError::InvalidUrl {
+                        field: context.to_string(),
+                        url: url_str.to_string(),
+                        reason: "URL must use http:// or https:// scheme".to_string(),
+                    });
+                }
+
+                if url.host().is_none() {
+                    return Err(ValidationError::InvalidUrl {
+                        field: context.to_string(),
+                        url: url_str.to_string(),
+                        reason: "URL must have a valid host".to_string(),
+                    });
+                }
+
+                Ok(())
+            }
+            Err(e) => Err(ValidationError::InvalidUrl {
+                field: context.to_string(),
+                url: url_str.to_string(),
+                reason: format!("Invalid URL format: {e}"),
+            }),
+        }
+    }
+
+    /// Validate WebSocket URL format
+    fn validate_websocket_url(url_str: &str, context: &str) -> ValidationResult<()> {
+        match Url::parse(url_str) {
+            Ok(url) => {
+                if !matches!(url.scheme(), "ws" | "wss" | "http" | "https") {
+                    return Err(ValidationError::InvalidUrl {
+                        field: context.to_string(),
+                        url: url_str.to_string(),
+                        reason: "WebSocket URL must use ws://, wss://, http://, or https:// scheme"
+                            .to_string(),
+                    });
+                }
+
+                if url.host().is_none() {
+                    return Err(ValidationError::InvalidUrl {
+                        field: context.to_string(),
                         url: url_str.to_string(),
                         reason: "WebSocket URL must have a valid host".to_string(),
                     });
@@ -378,6 +1959,8 @@ impl ConfigValidator {
                     message: "Cannot specify both manual certificates (cert_path/key_path) and ACME configuration".to_string(),
                 });
             }
+            // SNI certificates alone, without a default for other names
+            (None, None, None) if !config.certificates.is_empty() => {}
             _ => {
                 return Err(ValidationError::InvalidTls {
                     message: "TLS configuration must specify either manual certificates (cert_path + key_path) or ACME configuration".to_string(),
@@ -385,6 +1968,51 @@ impl ConfigValidator {
             }
         }
 
+        if let Some(responder) = &config.ocsp_responder
+            && !Url::parse(responder).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+        {
+            return Err(ValidationError::InvalidTls {
+                message: format!("ocsp_responder must be an http(s) URL, got: {responder}"),
+            });
+        }
+
+        Self::validate_tls_certificates(&config.certificates)
+    }
+
+    /// Validate SNI certificates: existing files, valid domains, and no domain claimed twice
+    fn validate_tls_certificates(certificates: &[TlsCertificate]) -> ValidationResult<()> {
+        let mut claims: HashMap<String, usize> = HashMap::new();
+        for (i, certificate) in certificates.iter().enumerate() {
+            if certificate.domains.is_empty() {
+                return Err(ValidationError::InvalidTls {
+                    message: format!("Certificate {i} must list at least one domain"),
+                });
+            }
+            for path in [&certificate.cert_path, &certificate.key_path] {
+                if !Path::new(path).exists() {
+                    return Err(ValidationError::InvalidTls {
+                        message: format!("Certificate {i} file not found: {path}"),
+                    });
+                }
+            }
+            for domain in &certificate.domains {
+                let name = domain.strip_prefix("*.").unwrap_or(domain);
+                if !Self::is_valid_domain(name) {
+                    return Err(ValidationError::InvalidTls {
+                        message: format!("Certificate {i} has an invalid domain: {domain}"),
+                    });
+                }
+                if let Some(other) = claims.insert(domain.to_ascii_lowercase(), i)
+                    && other != i
+                {
+                    return Err(ValidationError::InvalidTls {
+                        message: format!(
+                            "Domain '{domain}' is claimed by both certificate {other} and certificate {i}"
+                        ),
+                    });
+                }
+            }
+        }
         Ok(())
     }
 
@@ -407,11 +2035,55 @@ impl ConfigValidator {
         }
 
         for domain in &config.domains {
-            if !Self::is_valid_domain(domain) {
+            let wildcard = domain.strip_prefix("*.");
+            if !Self::is_valid_domain(wildcard.unwrap_or(domain)) {
                 return Err(ValidationError::InvalidAcme {
                     message: format!("Invalid domain name: {domain}"),
                 });
             }
+            if wildcard.is_some() && config.challenge_type != AcmeChallengeType::Dns01 {
+                return Err(ValidationError::InvalidAcme {
+                    message: format!("Wildcard domain {domain} requires challenge_type: dns-01"),
+                });
+            }
+        }
+
+        if let Some(ca_url) = &config.ca_url
+            && !Url::parse(ca_url).is_ok_and(|url| url.scheme() == "https")
+        {
+            return Err(ValidationError::InvalidAcme {
+                message: format!("ca_url must be an https:// ACME directory URL, got: {ca_url}"),
+            });
+        }
+
+        match (&config.eab_kid, &config.eab_hmac_key) {
+            (Some(_), Some(hmac_key)) => {
+                if base64::engine::general_purpose::URL_SAFE_NO_PAD
+                    .decode(hmac_key.trim_end_matches('='))
+                    .is_err()
+                {
+                    return Err(ValidationError::InvalidAcme {
+                        message: "eab_hmac_key must be base64url".to_string(),
+                    });
+                }
+            }
+            (None, None) => {}
+            _ => {
+                return Err(ValidationError::InvalidAcme {
+                    message: "eab_kid and eab_hmac_key must be set together".to_string(),
+                });
+            }
+        }
+
+        match (&config.challenge_type, &config.dns) {
+            (AcmeChallengeType::Dns01, None) => {
+                return Err(ValidationError::InvalidAcme {
+                    message: "challenge_type dns-01 requires a dns provider configuration"
+                        .to_string(),
+                });
+            }
+            (_, Some(dns)) => Self::validate_acme_dns(dns)?,
+            _ => {}
         }
 
         // Validate renewal days before expiry
@@ -427,19 +2099,121 @@ impl ConfigValidator {
         Ok(())
     }
 
-    /// Check for route conflicts (overlapping paths)
+    /// Validate the DNS-01 provider, resolvers and propagation timeout
+    fn validate_acme_dns(config: &AcmeDnsConfig) -> ValidationResult<()> {
+        let invalid = |message: String| Err(ValidationError::InvalidAcme { message });
+
+        if let Some(timeout) = &config.propagation_timeout
+            && let Err(e) = humantime::parse_duration(timeout)
+        {
+            return invalid(format!("Invalid propagation_timeout '{timeout}': {e}"));
+        }
+        for resolver in &config.resolvers {
+            if AcmeDnsConfig::parse_name_server(resolver).is_none() {
+                return invalid(format!("Invalid DNS resolver address: {resolver}"));
+            }
+        }
+
+        match &config.provider {
+            DnsProviderConfig::Cloudflare { api_token, .. } => {
+                if api_token.trim().is_empty() {
+                    return invalid("Cloudflare api_token must not be empty".to_string());
+                }
+            }
+            DnsProviderConfig::Rfc2136 {
+                server,
+                zone,
+                tsig_key_name,
+                tsig_secret,
+                ..
+            } => {
+                if AcmeDnsConfig::parse_name_server(server).is_none() {
+                    return invalid(format!("Invalid RFC 2136 server address: {server}"));
+                }
+                if !Self::is_valid_domain(zone.trim_end_matches('.')) {
+                    return invalid(format!("Invalid RFC 2136 zone: {zone}"));
+                }
+                match (tsig_key_name, tsig_secret) {
+                    (Some(_), Some(secret)) => {
+                        if base64::engine::general_purpose::STANDARD
+                            .decode(secret)
+                            .is_err()
+                        {
+                            return invalid("tsig_secret must be base64".to_string());
+                        }
+                    }
+                    (None, None) => {}
+                    _ => {
+                        return invalid(
+                            "tsig_key_name and tsig_secret must be set together".to_string(),
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check for ambiguous routes: same path, same request conditions and same priority
     fn check_route_conflicts(
         routes: &HashMap<String, RouteConfig>,
     ) -> Result<(), Vec<ValidationError>> {
         let mut errors = Vec::new();
-        let route_paths: Vec<&String> = routes.keys().collect();
-
-        for (i, path1) in route_paths.iter().enumerate() {
-            for path2 in route_paths.iter().skip(i + 1) {
-                if Self::routes_conflict(path1, path2) {
+        // Regex routes don't claim a path, so only prefix and exact routes can collide; routes
+        // that differ in their match type, header, method or query conditions are told apart
+        // at match time
+        let mut route_paths: Vec<(&String, &String, &RouteOptions)> = routes
+            .iter()
+            .filter(|(_, route)| {
+                matches!(
+                    route.options().path_match,
+                    PathMatch::Prefix | PathMatch::Exact
+                )
+            })
+            .map(|(key, route)| {
+                (
+                    key,
+                    route.options().path.as_ref().unwrap_or(key),
+                    route.options(),
+                )
+            })
+            .collect();
+        route_paths.sort_by_key(|(key, ..)| *key);
+
+        for (i, (key1, path1, options1)) in route_paths.iter().enumerate() {
+            for (key2, path2, options2) in route_paths.iter().skip(i + 1) {
+                if options1.path_match != options2.path_match
+                    || options1.priority != options2.priority
+                    || options1.match_headers != options2.match_headers
+                    || options1.match_query != options2.match_query
+                    || !Self::routes_conflict(path1, path2)
+                {
+                    continue;
+                }
+                let methods1 = normalize_methods(&options1.match_methods);
+                let methods2 = normalize_methods(&options2.match_methods);
+                if methods1 == methods2 {
                     errors.push(ValidationError::RouteConflict {
-                        message: format!("Routes '{path1}' and '{path2}' have conflicting paths"),
+                        message: format!(
+                            "Routes '{path1}' and '{path2}' have conflicting paths \
+                             (same path, match type, conditions and priority)"
+                        ),
                     });
+                } else {
+                    // Both count as one condition, so a request matching both method lists
+                    // would go to whichever route key sorts first
+                    let shared: Vec<&str> = methods1
+                        .iter()
+                        .filter(|method| methods2.contains(method))
+                        .map(String::as_str)
+                        .collect();
+                    if !shared.is_empty() {
+                        errors.push(ValidationError::AmbiguousRoutes {
+                            first: key1.to_string(),
+                            second: key2.to_string(),
+                            methods: shared.join(", "),
+                        });
+                    }
                 }
             }
         }
@@ -451,49 +2225,57 @@ impl ConfigValidator {
         }
     }
 
-    /// Check if two route paths conflict
-    fn routes_conflict(path1: &str, path2: &str) -> bool {
-        // Exact match
-        if path1 == path2 {
-            return true;
-        }
-
-        // Normalize paths (remove trailing slashes, but keep root "/")
-        let path1_norm = if path1 == "/" {
-            "/"
-        } else {
-            path1.trim_end_matches('/')
-        };
-
-        let path2_norm = if path2 == "/" {
-            "/"
-        } else {
-            path2.trim_end_matches('/')
-        };
-
-        if path1_norm == path2_norm {
-            return true;
-        }
-
-        // Special case: root path "/" doesn't conflict with specific paths like "/api"
-        if path1_norm == "/" || path2_norm == "/" {
-            return false;
+    /// Check that `on_not_found` on static routes points at a usable route
+    fn validate_not_found_targets(routes: &HashMap<String, RouteConfig>) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        for (path, route) in routes {
+            let RouteConfig::Static { static_options, .. } = route else {
+                continue;
+            };
+            let Some(action) = &static_options.on_not_found else {
+                continue;
+            };
+            let field = format!("route '{path}' on_not_found");
+            match (&action.proxy_to, action.fallthrough) {
+                (Some(_), true) => errors.push(ValidationError::InvalidField {
+                    field,
+                    message: "Set either fallthrough or proxy_to, not both".to_string(),
+                }),
+                (Some(target), false) if target == path => {
+                    errors.push(ValidationError::InvalidField {
+                        field,
+                        message: "proxy_to cannot point at the route itself".to_string(),
+                    })
+                }
+                (Some(target), false) if !routes.contains_key(target) => {
+                    errors.push(ValidationError::InvalidField {
+                        field,
+                        message: format!("proxy_to '{target}' is not a configured route"),
+                    })
+                }
+                (None, false) => errors.push(ValidationError::InvalidField {
+                    field,
+                    message: "Set fallthrough: true or proxy_to".to_string(),
+                }),
+                _ => {}
+            }
         }
+        errors
+    }
 
-        // Check if one is a prefix of the other with a path separator
-        let longer = if path1_norm.len() > path2_norm.len() {
-            path1_norm
-        } else {
-            path2_norm
-        };
-        let shorter = if path1_norm.len() <= path2_norm.len() {
-            path1_norm
-        } else {
-            path2_norm
+    /// Check if two route paths are the same prefix. Nested prefixes like `/api` and
+    /// `/api/admin` are fine: the longer one (or the higher priority) is tried first.
+    fn routes_conflict(path1: &str, path2: &str) -> bool {
+        // Parameter names don't matter: `/users/:id` and `/users/:uid` claim the same paths
+        let normalize = |path: &str| {
+            let path = router::segment_shape(path);
+            if path == "/" {
+                path
+            } else {
+                path.trim_end_matches('/').to_string()
+            }
         };
-
-        longer.starts_with(shorter)
-            && (longer.len() == shorter.len() || longer.chars().nth(shorter.len()) == Some('/'))
+        normalize(path1) == normalize(path2)
     }
 
     /// Validate path rewrite pattern
@@ -585,6 +2367,67 @@ impl ConfigValidator {
     }
 }
 
+/// Conservative cookie name/value check: letters, digits, '-', '_' and '.'
+fn is_cookie_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// An RFC 9110 token, the syntax of WebSocket subprotocol names
+fn is_http_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
+/// Whether two `match_methods` lists accept the same methods
+fn normalize_methods(methods: &[String]) -> Vec<String> {
+    let mut methods: Vec<String> = methods.iter().map(|m| m.to_ascii_uppercase()).collect();
+    methods.sort();
+    methods.dedup();
+    methods
+}
+
+/// References in a rewrite template to capture groups the regex doesn't have
+fn missing_captures(regex: &Regex, template: &str) -> Vec<String> {
+    capture_references(template)
+        .into_iter()
+        .filter(|reference| match reference.parse::<usize>() {
+            Ok(index) => index >= regex.captures_len(),
+            Err(_) => !regex.capture_names().flatten().any(|n| n == reference),
+        })
+        .collect()
+}
+
+/// `$1`, `$name` and `${name}` references in a rewrite template (`$$` is a literal '$')
+fn capture_references(template: &str) -> Vec<String> {
+    let mut references = Vec::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find('$') {
+        rest = &rest[pos + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{')
+            && let Some(end) = braced.find('}')
+        {
+            references.push(braced[..end].to_string());
+            rest = &braced[end + 1..];
+        } else {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if len > 0 {
+                references.push(rest[..len].to_string());
+            }
+            rest = &rest[len..];
+        }
+    }
+    references
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -603,38 +2446,251 @@ mod tests {
                 response_headers: None,
                 request_body: None,
                 response_body: None,
+                mirror: None,
+                path_rewrite_regex: None,
+                options: Default::default(),
             },
         );
-
-        ServerConfig {
-            listen_addr: "127.0.0.1:3000".to_string(),
-            routes,
-            tls: None,
-            health_check: Default::default(),
-            backend_health_paths: HashMap::new(),
-            protocols: Default::default(),
-        }
+
+        ServerConfig {
+            listen_addr: "127.0.0.1:3000".to_string(),
+            routes,
+            tls: None,
+            health_check: Default::default(),
+            backend_health_paths: HashMap::new(),
+            backend_health_checks: HashMap::new(),
+            protocols: Default::default(),
+            virtual_hosts: HashMap::new(),
+            error_pages: HashMap::new(),
+            normalize_paths: Default::default(),
+            default_route: None,
+            access_log: None,
+            telemetry: Default::default(),
+            debug_errors: false,
+            debug_headers: false,
+            log_redaction: Default::default(),
+            admin: Default::default(),
+            metrics: Default::default(),
+            shutdown: Default::default(),
+            connection_limits: Default::default(),
+            include: Vec::new(),
+            strict: false,
+        }
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let config = create_valid_config();
+        assert!(ConfigValidator::validate(&config).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_listen_address() {
+        let mut config = create_valid_config();
+        config.listen_addr = "invalid_address".to_string();
+
+        let result = ConfigValidator::validate(&config);
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Invalid listen address")
+        );
+    }
+
+    #[test]
+    fn test_admin_endpoints_not_public_by_default() {
+        let mut config = create_valid_config();
+        config.listen_addr = "0.0.0.0:8080".to_string();
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("would be open to anyone on 0.0.0.0:8080"));
+
+        // Any one of these keeps them private
+        config.admin.listen_addr = Some("127.0.0.1:9901".to_string());
+        assert!(ConfigValidator::validate(&config).is_ok());
+        config.admin.listen_addr = None;
+        config.admin.auth = Some(AdminAuth::Bearer {
+            token: "s3cret".to_string(),
+            token_file: None,
+        });
+        assert!(ConfigValidator::validate(&config).is_ok());
+        config.admin.auth = None;
+        config.admin.allow_unauthenticated = true;
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.admin.allowed_ips = vec!["10.0.0.0/33".to_string()];
+        config.admin.auth = Some(AdminAuth::Bearer {
+            token: String::new(),
+            token_file: None,
+        });
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("admin.allowed_ips"));
+        assert!(message.contains("admin.auth.token"));
+    }
+
+    #[test]
+    fn test_check_reports_fields_and_warnings() {
+        let mut config = create_valid_config();
+        assert!(ConfigValidator::check(&config).warnings.is_empty());
+
+        config.listen_addr = "0.0.0.0:8080".to_string();
+        config.admin.allow_unauthenticated = true;
+        config.debug_errors = true;
+        config.admin.history.max_entries = 0;
+        let report = ConfigValidator::check(&config);
+        assert!(!report.is_valid());
+        let fields = report
+            .errors
+            .iter()
+            .map(ValidationError::field)
+            .collect::<Vec<_>>();
+        assert_eq!(fields, [Some("admin.history.max_entries".to_string())]);
+        let warned = report
+            .warnings
+            .iter()
+            .filter_map(|warning| warning.field.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(warned, ["debug_errors", "admin.allow_unauthenticated"]);
+
+        let mut config = create_valid_config();
+        let route = config.routes.values_mut().next().unwrap();
+        if let RouteConfig::Proxy { rate_limit, .. } = route {
+            *rate_limit = Some(RateLimitConfig {
+                by: RateLimitBy::Ip,
+                header_name: None,
+                requests: 100,
+                period: "720h".to_string(),
+                status_code: 429,
+                message: "Too many requests".to_string(),
+                algorithm: RateLimitAlgorithm::TokenBucket,
+                on_missing_key: MissingKeyPolicy::Allow,
+            });
+        }
+        let report = ConfigValidator::check(&config);
+        assert!(report.is_valid(), "{:?}", report.errors);
+        let warnings = report
+            .warnings
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(warnings[0].contains("720h is over a day"));
+
+        let mut config = create_valid_config();
+        config.shutdown.grace_period_before_drain = "10s".to_string();
+        config.shutdown.force_close_after = Some("20s".to_string());
+        let report = ConfigValidator::check(&config);
+        assert_eq!(
+            report.warnings[0].field.as_deref(),
+            Some("shutdown.force_close_after")
+        );
+        config.shutdown.drain_timeout = "soon".to_string();
+        assert_eq!(
+            ConfigValidator::check(&config).errors[0].field(),
+            Some("shutdown.drain_timeout".to_string())
+        );
+
+        let mut config = create_valid_config();
+        config.connection_limits.max_connection_age = Some("0s".to_string());
+        config.connection_limits.max_requests_per_connection = Some(0);
+        let fields = ConfigValidator::check(&config)
+            .errors
+            .iter()
+            .filter_map(ValidationError::field)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                "connection_limits.max_connection_age",
+                "connection_limits.max_requests_per_connection"
+            ]
+        );
+
+        assert!(ConfigValidator::is_public_domain("api.example.com"));
+        assert!(!ConfigValidator::is_public_domain("prox.test"));
+        assert!(!ConfigValidator::is_public_domain("127.0.0.1"));
+    }
+
+    #[test]
+    fn test_self_referential_duplicate_and_ambiguous_routes() {
+        let mut config = create_valid_config();
+        config.listen_addr = "0.0.0.0:3000".to_string();
+        config.admin.allow_unauthenticated = true;
+        let routes: HashMap<String, RouteConfig> = serde_yaml::from_str(
+            r#"
+"/loop": { type: proxy, target: "http://localhost:3000" }
+"/pool": { type: load_balance, strategy: round_robin, targets: ["http://a:8080", "http://a:8080/", "http://b:8080"] }
+"/ws": { type: websocket, target: "ws://chat:9000", match_methods: ["GET"], path: "/chat" }
+"/chat": { type: proxy, target: "http://chat:8080", match_methods: ["get", "POST"] }
+"/other": { type: proxy, target: "http://other:3000" }
+"#,
+        )
+        .unwrap();
+        config.routes.extend(routes);
+
+        let mut errors = ConfigValidator::check(&config).errors;
+        errors.sort_by_key(ToString::to_string);
+        assert!(
+            matches!(
+                &errors[..],
+                [
+                    ValidationError::SelfReferentialTarget { route: looping, .. },
+                    ValidationError::DuplicateTarget { route: duplicate, .. },
+                    ValidationError::AmbiguousRoutes { first, second, methods },
+                ] if first == "/chat" && second == "/ws" && methods == "GET"
+                    && duplicate == "/pool" && looping == "/loop"
+            ),
+            "{errors:?}"
+        );
     }
 
     #[test]
-    fn test_valid_config() {
-        let config = create_valid_config();
+    fn test_metrics_validation() {
+        let mut config = create_valid_config();
+        config.metrics.buckets = Some(vec![0.001, 0.005, 0.01, 0.1]);
+        config.metrics.prefix = Some("edge_proxy".to_string());
         assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.metrics.buckets = Some(vec![0.01, 0.005]);
+        config.metrics.prefix = Some("edge-proxy".to_string());
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("metrics.buckets"));
+        assert!(message.contains("metrics.prefix"));
     }
 
     #[test]
-    fn test_invalid_listen_address() {
+    fn test_http3_listen_address() {
         let mut config = create_valid_config();
-        config.listen_addr = "invalid_address".to_string();
+        config.listen_addr = "0.0.0.0:443".to_string();
+        config.admin.listen_addr = Some("127.0.0.1:9901".to_string());
+        assert_eq!(config.http3_listen_addr().unwrap().port(), 443);
 
-        let result = ConfigValidator::validate(&config);
-        assert!(result.is_err());
-        assert!(
-            result
-                .unwrap_err()
-                .to_string()
-                .contains("Invalid listen address")
+        config.protocols.http3_listen_addr = Some("0.0.0.0:8443".to_string());
+        assert!(ConfigValidator::validate(&config).is_ok());
+        assert_eq!(
+            config.http3_listen_addr().unwrap(),
+            "0.0.0.0:8443".parse::<SocketAddr>().unwrap()
         );
+
+        config.protocols.http3_listen_addr = Some("8443".to_string());
+        let error = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(error.contains("Invalid listen address: 8443"));
+    }
+
+    #[test]
+    fn test_http3_qlog_max_files() {
+        let mut config = create_valid_config();
+        config.protocols.http3_config = Some(Http3Config {
+            qlog_max_files: 0,
+            ..Http3Config::default()
+        });
+        // Unused while qlog is off
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.protocols.http3_config.as_mut().unwrap().qlog_dir = Some("/tmp/qlog".to_string());
+        let error = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(error.contains("protocols.http3_config.qlog_max_files"));
     }
 
     #[test]
@@ -665,6 +2721,9 @@ mod tests {
                 response_headers: None,
                 request_body: None,
                 response_body: None,
+                mirror: None,
+                path_rewrite_regex: None,
+                options: Default::default(),
             },
         );
 
@@ -708,11 +2767,21 @@ mod tests {
         config.routes.insert(
             "/ws".to_string(),
             RouteConfig::Websocket {
-                target: "wss://echo.websocket.org".to_string(),
+                target: Some("wss://echo.websocket.org".to_string()),
+                targets: Vec::new(),
+                strategy: Default::default(),
+                sticky: None,
                 path_rewrite: None,
                 rate_limit: None,
+                request_headers: None,
+                response_headers: None,
                 max_frame_size: Some(65536),
                 max_message_size: Some(1048576),
+                idle_timeout: None,
+                max_connection_duration: None,
+                subprotocols: Vec::new(),
+                compression: Default::default(),
+                options: Default::default(),
             },
         );
 
@@ -722,11 +2791,21 @@ mod tests {
         config.routes.insert(
             "/ws-invalid".to_string(),
             RouteConfig::Websocket {
-                target: "ftp://invalid.com".to_string(),
+                target: Some("ftp://invalid.com".to_string()),
+                targets: Vec::new(),
+                strategy: Default::default(),
+                sticky: None,
                 path_rewrite: None,
                 rate_limit: None,
+                request_headers: None,
+                response_headers: None,
                 max_frame_size: None,
                 max_message_size: None,
+                idle_timeout: None,
+                max_connection_duration: None,
+                subprotocols: Vec::new(),
+                compression: Default::default(),
+                options: Default::default(),
             },
         );
 
@@ -748,11 +2827,21 @@ mod tests {
         config.routes.insert(
             "/ws".to_string(),
             RouteConfig::Websocket {
-                target: "ws://example.com".to_string(),
+                target: Some("ws://example.com".to_string()),
+                targets: Vec::new(),
+                strategy: Default::default(),
+                sticky: None,
                 path_rewrite: None,
                 rate_limit: None,
+                request_headers: None,
+                response_headers: None,
                 max_frame_size: Some(0),
                 max_message_size: None,
+                idle_timeout: None,
+                max_connection_duration: None,
+                subprotocols: Vec::new(),
+                compression: Default::default(),
+                options: Default::default(),
             },
         );
 
@@ -765,4 +2854,764 @@ mod tests {
                 .contains("WebSocket max frame size must be greater than 0")
         );
     }
+
+    #[test]
+    fn test_allowed_origins_validation() {
+        let mut config = create_valid_config();
+        config.routes.insert(
+            "/api".to_string(),
+            RouteConfig::Proxy {
+                target: "https://example.com".to_string(),
+                path_rewrite: None,
+                rate_limit: None,
+                request_headers: None,
+                response_headers: None,
+                request_body: None,
+                response_body: None,
+                mirror: None,
+                path_rewrite_regex: None,
+                options: RouteOptions {
+                    require_same_origin: true,
+                    allowed_origins: vec![
+                        "https://app.example.com".to_string(),
+                        "https://app.example.com/login".to_string(),
+                    ],
+                    on_missing_origin: MissingOriginPolicy::Deny,
+                    ..Default::default()
+                },
+            },
+        );
+
+        let result = ConfigValidator::validate(&config);
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Allowed origins must not contain a path")
+        );
+    }
+
+    #[test]
+    fn test_error_pages_validation() {
+        let mut config = create_valid_config();
+        config.error_pages.insert(
+            "5xx".to_string(),
+            ErrorPageConfig {
+                body: Some("<h1>Oops</h1>".to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.error_pages.insert(
+            "302".to_string(),
+            ErrorPageConfig {
+                file: Some("/nonexistent/302.html".to_string()),
+                ..Default::default()
+            },
+        );
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("Error page keys must be"));
+        assert!(message.contains("File not found: /nonexistent/302.html"));
+    }
+
+    #[test]
+    fn test_on_not_found_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = create_valid_config();
+        let static_route = |on_not_found| RouteConfig::Static {
+            root: dir.path().to_str().unwrap().to_string(),
+            rate_limit: None,
+            static_options: StaticOptions {
+                on_not_found: Some(on_not_found),
+                ..Default::default()
+            },
+            options: Default::default(),
+        };
+
+        config.routes.insert(
+            "/assets".to_string(),
+            static_route(NotFoundAction {
+                proxy_to: Some("/test".to_string()),
+                ..Default::default()
+            }),
+        );
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.routes.insert(
+            "/assets".to_string(),
+            static_route(NotFoundAction {
+                proxy_to: Some("/missing".to_string()),
+                ..Default::default()
+            }),
+        );
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("proxy_to '/missing' is not a configured route"));
+
+        config.routes.insert(
+            "/assets".to_string(),
+            static_route(NotFoundAction::default()),
+        );
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("Set fallthrough: true or proxy_to"));
+    }
+
+    #[test]
+    fn test_virtual_host_validation() {
+        let mut config = create_valid_config();
+        let routes = config.routes.clone();
+        config
+            .virtual_hosts
+            .insert("*.example.com".to_string(), VirtualHostConfig { routes });
+        // The same prefix under another host is not a conflict
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.routes.clear();
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.virtual_hosts.insert(
+            "api.*.com".to_string(),
+            VirtualHostConfig {
+                routes: HashMap::new(),
+            },
+        );
+        config
+            .virtual_hosts
+            .insert("*.EXAMPLE.com".to_string(), VirtualHostConfig::default());
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("'api.*.com' must be a host name"));
+        assert!(message.contains("match the same names"));
+        assert!(message.contains("virtual_hosts 'api.*.com' routes"));
+    }
+
+    #[test]
+    fn test_regex_route_validation() {
+        let mut config = create_valid_config();
+        let regex_route = |pattern: &str, path_rewrite: &str| RouteConfig::Proxy {
+            target: "https://example.com".to_string(),
+            path_rewrite: Some(path_rewrite.to_string()),
+            rate_limit: None,
+            request_headers: None,
+            response_headers: None,
+            request_body: None,
+            response_body: None,
+            mirror: None,
+            path_rewrite_regex: None,
+            options: RouteOptions {
+                path_match: PathMatch::Regex {
+                    pattern: pattern.to_string(),
+                },
+                ..Default::default()
+            },
+        };
+
+        config.routes.insert(
+            "versioned".to_string(),
+            regex_route(r"^/test/v(?P<version>\d+)/", "/internal/${version}/$1"),
+        );
+        // Regex routes don't conflict with the "/test" prefix
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.routes.insert(
+            "versioned".to_string(),
+            regex_route(r"^/test/v(\d+)/", "/internal/$2/$1users"),
+        );
+        config
+            .routes
+            .insert("broken".to_string(), regex_route("^/(unclosed", "/x"));
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("'$2' does not match a capture group"));
+        assert!(message.contains("'$1users' does not match a capture group"));
+        assert!(message.contains("Invalid regex '^/(unclosed'"));
+        assert_eq!(capture_references("/a/$$/${1}x/$name-"), ["1", "name"]);
+    }
+
+    #[test]
+    fn test_header_routes_sharing_a_path() {
+        let mut config = create_valid_config();
+        let tenant_route = |tenant: &str| RouteConfig::Proxy {
+            target: format!("https://{tenant}.example.com"),
+            path_rewrite: None,
+            rate_limit: None,
+            request_headers: None,
+            response_headers: None,
+            request_body: None,
+            response_body: None,
+            mirror: None,
+            path_rewrite_regex: None,
+            options: RouteOptions {
+                path: Some("/test".to_string()),
+                match_headers: HashMap::from([(
+                    "X-Tenant".to_string(),
+                    ValueMatch::Exact(tenant.to_string()),
+                )]),
+                ..Default::default()
+            },
+        };
+
+        config
+            .routes
+            .insert("test-acme".to_string(), tenant_route("acme"));
+        config
+            .routes
+            .insert("test-globex".to_string(), tenant_route("globex"));
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        // Different methods on the same path are fine too
+        let mut writes = tenant_route("acme");
+        if let RouteConfig::Proxy { options, .. } = &mut writes {
+            options.match_methods = vec!["POST".to_string()];
+        }
+        config.routes.insert("test-acme-writes".to_string(), writes);
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        // Same path and same conditions is ambiguous
+        config
+            .routes
+            .insert("test-acme-again".to_string(), tenant_route("acme"));
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("have conflicting paths"));
+    }
+
+    #[test]
+    fn test_health_check_validation() {
+        let mut config = create_valid_config();
+        config.health_check.expected_statuses = vec![200, 204];
+        config.health_check.body_contains = Some("\"status\":\"ok\"".to_string());
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.health_check.expected_statuses = vec![200, 1000];
+        config.backend_health_checks.insert(
+            "http://legacy:8080".to_string(),
+            HealthCheckOverride {
+                method: Some("HEAD".to_string()),
+                ..Default::default()
+            },
+        );
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("1000 is not an HTTP status code"));
+        assert!(message.contains("backend_health_checks 'http://legacy:8080'.method"));
+    }
+
+    #[test]
+    fn test_health_check_overrides_validation() {
+        let yaml = r#"
+listen_addr: "127.0.0.1:3000"
+health_check: { interval_secs: 10, timeout_secs: 2 }
+routes:
+  "/legacy":
+    type: load_balance
+    targets: ["http://legacy-1:8080", "http://legacy-2:8080"]
+    strategy: round_robin
+    health_check: { interval_secs: 30, timeout_secs: 15 }
+backend_health_checks:
+  "http://legacy-2:8080": { interval_secs: 5 }
+"#;
+        let mut config: ServerConfig = serde_yaml::from_str(yaml).unwrap();
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains(
+            "backend_health_checks 'http://legacy-2:8080'.interval_secs': Interval of 5s is shorter than the 15s timeout"
+        ));
+
+        config.backend_health_checks.clear();
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        // Two routes overriding the same backend's checks
+        let mut other = config.routes["/legacy"].clone();
+        if let RouteConfig::LoadBalance { targets, .. } = &mut other {
+            targets.truncate(1);
+        }
+        config.routes.insert("/old".to_string(), other);
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("backend 'http://legacy-1:8080'"));
+    }
+
+    #[test]
+    fn test_passive_health_check_validation() {
+        let yaml = r#"
+listen_addr: "127.0.0.1:3000"
+health_check: { enabled: false }
+routes:
+  "/api":
+    type: proxy
+    target: "http://api:8080"
+    passive_health_check: { failure_statuses: [500, 502, 700] }
+  "/":
+    type: redirect
+    target: "https://example.com"
+    passive_health_check: {}
+"#;
+        let mut config: ServerConfig = serde_yaml::from_str(yaml).unwrap();
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("700 is not an HTTP status code"));
+        assert!(message.contains(
+            "route '/api' passive_health_check': Passive health checks need health_check.enabled"
+        ));
+        assert!(
+            message.contains("Only proxy and load balance routes support passive health checks")
+        );
+
+        config.health_check.enabled = true;
+        config.routes.remove("/");
+        if let RouteConfig::Proxy { options, .. } = config.routes.get_mut("/api").unwrap() {
+            options.passive_health_check = Some(Default::default());
+        }
+        assert!(ConfigValidator::validate(&config).is_ok());
+    }
+
+    #[test]
+    fn test_telemetry_validation() {
+        let yaml = r#"
+listen_addr: "127.0.0.1:3000"
+routes:
+  "/": { type: static, root: "." }
+telemetry:
+  sampling_ratio: 1.5
+  log_level: "info,prox=loud"
+  otlp:
+    endpoint: "collector:4318"
+    headers: { "Authorization": "Bearer s3cret\n" }
+"#;
+        let mut config: ServerConfig = serde_yaml::from_str(yaml).unwrap();
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("1.5 is not between 0.0 and 1.0"));
+        assert!(message.contains("Invalid log filter 'info,prox=loud'"));
+        assert!(message.contains("'collector:4318' is not an http(s) URL"));
+        assert!(message.contains("The value of 'Authorization' is not a valid header value"));
+        assert!(!message.contains("s3cret"));
+
+        config.telemetry.sampling_ratio = 0.05;
+        config.telemetry.log_level = Some("warn,prox::adapters=debug".to_string());
+        let otlp = config.telemetry.otlp.as_mut().unwrap();
+        otlp.endpoint = "http://collector:4318/v1/traces".to_string();
+        otlp.headers.0.clear();
+        assert!(ConfigValidator::validate(&config).is_ok());
+    }
+
+    #[test]
+    fn test_split_routes() {
+        let mut config = create_valid_config();
+        let yaml = r#"
+type: split
+variants:
+  - { name: stable, target: "http://stable:8080", weight: 95 }
+  - { name: canary, target: "http://canary:8080", weight: 5 }
+sticky: { by: cookie }
+"#;
+        let split: RouteConfig = serde_yaml::from_str(yaml).unwrap();
+        match &split {
+            RouteConfig::Split { sticky, .. } => assert_eq!(
+                sticky,
+                &Some(SplitStickiness::Cookie {
+                    name: "prox_variant".to_string()
+                })
+            ),
+            other => panic!("unexpected route {other:?}"),
+        }
+        config.routes.insert("/split".to_string(), split);
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        let mut broken = serde_yaml::from_str::<RouteConfig>(yaml).unwrap();
+        if let RouteConfig::Split { variants, .. } = &mut broken {
+            variants[0].weight = 0;
+            variants[1].weight = 0;
+            variants[1].name = None;
+        }
+        config.routes.insert("/split".to_string(), broken);
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("at least one variant with a weight above 0"));
+        assert!(message.contains("Cookie stickiness requires every variant to have a name"));
+    }
+
+    #[test]
+    fn test_mirror_settings() {
+        let mut config = create_valid_config();
+        let route: RouteConfig = serde_yaml::from_str(
+            r#"
+type: proxy
+target: "http://api:8080"
+mirror: { target: "https://staging.example.com", sample_rate: 0.1 }
+"#,
+        )
+        .unwrap();
+        match &route {
+            RouteConfig::Proxy {
+                mirror: Some(mirror),
+                ..
+            } => assert_eq!(mirror.max_body_size, 1024 * 1024),
+            other => panic!("unexpected route {other:?}"),
+        }
+        config.routes.insert("/mirrored".to_string(), route.clone());
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        let mut broken = route;
+        if let RouteConfig::Proxy {
+            mirror: Some(mirror),
+            ..
+        } = &mut broken
+        {
+            mirror.target = "staging".to_string();
+            mirror.sample_rate = 1.5;
+        }
+        config.routes.insert("/mirrored".to_string(), broken);
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("mirror target"));
+        assert!(message.contains("1.5 must be between 0.0 and 1.0"));
+    }
+
+    #[test]
+    fn test_nested_prefixes_and_priorities_are_not_conflicts() {
+        let mut config = create_valid_config();
+        let nested = |target: &str, priority| RouteConfig::Proxy {
+            target: target.to_string(),
+            path_rewrite: None,
+            rate_limit: None,
+            request_headers: None,
+            response_headers: None,
+            request_body: None,
+            response_body: None,
+            mirror: None,
+            path_rewrite_regex: None,
+            options: RouteOptions {
+                path: Some("/test/admin".to_string()),
+                priority,
+                ..Default::default()
+            },
+        };
+        config
+            .routes
+            .insert("admin".to_string(), nested("https://admin.example.com", 0));
+        config.routes.insert(
+            "admin-override".to_string(),
+            nested("https://new.example.com", 5),
+        );
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.routes.insert(
+            "admin-again".to_string(),
+            nested("https://other.example.com", 5),
+        );
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("have conflicting paths"));
+    }
+
+    #[test]
+    fn test_default_route() {
+        let mut config = create_valid_config();
+        config.routes.clear();
+        config.default_route = Some(Box::new(
+            serde_yaml::from_str(
+                r#"
+type: redirect
+target: "https://legacy.example.com"
+status_code: 308
+"#,
+            )
+            .unwrap(),
+        ));
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.default_route = Some(Box::new(
+            serde_yaml::from_str(
+                r#"
+type: redirect
+target: "https://legacy.example.com"
+status_code: 200
+match_methods: ["GET"]
+"#,
+            )
+            .unwrap(),
+        ));
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("default_route"));
+        assert!(message.contains("remove match, path and match_* settings"));
+        assert!(message.contains("Status code 200 is not a valid redirect code"));
+    }
+
+    #[test]
+    fn test_exact_and_prefix_routes_share_a_path() {
+        let mut config = create_valid_config();
+        let exact: RouteConfig = serde_yaml::from_str(
+            r#"
+type: redirect
+target: "/status"
+path: "/test"
+match: exact
+"#,
+        )
+        .unwrap();
+        config
+            .routes
+            .insert("test-exact".to_string(), exact.clone());
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.routes.insert("test-exact-again".to_string(), exact);
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("have conflicting paths"));
+    }
+
+    #[test]
+    fn test_path_rewrite_regex_validation() {
+        let mut config = create_valid_config();
+        let route = |pattern: &str, replacement: &str| -> RouteConfig {
+            serde_yaml::from_str(&format!(
+                "type: proxy\ntarget: \"http://users:8080\"\npath_rewrite_regex: {{ pattern: '{pattern}', replacement: '{replacement}' }}\n"
+            ))
+            .unwrap()
+        };
+        config.routes.insert(
+            "/users".to_string(),
+            route(r"^/users/(\d+)/avatar$", "/v2/avatars?id=$1"),
+        );
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.routes.insert(
+            "/users".to_string(),
+            route(r"^/users/(\d+)/avatar$", "/v2/avatars?id=$2"),
+        );
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("path_rewrite_regex replacement"));
+        assert!(message.contains("'$2' does not match a capture group"));
+
+        config
+            .routes
+            .insert("/users".to_string(), route("^/users/(", "/x"));
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("path_rewrite_regex pattern"));
+    }
+
+    #[test]
+    fn test_maintenance_validation() {
+        let mut config = create_valid_config();
+        let route = |maintenance: &str| -> RouteConfig {
+            serde_yaml::from_str(&format!(
+                "type: proxy\ntarget: \"http://app:8080\"\nmaintenance: {maintenance}\n"
+            ))
+            .unwrap()
+        };
+        config.routes.insert(
+            "/app".to_string(),
+            route(r#"{ enabled: true, body: "<h1>Back soon</h1>", allow_ips: ["10.0.0.0/8", "::1"] }"#),
+        );
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.routes.insert(
+            "/app".to_string(),
+            route(r#"{ status: 200, retry_after: "soon", allow_ips: ["10.0.0.0/40"], body_file: "/nonexistent/page.html" }"#),
+        );
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("Status 200 must be a 4xx or 5xx code"));
+        assert!(message.contains("Invalid retry_after 'soon'"));
+        assert!(message.contains("invalid prefix length '40'"));
+        assert!(message.contains("/nonexistent/page.html"));
+    }
+
+    #[test]
+    fn test_segment_routes_validation() {
+        let mut config = create_valid_config();
+        let route = |extra: &str| -> RouteConfig {
+            serde_yaml::from_str(&format!(
+                "type: proxy\ntarget: \"http://app:8080\"\n{extra}"
+            ))
+            .unwrap()
+        };
+        config
+            .routes
+            .insert("/tenants/*/api".to_string(), route(""));
+        config
+            .routes
+            .insert("/tenants/acme/billing".to_string(), route(""));
+        config.routes.insert(
+            "/users/:id".to_string(),
+            route("path_rewrite: \"/v2/users/{path_param:id}\"\n"),
+        );
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        config.routes.insert("/users/:uid/".to_string(), route(""));
+        config.routes.insert(
+            "/orders/:id/:id".to_string(),
+            route("request_headers: { add: { X-Order: \"{path_param:order}\" } }\n"),
+        );
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("have conflicting paths"));
+        assert!(message.contains("Parameter ':id' is used more than once"));
+        assert!(message.contains("'{path_param:order}' does not match a ':order' segment"));
+    }
+
+    #[test]
+    fn test_sni_certificates_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, "placeholder").unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let certificate = |domains: &[&str]| TlsCertificate {
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            cert_path: file("cert.pem"),
+            key_path: file("key.pem"),
+        };
+        let mut config = create_valid_config();
+        config.tls = Some(TlsConfig {
+            cert_path: None,
+            key_path: None,
+            acme: None,
+            certificates: vec![
+                certificate(&["app.example.com"]),
+                certificate(&["api.other.com", "*.other.com"]),
+            ],
+            ..Default::default()
+        });
+        assert!(ConfigValidator::validate(&config).is_ok());
+
+        let tls = config.tls.as_mut().unwrap();
+        tls.certificates.push(certificate(&["API.other.com"]));
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("claimed by both certificate 1 and certificate 2"));
+
+        let tls = config.tls.as_mut().unwrap();
+        tls.certificates.pop();
+        tls.certificates.push(certificate(&["*.*.bad"]));
+        assert!(ConfigValidator::validate(&config).is_err());
+
+        let tls = config.tls.as_mut().unwrap();
+        tls.certificates.pop();
+        tls.ocsp_stapling = true;
+        tls.ocsp_responder = Some("ocsp.example.com".to_string());
+        assert!(ConfigValidator::validate(&config).is_err());
+        config.tls.as_mut().unwrap().ocsp_responder = Some("http://ocsp.example.com".to_string());
+        assert!(ConfigValidator::validate(&config).is_ok());
+    }
+
+    #[test]
+    fn test_acme_dns_validation() {
+        let acme = |yaml: &str| -> AcmeConfig {
+            let base = "enabled: true\nemail: admin@example.com\n";
+            serde_yaml::from_str(&format!("{base}{yaml}")).unwrap()
+        };
+        let validate = |config: &AcmeConfig| ConfigValidator::validate_acme_config(config);
+
+        let wildcard_http = acme("domains: ['*.example.com']\n");
+        assert!(
+            validate(&wildcard_http)
+                .unwrap_err()
+                .to_string()
+                .contains("requires challenge_type: dns-01")
+        );
+
+        let cloudflare = acme(
+            r#"
+domains: ["example.com", "*.example.com"]
+challenge_type: dns-01
+dns:
+  provider: cloudflare
+  api_token: "token"
+  propagation_timeout: 3m
+  resolvers: ["1.1.1.1", "[2606:4700:4700::1111]:53"]
+"#,
+        );
+        assert!(validate(&cloudflare).is_ok());
+
+        let rfc2136 = acme(
+            r#"
+domains: ["*.example.com"]
+challenge_type: dns-01
+dns:
+  provider: rfc2136
+  server: "10.0.0.53"
+  zone: example.com.
+  tsig_key_name: acme-key
+  tsig_secret: "c2VjcmV0LXNlY3JldC1zZWNyZXQ="
+  tsig_algorithm: hmac-sha512
+"#,
+        );
+        assert!(validate(&rfc2136).is_ok());
+
+        let mut missing_secret = rfc2136.clone();
+        if let Some(AcmeDnsConfig {
+            provider: DnsProviderConfig::Rfc2136 { tsig_secret, .. },
+            ..
+        }) = &mut missing_secret.dns
+        {
+            *tsig_secret = None;
+        }
+        assert!(validate(&missing_secret).is_err());
+
+        let mut no_provider = cloudflare.clone();
+        no_provider.dns = None;
+        assert!(validate(&no_provider).is_err());
+
+        let zerossl = acme(
+            r#"
+domains: ["example.com"]
+ca_url: "https://acme.zerossl.com/v2/DV90"
+eab_kid: "kid-1"
+eab_hmac_key: "c2VjcmV0LWhtYWMta2V5"
+"#,
+        );
+        assert!(validate(&zerossl).is_ok());
+        let mut half_eab = zerossl.clone();
+        half_eab.eab_hmac_key = None;
+        assert!(validate(&half_eab).is_err());
+        let mut plain_http = zerossl;
+        plain_http.ca_url = Some("http://ca.internal/directory".to_string());
+        assert!(validate(&plain_http).is_err());
+    }
+
+    #[test]
+    fn test_header_actions_validation() {
+        let mut config = create_valid_config();
+        let route: RouteConfig = serde_yaml::from_str(
+            r#"
+type: websocket
+target: "ws://chat:9000"
+request_headers:
+  add: { "Bad Header": "x" }
+  remove: ["Cookie"]
+response_headers:
+  add: { X-Routed: "true" }
+  condition:
+    path_matches: "^/ws/(unclosed"
+    method_is: "GET"
+    has_header: { name: "X-Tenant", value_matches: "^acme$" }
+"#,
+        )
+        .unwrap();
+        config.routes.insert("/ws".to_string(), route);
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("'Bad Header' is not a valid header name"));
+        assert!(message.contains("Invalid regex '^/ws/(unclosed'"));
+        assert!(!message.contains("X-Tenant"));
+
+        let mut config = create_valid_config();
+        let route: RouteConfig = serde_yaml::from_str(
+            r#"
+type: proxy
+target: "http://api:8080"
+request_headers:
+  add:
+    X-Real-IP: "{client_ip}"
+    X-Forwarded-Ip: "ip={client_ip}"
+    X-Request-Id: "{request_id}"
+request_body:
+  set_json:
+    path: "{uri_path}"
+    ip: {client_ip}
+    at: "{timestamp}"
+response_body:
+  set_text: "Served {uri_path} at {timestamp_iso}"
+  condition: { has_header: { name: "X-Debug", value_matches: "[" } }
+"#,
+        )
+        .unwrap();
+        config.routes.insert("/api".to_string(), route);
+        let message = ConfigValidator::validate(&config).unwrap_err().to_string();
+        assert!(message.contains("Found 5 validation error(s)"), "{message}");
+        assert!(message.contains("'{client_ip}' is only replaced when it is the whole value"));
+        assert!(message.contains("Unknown placeholder '{request_id}'"));
+        assert!(message.contains("unquoted {client_ip}"));
+        assert!(message.contains("Unknown placeholder '{timestamp}'; bodies"));
+        assert!(message.contains("route '/api' response_body condition"));
+    }
 }
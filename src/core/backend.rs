@@ -1,8 +1,14 @@
 use crate::config::HealthStatus;
-use crate::metrics::set_backend_health_status;
+use crate::metrics::{
+    increment_backend_health_transition, set_backend_health_override, set_backend_health_status,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
+use std::sync::Mutex;
 use std::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 // Constants for health status to replace magic numbers
@@ -39,13 +45,13 @@ impl BackendUrl {
     /// # Returns
     /// A result containing the BackendUrl or an error
     pub fn new(url: &str) -> BackendResult<Self> {
-        // Basic validation - ensure URL starts with http:// or https://
-        let is_secure = url.starts_with("https://");
-        let is_http = url.starts_with("http://");
+        // Basic validation - ensure URL starts with http(s):// or, for WebSocket backends, ws(s)://
+        let is_secure = url.starts_with("https://") || url.starts_with("wss://");
+        let is_http = url.starts_with("http://") || url.starts_with("ws://");
 
         if !is_secure && !is_http {
             return Err(BackendError::InvalidUrl(format!(
-                "Backend URL must start with http:// or https://, got: {url}"
+                "Backend URL must start with http://, https://, ws:// or wss://, got: {url}"
             )));
         }
 
@@ -85,6 +91,57 @@ impl fmt::Display for BackendUrl {
     }
 }
 
+/// What changed a backend's health
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthSource {
+    /// Health checks sent by prox
+    Active,
+    /// Outcomes of proxied requests
+    Passive,
+    /// An operator's override
+    Manual,
+}
+
+impl HealthSource {
+    /// Label used in metrics and logs
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HealthSource::Active => "active",
+            HealthSource::Passive => "passive",
+            HealthSource::Manual => "manual",
+        }
+    }
+}
+
+/// A backend's health as forced through the admin API, regardless of its checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthOverride {
+    /// Health follows the checks
+    #[default]
+    Auto,
+    Healthy,
+    Unhealthy,
+}
+
+impl HealthOverride {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => HealthOverride::Healthy,
+            2 => HealthOverride::Unhealthy,
+            _ => HealthOverride::Auto,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            HealthOverride::Auto => 0,
+            HealthOverride::Healthy => 1,
+            HealthOverride::Unhealthy => 2,
+        }
+    }
+}
+
 /// Tracks the health status of a backend
 #[derive(Debug)]
 pub struct BackendHealth {
@@ -95,6 +152,14 @@ pub struct BackendHealth {
     pub consecutive_successes: AtomicU32,
     /// Counter for consecutive failed health checks
     pub consecutive_failures: AtomicU32,
+    /// When the last health check ran or a proxied request failed
+    last_checked: Mutex<Option<DateTime<Utc>>>,
+    /// Why the backend last failed
+    last_error: Mutex<Option<String>>,
+    /// When the backend first appeared in the config; kept across reloads
+    first_seen: Instant,
+    /// Manual override, a `HealthOverride` as u8
+    forced: AtomicU8,
 }
 
 impl BackendHealth {
@@ -106,20 +171,34 @@ impl BackendHealth {
     /// # Returns
     /// A new BackendHealth instance initialized as healthy
     pub fn new(target: BackendUrl) -> Self {
-        // Modified to accept and store target_url
+        // Export the initial state, not just later changes
+        set_backend_health_status(target.as_str(), true);
         Self {
             target_url: target,
             status: AtomicU8::new(HEALTH_STATUS_HEALTHY), // Start as healthy
             consecutive_successes: AtomicU32::new(0),
             consecutive_failures: AtomicU32::new(0),
+            last_checked: Mutex::new(None),
+            last_error: Mutex::new(None),
+            first_seen: Instant::now(),
+            forced: AtomicU8::new(HealthOverride::Auto.as_u8()),
         }
     }
 
-    /// Get the current health status
+    /// Get the current health status, with any manual override applied
     ///
     /// # Returns
     /// The current health status (Healthy or Unhealthy)
     pub fn status(&self) -> HealthStatus {
+        match self.health_override() {
+            HealthOverride::Healthy => HealthStatus::Healthy,
+            HealthOverride::Unhealthy => HealthStatus::Unhealthy,
+            HealthOverride::Auto => self.checked_status(),
+        }
+    }
+
+    /// The health status according to the checks alone
+    pub fn checked_status(&self) -> HealthStatus {
         // Use Acquire ordering for better correctness when reading status
         if self.status.load(Ordering::Acquire) == HEALTH_STATUS_HEALTHY {
             HealthStatus::Healthy
@@ -128,6 +207,40 @@ impl BackendHealth {
         }
     }
 
+    /// The manual override in effect
+    pub fn health_override(&self) -> HealthOverride {
+        HealthOverride::from_u8(self.forced.load(Ordering::Acquire))
+    }
+
+    /// Force the backend's status, or with `Auto` hand it back to the checks
+    pub fn set_override(&self, health_override: HealthOverride) {
+        let before = self.status();
+        self.forced
+            .store(health_override.as_u8(), Ordering::Release);
+        let after = self.status();
+        set_backend_health_override(
+            self.target_url.as_str(),
+            health_override != HealthOverride::Auto,
+        );
+        if before == after {
+            return;
+        }
+
+        let healthy = after == HealthStatus::Healthy;
+        set_backend_health_status(self.target_url.as_str(), healthy);
+        tracing::warn!(
+            backend = self.target_url.as_str(),
+            status = if healthy { "healthy" } else { "unhealthy" },
+            source = HealthSource::Manual.as_str(),
+            "Backend health changed"
+        );
+        increment_backend_health_transition(
+            self.target_url.as_str(),
+            HealthSource::Manual.as_str(),
+            healthy,
+        );
+    }
+
     /// Mark the backend as healthy and reset failure count
     pub fn mark_healthy(&self) {
         // Use Release ordering for updates to ensure visibility to other threads
@@ -155,6 +268,117 @@ impl BackendHealth {
         set_backend_health_status(self.target_url.as_str(), false); // Uncommented and using target_url
     }
 
+    /// Whether the backend was first seen less than `grace_period` ago, so its failures
+    /// shouldn't count yet
+    pub fn in_grace_period(&self, grace_period: Duration) -> bool {
+        self.first_seen.elapsed() < grace_period
+    }
+
+    /// Count a failed check or request, marking the backend unhealthy once
+    /// `unhealthy_threshold` failures are in a row
+    ///
+    /// # Returns
+    /// Whether this failure made the backend unhealthy
+    pub fn record_failure(
+        &self,
+        unhealthy_threshold: u32,
+        reason: &str,
+        source: HealthSource,
+    ) -> bool {
+        self.touch();
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = Some(reason.to_string());
+        }
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::AcqRel) + 1;
+        self.consecutive_successes.store(0, Ordering::Release);
+        if failures < unhealthy_threshold || !self.transition(HEALTH_STATUS_UNHEALTHY) {
+            return false;
+        }
+        if self.health_override() != HealthOverride::Auto {
+            tracing::info!(
+                "Backend {} failed its checks but stays {:?} until its override is cleared: {}",
+                self.target_url,
+                self.status(),
+                reason
+            );
+            return true;
+        }
+
+        // Stable message and fields, for alerting on logs
+        tracing::warn!(
+            backend = self.target_url.as_str(),
+            status = "unhealthy",
+            source = source.as_str(),
+            consecutive_failures = failures,
+            reason,
+            "Backend health changed"
+        );
+        increment_backend_health_transition(self.target_url.as_str(), source.as_str(), false);
+        true
+    }
+
+    /// Count a successful check, marking the backend healthy once `healthy_threshold`
+    /// successes are in a row
+    ///
+    /// # Returns
+    /// Whether this success made the backend healthy
+    pub fn record_success(&self, healthy_threshold: u32, source: HealthSource) -> bool {
+        self.touch();
+        let successes = self.consecutive_successes.fetch_add(1, Ordering::AcqRel) + 1;
+        self.consecutive_failures.store(0, Ordering::Release);
+        if successes < healthy_threshold || !self.transition(HEALTH_STATUS_HEALTHY) {
+            return false;
+        }
+        if self.health_override() != HealthOverride::Auto {
+            tracing::info!(
+                "Backend {} passed its checks but stays {:?} until its override is cleared",
+                self.target_url,
+                self.status()
+            );
+            return true;
+        }
+
+        tracing::info!(
+            backend = self.target_url.as_str(),
+            status = "healthy",
+            source = source.as_str(),
+            consecutive_successes = successes,
+            "Backend health changed"
+        );
+        increment_backend_health_transition(self.target_url.as_str(), source.as_str(), true);
+        true
+    }
+
+    /// End a run of failures without counting toward recovery, for requests that reached
+    /// the backend while it was still in rotation
+    pub fn reset_failures(&self) {
+        self.consecutive_failures.store(0, Ordering::Release);
+    }
+
+    fn touch(&self) {
+        if let Ok(mut last_checked) = self.last_checked.lock() {
+            *last_checked = Some(Utc::now());
+        }
+    }
+
+    /// Switch to `status`; false when the backend already had it, so concurrent
+    /// callers report each transition once
+    fn transition(&self, status: u8) -> bool {
+        let from = if status == HEALTH_STATUS_HEALTHY {
+            HEALTH_STATUS_UNHEALTHY
+        } else {
+            HEALTH_STATUS_HEALTHY
+        };
+        let changed = self
+            .status
+            .compare_exchange(from, status, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        if changed && self.health_override() == HealthOverride::Auto {
+            set_backend_health_status(self.target_url.as_str(), status == HEALTH_STATUS_HEALTHY);
+        }
+        changed
+    }
+
     /// Get the number of consecutive successful health checks
     pub fn consecutive_successes(&self) -> u32 {
         self.consecutive_successes.load(Ordering::Relaxed)
@@ -164,6 +388,16 @@ impl BackendHealth {
     pub fn consecutive_failures(&self) -> u32 {
         self.consecutive_failures.load(Ordering::Relaxed)
     }
+
+    /// When the last health check ran or a proxied request failed, if ever
+    pub fn last_checked(&self) -> Option<DateTime<Utc>> {
+        self.last_checked.lock().ok().and_then(|last| *last)
+    }
+
+    /// Why the backend last failed, if it ever has
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().ok().and_then(|last| last.clone())
+    }
 }
 
 #[cfg(test)]
@@ -235,4 +469,58 @@ mod tests {
         assert_eq!(health.consecutive_successes(), 1);
         assert_eq!(health.consecutive_failures(), 0);
     }
+
+    #[test]
+    fn test_backend_health_thresholds() {
+        let url = BackendUrl::new("http://example.com")
+            .expect("Creating BackendUrl for health test should succeed");
+        let health = BackendHealth::new(url);
+
+        assert!(!health.record_failure(3, "connection refused", HealthSource::Passive));
+        // A successful request ends the run of failures
+        health.reset_failures();
+        assert!(!health.record_failure(3, "connection refused", HealthSource::Passive));
+        assert!(!health.record_failure(3, "connection refused", HealthSource::Passive));
+        assert!(health.record_failure(3, "connection refused", HealthSource::Passive));
+        assert_eq!(health.status(), HealthStatus::Unhealthy);
+        // Already ejected: further failures are not another transition
+        assert!(!health.record_failure(3, "connection refused", HealthSource::Passive));
+
+        assert!(!health.record_success(2, HealthSource::Active));
+        assert!(health.record_success(2, HealthSource::Active));
+        assert_eq!(health.status(), HealthStatus::Healthy);
+        assert_eq!(health.consecutive_failures(), 0);
+        assert!(health.last_checked().is_some());
+        assert_eq!(health.last_error().as_deref(), Some("connection refused"));
+    }
+
+    #[test]
+    fn test_backend_health_override() {
+        let url = BackendUrl::new("http://example.com")
+            .expect("Creating BackendUrl for health test should succeed");
+        let health = BackendHealth::new(url);
+
+        health.set_override(HealthOverride::Unhealthy);
+        assert_eq!(health.status(), HealthStatus::Unhealthy);
+        assert_eq!(health.checked_status(), HealthStatus::Healthy);
+
+        // Checks keep running underneath the override
+        health.set_override(HealthOverride::Healthy);
+        assert!(health.record_failure(1, "data corruption", HealthSource::Active));
+        assert_eq!(health.status(), HealthStatus::Healthy);
+        assert_eq!(health.checked_status(), HealthStatus::Unhealthy);
+
+        health.set_override(HealthOverride::Auto);
+        assert_eq!(health.status(), HealthStatus::Unhealthy);
+    }
+
+    #[test]
+    fn test_backend_health_grace_period() {
+        let url = BackendUrl::new("http://example.com")
+            .expect("Creating BackendUrl for health test should succeed");
+        let health = BackendHealth::new(url);
+
+        assert!(health.in_grace_period(Duration::from_secs(60)));
+        assert!(!health.in_grace_period(Duration::ZERO));
+    }
 }
@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ConfigHistoryConfig, ServerConfig};
+
+const HISTORY_FILE: &str = "config-history.json";
+
+/// What put a config in place
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConfigSource {
+    /// Loaded when prox started
+    Startup,
+    /// The config file changed
+    File,
+    /// `POST` or `PATCH /-/config`
+    Api,
+    /// `POST /-/config/rollback` to an earlier version
+    Rollback { version: u64 },
+}
+
+/// An applied config and where it came from
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConfigHistoryEntry {
+    pub version: u64,
+    pub source: ConfigSource,
+    pub applied_at: DateTime<Utc>,
+    pub config: ServerConfig,
+}
+
+/// The last `admin.history.max_entries` applied configs, shared by every `ProxyService`
+/// built from one another
+#[derive(Clone, Default)]
+pub struct ConfigHistory {
+    entries: Arc<Mutex<VecDeque<ConfigHistoryEntry>>>,
+}
+
+impl ConfigHistory {
+    /// The history saved in `state_dir`, or an empty one
+    pub fn load(settings: &ConfigHistoryConfig) -> Self {
+        let entries = settings
+            .state_dir
+            .as_deref()
+            .map(history_path)
+            .filter(|path| path.exists())
+            .and_then(|path| {
+                let data = fs::read(&path)
+                    .inspect_err(|e| tracing::warn!("Failed to read {:?}: {}", path, e))
+                    .ok()?;
+                serde_json::from_slice(&data)
+                    .inspect_err(|e| tracing::warn!("Ignoring unreadable {:?}: {}", path, e))
+                    .ok()
+            })
+            .unwrap_or_default();
+        Self {
+            entries: Arc::new(Mutex::new(entries)),
+        }
+    }
+
+    /// The version the next applied config gets: one more than the last recorded
+    pub fn next_version(&self) -> u64 {
+        self.lock()
+            .back()
+            .map_or(1, |entry| entry.version.saturating_add(1))
+    }
+
+    /// Remember `config` as applied, dropping the oldest entries beyond its
+    /// `admin.history.max_entries` and saving to its `state_dir`
+    pub fn record(&self, version: u64, source: ConfigSource, config: &ServerConfig) {
+        let settings = &config.admin.history;
+        let mut entries = self.lock();
+        entries.push_back(ConfigHistoryEntry {
+            version,
+            source,
+            applied_at: Utc::now(),
+            config: config.clone(),
+        });
+        while entries.len() > settings.max_entries.max(1) {
+            entries.pop_front();
+        }
+        if let Some(state_dir) = &settings.state_dir
+            && let Err(e) = save(Path::new(state_dir), &entries)
+        {
+            tracing::error!("Failed to save config history: {:#}", e);
+        }
+    }
+
+    /// Every remembered entry, oldest first
+    pub fn entries(&self) -> Vec<ConfigHistoryEntry> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// The config applied as `version`, if it is still remembered
+    pub fn get(&self, version: u64) -> Option<ServerConfig> {
+        self.lock()
+            .iter()
+            .find(|entry| entry.version == version)
+            .map(|entry| entry.config.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<ConfigHistoryEntry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn history_path(state_dir: &str) -> PathBuf {
+    Path::new(state_dir).join(HISTORY_FILE)
+}
+
+/// Write the history with owner-only permissions, as configs hold credentials
+fn save(state_dir: &Path, entries: &VecDeque<ConfigHistoryEntry>) -> Result<()> {
+    fs::create_dir_all(state_dir)
+        .with_context(|| format!("Failed to create state directory {state_dir:?}"))?;
+    let path = state_dir.join(HISTORY_FILE);
+    let temp_path = state_dir.join(format!("{HISTORY_FILE}.tmp"));
+    let data = serde_json::to_vec_pretty(entries)?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // `mode` only applies to new files
+        if temp_path.exists() {
+            fs::set_permissions(&temp_path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    std::io::Write::write_all(&mut options.open(&temp_path)?, &data)
+        .with_context(|| format!("Failed to write {temp_path:?}"))?;
+    fs::rename(&temp_path, &path).with_context(|| format!("Failed to replace {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_entries: usize, state_dir: Option<&Path>) -> ServerConfig {
+        let mut config: ServerConfig = serde_yaml::from_str(
+            r#"
+listen_addr: "127.0.0.1:8080"
+routes:
+  "/": { type: proxy, target: "http://backend:8080" }
+"#,
+        )
+        .unwrap();
+        config.admin.history = ConfigHistoryConfig {
+            max_entries,
+            state_dir: state_dir.map(|dir| dir.to_string_lossy().into_owned()),
+        };
+        config
+    }
+
+    #[test]
+    fn test_history_is_bounded_and_survives_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(2, Some(dir.path()));
+
+        let history = ConfigHistory::load(&config.admin.history);
+        assert_eq!(history.next_version(), 1);
+        history.record(1, ConfigSource::Startup, &config);
+        history.record(2, ConfigSource::Api, &config);
+        history.record(3, ConfigSource::Rollback { version: 1 }, &config);
+
+        let versions = |history: &ConfigHistory| {
+            history
+                .entries()
+                .iter()
+                .map(|entry| entry.version)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(versions(&history), [2, 3]);
+        assert!(history.get(1).is_none());
+        assert!(history.get(2).is_some());
+
+        let restarted = ConfigHistory::load(&config.admin.history);
+        assert_eq!(versions(&restarted), [2, 3]);
+        assert_eq!(
+            restarted.entries()[1].source,
+            ConfigSource::Rollback { version: 1 }
+        );
+        assert_eq!(restarted.next_version(), 4);
+    }
+}
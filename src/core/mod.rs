@@ -1,7 +1,12 @@
 pub mod backend;
+pub mod config_history;
+pub mod ip_range;
 pub mod load_balancer;
+pub mod origin;
 pub mod proxy;
 pub mod rate_limiter;
+pub mod router;
+pub mod split;
 
 pub use load_balancer::LoadBalancerFactory;
 pub use proxy::ProxyService;
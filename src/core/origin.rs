@@ -0,0 +1,201 @@
+use http::{HeaderMap, Method, header};
+use url::Url;
+
+use crate::config::models::{MissingOriginPolicy, RouteOptions};
+
+/// Reason a request was refused by the same-origin check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OriginRejection {
+    /// Neither Origin nor Referer was present and the route denies such requests
+    Missing,
+    /// Origin/Referer was present but not a usable origin (e.g. "null")
+    Invalid,
+    /// Origin/Referer doesn't match the request host or the allowed origins
+    Mismatch,
+}
+
+impl OriginRejection {
+    /// Label used for logging and the rejection metric
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OriginRejection::Missing => "missing",
+            OriginRejection::Invalid => "invalid",
+            OriginRejection::Mismatch => "mismatch",
+        }
+    }
+}
+
+/// Methods that can change server state and are therefore subject to the check
+pub fn is_state_changing(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    )
+}
+
+/// Validate the request's Origin (or Referer, as a fallback) against the host it was sent to.
+///
+/// `authority` is the authority from the request URI, used when no Host header is present
+/// (e.g. HTTP/2 requests carrying only `:authority`).
+pub fn check_same_origin(
+    method: &Method,
+    headers: &HeaderMap,
+    authority: Option<&str>,
+    options: &RouteOptions,
+) -> Result<(), OriginRejection> {
+    if !options.require_same_origin || !is_state_changing(method) {
+        return Ok(());
+    }
+
+    let source = headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER));
+
+    let Some(source) = source else {
+        return match options.on_missing_origin {
+            MissingOriginPolicy::Allow => Ok(()),
+            MissingOriginPolicy::Deny => Err(OriginRejection::Missing),
+        };
+    };
+
+    let origin = source
+        .to_str()
+        .ok()
+        .and_then(|value| Url::parse(value).ok())
+        .filter(|url| url.has_host())
+        .ok_or(OriginRejection::Invalid)?;
+
+    let request_host = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .or(authority);
+
+    if request_host.is_some_and(|host| host_matches(&origin, host)) {
+        return Ok(());
+    }
+
+    let serialized = origin.origin().ascii_serialization();
+    if options.allowed_origins.iter().any(|allowed| {
+        allowed
+            .trim_end_matches('/')
+            .eq_ignore_ascii_case(&serialized)
+    }) {
+        return Ok(());
+    }
+
+    Err(OriginRejection::Mismatch)
+}
+
+/// Compare the origin's host and port with a Host header value, treating default ports as implicit
+fn host_matches(origin: &Url, request_host: &str) -> bool {
+    let Some(origin_host) = origin.host_str() else {
+        return false;
+    };
+
+    // Reuse the origin's scheme so "example.com" and "example.com:443" compare equal for https
+    let Ok(request) = Url::parse(&format!("{}://{}", origin.scheme(), request_host)) else {
+        return false;
+    };
+
+    request
+        .host_str()
+        .is_some_and(|host| host.eq_ignore_ascii_case(origin_host))
+        && request.port_or_known_default() == origin.port_or_known_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn options(on_missing_origin: MissingOriginPolicy, allowed: &[&str]) -> RouteOptions {
+        RouteOptions {
+            require_same_origin: true,
+            allowed_origins: allowed.iter().map(|s| s.to_string()).collect(),
+            on_missing_origin,
+            ..Default::default()
+        }
+    }
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_safe_methods_are_not_checked() {
+        let opts = options(MissingOriginPolicy::Deny, &[]);
+        let h = headers(&[(header::ORIGIN, "https://evil.example")]);
+        assert!(check_same_origin(&Method::GET, &h, None, &opts).is_ok());
+    }
+
+    #[test]
+    fn test_matching_origin_with_default_port() {
+        let opts = options(MissingOriginPolicy::Deny, &[]);
+        let h = headers(&[
+            (header::HOST, "example.com:443"),
+            (header::ORIGIN, "https://EXAMPLE.com"),
+        ]);
+        assert!(check_same_origin(&Method::POST, &h, None, &opts).is_ok());
+    }
+
+    #[test]
+    fn test_mismatched_origin_is_rejected() {
+        let opts = options(MissingOriginPolicy::Allow, &[]);
+        let h = headers(&[
+            (header::HOST, "example.com"),
+            (header::ORIGIN, "https://evil.example"),
+        ]);
+        assert_eq!(
+            check_same_origin(&Method::DELETE, &h, None, &opts),
+            Err(OriginRejection::Mismatch)
+        );
+    }
+
+    #[test]
+    fn test_allowed_origins_and_referer_fallback() {
+        let opts = options(MissingOriginPolicy::Deny, &["https://app.example.com"]);
+        let h = headers(&[
+            (header::HOST, "api.example.com"),
+            (header::REFERER, "https://app.example.com/settings?tab=1"),
+        ]);
+        assert!(check_same_origin(&Method::PUT, &h, None, &opts).is_ok());
+    }
+
+    #[test]
+    fn test_missing_and_null_origin() {
+        let h = headers(&[(header::HOST, "example.com")]);
+        assert!(
+            check_same_origin(
+                &Method::POST,
+                &h,
+                None,
+                &options(MissingOriginPolicy::Allow, &[])
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            check_same_origin(
+                &Method::POST,
+                &h,
+                None,
+                &options(MissingOriginPolicy::Deny, &[])
+            ),
+            Err(OriginRejection::Missing)
+        );
+
+        let h = headers(&[(header::HOST, "example.com"), (header::ORIGIN, "null")]);
+        assert_eq!(
+            check_same_origin(
+                &Method::PATCH,
+                &h,
+                None,
+                &options(MissingOriginPolicy::Allow, &[])
+            ),
+            Err(OriginRejection::Invalid)
+        );
+    }
+}
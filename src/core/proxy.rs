@@ -1,47 +1,139 @@
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::config::{HealthCheckConfig, HealthStatus, RouteConfig, ServerConfig};
-use crate::core::backend::{BackendHealth, BackendUrl};
+use crate::config::{
+    ErrorPageConfig, HealthCheckConfig, HealthStatus, ObservabilityConfig, PathNormalization,
+    RouteConfig, ServerConfig,
+};
+use crate::core::backend::{BackendHealth, BackendUrl, HealthOverride};
+use crate::core::config_history::{ConfigHistory, ConfigSource};
+use crate::core::router::{DEFAULT_ROUTE_KEY, RouteMatch, RouteRequest, RouteTable};
 
 pub struct ProxyService {
     config: Arc<ServerConfig>,
-    backend_health: Arc<DashMap<String, BackendHealth>>,
+    backend_health: Arc<DashMap<String, Arc<BackendHealth>>>,
+    default_routes: RouteTable,
+    virtual_host_routes: HashMap<String, RouteTable>,
+    /// 1 for the config loaded at startup (or one past the saved history), one more for
+    /// each reload
+    config_version: u64,
+    /// When this config was loaded
+    loaded_at: DateTime<Utc>,
+    /// Configs applied so far, shared with the services this one is reloaded into
+    history: ConfigHistory,
+    /// Whether any route changes its access logging or tracing
+    observability_overridden: bool,
 }
 
 impl ProxyService {
     pub fn new(config: Arc<ServerConfig>) -> Self {
+        let history = ConfigHistory::load(&config.admin.history);
+        let service = Self {
+            config_version: history.next_version(),
+            history,
+            ..Self::build(config, None)
+        };
+        service.history.record(
+            service.config_version,
+            ConfigSource::Startup,
+            &service.config,
+        );
+        service
+    }
+
+    /// A service for a reloaded `config` that keeps the health of the backends it shares
+    /// with this one, so a reload during an outage doesn't put dead backends back in rotation
+    pub fn reload(&self, config: Arc<ServerConfig>, source: ConfigSource) -> Self {
+        let service = Self {
+            config_version: self.config_version + 1,
+            history: self.history.clone(),
+            ..Self::build(config, Some(&self.backend_health))
+        };
+        service
+            .history
+            .record(service.config_version, source, &service.config);
+        service
+    }
+
+    fn build(
+        config: Arc<ServerConfig>,
+        previous_health: Option<&DashMap<String, Arc<BackendHealth>>>,
+    ) -> Self {
         let backend_health = Arc::new(DashMap::new());
 
-        let backends = Self::collect_backends(&config.routes);
+        let backends = Self::collect_backends(&config);
 
         for backend in &backends {
-            if let Ok(backend_url) = BackendUrl::new(backend) {
-                backend_health.insert(backend.clone(), BackendHealth::new(backend_url));
+            if let Some(health) = previous_health.and_then(|previous| previous.get(backend)) {
+                backend_health.insert(backend.clone(), health.clone());
+            } else if let Ok(backend_url) = BackendUrl::new(backend) {
+                backend_health.insert(backend.clone(), Arc::new(BackendHealth::new(backend_url)));
             } else {
                 tracing::error!("Invalid backend URL: {}", backend);
             }
         }
 
+        let table = match config.normalize_paths {
+            PathNormalization::Off => RouteTable::new,
+            PathNormalization::Internal | PathNormalization::Redirect => RouteTable::normalized,
+        };
+        let default_routes = table(&config.routes);
+        let virtual_host_routes = config
+            .virtual_hosts
+            .iter()
+            .map(|(host, vhost)| (host.clone(), table(&vhost.routes)))
+            .collect();
+        let observability_overridden = std::iter::once(&config.routes)
+            .chain(config.virtual_hosts.values().map(|vhost| &vhost.routes))
+            .flat_map(|routes| routes.values())
+            .chain(config.default_route.as_deref())
+            .any(|route| route.options().observability != ObservabilityConfig::default());
+
         Self {
             config,
             backend_health,
+            default_routes,
+            virtual_host_routes,
+            config_version: 1,
+            loaded_at: Utc::now(),
+            history: ConfigHistory::default(),
+            observability_overridden,
         }
     }
 
-    pub fn backend_health(&self) -> &DashMap<String, BackendHealth> {
+    /// How many configs this service has gone through, counting the initial one
+    pub fn config_version(&self) -> u64 {
+        self.config_version
+    }
+
+    /// The config this service was built from
+    pub fn config(&self) -> &Arc<ServerConfig> {
+        &self.config
+    }
+
+    /// Configs applied before and including this one
+    pub fn config_history(&self) -> &ConfigHistory {
+        &self.history
+    }
+
+    /// When the running config was loaded, at startup or by the last successful reload
+    pub fn loaded_at(&self) -> DateTime<Utc> {
+        self.loaded_at
+    }
+
+    pub fn backend_health(&self) -> &DashMap<String, Arc<BackendHealth>> {
         &self.backend_health
     }
 
-    pub fn collect_backends(routes: &HashMap<String, RouteConfig>) -> Vec<String> {
-        let mut backends = routes
-            .values()
-            .flat_map(|route_config| match route_config {
-                RouteConfig::LoadBalance { targets, .. } => targets.clone(),
-                RouteConfig::Proxy { target, .. } => vec![target.clone()],
-                _ => Vec::new(),
-            })
+    /// Backends referenced by the default routes and every virtual host
+    pub fn collect_backends(config: &ServerConfig) -> Vec<String> {
+        let mut backends = std::iter::once(&config.routes)
+            .chain(config.virtual_hosts.values().map(|vhost| &vhost.routes))
+            .flat_map(|routes| routes.values())
+            .chain(config.default_route.as_deref())
+            .flat_map(route_backends)
             .collect::<Vec<_>>();
 
         backends.sort();
@@ -49,19 +141,153 @@ impl ProxyService {
         backends
     }
 
-    pub fn find_matching_route(&self, path: &str) -> Option<(String, RouteConfig)> {
-        self.config
+    /// Routes sending traffic to `target`, virtual host routes prefixed with their host
+    pub fn backend_routes(&self, target: &str) -> Vec<String> {
+        let uses_target = |route: &RouteConfig| {
+            route_backends(route)
+                .iter()
+                .any(|backend| backend == target)
+        };
+        let mut routes: Vec<String> = self
+            .config
             .routes
             .iter()
-            .filter(|(prefix, _)| path.starts_with(*prefix))
-            .max_by_key(|(prefix, _)| prefix.len())
-            .map(|(prefix, config)| (prefix.to_string(), config.clone()))
+            .filter(|(_, route)| uses_target(route))
+            .map(|(key, _)| key.clone())
+            .chain(self.config.virtual_hosts.iter().flat_map(|(host, vhost)| {
+                vhost
+                    .routes
+                    .iter()
+                    .filter(|(_, route)| uses_target(route))
+                    .map(move |(key, _)| format!("{host}{key}"))
+            }))
+            .collect();
+        routes.sort();
+        if self
+            .config
+            .default_route
+            .as_deref()
+            .is_some_and(uses_target)
+        {
+            routes.push(DEFAULT_ROUTE_KEY.to_string());
+        }
+        routes
+    }
+
+    /// Route table for a request host: exact names win over wildcards, the longest
+    /// wildcard wins, and the top-level routes serve every other host
+    fn routes_for_host(&self, host: Option<&str>) -> &RouteTable {
+        self.virtual_host_for(host)
+            .and_then(|pattern| self.virtual_host_routes.get(pattern))
+            .unwrap_or(&self.default_routes)
+    }
+
+    /// The virtual host pattern serving a request host, `None` for the default routes
+    pub fn virtual_host_for(&self, host: Option<&str>) -> Option<&str> {
+        let host = normalize_host(host?);
+        self.config
+            .virtual_hosts
+            .keys()
+            .filter(|pattern| host_pattern_matches(pattern, &host))
+            .max_by_key(|pattern| (!pattern.starts_with("*."), pattern.len()))
+            .map(String::as_str)
+    }
+
+    pub fn find_matching_route(&self, request: &RouteRequest) -> Option<RouteMatch> {
+        self.routes_for_host(request.host).matches(request).next()
+    }
+
+    /// Access log and tracing settings of the route a request goes to. Cheap when no
+    /// route overrides them, as it's looked up before the request is handled.
+    pub fn route_observability(&self, request: &RouteRequest) -> ObservabilityConfig {
+        if !self.observability_overridden {
+            return ObservabilityConfig::default();
+        }
+        self.find_matching_route(request)
+            .or_else(|| {
+                // Like the handler: the catch-all only takes paths no route claims
+                self.allowed_methods(request)
+                    .is_empty()
+                    .then(|| self.default_route())
+                    .flatten()
+            })
+            .map(|route| route.config.options().observability)
+            .unwrap_or_default()
+    }
+
+    /// Methods accepted on the request's path when no route accepts its method
+    pub fn allowed_methods(&self, request: &RouteRequest) -> Vec<http::Method> {
+        self.routes_for_host(request.host).allowed_methods(request)
+    }
+
+    /// The catch-all route for requests no other route matches
+    pub fn default_route(&self) -> Option<RouteMatch> {
+        self.config.default_route.as_ref().map(|config| RouteMatch {
+            key: DEFAULT_ROUTE_KEY.to_string(),
+            prefix: String::new(),
+            config: config.as_ref().clone(),
+        })
+    }
+
+    /// The next route after `current_key`, in match order, that also matches the request
+    pub fn find_fallthrough_route(
+        &self,
+        request: &RouteRequest,
+        current_key: &str,
+    ) -> Option<RouteMatch> {
+        self.routes_for_host(request.host)
+            .matches(request)
+            .skip_while(|route| route.key != current_key)
+            .nth(1)
+    }
+
+    /// The route with the given key, matched against the path when it applies
+    pub fn route(&self, host: Option<&str>, key: &str, path: &str) -> Option<RouteMatch> {
+        self.routes_for_host(host).get(key, path)
+    }
+
+    /// Log the order in which routes are tried, per virtual host
+    pub fn log_route_order(&self) {
+        let tables = std::iter::once(("default", &self.default_routes)).chain(
+            self.virtual_host_routes
+                .iter()
+                .map(|(host, table)| (host.as_str(), table)),
+        );
+        for (host, table) in tables {
+            for (i, route) in table.describe().iter().enumerate() {
+                tracing::info!("Route order [{}] {}: {}", host, i + 1, route);
+            }
+        }
+        if self.config.default_route.is_some() {
+            tracing::info!("Unmatched requests go to {}", DEFAULT_ROUTE_KEY);
+        }
+    }
+
+    pub fn path_normalization(&self) -> PathNormalization {
+        self.config.normalize_paths
+    }
+
+    pub fn error_pages(&self) -> &HashMap<String, ErrorPageConfig> {
+        &self.config.error_pages
+    }
+
+    pub fn debug_errors(&self) -> bool {
+        self.config.debug_errors
+    }
+
+    pub fn debug_headers(&self) -> bool {
+        self.config.debug_headers
     }
 
     pub fn health_config(&self) -> &HealthCheckConfig {
         &self.config.health_check
     }
 
+    /// The health check settings for `target`, with its overrides applied
+    pub fn backend_health_check(&self, target: &str) -> HealthCheckConfig {
+        self.config.backend_health_check(target)
+    }
+
     pub fn get_backend_health_path(&self, target: &str) -> String {
         self.config
             .backend_health_paths
@@ -79,7 +305,16 @@ impl ProxyService {
 
     pub fn get_healthy_backends(&self, targets: &[String]) -> Vec<String> {
         if !self.config.health_check.enabled {
-            return targets.to_vec();
+            // Nothing is checked, but a backend can still be forced out of rotation
+            return targets
+                .iter()
+                .filter(|target| {
+                    self.backend_health.get(*target).is_none_or(|backend| {
+                        backend.health_override() != HealthOverride::Unhealthy
+                    })
+                })
+                .cloned()
+                .collect();
         }
 
         targets
@@ -89,3 +324,252 @@ impl ProxyService {
             .collect()
     }
 }
+
+/// Backends a route sends traffic to
+pub fn route_backends(route: &RouteConfig) -> Vec<String> {
+    match route {
+        RouteConfig::LoadBalance { targets, .. } => targets.clone(),
+        RouteConfig::Proxy { target, .. } => vec![target.clone()],
+        RouteConfig::Split { variants, .. } => variants.iter().map(|v| v.target.clone()).collect(),
+        RouteConfig::Websocket {
+            target, targets, ..
+        } => target.iter().chain(targets).cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Lowercase a Host value and drop its port and any trailing dot
+fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let without_port = if let Some(rest) = host.strip_prefix('[') {
+        // IPv6 literal, e.g. "[::1]:8080"
+        rest.split(']').next().unwrap_or(rest)
+    } else {
+        host.rsplit_once(':').map_or(host, |(name, _)| name)
+    };
+    without_port.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Match a normalized host against "example.com" or "*.example.com" (any depth of subdomain)
+pub fn host_pattern_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host
+            .strip_suffix(suffix)
+            .is_some_and(|label| label.len() > 1 && label.ends_with('.')),
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{TracingMode, VirtualHostConfig};
+
+    fn redirect(target: &str) -> RouteConfig {
+        RouteConfig::Redirect {
+            target: target.to_string(),
+            status_code: None,
+            rate_limit: None,
+            options: Default::default(),
+        }
+    }
+
+    fn vhost(prefix: &str, target: &str) -> VirtualHostConfig {
+        VirtualHostConfig {
+            routes: HashMap::from([(prefix.to_string(), redirect(target))]),
+        }
+    }
+
+    #[test]
+    fn test_virtual_host_selection() {
+        let config = ServerConfig::builder()
+            .listen_addr("127.0.0.1:3000")
+            .route("/", redirect("default"))
+            .virtual_host("api.example.com", vhost("/", "exact"))
+            .virtual_host("*.example.com", vhost("/", "wildcard"))
+            .virtual_host("*.eu.example.com", vhost("/", "longer wildcard"))
+            .build()
+            .unwrap();
+        let service = ProxyService::new(Arc::new(config));
+        let headers = http::HeaderMap::new();
+        let target = |host| {
+            let request = RouteRequest {
+                host,
+                path: "/x",
+                query: None,
+                method: &http::Method::GET,
+                headers: &headers,
+            };
+            service.find_matching_route(&request).map(|m| m.config)
+        };
+        let target = |host| match target(host) {
+            Some(RouteConfig::Redirect { target, .. }) => target,
+            other => panic!("unexpected route {other:?}"),
+        };
+
+        assert_eq!(target(Some("API.example.com:8443")), "exact");
+        assert_eq!(target(Some("www.example.com")), "wildcard");
+        assert_eq!(target(Some("a.b.example.com.")), "wildcard");
+        assert_eq!(target(Some("shop.eu.example.com")), "longer wildcard");
+        assert_eq!(target(Some("example.com")), "default");
+        assert_eq!(target(Some("[::1]:8080")), "default");
+        assert_eq!(target(None), "default");
+        assert_eq!(
+            service.virtual_host_for(Some("www.example.com")),
+            Some("*.example.com")
+        );
+    }
+
+    #[test]
+    fn test_route_observability() {
+        let config: ServerConfig = serde_yaml::from_str(
+            r#"
+listen_addr: "127.0.0.1:3000"
+routes:
+  "/healthz":
+    type: redirect
+    target: "/"
+    observability: { access_log: false, tracing: off }
+  "/api":
+    type: redirect
+    target: "/"
+    observability: { tracing: minimal }
+  "/":
+    type: redirect
+    target: "/"
+"#,
+        )
+        .unwrap();
+        let service = ProxyService::new(Arc::new(config));
+        let headers = http::HeaderMap::new();
+        let observability = |path| {
+            service.route_observability(&RouteRequest {
+                host: None,
+                path,
+                query: None,
+                method: &http::Method::GET,
+                headers: &headers,
+            })
+        };
+
+        let health = observability("/healthz");
+        assert!(!health.access_log);
+        assert_eq!(health.tracing, TracingMode::Off);
+        let api = observability("/api/users");
+        assert!(api.access_log);
+        assert_eq!(api.tracing, TracingMode::Minimal);
+        assert_eq!(observability("/other"), ObservabilityConfig::default());
+    }
+
+    #[test]
+    fn test_reload_keeps_backend_health() {
+        let config = |targets: &str| -> Arc<ServerConfig> {
+            Arc::new(
+                serde_yaml::from_str(&format!(
+                    r#"
+listen_addr: "127.0.0.1:3000"
+routes:
+  "/":
+    type: load_balance
+    targets: {targets}
+    strategy: round_robin
+"#
+                ))
+                .unwrap(),
+            )
+        };
+        let service = ProxyService::new(config(r#"["http://a:8080", "http://b:8080"]"#));
+        service
+            .backend_health()
+            .get("http://a:8080")
+            .unwrap()
+            .mark_unhealthy();
+
+        let reloaded = service.reload(
+            config(r#"["http://a:8080", "http://c:8080"]"#),
+            ConfigSource::File,
+        );
+        assert_eq!(
+            reloaded.get_backend_health_status("http://a:8080"),
+            HealthStatus::Unhealthy
+        );
+        assert_eq!(
+            reloaded
+                .get_healthy_backends(&["http://a:8080".to_string(), "http://c:8080".to_string()]),
+            vec!["http://c:8080".to_string()]
+        );
+        assert!(reloaded.backend_health().get("http://b:8080").is_none());
+        assert_eq!(service.config_version(), 1);
+        assert_eq!(reloaded.config_version(), 2);
+    }
+
+    #[test]
+    fn test_backend_routes() {
+        let proxy = |target: &str| RouteConfig::Proxy {
+            target: target.to_string(),
+            path_rewrite: None,
+            rate_limit: None,
+            request_headers: None,
+            response_headers: None,
+            request_body: None,
+            response_body: None,
+            mirror: None,
+            path_rewrite_regex: None,
+            options: Default::default(),
+        };
+        let config = ServerConfig::builder()
+            .listen_addr("127.0.0.1:3000")
+            .route("/api", proxy("http://api:8080"))
+            .route("/v1", proxy("http://api:8080"))
+            .route("/other", proxy("http://other:8080"))
+            .virtual_host(
+                "api.example.com",
+                VirtualHostConfig {
+                    routes: HashMap::from([("/".to_string(), proxy("http://api:8080"))]),
+                },
+            )
+            .build()
+            .unwrap();
+        let service = ProxyService::new(Arc::new(config));
+
+        assert_eq!(
+            service.backend_routes("http://api:8080"),
+            vec!["/api", "/v1", "api.example.com/"]
+        );
+        assert!(service.backend_routes("http://missing:8080").is_empty());
+    }
+
+    #[test]
+    fn test_backend_health_check_overrides() {
+        let config: ServerConfig = serde_yaml::from_str(
+            r#"
+listen_addr: "127.0.0.1:3000"
+health_check: { interval_secs: 10, timeout_secs: 2, path: "/health" }
+backend_health_paths:
+  "http://legacy-1:8080": "/ping"
+routes:
+  "/legacy":
+    type: load_balance
+    targets: ["http://legacy-1:8080", "http://legacy-2:8080"]
+    strategy: round_robin
+    health_check: { interval_secs: 60, timeout_secs: 20 }
+  "/api": { type: proxy, target: "http://api:8080" }
+backend_health_checks:
+  "http://legacy-2:8080": { timeout_secs: 30, path: "/status" }
+"#,
+        )
+        .unwrap();
+        let service = ProxyService::new(Arc::new(config));
+        let check = |target| {
+            let config = service.backend_health_check(target);
+            (config.interval_secs, config.timeout_secs, config.path)
+        };
+
+        assert_eq!(check("http://api:8080"), (10, 2, "/health".to_string()));
+        assert_eq!(check("http://legacy-1:8080"), (60, 20, "/ping".to_string()));
+        assert_eq!(
+            check("http://legacy-2:8080"),
+            (60, 30, "/status".to_string())
+        );
+    }
+}
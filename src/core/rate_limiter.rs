@@ -15,6 +15,7 @@ use governor::state::{InMemoryState, NotKeyed};
 use governor::{Quota, RateLimiter};
 
 use crate::config::models::{MissingKeyPolicy, RateLimitAlgorithm, RateLimitBy, RateLimitConfig};
+use crate::utils::log_redaction;
 
 #[derive(Clone)]
 pub struct LimiterWrapper<RL> {
@@ -250,7 +251,7 @@ impl RouteRateLimiter {
                             tracing::warn!(
                                 "Header rate limit exceeded for header \'{}\', value \'{}\': {}",
                                 header_name,
-                                value_str,
+                                log_redaction::header_value(header_name.as_str(), value_str),
                                 limiter.message
                             );
                         })
@@ -0,0 +1,993 @@
+use http::{HeaderMap, HeaderName, Method};
+use regex::Regex;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::config::{PathMatch, RouteConfig, ValueMatch};
+
+/// Label of the `default_route`, used in logs, metrics and rate limiter keys
+pub const DEFAULT_ROUTE_KEY: &str = "(default)";
+
+/// The parts of a request that route selection looks at
+#[derive(Debug, Clone, Copy)]
+pub struct RouteRequest<'a> {
+    /// Host header (or `:authority`), used to pick the virtual host
+    pub host: Option<&'a str>,
+    pub path: &'a str,
+    /// Raw query string, without the leading '?'
+    pub query: Option<&'a str>,
+    pub method: &'a Method,
+    pub headers: &'a HeaderMap,
+}
+
+/// Key of the route serving a request, carried in request and response extensions so
+/// latency metrics are labelled by route rather than by raw path
+#[derive(Debug, Clone)]
+pub struct MatchedRoute(pub String);
+
+/// A route selected for a request path
+#[derive(Debug, Clone)]
+pub struct RouteMatch {
+    /// Route key from the configuration, used as the route's label
+    pub key: String,
+    /// Leading part of the path consumed by the route, stripped before forwarding
+    pub prefix: String,
+    /// The route's configuration, with regex captures substituted into `path_rewrite`
+    pub config: RouteConfig,
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Prefix(String),
+    Exact(String),
+    /// A prefix or exact path with `*` or `:name` segments
+    Segments(SegmentPattern),
+    Regex(Regex),
+}
+
+/// One `/`-separated piece of a segmented route path
+#[derive(Debug)]
+enum Segment {
+    Literal(String),
+    /// `*` (unnamed) or `:name`; matches exactly one non-empty path segment
+    Param(Option<String>),
+}
+
+/// A route path such as `/tenants/*/api` or `/users/:id/profile`. Unlike plain
+/// prefixes, matching is aligned to whole segments.
+#[derive(Debug)]
+struct SegmentPattern {
+    source: String,
+    segments: Vec<Segment>,
+    exact: bool,
+}
+
+impl SegmentPattern {
+    fn parse(path: &str, exact: bool) -> Option<Self> {
+        if !is_segment_pattern(path) {
+            return None;
+        }
+        let segments = path
+            .split('/')
+            .map(|segment| match segment {
+                "*" => Segment::Param(None),
+                _ => match segment.strip_prefix(':') {
+                    Some(name) => Segment::Param(Some(name.to_string())),
+                    None => Segment::Literal(segment.to_string()),
+                },
+            })
+            .collect();
+        Some(Self {
+            source: path.to_string(),
+            segments,
+            exact,
+        })
+    }
+
+    /// Length of the matched prefix of `path` and the captured parameters
+    fn match_path(&self, path: &str) -> Option<(usize, Vec<(String, String)>)> {
+        let mut parts = path.split('/');
+        let mut consumed = 0;
+        let mut params = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            let part = parts.next()?;
+            match segment {
+                Segment::Literal(literal) if part != literal => return None,
+                Segment::Literal(_) => {}
+                Segment::Param(_) if part.is_empty() => return None,
+                Segment::Param(name) => {
+                    if let Some(name) = name {
+                        params.push((name.clone(), part.to_string()));
+                    }
+                }
+            }
+            consumed += part.len() + usize::from(i > 0);
+        }
+        if self.exact && parts.next().is_some() {
+            return None;
+        }
+        Some((consumed, params))
+    }
+
+    /// Whether each segment is a parameter, so literal segments sort before parameters
+    fn shape(&self) -> Vec<bool> {
+        self.segments
+            .iter()
+            .map(|segment| matches!(segment, Segment::Param(_)))
+            .collect()
+    }
+}
+
+/// A compiled `match_headers` or `match_query` value condition
+#[derive(Debug)]
+enum ValueCondition {
+    Exact(String),
+    Regex(Regex),
+    Present(bool),
+}
+
+impl ValueCondition {
+    fn compile(value_match: &ValueMatch) -> Result<Self, regex::Error> {
+        Ok(match value_match {
+            ValueMatch::Exact(value) => ValueCondition::Exact(value.clone()),
+            ValueMatch::Regex { regex } => ValueCondition::Regex(Regex::new(regex)?),
+            ValueMatch::Present { present } => ValueCondition::Present(*present),
+        })
+    }
+
+    /// Whether any of the given values satisfies the condition
+    fn matches<'v>(&self, mut values: impl Iterator<Item = &'v str>) -> bool {
+        match self {
+            ValueCondition::Exact(expected) => values.any(|value| value == expected),
+            ValueCondition::Regex(regex) => values.any(|value| regex.is_match(value)),
+            ValueCondition::Present(present) => values.next().is_some() == *present,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CompiledRoute {
+    key: String,
+    config: RouteConfig,
+    matcher: Matcher,
+    headers: Vec<(HeaderName, ValueCondition)>,
+    methods: Vec<Method>,
+    query: Vec<(String, ValueCondition)>,
+    priority: i32,
+}
+
+impl CompiledRoute {
+    fn compile(key: &str, config: &RouteConfig) -> Result<Self, String> {
+        let options = config.options();
+        let path = || options.path.clone().unwrap_or_else(|| key.to_string());
+        let matcher = match &options.path_match {
+            PathMatch::Prefix => SegmentPattern::parse(&path(), false)
+                .map_or_else(|| Matcher::Prefix(path()), Matcher::Segments),
+            PathMatch::Exact => SegmentPattern::parse(&path(), true)
+                .map_or_else(|| Matcher::Exact(path()), Matcher::Segments),
+            PathMatch::Regex { pattern } => Matcher::Regex(
+                Regex::new(pattern).map_err(|e| format!("invalid pattern '{pattern}': {e}"))?,
+            ),
+        };
+        let headers = options
+            .match_headers
+            .iter()
+            .map(|(name, value_match)| {
+                let name = HeaderName::try_from(name.as_str())
+                    .map_err(|e| format!("invalid header name '{name}': {e}"))?;
+                let condition = ValueCondition::compile(value_match)
+                    .map_err(|e| format!("invalid regex for header '{name}': {e}"))?;
+                Ok((name, condition))
+            })
+            .collect::<Result<_, String>>()?;
+        let methods = options
+            .match_methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .map_err(|e| format!("invalid method '{method}': {e}"))
+            })
+            .collect::<Result<_, String>>()?;
+        let query = options
+            .match_query
+            .iter()
+            .map(|(name, value_match)| {
+                let condition = ValueCondition::compile(value_match)
+                    .map_err(|e| format!("invalid regex for query parameter '{name}': {e}"))?;
+                Ok((name.clone(), condition))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            key: key.to_string(),
+            config: config.clone(),
+            matcher,
+            headers,
+            methods,
+            query,
+            priority: options.priority,
+        })
+    }
+
+    fn match_request(&self, request: &RouteRequest) -> Option<RouteMatch> {
+        if !self.accepts_method(request.method) {
+            return None;
+        }
+        self.match_ignoring_method(request)
+    }
+
+    fn accepts_method(&self, method: &Method) -> bool {
+        self.methods.is_empty() || self.methods.contains(method)
+    }
+
+    fn match_ignoring_method(&self, request: &RouteRequest) -> Option<RouteMatch> {
+        let headers_match = self.headers.iter().all(|(name, condition)| {
+            condition.matches(
+                request
+                    .headers
+                    .get_all(name)
+                    .iter()
+                    .filter_map(|value| value.to_str().ok()),
+            )
+        });
+        if headers_match && self.query_matches(request.query) {
+            self.match_path(request.path)
+        } else {
+            None
+        }
+    }
+
+    fn query_matches(&self, query: Option<&str>) -> bool {
+        if self.query.is_empty() {
+            return true;
+        }
+        let params: Vec<(String, String)> = query
+            .map(|query| {
+                url::form_urlencoded::parse(query.as_bytes())
+                    .into_owned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.query.iter().all(|(name, condition)| {
+            condition.matches(
+                params
+                    .iter()
+                    .filter(|(param, _)| param == name)
+                    .map(|(_, value)| value.as_str()),
+            )
+        })
+    }
+
+    fn match_path(&self, path: &str) -> Option<RouteMatch> {
+        match &self.matcher {
+            Matcher::Prefix(prefix) => path.starts_with(prefix.as_str()).then(|| RouteMatch {
+                key: self.key.clone(),
+                prefix: prefix.clone(),
+                config: self.config.clone(),
+            }),
+            Matcher::Exact(exact) => (path == exact).then(|| RouteMatch {
+                key: self.key.clone(),
+                prefix: exact.clone(),
+                config: self.config.clone(),
+            }),
+            Matcher::Segments(pattern) => {
+                let (consumed, params) = pattern.match_path(path)?;
+                let mut config = self.config.clone();
+                if !params.is_empty() {
+                    if let Some(rewrite) = config.path_rewrite_mut() {
+                        *rewrite = expand_path_params(rewrite, &params);
+                    }
+                    for actions in config.header_actions_mut() {
+                        for value in actions.add.values_mut() {
+                            *value = expand_path_params(value, &params);
+                        }
+                    }
+                }
+                Some(RouteMatch {
+                    key: self.key.clone(),
+                    prefix: path[..consumed].to_string(),
+                    config,
+                })
+            }
+            Matcher::Regex(regex) => {
+                let captures = regex.captures(path)?;
+                let whole = captures.get(0)?;
+                // Only a match anchored at the start of the path can be stripped like a prefix
+                let prefix = if whole.start() == 0 {
+                    whole.as_str()
+                } else {
+                    ""
+                };
+                let mut config = self.config.clone();
+                if let Some(rewrite) = config.path_rewrite_mut() {
+                    let mut expanded = String::new();
+                    captures.expand(rewrite, &mut expanded);
+                    *rewrite = expanded;
+                }
+                Some(RouteMatch {
+                    key: self.key.clone(),
+                    prefix: prefix.to_string(),
+                    config,
+                })
+            }
+        }
+    }
+
+    fn condition_count(&self) -> usize {
+        self.headers.len() + usize::from(!self.methods.is_empty()) + self.query.len()
+    }
+
+    /// Higher priorities come first. Within a priority, exact routes come first, then
+    /// regex routes ordered by key, then prefix routes, deepest first. Paths of the same
+    /// depth put literal segments before `*`/`:name` ones, then the longer path first.
+    /// Among routes sharing a path, the one with more request conditions wins.
+    fn precedence(&self) -> Precedence<'_> {
+        let priority = Reverse(self.priority);
+        let conditions = Reverse(self.condition_count());
+        let plain = |path: &str| {
+            let depth = path.split('/').count();
+            (Reverse(depth), vec![false; depth])
+        };
+        let (kind, (depth, shape), len) = match &self.matcher {
+            Matcher::Exact(path) => (0, plain(path), path.len()),
+            Matcher::Segments(pattern) => (
+                if pattern.exact { 0 } else { 2 },
+                (Reverse(pattern.segments.len()), pattern.shape()),
+                pattern.source.len(),
+            ),
+            Matcher::Regex(_) => (1, (Reverse(0), Vec::new()), 0),
+            Matcher::Prefix(prefix) => (2, plain(prefix), prefix.len()),
+        };
+        (
+            priority,
+            kind,
+            depth,
+            shape,
+            Reverse(len),
+            conditions,
+            &self.key,
+        )
+    }
+
+    fn describe(&self) -> String {
+        let matcher = match &self.matcher {
+            Matcher::Prefix(prefix) => format!("prefix '{prefix}'"),
+            Matcher::Exact(path) => format!("exact '{path}'"),
+            Matcher::Segments(pattern) if pattern.exact => format!("exact '{}'", pattern.source),
+            Matcher::Segments(pattern) => format!("prefix '{}'", pattern.source),
+            Matcher::Regex(regex) => format!("regex '{}'", regex.as_str()),
+        };
+        format!(
+            "{} ({matcher}, priority {}, {} conditions)",
+            self.key,
+            self.priority,
+            self.condition_count()
+        )
+    }
+}
+
+type Precedence<'a> = (
+    Reverse<i32>,
+    u8,
+    Reverse<usize>,
+    Vec<bool>,
+    Reverse<usize>,
+    Reverse<usize>,
+    &'a str,
+);
+
+/// Compiled routes of the default routes or of one virtual host, in match order
+#[derive(Debug, Default)]
+pub struct RouteTable {
+    routes: Vec<CompiledRoute>,
+}
+
+impl RouteTable {
+    pub fn new(routes: &HashMap<String, RouteConfig>) -> Self {
+        Self::build(routes, false)
+    }
+
+    /// Like `new`, for normalized request paths: prefixes are compared without a
+    /// trailing slash, so "/api/" also serves "/api"
+    pub fn normalized(routes: &HashMap<String, RouteConfig>) -> Self {
+        Self::build(routes, true)
+    }
+
+    fn build(routes: &HashMap<String, RouteConfig>, trim_prefixes: bool) -> Self {
+        let mut compiled: Vec<CompiledRoute> = routes
+            .iter()
+            .filter_map(|(key, config)| {
+                CompiledRoute::compile(key, config)
+                    .map_err(|e| tracing::error!("Skipping route '{}': {}", key, e))
+                    .ok()
+            })
+            .collect();
+        if trim_prefixes {
+            for route in &mut compiled {
+                match &mut route.matcher {
+                    Matcher::Prefix(prefix) if prefix.len() > 1 => {
+                        prefix.truncate(prefix.trim_end_matches('/').len());
+                    }
+                    Matcher::Segments(pattern) if !pattern.exact => {
+                        while pattern.segments.len() > 2
+                            && matches!(pattern.segments.last(), Some(Segment::Literal(l)) if l.is_empty())
+                        {
+                            pattern.segments.pop();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        compiled.sort_by(|a, b| a.precedence().cmp(&b.precedence()));
+        Self { routes: compiled }
+    }
+
+    /// One line per route in match order, for auditing the effective ordering
+    pub fn describe(&self) -> Vec<String> {
+        self.routes.iter().map(CompiledRoute::describe).collect()
+    }
+
+    /// Every route matching the request, best match first
+    pub fn matches<'a>(
+        &'a self,
+        request: &'a RouteRequest<'a>,
+    ) -> impl Iterator<Item = RouteMatch> + 'a {
+        self.routes
+            .iter()
+            .filter_map(move |route| route.match_request(request))
+    }
+
+    /// Methods of the routes that would match the request if its method were different,
+    /// used for the `Allow` header of a 405 response
+    pub fn allowed_methods(&self, request: &RouteRequest) -> Vec<Method> {
+        let mut allowed: Vec<Method> = self
+            .routes
+            .iter()
+            .filter(|route| route.match_ignoring_method(request).is_some())
+            .flat_map(|route| route.methods.iter().cloned())
+            .collect();
+        allowed.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        allowed.dedup();
+        allowed
+    }
+
+    /// The route with the given key regardless of its request conditions;
+    /// if its path doesn't match, it sees the full path
+    pub fn get(&self, key: &str, path: &str) -> Option<RouteMatch> {
+        let route = self.routes.iter().find(|route| route.key == key)?;
+        route.match_path(path).or_else(|| {
+            Some(RouteMatch {
+                key: route.key.clone(),
+                prefix: String::new(),
+                config: route.config.clone(),
+            })
+        })
+    }
+}
+
+/// Whether a route path has `*` or `:name` segments and is matched segment by segment
+pub fn is_segment_pattern(path: &str) -> bool {
+    path.split('/')
+        .any(|segment| segment == "*" || segment.starts_with(':'))
+}
+
+/// Names of the `:name` segments of a route path
+pub fn path_param_names(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix(':'))
+        .collect()
+}
+
+/// A route path with every parameter written as `*`, so `/users/:id` and
+/// `/users/:uid` are recognised as the same path
+pub fn segment_shape(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if segment.starts_with(':') {
+                "*"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Names referenced as `{path_param:name}` in a template
+pub fn path_param_references(template: &str) -> Vec<&str> {
+    template
+        .split("{path_param:")
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+        .collect()
+}
+
+/// Replace `{path_param:name}` placeholders with the captured segment values
+fn expand_path_params(template: &str, params: &[(String, String)]) -> String {
+    params
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{path_param:{name}}}"), value)
+        })
+}
+
+/// Collapse runs of slashes in a request path ("//api///x" becomes "/api/x")
+pub fn normalize_path(path: &str) -> Cow<'_, str> {
+    if !path.contains("//") {
+        return Cow::Borrowed(path);
+    }
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.chars() {
+        if !(c == '/' && normalized.ends_with('/')) {
+            normalized.push(c);
+        }
+    }
+    Cow::Owned(normalized)
+}
+
+/// The part of the original path that normalizes to the first `normalized_len` bytes,
+/// so a prefix matched on the normalized path can be stripped from the original one
+pub fn original_prefix(original: &str, normalized_len: usize) -> &str {
+    let mut emitted = 0;
+    let mut previous_slash = false;
+    for (index, c) in original.char_indices() {
+        if emitted == normalized_len {
+            return &original[..index];
+        }
+        let is_slash = c == '/';
+        if !(is_slash && previous_slash) {
+            emitted += c.len_utf8();
+        }
+        previous_slash = is_slash;
+    }
+    original
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RouteOptions;
+
+    fn proxy(target: &str, path_rewrite: Option<&str>, path_match: PathMatch) -> RouteConfig {
+        proxy_with(
+            target,
+            path_rewrite,
+            RouteOptions {
+                path_match,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn proxy_with(target: &str, path_rewrite: Option<&str>, options: RouteOptions) -> RouteConfig {
+        RouteConfig::Proxy {
+            target: target.to_string(),
+            path_rewrite: path_rewrite.map(str::to_string),
+            rate_limit: None,
+            request_headers: None,
+            response_headers: None,
+            request_body: None,
+            response_body: None,
+            mirror: None,
+            path_rewrite_regex: None,
+            options,
+        }
+    }
+
+    #[test]
+    fn test_regex_routes_precede_prefixes_and_expand_captures() {
+        let routes = HashMap::from([
+            (
+                "/api".to_string(),
+                proxy("http://legacy", None, PathMatch::Prefix),
+            ),
+            (
+                "versioned-users".to_string(),
+                proxy(
+                    "http://users",
+                    Some("/internal/$1/users"),
+                    PathMatch::Regex {
+                        pattern: r"^/api/v(\d+)/users".to_string(),
+                    },
+                ),
+            ),
+        ]);
+        let table = RouteTable::new(&routes);
+
+        let headers = HeaderMap::new();
+        let request = |path| RouteRequest {
+            host: None,
+            path,
+            query: None,
+            method: &Method::GET,
+            headers: &headers,
+        };
+        let matched = table.matches(&request("/api/v2/users/42")).next().unwrap();
+        assert_eq!(matched.key, "versioned-users");
+        assert_eq!(matched.prefix, "/api/v2/users");
+        match matched.config {
+            RouteConfig::Proxy { path_rewrite, .. } => {
+                assert_eq!(path_rewrite.as_deref(), Some("/internal/2/users"))
+            }
+            other => panic!("unexpected route {other:?}"),
+        }
+
+        let keys: Vec<_> = table
+            .matches(&request("/api/v1/orders"))
+            .map(|m| m.key)
+            .collect();
+        assert_eq!(keys, ["/api"]);
+    }
+
+    #[test]
+    fn test_match_is_parsed_from_yaml() {
+        let route: RouteConfig = serde_yaml::from_str(
+            r#"
+type: "proxy"
+target: "http://users"
+path_rewrite: "/internal/$1"
+match:
+  type: "regex"
+  pattern: "^/api/v(\\d+)/"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            route.options().path_match,
+            PathMatch::Regex {
+                pattern: r"^/api/v(\d+)/".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_header_conditions_pick_the_most_specific_route() {
+        let with_headers = |path: &str, conditions: &[(&str, ValueMatch)]| RouteOptions {
+            path: Some(path.to_string()),
+            match_headers: conditions
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        let routes = HashMap::from([
+            (
+                "/things".to_string(),
+                proxy("http://default", None, PathMatch::Prefix),
+            ),
+            (
+                "things-acme".to_string(),
+                proxy_with(
+                    "http://acme",
+                    None,
+                    with_headers("/things", &[("X-Tenant", ValueMatch::Exact("acme".into()))]),
+                ),
+            ),
+            (
+                "things-acme-v2".to_string(),
+                proxy_with(
+                    "http://acme-v2",
+                    None,
+                    with_headers(
+                        "/things",
+                        &[
+                            ("X-Tenant", ValueMatch::Exact("acme".into())),
+                            (
+                                "Accept",
+                                ValueMatch::Regex {
+                                    regex: r"application/vnd\.v2\+json".into(),
+                                },
+                            ),
+                        ],
+                    ),
+                ),
+            ),
+        ]);
+        let table = RouteTable::new(&routes);
+        let best = |pairs: &[(&str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.append(HeaderName::try_from(*name).unwrap(), value.parse().unwrap());
+            }
+            let request = RouteRequest {
+                host: None,
+                path: "/things/1",
+                query: None,
+                method: &Method::GET,
+                headers: &headers,
+            };
+            table.matches(&request).next().map(|m| (m.key, m.prefix))
+        };
+
+        assert_eq!(best(&[]), Some(("/things".into(), "/things".into())));
+        assert_eq!(
+            best(&[("x-tenant", "acme")]),
+            Some(("things-acme".into(), "/things".into()))
+        );
+        assert_eq!(
+            best(&[("x-tenant", "acme"), ("accept", "application/vnd.v2+json")]),
+            Some(("things-acme-v2".into(), "/things".into()))
+        );
+        assert_eq!(
+            best(&[("x-tenant", "other")]).map(|m| m.0),
+            Some("/things".into())
+        );
+    }
+
+    #[test]
+    fn test_method_routing_and_allowed_methods() {
+        let with_methods = |methods: &[&str]| RouteOptions {
+            path: Some("/things".to_string()),
+            match_methods: methods.iter().map(|m| m.to_string()).collect(),
+            ..Default::default()
+        };
+        let routes = HashMap::from([
+            (
+                "things-read".to_string(),
+                proxy_with("http://replica", None, with_methods(&["GET", "head"])),
+            ),
+            (
+                "things-write".to_string(),
+                proxy_with("http://primary", None, with_methods(&["POST"])),
+            ),
+        ]);
+        let table = RouteTable::new(&routes);
+        let headers = HeaderMap::new();
+        let request = |method| RouteRequest {
+            host: None,
+            path: "/things",
+            query: None,
+            method,
+            headers: &headers,
+        };
+
+        let key = |method| table.matches(&request(method)).next().map(|m| m.key);
+        assert_eq!(key(&Method::HEAD).as_deref(), Some("things-read"));
+        assert_eq!(key(&Method::POST).as_deref(), Some("things-write"));
+        assert_eq!(key(&Method::DELETE), None);
+        assert_eq!(
+            table.allowed_methods(&request(&Method::DELETE)),
+            [Method::GET, Method::HEAD, Method::POST]
+        );
+    }
+
+    #[test]
+    fn test_query_conditions() {
+        let with_query = |conditions: &[(&str, ValueMatch)]| RouteOptions {
+            path: Some("/search".to_string()),
+            match_query: conditions
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            ..Default::default()
+        };
+        let routes = HashMap::from([
+            (
+                "/search".to_string(),
+                proxy("http://search", None, PathMatch::Prefix),
+            ),
+            (
+                "search-beta".to_string(),
+                proxy_with(
+                    "http://search-beta",
+                    None,
+                    with_query(&[("engine", ValueMatch::Exact("beta v2".into()))]),
+                ),
+            ),
+            (
+                "search-debug".to_string(),
+                proxy_with(
+                    "http://search-debug",
+                    None,
+                    with_query(&[("debug", ValueMatch::Present { present: true })]),
+                ),
+            ),
+        ]);
+        let table = RouteTable::new(&routes);
+        let headers = HeaderMap::new();
+        let key = |query| {
+            let request = RouteRequest {
+                host: None,
+                path: "/search",
+                query,
+                method: &Method::GET,
+                headers: &headers,
+            };
+            table.matches(&request).next().map(|m| m.key)
+        };
+
+        assert_eq!(
+            key(Some("q=x&engine=beta+v2")).as_deref(),
+            Some("search-beta")
+        );
+        assert_eq!(
+            key(Some("engine=beta%20v2")).as_deref(),
+            Some("search-beta")
+        );
+        assert_eq!(key(Some("debug")).as_deref(), Some("search-debug"));
+        assert_eq!(key(Some("engine=stable")).as_deref(), Some("/search"));
+        assert_eq!(key(None).as_deref(), Some("/search"));
+    }
+
+    #[test]
+    fn test_priority_overrides_specificity() {
+        let with_priority = |priority| RouteOptions {
+            priority,
+            ..Default::default()
+        };
+        let routes = HashMap::from([
+            (
+                "/api".to_string(),
+                proxy_with("http://api", None, with_priority(10)),
+            ),
+            (
+                "/api/admin".to_string(),
+                proxy("http://admin", None, PathMatch::Prefix),
+            ),
+            (
+                "/api/admin/audit".to_string(),
+                proxy_with("http://audit", None, with_priority(20)),
+            ),
+        ]);
+        let table = RouteTable::new(&routes);
+        let headers = HeaderMap::new();
+        let request = |path| RouteRequest {
+            host: None,
+            path,
+            query: None,
+            method: &Method::GET,
+            headers: &headers,
+        };
+        let keys = |path| -> Vec<String> { table.matches(&request(path)).map(|m| m.key).collect() };
+
+        assert_eq!(
+            keys("/api/admin/audit/1"),
+            ["/api/admin/audit", "/api", "/api/admin"]
+        );
+        assert_eq!(keys("/api/admin/users"), ["/api", "/api/admin"]);
+        assert_eq!(
+            table.describe()[0],
+            "/api/admin/audit (prefix '/api/admin/audit', priority 20, 0 conditions)"
+        );
+    }
+
+    #[test]
+    fn test_path_normalization() {
+        assert_eq!(normalize_path("/api/x"), "/api/x");
+        assert_eq!(normalize_path("//api///x/"), "/api/x/");
+        assert_eq!(original_prefix("//api///x", 4), "//api");
+        assert_eq!(original_prefix("//api///x", 0), "");
+        assert_eq!(original_prefix("/api", 4), "/api");
+
+        let routes = HashMap::from([(
+            "/api/".to_string(),
+            proxy("http://api", None, PathMatch::Prefix),
+        )]);
+        let headers = HeaderMap::new();
+        let request = |path| RouteRequest {
+            host: None,
+            path,
+            query: None,
+            method: &Method::GET,
+            headers: &headers,
+        };
+        assert!(
+            RouteTable::new(&routes)
+                .matches(&request("/api"))
+                .next()
+                .is_none()
+        );
+        let matched = RouteTable::normalized(&routes)
+            .matches(&request("/api"))
+            .next()
+            .unwrap();
+        assert_eq!(matched.prefix, "/api");
+    }
+
+    #[test]
+    fn test_exact_routes() {
+        let routes = HashMap::from([
+            (
+                "/healthz".to_string(),
+                proxy("http://backend", None, PathMatch::Prefix),
+            ),
+            (
+                "local-healthz".to_string(),
+                proxy_with(
+                    "http://local",
+                    None,
+                    RouteOptions {
+                        path: Some("/healthz".to_string()),
+                        path_match: serde_yaml::from_str("exact").unwrap(),
+                        ..Default::default()
+                    },
+                ),
+            ),
+        ]);
+        let table = RouteTable::new(&routes);
+        let headers = HeaderMap::new();
+        let request = |path| RouteRequest {
+            host: None,
+            path,
+            query: None,
+            method: &Method::GET,
+            headers: &headers,
+        };
+        let key = |path| table.matches(&request(path)).next().map(|m| m.key);
+
+        assert_eq!(key("/healthz").as_deref(), Some("local-healthz"));
+        assert_eq!(key("/healthz/detail").as_deref(), Some("/healthz"));
+        assert_eq!(
+            serde_yaml::from_str::<PathMatch>("type: exact").unwrap(),
+            PathMatch::Exact
+        );
+    }
+
+    #[test]
+    fn test_segment_routes() {
+        let mut profile = proxy(
+            "http://users",
+            Some("/v2/users/{path_param:id}"),
+            PathMatch::Prefix,
+        );
+        if let RouteConfig::Proxy {
+            request_headers, ..
+        } = &mut profile
+        {
+            *request_headers = Some(crate::config::HeaderActions {
+                add: HashMap::from([("X-User-Id".to_string(), "{path_param:id}".to_string())]),
+                ..Default::default()
+            });
+        }
+        let routes = HashMap::from([
+            ("/users/:id/profile".to_string(), profile),
+            (
+                "/tenants/*/api".to_string(),
+                proxy("http://tenants", None, PathMatch::Prefix),
+            ),
+            (
+                "/tenants/acme".to_string(),
+                proxy("http://acme", None, PathMatch::Prefix),
+            ),
+            (
+                "/tenants/acme/api".to_string(),
+                proxy("http://acme-api", None, PathMatch::Prefix),
+            ),
+        ]);
+        let table = RouteTable::new(&routes);
+        let headers = HeaderMap::new();
+        let request = |path| RouteRequest {
+            host: None,
+            path,
+            query: None,
+            method: &Method::GET,
+            headers: &headers,
+        };
+        let route = |path| table.matches(&request(path)).next();
+
+        let matched = route("/users/42/profile/photo").unwrap();
+        assert_eq!(matched.prefix, "/users/42/profile");
+        let RouteConfig::Proxy {
+            path_rewrite,
+            request_headers,
+            ..
+        } = matched.config
+        else {
+            panic!("expected a proxy route");
+        };
+        assert_eq!(path_rewrite.as_deref(), Some("/v2/users/42"));
+        assert_eq!(request_headers.unwrap().add["X-User-Id"], "42");
+        // Segments are matched whole and parameters must not be empty
+        assert!(route("/users/42/profiles").is_none());
+        assert!(route("/users//profile").is_none());
+
+        // The deeper pattern beats the shorter literal prefix, literals beat wildcards
+        assert_eq!(
+            route("/tenants/globex/api/v1").unwrap().key,
+            "/tenants/*/api"
+        );
+        assert_eq!(route("/tenants/acme/api").unwrap().key, "/tenants/acme/api");
+        assert_eq!(route("/tenants/acme/billing").unwrap().key, "/tenants/acme");
+    }
+}
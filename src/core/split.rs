@@ -0,0 +1,168 @@
+use http::{HeaderMap, header};
+use rand::Rng;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::config::SplitVariant;
+
+/// Pick a variant by weight. With a sticky key the choice is a deterministic
+/// function of the key and the current weights; without one it is random.
+pub fn pick_variant<K: Hash>(
+    variants: &[SplitVariant],
+    sticky_key: Option<K>,
+) -> Option<&SplitVariant> {
+    let total: u64 = variants.iter().map(|v| u64::from(v.weight)).sum();
+    if total == 0 {
+        return None;
+    }
+    let point = match sticky_key {
+        Some(key) => {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish() % total
+        }
+        None => rand::rng().random_range(0..total),
+    };
+    variant_at(variants, point)
+}
+
+/// The variant whose cumulative weight range contains `point`
+fn variant_at(variants: &[SplitVariant], mut point: u64) -> Option<&SplitVariant> {
+    variants.iter().find(|variant| {
+        let weight = u64::from(variant.weight);
+        if point < weight {
+            true
+        } else {
+            point -= weight;
+            false
+        }
+    })
+}
+
+/// A variant still in rotation with the given label, used to honour stickiness cookies
+pub fn variant_by_label<'a>(variants: &'a [SplitVariant], label: &str) -> Option<&'a SplitVariant> {
+    variants
+        .iter()
+        .find(|variant| variant.weight > 0 && variant.label() == label)
+}
+
+/// Stable, opaque stickiness label of a backend, so cookies don't expose internal URLs.
+/// `ws://` and `wss://` targets share the label of their `http://` and `https://`
+/// counterparts, which lets HTTP and WebSocket routes to one service share affinity.
+pub fn target_label(target: &str) -> String {
+    let target = target.trim_end_matches('/');
+    let normalized = if let Some(rest) = target.strip_prefix("ws://") {
+        format!("http://{rest}")
+    } else if let Some(rest) = target.strip_prefix("wss://") {
+        format!("https://{rest}")
+    } else {
+        target.to_string()
+    };
+    let mut hasher = DefaultHasher::new();
+    normalized.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// The target with the given stickiness label, used to honour affinity cookies
+pub fn target_by_label<'a>(targets: &'a [String], label: &str) -> Option<&'a String> {
+    targets.iter().find(|target| target_label(target) == label)
+}
+
+/// A target chosen as a deterministic function of a sticky key and the target list
+pub fn target_by_key<K: Hash>(targets: &[String], key: K) -> Option<&String> {
+    if targets.is_empty() {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    targets.get((hasher.finish() % targets.len() as u64) as usize)
+}
+
+/// Value of a request cookie, searching every Cookie header
+pub fn cookie_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.trim_matches('"'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn variant(name: &str, weight: u32) -> SplitVariant {
+        SplitVariant {
+            name: Some(name.to_string()),
+            target: format!("http://{name}"),
+            weight,
+        }
+    }
+
+    #[test]
+    fn test_weights_partition_the_range() {
+        let variants = [variant("stable", 95), variant("canary", 5)];
+        assert_eq!(variant_at(&variants, 0).unwrap().label(), "stable");
+        assert_eq!(variant_at(&variants, 94).unwrap().label(), "stable");
+        assert_eq!(variant_at(&variants, 95).unwrap().label(), "canary");
+        assert_eq!(variant_at(&variants, 99).unwrap().label(), "canary");
+
+        let canary = (0..10_000)
+            .filter(|_| pick_variant::<u8>(&variants, None).unwrap().label() == "canary")
+            .count();
+        assert!((200..800).contains(&canary), "canary picked {canary} times");
+
+        let drained = [variant("stable", 0), variant("canary", 0)];
+        assert!(pick_variant::<u8>(&drained, None).is_none());
+    }
+
+    #[test]
+    fn test_sticky_keys_are_deterministic() {
+        let variants = [variant("stable", 50), variant("canary", 50)];
+        let first = pick_variant(&variants, Some("10.0.0.7")).unwrap();
+        for _ in 0..20 {
+            assert_eq!(pick_variant(&variants, Some("10.0.0.7")).unwrap(), first);
+        }
+        assert!(variant_by_label(&variants, "canary").is_some());
+        assert!(variant_by_label(&[variant("canary", 0)], "canary").is_none());
+    }
+
+    #[test]
+    fn test_target_labels() {
+        let targets = [
+            "http://app-1:8080".to_string(),
+            "http://app-2:8080/".to_string(),
+        ];
+        assert_eq!(target_label("ws://app-1:8080"), target_label(&targets[0]));
+        assert_eq!(
+            target_label("wss://app-2:8080"),
+            target_label("https://app-2:8080")
+        );
+        assert_ne!(target_label(&targets[0]), target_label(&targets[1]));
+        assert_eq!(
+            target_by_label(&targets, &target_label("ws://app-2:8080")),
+            Some(&targets[1])
+        );
+        assert_eq!(
+            target_by_key(&targets, "10.0.0.7"),
+            target_by_key(&targets, "10.0.0.7")
+        );
+        assert!(target_by_key::<u8>(&[], 1).is_none());
+    }
+
+    #[test]
+    fn test_cookie_value() {
+        let mut headers = HeaderMap::new();
+        headers.append(header::COOKIE, HeaderValue::from_static("a=1; b=2"));
+        headers.append(
+            header::COOKIE,
+            HeaderValue::from_static("prox_variant=canary"),
+        );
+        assert_eq!(cookie_value(&headers, "b"), Some("2"));
+        assert_eq!(cookie_value(&headers, "prox_variant"), Some("canary"));
+        assert_eq!(cookie_value(&headers, "missing"), None);
+    }
+}
@@ -24,3 +24,4 @@ pub use crate::adapters::http::server::HyperServer;
 pub use crate::adapters::http_client::HyperHttpClient;
 pub use crate::adapters::unified_server::UnifiedServer;
 pub use crate::core::ProxyService;
+pub use crate::core::config_history::ConfigSource;
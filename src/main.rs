@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::time::Duration;
@@ -6,12 +7,17 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use notify::{RecursiveMode, Watcher};
 use std::path::Path;
-use tokio::sync::{Mutex as TokioMutex, mpsc};
+use tokio::sync::{Mutex as TokioMutex, broadcast, mpsc};
 
 use prox::{
     HealthChecker, HyperHttpClient, ProxyService, TowerFileSystem, UnifiedServer,
-    config::loader::load_config, config::models::ServerConfig, tracing_setup,
+    config::loader::{ConfigOverrides, LoadOptions, include_patterns, load_config_with},
+    config::models::{LogFormat, ServerConfig},
+    config::validation::ConfigValidator,
+    tracing_setup,
+    utils::config_reload::ConfigReloader,
     utils::graceful_shutdown::GracefulShutdown,
+    utils::log_redaction,
 };
 
 #[derive(Parser, Debug)]
@@ -22,6 +28,48 @@ struct Args {
 
     #[clap(short, long, default_value = "config.yaml")]
     config: String,
+
+    /// Read `${...}` in the config file literally instead of from the environment
+    #[clap(long, global = true)]
+    no_env_interpolation: bool,
+
+    /// Refuse config files with unknown keys, as if they set `strict: true`
+    #[clap(long, global = true)]
+    strict: bool,
+
+    #[clap(flatten)]
+    overrides: OverrideArgs,
+}
+
+/// Settings that replace the config file's, here and on every reload
+#[derive(clap::Args, Debug, Clone)]
+struct OverrideArgs {
+    /// Address to listen on instead of `listen_addr`
+    #[clap(long, env = "PROX_LISTEN_ADDR")]
+    listen_addr: Option<String>,
+
+    /// Log filter such as "info" or "warn,prox=debug", instead of `telemetry.log_level`
+    #[clap(long, env = "PROX_LOG_LEVEL")]
+    log_level: Option<String>,
+
+    /// `json` or `text`, instead of `telemetry.log_format`
+    #[clap(long, env = "PROX_LOG_FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Turn off active health checks whatever `health_check.enabled` says
+    #[clap(long, env = "PROX_DISABLE_HEALTH_CHECKS")]
+    disable_health_checks: bool,
+}
+
+impl From<OverrideArgs> for ConfigOverrides {
+    fn from(args: OverrideArgs) -> Self {
+        Self {
+            listen_addr: args.listen_addr,
+            log_level: args.log_level,
+            log_format: args.log_format,
+            disable_health_checks: args.disable_health_checks,
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -37,23 +85,90 @@ enum Commands {
         /// Configuration file to use
         #[clap(short, long, default_value = "config.yaml")]
         config: String,
+
+        #[clap(flatten)]
+        overrides: OverrideArgs,
+    },
+    /// Print the JSON Schema of the configuration file
+    Schema,
+    /// Inspect the configuration
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
     },
 }
 
+#[derive(clap::Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print the validated configuration with every default filled in, secrets redacted
+    Print {
+        /// Configuration file to print
+        #[clap(short, long, default_value = "config.yaml")]
+        config: String,
+
+        #[clap(long, value_enum, default_value = "yaml")]
+        format: PrintFormat,
+
+        #[clap(flatten)]
+        overrides: OverrideArgs,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum PrintFormat {
+    Yaml,
+    Json,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    let mut load_options = LoadOptions {
+        interpolate_env: !args.no_env_interpolation,
+        strict: args.strict,
+        ..LoadOptions::default()
+    };
+
+    let mut print_format = PrintFormat::Yaml;
 
     // Determine the command to run
     let (command, config_path) = match args.command {
         Some(Commands::Validate { config }) => ("validate", config),
-        Some(Commands::Serve { config }) => ("serve", config),
-        None => ("serve", args.config), // Default to serve with config from args
+        Some(Commands::Schema) => ("schema", args.config),
+        Some(Commands::Config {
+            command:
+                ConfigCommands::Print {
+                    config,
+                    format,
+                    overrides,
+                },
+        }) => {
+            load_options.overrides = overrides.into();
+            print_format = format;
+            ("print", config)
+        }
+        Some(Commands::Serve { config, overrides }) => {
+            load_options.overrides = overrides.into();
+            ("serve", config)
+        }
+        None => {
+            // Default to serve with config and overrides from args
+            load_options.overrides = args.overrides.into();
+            ("serve", args.config)
+        }
     };
 
     match command {
         "validate" => {
-            return validate_config_command(&config_path).await;
+            return validate_config_command(&config_path, load_options).await;
+        }
+        "schema" => {
+            let schema = prox::config::schema::config_schema();
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            return Ok(());
+        }
+        "print" => {
+            return print_config_command(&config_path, load_options, print_format).await;
         }
         "serve" => {
             // Continue with normal server startup
@@ -73,13 +188,18 @@ async fn main() -> Result<()> {
         tracing::info!("Successfully installed aws-lc-rs as the default crypto provider.");
     }
 
-    // Configure tracing_subscriber for JSON output with OpenTelemetry
-    tracing_setup::init_tracing().expect("Failed to initialize tracing with OpenTelemetry");
-
-    tracing::info!("Loading initial configuration from {config_path}");
-    let initial_server_config_data: ServerConfig = load_config(&config_path)
-        .await
-        .with_context(|| format!("Failed to load initial config from {config_path}"))?;
+    // Logging and tracing are configured by the file, so it is loaded first
+    let initial_server_config_data: ServerConfig =
+        load_config_with(&config_path, load_options.clone())
+            .await
+            .with_context(|| format!("Failed to load initial config from {config_path}"))?;
+    tracing_setup::init_tracing(&initial_server_config_data.telemetry)
+        .map_err(|e| anyhow::anyhow!("Failed to initialize tracing: {e}"))?;
+    log_redaction::configure(&initial_server_config_data.log_redaction);
+    tracing::info!("Loaded initial configuration from {config_path}");
+    for warning in ConfigValidator::warnings(&initial_server_config_data) {
+        tracing::warn!("Configuration warning: {}", warning);
+    }
 
     let initial_config_arc = Arc::new(initial_server_config_data);
     let config_holder = Arc::new(RwLock::new(initial_config_arc.clone()));
@@ -134,12 +254,20 @@ async fn main() -> Result<()> {
         }
     }
 
+    // The file watcher, SIGHUP and POST /-/config/reload all reload through this
+    let config_reloader = ConfigReloader::new(
+        config_holder.clone(),
+        proxy_service_holder.clone(),
+        http_client.clone(),
+        health_checker_handle_arc_mutex.clone(),
+    )
+    .with_config_path(config_path.clone())
+    .with_load_options(load_options);
+
     // File Watcher Task
     let config_path_for_watcher = config_path.clone();
-    let config_holder_clone = config_holder.clone();
-    let proxy_service_holder_clone = proxy_service_holder.clone();
-    let http_client_for_watcher = http_client.clone();
-    let health_handle_for_watcher = health_checker_handle_arc_mutex.clone();
+    let reloader_for_watcher = config_reloader.clone();
+    let config_holder_for_watcher = config_holder.clone();
     let debounce_duration = Duration::from_secs(2);
 
     tokio::spawn(async move {
@@ -153,18 +281,27 @@ async fn main() -> Result<()> {
             .to_path_buf();
 
         let config_file_path_for_closure = config_path_for_watcher.clone();
+        let config_holder_for_closure = config_holder_for_watcher.clone();
 
         let mut watcher = match notify::recommended_watcher(
             move |res: Result<notify::Event, notify::Error>| match res {
                 Ok(event) => {
-                    let config_file_name_to_check = Path::new(&config_file_path_for_closure)
-                        .file_name()
+                    let config_file_as_path = Path::new(&config_file_path_for_closure);
+                    let config_file_name_to_check =
+                        config_file_as_path.file_name().unwrap_or_default();
+                    // Files pulled in by `include` in the running config
+                    let includes = config_holder_for_closure
+                        .read()
+                        .ok()
+                        .and_then(|config| {
+                            include_patterns(config_file_as_path, &config.include).ok()
+                        })
                         .unwrap_or_default();
                     if (event.kind.is_modify() || event.kind.is_create() || event.kind.is_remove())
-                        && event
-                            .paths
-                            .iter()
-                            .any(|p| p.file_name().unwrap_or_default() == config_file_name_to_check)
+                        && event.paths.iter().any(|p| {
+                            p.file_name().unwrap_or_default() == config_file_name_to_check
+                                || includes.iter().any(|include| include.matches(p))
+                        })
                     {
                         tracing::debug!(
                             "Config file event detected: {:?}, sending signal for reload.",
@@ -210,6 +347,43 @@ async fn main() -> Result<()> {
                 .to_string_lossy()
         );
 
+        // Directories of included files, added as the config starts including them
+        let mut watched_directories = HashSet::from([directory_to_watch]);
+        let mut watch_included_directories = |watcher: &mut notify::RecommendedWatcher| {
+            let Ok(config) = config_holder_for_watcher
+                .read()
+                .map(|config| config.clone())
+            else {
+                return;
+            };
+            let Ok(includes) =
+                include_patterns(Path::new(&config_path_for_watcher), &config.include)
+            else {
+                return;
+            };
+            for include in includes {
+                let directory = include.dir().to_path_buf();
+                if watched_directories.contains(&directory) {
+                    continue;
+                }
+                match watcher.watch(&directory, RecursiveMode::NonRecursive) {
+                    Ok(()) => {
+                        tracing::info!(
+                            "Watching included config files in directory: {:?}",
+                            directory
+                        );
+                        watched_directories.insert(directory);
+                    }
+                    Err(e) => tracing::warn!(
+                        "Failed to watch included config directory {:?}: {}",
+                        directory,
+                        e
+                    ),
+                }
+            }
+        };
+        watch_included_directories(&mut watcher);
+
         let mut last_reload_attempt_time = tokio::time::Instant::now();
         // Allow first event to trigger reload immediately after startup if a quick change happens
         // by setting the last attempt time to be older than the debounce duration.
@@ -227,101 +401,21 @@ async fn main() -> Result<()> {
             }
             last_reload_attempt_time = tokio::time::Instant::now();
 
-            tracing::info!(
-                "Attempting to reload configuration from {}",
-                config_path_for_watcher
-            );
-            match load_config(&config_path_for_watcher).await {
-                Ok(new_config_data) => {
-                    let new_config_arc: Arc<ServerConfig> = Arc::new(new_config_data);
-                    tracing::info!("Successfully loaded new configuration.");
-
-                    {
-                        match config_holder_clone.write() {
-                            Ok(mut config_w) => {
-                                *config_w = new_config_arc.clone();
-                                tracing::info!("Global ServerConfig Arc updated.");
-                            }
-                            Err(e) => {
-                                tracing::error!(
-                                    "Failed to acquire config write lock during reload: {}",
-                                    e
-                                );
-                                continue;
-                            }
-                        }
-                    }
-
-                    let new_proxy_service = Arc::new(ProxyService::new(new_config_arc.clone()));
-                    {
-                        match proxy_service_holder_clone.write() {
-                            Ok(mut proxy_s_w) => {
-                                *proxy_s_w = new_proxy_service.clone();
-                                tracing::info!("Global ProxyService Arc updated.");
-                            }
-                            Err(e) => {
-                                tracing::error!(
-                                    "Failed to acquire proxy service write lock during reload: {}",
-                                    e
-                                );
-                                continue;
-                            }
-                        }
-                    }
-
-                    let mut handle_guard = health_handle_for_watcher.lock().await;
-                    if let Some(old_handle) = handle_guard.take() {
-                        tracing::info!("Aborting previous health checker task...");
-                        old_handle.abort();
-                    }
-
-                    if new_config_arc.health_check.enabled {
-                        tracing::info!(
-                            "Starting new health checker task with updated configuration..."
-                        );
-
-                        let health_checker = HealthChecker::new(
-                            new_proxy_service.clone(),
-                            http_client_for_watcher.clone(),
-                        );
-                        let config_for_logging = new_config_arc.clone();
-
-                        *handle_guard = Some(tokio::spawn(async move {
-                            tracing::info!(
-                                "File Reload health checker task started. Interval: {}s, Path: {}, Unhealthy Threshold: {}, Healthy Threshold: {}",
-                                config_for_logging.health_check.interval_secs,
-                                config_for_logging.health_check.path,
-                                config_for_logging.health_check.unhealthy_threshold,
-                                config_for_logging.health_check.healthy_threshold
-                            );
-                            if let Err(e) = health_checker.run().await {
-                                tracing::error!("File Reload health checker run error: {}", e);
-                            }
-                        }));
-                    } else {
-                        tracing::info!(
-                            "Health checking is disabled in the new configuration. Not starting health checker task."
-                        );
-                    }
-                    tracing::info!(
-                        "Configuration reloaded and health checker (if enabled) managed."
-                    );
-                }
-                Err(e) => {
-                    tracing::error!(
-                        "Failed to reload configuration: {}. Keeping old configuration.",
-                        e
-                    );
-                }
+            if let Err(e) = reloader_for_watcher.reload_from_file().await {
+                tracing::error!(
+                    "Failed to reload configuration: {:#}. Keeping old configuration.",
+                    e
+                );
             }
+            watch_included_directories(&mut watcher);
             // Consume any other queued signals that might have arrived during processing to prevent immediate re-trigger.
             while notify_rx.try_recv().is_ok() {}
         }
         tracing::info!("File watcher task is shutting down.");
     });
 
-    // Create graceful shutdown manager
-    let graceful_shutdown = Arc::new(GracefulShutdown::new());
+    // Create graceful shutdown manager; its settings are read at startup only
+    let graceful_shutdown = Arc::new(GracefulShutdown::from_config(&initial_config_arc.shutdown));
 
     // Start signal handler for graceful shutdown
     let signal_handler_shutdown = graceful_shutdown.clone();
@@ -331,6 +425,22 @@ async fn main() -> Result<()> {
         }
     });
 
+    // Reload on SIGHUP, for setups where file events don't reach the watcher
+    let mut reload_requests = graceful_shutdown.subscribe_reload();
+    let reloader_for_signals = config_reloader.clone();
+    tokio::spawn(async move {
+        while let Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) =
+            reload_requests.recv().await
+        {
+            if let Err(e) = reloader_for_signals.reload_from_file().await {
+                tracing::error!(
+                    "Failed to reload configuration: {:#}. Keeping old configuration.",
+                    e
+                );
+            }
+        }
+    });
+
     // Create the unified server (supports HTTP/1.1, HTTP/2, and HTTP/3)
     let server = UnifiedServer::new(
         proxy_service_holder.clone(),
@@ -340,7 +450,8 @@ async fn main() -> Result<()> {
         health_checker_handle_arc_mutex.clone(), // Pass the health checker handle
         graceful_shutdown.clone(),
     )
-    .await?;
+    .await?
+    .with_config_reloader(config_reloader);
 
     // Log initial routes from the config_holder
     {
@@ -350,6 +461,18 @@ async fn main() -> Result<()> {
         for (prefix, route) in &ch.routes {
             tracing::info!("Configured route: {} -> {:?}", prefix, route);
         }
+        for (host, vhost) in &ch.virtual_hosts {
+            for (prefix, route) in &vhost.routes {
+                tracing::info!("Configured route: {}{} -> {:?}", host, prefix, route);
+            }
+        }
+        if let Some(route) = &ch.default_route {
+            tracing::info!("Configured default route -> {:?}", route);
+        }
+        match proxy_service_holder.read() {
+            Ok(service) => service.log_route_order(),
+            Err(e) => tracing::error!("Failed to acquire proxy service read lock: {}", e),
+        }
 
         let protocols = &ch.protocols;
         tracing::info!(
@@ -378,8 +501,10 @@ async fn main() -> Result<()> {
     }
 
     // Run the server and wait for shutdown
+    let server_run = server.run();
+    tokio::pin!(server_run);
     let server_result = tokio::select! {
-        result = server.run() => result,
+        result = &mut server_run => result,
         shutdown_reason = graceful_shutdown.wait_for_shutdown_signal() => {
             tracing::info!("Shutdown signal received: {:?}", shutdown_reason);
 
@@ -390,8 +515,24 @@ async fn main() -> Result<()> {
                 health_handle.abort();
             }
 
-            tracing::info!("Graceful shutdown completed");
-            Ok(())
+            // Let the listeners drain their connections
+            match graceful_shutdown.force_close_after() {
+                Some(limit) => match tokio::time::timeout(limit, server_run).await {
+                    Ok(result) => {
+                        tracing::info!("Graceful shutdown completed");
+                        result
+                    }
+                    Err(_) => {
+                        tracing::warn!("Shutdown still running after {:?}, exiting anyway", limit);
+                        Ok(())
+                    }
+                },
+                None => {
+                    let result = server_run.await;
+                    tracing::info!("Graceful shutdown completed");
+                    result
+                }
+            }
         }
     };
 
@@ -404,9 +545,10 @@ async fn main() -> Result<()> {
 }
 
 /// Validate configuration file and exit
-async fn validate_config_command(config_path: &str) -> Result<()> {
-    use prox::config::loader::load_config_unchecked;
-    use prox::config::validation::ConfigValidator;
+async fn validate_config_command(config_path: &str, load_options: LoadOptions) -> Result<()> {
+    use prox::config::loader::{
+        ConfigError, ConfigFormat, load_config_unchecked_with, unknown_config_keys,
+    };
 
     println!("🔍 Validating configuration file: {config_path}");
 
@@ -416,27 +558,49 @@ async fn validate_config_command(config_path: &str) -> Result<()> {
         std::process::exit(1);
     }
 
-    // Try to parse the YAML
-    let config = match load_config_unchecked(config_path).await {
+    // Try to parse the file, as YAML, TOML or JSON by its extension
+    let format = ConfigFormat::from_path(Path::new(config_path)).name();
+    let config = match load_config_unchecked_with(config_path, load_options.clone()).await {
         Ok(config) => {
-            println!("✅ YAML parsing: OK");
+            println!("✅ {format} parsing: OK");
             config
         }
+        Err(ConfigError::UnknownKeys { keys }) => {
+            eprintln!("❌ Unknown keys (strict mode):");
+            for key in keys {
+                eprintln!("   • {key}");
+            }
+            std::process::exit(1);
+        }
         Err(e) => {
-            eprintln!("❌ YAML parsing failed:");
+            eprintln!("❌ {format} parsing failed:");
             eprintln!("   {e}");
             std::process::exit(1);
         }
     };
 
-    // Validate the configuration
-    match ConfigValidator::validate(&config) {
+    // Validate the configuration, the same way POST /-/config/validate does
+    let report = ConfigValidator::check(&config);
+    // Unknown keys are warned about even without strict mode, which would have failed above
+    let unknown_keys = unknown_config_keys(config_path, &load_options).await?;
+    if !report.warnings.is_empty() || !unknown_keys.is_empty() {
+        println!("⚠️  Warnings:");
+        for key in &unknown_keys {
+            println!("   • Unknown key '{key}' is ignored");
+        }
+        for warning in &report.warnings {
+            println!("   • {warning}");
+        }
+    }
+    match report.into_result() {
         Ok(()) => {
             println!("✅ Configuration validation: OK");
             println!();
             println!("📋 Configuration Summary:");
             println!("   • Listen Address: {}", config.listen_addr);
             println!("   • Routes: {}", config.routes.len());
+            println!("   • Virtual Hosts: {}", config.virtual_hosts.len());
+            println!("   • Default Route: {}", config.default_route.is_some());
             println!("   • TLS Enabled: {}", config.tls.is_some());
             println!("   • Health Checks: {}", config.health_check.enabled);
             println!();
@@ -456,3 +620,23 @@ async fn validate_config_command(config_path: &str) -> Result<()> {
         }
     }
 }
+
+/// Load the config the way `serve` would and print it as `GET /-/config` shows it
+async fn print_config_command(
+    config_path: &str,
+    load_options: LoadOptions,
+    format: PrintFormat,
+) -> Result<()> {
+    let config = match load_config_with(config_path, load_options).await {
+        Ok(config) => config.redacted(),
+        Err(e) => {
+            eprintln!("❌ {e}");
+            std::process::exit(1);
+        }
+    };
+    match format {
+        PrintFormat::Yaml => print!("{}", serde_yaml::to_string(&config)?),
+        PrintFormat::Json => println!("{}", serde_json::to_string_pretty(&config)?),
+    }
+    Ok(())
+}
@@ -6,26 +6,91 @@ use std::collections::HashMap;
 use std::sync::Mutex;
 use std::time::Instant;
 
+use crate::utils::connection_tracker::ConnectionStats;
+
 pub const PROX_BACKEND_HEALTH_STATUS: &str = "prox_backend_health_status";
+pub const PROX_BACKEND_HEALTHY: &str = "prox_backend_healthy";
+pub const PROX_BACKEND_HEALTH_OVERRIDDEN: &str = "prox_backend_health_overridden";
+pub const PROX_BACKEND_HEALTH_TRANSITIONS_TOTAL: &str = "prox_backend_health_transitions_total";
+pub const PROX_HEALTH_CHECKS_TOTAL: &str = "prox_health_checks_total";
+pub const PROX_HEALTH_CHECK_DURATION_SECONDS: &str = "prox_health_check_duration_seconds";
 pub const PROX_REQUESTS_TOTAL: &str = "prox_requests_total";
 pub const PROX_REQUEST_DURATION_SECONDS: &str = "prox_request_duration_seconds";
+pub const PROX_ROUTE_REQUEST_DURATION_SECONDS: &str = "prox_route_request_duration_seconds";
 pub const PROX_BACKEND_REQUESTS_TOTAL: &str = "prox_backend_requests_total";
 pub const PROX_BACKEND_REQUEST_DURATION_SECONDS: &str = "prox_backend_request_duration_seconds";
+pub const PROX_ORIGIN_REJECTIONS_TOTAL: &str = "prox_origin_rejections_total";
+pub const PROX_STATIC_CACHE_REQUESTS_TOTAL: &str = "prox_static_cache_requests_total";
+pub const PROX_SPLIT_REQUESTS_TOTAL: &str = "prox_split_requests_total";
+pub const PROX_SPLIT_REQUEST_DURATION_SECONDS: &str = "prox_split_request_duration_seconds";
+pub const PROX_MIRROR_REQUESTS_TOTAL: &str = "prox_mirror_requests_total";
+pub const PROX_MIRROR_ERRORS_TOTAL: &str = "prox_mirror_errors_total";
+pub const PROX_WEBSOCKET_CONNECTIONS_OPEN: &str = "prox_websocket_connections_open";
+pub const PROX_WEBSOCKET_HANDSHAKES_TOTAL: &str = "prox_websocket_handshakes_total";
+pub const PROX_WEBSOCKET_CONNECTION_DURATION_SECONDS: &str =
+    "prox_websocket_connection_duration_seconds";
+pub const PROX_WEBSOCKET_CONNECTION_BYTES: &str = "prox_websocket_connection_bytes";
+pub const PROX_OCSP_STAPLE_AGE_SECONDS: &str = "prox_ocsp_staple_age_seconds";
+pub const PROX_HTTP3_CONNECTIONS: &str = "prox_http3_connections";
+pub const PROX_HTTP3_HANDSHAKES_TOTAL: &str = "prox_http3_handshakes_total";
+pub const PROX_HTTP3_PACKETS_TOTAL: &str = "prox_http3_packets_total";
+pub const PROX_HTTP3_STREAMS_TOTAL: &str = "prox_http3_streams_total";
+pub const PROX_ACTIVE_CONNECTIONS: &str = "prox_active_connections";
+pub const PROX_IDLE_CONNECTIONS: &str = "prox_idle_connections";
+pub const PROX_ACTIVE_REQUESTS: &str = "prox_active_requests";
+pub const PROX_OLDEST_CONNECTION_AGE_SECONDS: &str = "prox_oldest_connection_age_seconds";
+pub const PROX_UPTIME_SECONDS: &str = "prox_uptime_seconds";
+pub const PROX_CONFIG_VERSION: &str = "prox_config_version";
+pub const PROX_ACCESS_LOG_DROPPED_TOTAL: &str = "prox_access_log_dropped_total";
+pub const PROX_UPSTREAM_ERRORS_TOTAL: &str = "prox_upstream_errors_total";
+pub const PROX_SHUTDOWN_DRAIN_REMAINING_SECONDS: &str = "prox_shutdown_drain_remaining_seconds";
+pub const PROX_CONNECTIONS_CLOSED_TOTAL: &str = "prox_connections_closed_total";
+
+/// `cause` of a backend response passed through with a 5xx status
+pub const UPSTREAM_5XX: &str = "upstream_5xx";
 
 pub static BACKEND_HEALTH_GAUGES: Lazy<Mutex<HashMap<String, f64>>> = Lazy::new(|| {
     describe_gauge!(
         PROX_BACKEND_HEALTH_STATUS,
         "Health status of individual backends (1 for healthy, 0 for unhealthy)"
     );
+    describe_gauge!(
+        PROX_BACKEND_HEALTHY,
+        "Whether each backend is in rotation (1) or ejected (0), labelled by target."
+    );
+    describe_gauge!(
+        PROX_BACKEND_HEALTH_OVERRIDDEN,
+        "Whether each backend's health is forced through the admin API (1) or follows its checks (0), labelled by target."
+    );
+    describe_counter!(
+        PROX_HEALTH_CHECKS_TOTAL,
+        Unit::Count,
+        "Active health checks, labelled by target and result (success or failure)."
+    );
+    describe_histogram!(
+        PROX_HEALTH_CHECK_DURATION_SECONDS,
+        Unit::Seconds,
+        "Latency of active health checks, labelled by target."
+    );
+    describe_counter!(
+        PROX_BACKEND_HEALTH_TRANSITIONS_TOTAL,
+        Unit::Count,
+        "Backend health changes, labelled by backend, status and source (active or passive)."
+    );
     describe_counter!(
         PROX_REQUESTS_TOTAL,
         Unit::Count,
-        "Total number of HTTP requests processed by the proxy."
+        "Total number of HTTP requests processed by the proxy, labelled by protocol."
     );
     describe_histogram!(
         PROX_REQUEST_DURATION_SECONDS,
         Unit::Seconds,
-        "Latency of HTTP requests processed by the proxy."
+        "Latency of HTTP requests processed by the proxy, labelled by protocol."
+    );
+    describe_histogram!(
+        PROX_ROUTE_REQUEST_DURATION_SECONDS,
+        Unit::Seconds,
+        "Latency of HTTP requests, labelled by matched route, method, status class and protocol."
     );
     describe_counter!(
         PROX_BACKEND_REQUESTS_TOTAL,
@@ -35,7 +100,130 @@ pub static BACKEND_HEALTH_GAUGES: Lazy<Mutex<HashMap<String, f64>>> = Lazy::new(
     describe_histogram!(
         PROX_BACKEND_REQUEST_DURATION_SECONDS,
         Unit::Seconds,
-        "Latency of HTTP requests forwarded to backend services."
+        "Latency of HTTP requests forwarded to backend services, labelled by backend and route."
+    );
+    describe_counter!(
+        PROX_ORIGIN_REJECTIONS_TOTAL,
+        Unit::Count,
+        "Total number of state-changing requests rejected by the same-origin check."
+    );
+    describe_counter!(
+        PROX_STATIC_CACHE_REQUESTS_TOTAL,
+        Unit::Count,
+        "Static file cache lookups, labelled by result (hit or miss)."
+    );
+    describe_counter!(
+        PROX_SPLIT_REQUESTS_TOTAL,
+        Unit::Count,
+        "Requests served by split routes, labelled by route, variant and status class."
+    );
+    describe_histogram!(
+        PROX_SPLIT_REQUEST_DURATION_SECONDS,
+        Unit::Seconds,
+        "Latency of requests served by split routes, labelled by route and variant."
+    );
+    describe_counter!(
+        PROX_MIRROR_REQUESTS_TOTAL,
+        Unit::Count,
+        "Requests copied to mirror targets."
+    );
+    describe_counter!(
+        PROX_MIRROR_ERRORS_TOTAL,
+        Unit::Count,
+        "Mirrored requests that failed, labelled by reason (request or timeout)."
+    );
+    describe_gauge!(
+        PROX_WEBSOCKET_CONNECTIONS_OPEN,
+        "WebSocket connections currently proxied, labelled by route and backend target."
+    );
+    describe_counter!(
+        PROX_WEBSOCKET_HANDSHAKES_TOTAL,
+        Unit::Count,
+        "WebSocket handshakes, labelled by route and result (success or failure reason)."
+    );
+    describe_histogram!(
+        PROX_WEBSOCKET_CONNECTION_DURATION_SECONDS,
+        Unit::Seconds,
+        "Lifetime of proxied WebSocket connections, labelled by route and backend target."
+    );
+    describe_histogram!(
+        PROX_WEBSOCKET_CONNECTION_BYTES,
+        Unit::Bytes,
+        "Bytes relayed per WebSocket connection, labelled by route and direction."
+    );
+    describe_gauge!(
+        PROX_OCSP_STAPLE_AGE_SECONDS,
+        Unit::Seconds,
+        "Age of the stapled OCSP response, labelled by certificate file."
+    );
+    describe_gauge!(
+        PROX_HTTP3_CONNECTIONS,
+        "QUIC connections currently held by the HTTP/3 listener."
+    );
+    describe_counter!(
+        PROX_HTTP3_HANDSHAKES_TOTAL,
+        Unit::Count,
+        "QUIC handshakes, labelled by result (success or failure)."
+    );
+    describe_counter!(
+        PROX_HTTP3_PACKETS_TOTAL,
+        Unit::Count,
+        "QUIC packets, labelled by direction (received or sent)."
+    );
+    describe_counter!(
+        PROX_HTTP3_STREAMS_TOTAL,
+        Unit::Count,
+        "HTTP/3 request streams opened by clients."
+    );
+    describe_gauge!(
+        PROX_ACTIVE_CONNECTIONS,
+        Unit::Count,
+        "Client connections currently tracked."
+    );
+    describe_gauge!(
+        PROX_IDLE_CONNECTIONS,
+        Unit::Count,
+        "Tracked client connections with no request in flight."
+    );
+    describe_gauge!(
+        PROX_ACTIVE_REQUESTS,
+        Unit::Count,
+        "Requests currently in flight."
+    );
+    describe_gauge!(
+        PROX_OLDEST_CONNECTION_AGE_SECONDS,
+        Unit::Seconds,
+        "Age of the oldest tracked client connection."
+    );
+    describe_gauge!(
+        PROX_UPTIME_SECONDS,
+        Unit::Seconds,
+        "Time since the proxy started."
+    );
+    describe_gauge!(
+        PROX_CONFIG_VERSION,
+        Unit::Count,
+        "Configs loaded since startup, counting the initial one."
+    );
+    describe_counter!(
+        PROX_ACCESS_LOG_DROPPED_TOTAL,
+        Unit::Count,
+        "Access log lines dropped because the writer fell behind."
+    );
+    describe_counter!(
+        PROX_UPSTREAM_ERRORS_TOTAL,
+        Unit::Count,
+        "Proxied requests that failed or got a 5xx from the backend, labelled by route, backend and cause."
+    );
+    describe_gauge!(
+        PROX_SHUTDOWN_DRAIN_REMAINING_SECONDS,
+        Unit::Seconds,
+        "Time left before the shutdown drain gives up on in-flight requests; 0 when not draining."
+    );
+    describe_counter!(
+        PROX_CONNECTIONS_CLOSED_TOTAL,
+        Unit::Count,
+        "Client connections prox asked to close, labelled by reason."
     );
     Mutex::new(HashMap::new())
 });
@@ -51,25 +239,131 @@ pub fn set_backend_health_status(backend_id: &str, is_healthy: bool) {
 
     let backend_label = backend_id.to_string();
     gauge!(PROX_BACKEND_HEALTH_STATUS, "backend" => backend_label).set(health_value);
+    gauge!(PROX_BACKEND_HEALTHY, "target" => backend_id.to_string()).set(health_value);
+}
+
+/// Flag whether `target`'s health is manually forced
+pub fn set_backend_health_override(target: &str, overridden: bool) {
+    gauge!(PROX_BACKEND_HEALTH_OVERRIDDEN, "target" => target.to_string()).set(if overridden {
+        1.0
+    } else {
+        0.0
+    });
+}
+
+/// Record one active health check of `target`
+pub fn record_health_check(target: &str, success: bool, duration: std::time::Duration) {
+    let result = if success { "success" } else { "failure" };
+    counter!(PROX_HEALTH_CHECKS_TOTAL, "target" => target.to_string(), "result" => result)
+        .increment(1);
+    histogram!(PROX_HEALTH_CHECK_DURATION_SECONDS, "target" => target.to_string())
+        .record(duration.as_secs_f64());
+}
+
+/// Count a backend becoming healthy or unhealthy; `source` is "active", "passive" or
+/// "manual"
+pub fn increment_backend_health_transition(backend: &str, source: &str, is_healthy: bool) {
+    let status = if is_healthy { "healthy" } else { "unhealthy" };
+    counter!(
+        PROX_BACKEND_HEALTH_TRANSITIONS_TOTAL,
+        "backend" => backend.to_string(),
+        "source" => source.to_string(),
+        "status" => status
+    )
+    .increment(1);
+}
+
+/// Refresh the connection and process gauges, right before a scrape
+pub fn set_server_stats(stats: &ConnectionStats, uptime: std::time::Duration, config_version: u64) {
+    gauge!(PROX_ACTIVE_CONNECTIONS).set(stats.total_connections as f64);
+    gauge!(PROX_IDLE_CONNECTIONS).set(stats.idle_connections as f64);
+    gauge!(PROX_ACTIVE_REQUESTS).set(stats.total_active_requests as f64);
+    gauge!(PROX_OLDEST_CONNECTION_AGE_SECONDS).set(
+        stats
+            .oldest_connection_age
+            .map_or(0.0, |age| age.as_secs_f64()),
+    );
+    gauge!(PROX_UPTIME_SECONDS).set(uptime.as_secs_f64());
+    gauge!(PROX_CONFIG_VERSION).set(config_version as f64);
+}
+
+/// Time left in the connection drain during shutdown
+pub fn set_shutdown_drain_remaining(remaining: std::time::Duration) {
+    gauge!(PROX_SHUTDOWN_DRAIN_REMAINING_SECONDS).set(remaining.as_secs_f64());
+}
+
+/// Count a client connection prox asked to close for `reason`
+pub fn increment_connection_closed(reason: &'static str) {
+    counter!(PROX_CONNECTIONS_CLOSED_TOTAL, "reason" => reason).increment(1);
+}
+
+/// Count an access log line dropped because the writer's queue was full
+pub fn increment_access_log_dropped() {
+    counter!(PROX_ACCESS_LOG_DROPPED_TOTAL).increment(1);
+}
+
+pub fn increment_upstream_error(route: &str, backend: &str, cause: &'static str) {
+    counter!(
+        PROX_UPSTREAM_ERRORS_TOTAL,
+        "route" => route.to_string(),
+        "backend" => backend.to_string(),
+        "cause" => cause
+    )
+    .increment(1);
 }
 
 // --- Helper functions for new metrics ---
 
-pub fn increment_request_total(path: &str, method: &str, status: u16) {
+/// `protocol` label for requests received over `version`: `http1`, `h2` or `h3`
+pub fn protocol_label(version: http::Version) -> &'static str {
+    match version {
+        http::Version::HTTP_2 => "h2",
+        http::Version::HTTP_3 => "h3",
+        _ => "http1",
+    }
+}
+
+pub fn increment_request_total(path: &str, method: &str, status: u16, protocol: &'static str) {
     counter!(
         PROX_REQUESTS_TOTAL,
         "path" => path.to_string(),
         "method" => method.to_string(),
-        "status" => status.to_string()
+        "status" => status.to_string(),
+        "protocol" => protocol
     )
     .increment(1);
 }
 
-pub fn record_request_duration(path: &str, method: &str, duration: std::time::Duration) {
+pub fn record_request_duration(
+    path: &str,
+    method: &str,
+    protocol: &'static str,
+    duration: std::time::Duration,
+) {
     histogram!(
         PROX_REQUEST_DURATION_SECONDS,
         "path" => path.to_string(),
-        "method" => method.to_string()
+        "method" => method.to_string(),
+        "protocol" => protocol
+    )
+    .record(duration.as_secs_f64());
+}
+
+/// Record a request's latency under the key of the route that served it, or
+/// `UNMATCHED_ROUTE` when none did
+pub fn record_route_request_duration(
+    route: &str,
+    method: &str,
+    status: u16,
+    protocol: &'static str,
+    duration: std::time::Duration,
+) {
+    histogram!(
+        PROX_ROUTE_REQUEST_DURATION_SECONDS,
+        "route" => route.to_string(),
+        "method" => method.to_string(),
+        "status_class" => format!("{}xx", status / 100),
+        "protocol" => protocol
     )
     .record(duration.as_secs_f64());
 }
@@ -85,69 +379,206 @@ pub fn increment_backend_request_total(backend: &str, path: &str, method: &str,
     .increment(1);
 }
 
-pub fn record_backend_request_duration(
-    backend: &str,
-    path: &str,
-    method: &str,
-    duration: std::time::Duration,
-) {
+pub fn record_backend_request_duration(backend: &str, route: &str, duration: std::time::Duration) {
     histogram!(
         PROX_BACKEND_REQUEST_DURATION_SECONDS,
         "backend" => backend.to_string(),
-        "path" => path.to_string(),
-        "method" => method.to_string()
+        "route" => route.to_string()
+    )
+    .record(duration.as_secs_f64());
+}
+
+pub fn increment_origin_rejection(route: &str, reason: &str) {
+    counter!(
+        PROX_ORIGIN_REJECTIONS_TOTAL,
+        "route" => route.to_string(),
+        "reason" => reason.to_string()
+    )
+    .increment(1);
+}
+
+pub fn increment_static_cache_request(root: &str, hit: bool) {
+    counter!(
+        PROX_STATIC_CACHE_REQUESTS_TOTAL,
+        "root" => root.to_string(),
+        "result" => if hit { "hit" } else { "miss" }
+    )
+    .increment(1);
+}
+
+pub fn record_split_request(
+    route: &str,
+    variant: &str,
+    status: u16,
+    duration: std::time::Duration,
+) {
+    counter!(
+        PROX_SPLIT_REQUESTS_TOTAL,
+        "route" => route.to_string(),
+        "variant" => variant.to_string(),
+        "status_class" => format!("{}xx", status / 100)
+    )
+    .increment(1);
+    histogram!(
+        PROX_SPLIT_REQUEST_DURATION_SECONDS,
+        "route" => route.to_string(),
+        "variant" => variant.to_string()
+    )
+    .record(duration.as_secs_f64());
+}
+
+pub fn increment_mirror_request(target: &str) {
+    counter!(PROX_MIRROR_REQUESTS_TOTAL, "target" => target.to_string()).increment(1);
+}
+
+pub fn increment_mirror_error(target: &str, reason: &str) {
+    counter!(
+        PROX_MIRROR_ERRORS_TOTAL,
+        "target" => target.to_string(),
+        "reason" => reason.to_string()
+    )
+    .increment(1);
+}
+
+pub fn websocket_connection_opened(route: &str, target: &str) {
+    gauge!(
+        PROX_WEBSOCKET_CONNECTIONS_OPEN,
+        "route" => route.to_string(),
+        "target" => target.to_string()
+    )
+    .increment(1.0);
+}
+
+pub fn websocket_connection_closed(route: &str, target: &str) {
+    gauge!(
+        PROX_WEBSOCKET_CONNECTIONS_OPEN,
+        "route" => route.to_string(),
+        "target" => target.to_string()
+    )
+    .decrement(1.0);
+}
+
+pub fn increment_websocket_handshake(route: &str, result: &str) {
+    counter!(
+        PROX_WEBSOCKET_HANDSHAKES_TOTAL,
+        "route" => route.to_string(),
+        "result" => result.to_string()
+    )
+    .increment(1);
+}
+
+pub fn record_websocket_connection(
+    route: &str,
+    target: &str,
+    duration: std::time::Duration,
+    from_client: u64,
+    from_backend: u64,
+) {
+    histogram!(
+        PROX_WEBSOCKET_CONNECTION_DURATION_SECONDS,
+        "route" => route.to_string(),
+        "target" => target.to_string()
     )
     .record(duration.as_secs_f64());
+    histogram!(
+        PROX_WEBSOCKET_CONNECTION_BYTES,
+        "route" => route.to_string(),
+        "direction" => "client_to_backend"
+    )
+    .record(from_client as f64);
+    histogram!(
+        PROX_WEBSOCKET_CONNECTION_BYTES,
+        "route" => route.to_string(),
+        "direction" => "backend_to_client"
+    )
+    .record(from_backend as f64);
+}
+
+pub fn set_ocsp_staple_age(certificate: &str, age: std::time::Duration) {
+    gauge!(PROX_OCSP_STAPLE_AGE_SECONDS, "certificate" => certificate.to_string())
+        .set(age.as_secs_f64());
+}
+
+pub fn set_http3_connections(count: usize) {
+    gauge!(PROX_HTTP3_CONNECTIONS).set(count as f64);
+}
+
+pub fn increment_http3_handshake(success: bool) {
+    let result = if success { "success" } else { "failure" };
+    counter!(PROX_HTTP3_HANDSHAKES_TOTAL, "result" => result).increment(1);
+}
+
+pub fn increment_http3_packets_received(count: u64) {
+    counter!(PROX_HTTP3_PACKETS_TOTAL, "direction" => "received").increment(count);
+}
+
+pub fn increment_http3_packets_sent(count: u64) {
+    counter!(PROX_HTTP3_PACKETS_TOTAL, "direction" => "sent").increment(count);
+}
+
+pub fn increment_http3_streams() {
+    counter!(PROX_HTTP3_STREAMS_TOTAL).increment(1);
 }
 
 // Helper struct for measuring duration easily using RAII
+/// Route label for requests no route served
+pub const UNMATCHED_ROUTE: &str = "(unmatched)";
+
 pub struct RequestTimer {
     start: Instant,
     path: String,
     method: String,
+    protocol: &'static str,
+    /// Matched route key and response status, once the response is known
+    outcome: Option<(String, u16)>,
 }
 
 impl RequestTimer {
-    pub fn new(path: &str, method: &str) -> Self {
+    pub fn new(path: &str, method: &str, protocol: &'static str) -> Self {
         Self {
             start: Instant::now(),
             path: path.to_string(),
             method: method.to_string(),
+            protocol,
+            outcome: None,
         }
     }
+
+    /// Also record the request under `route` (`None` if unmatched) and `status`
+    pub fn set_outcome(&mut self, route: Option<&str>, status: u16) {
+        self.outcome = Some((route.unwrap_or(UNMATCHED_ROUTE).to_string(), status));
+    }
 }
 
 impl Drop for RequestTimer {
     fn drop(&mut self) {
-        record_request_duration(&self.path, &self.method, self.start.elapsed());
+        let elapsed = self.start.elapsed();
+        record_request_duration(&self.path, &self.method, self.protocol, elapsed);
+        if let Some((route, status)) = &self.outcome {
+            record_route_request_duration(route, &self.method, *status, self.protocol, elapsed);
+        }
     }
 }
 
 pub struct BackendRequestTimer {
     start: Instant,
     backend: String,
-    path: String,
-    method: String,
+    route: String,
 }
 
 impl BackendRequestTimer {
-    pub fn new(backend: &str, path: &str, method: &str) -> Self {
+    /// `route` is the key of the route forwarding the request, `None` if it has none
+    pub fn new(backend: &str, route: Option<&str>) -> Self {
         Self {
             start: Instant::now(),
             backend: backend.to_string(),
-            path: path.to_string(),
-            method: method.to_string(),
+            route: route.unwrap_or(UNMATCHED_ROUTE).to_string(),
         }
     }
 }
 
 impl Drop for BackendRequestTimer {
     fn drop(&mut self) {
-        record_backend_request_duration(
-            &self.backend,
-            &self.path,
-            &self.method,
-            self.start.elapsed(),
-        );
+        record_backend_request_duration(&self.backend, &self.route, self.start.elapsed());
     }
 }
@@ -3,6 +3,8 @@ use axum::body::Body as AxumBody;
 use hyper::{Request, Response};
 use thiserror::Error;
 
+use crate::config::StaticOptions;
+
 /// Error type for file system operations
 #[derive(Error, Debug)]
 #[non_exhaustive]
@@ -27,6 +29,7 @@ pub trait FileSystem: Send + Sync + 'static {
     /// * `root` - The root directory to serve files from
     /// * `path` - The path to the file relative to the root
     /// * `req` - The original HTTP request
+    /// * `options` - The static route's file-serving settings
     ///
     /// # Returns
     /// A future that resolves to the file response or an error
@@ -35,5 +38,6 @@ pub trait FileSystem: Send + Sync + 'static {
         root: &str,
         path: &str,
         req: Request<AxumBody>,
+        options: &StaticOptions,
     ) -> impl std::future::Future<Output = FileSystemResult<Response<AxumBody>>> + Send;
 }
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use axum::body::Body as AxumBody;
-use hyper::{Request, Response, StatusCode};
+use bytes::Bytes;
+use hyper::{HeaderMap, Method, Request, Response, StatusCode};
 use thiserror::Error;
 
 /// Custom error type for HTTP client operations
@@ -11,6 +12,14 @@ pub enum HttpClientError {
     #[error("Connection error: {0}")]
     ConnectionError(String),
 
+    /// Error when the backend refuses the connection
+    #[error("Connection refused: {0}")]
+    ConnectionRefused(String),
+
+    /// Error when the TLS handshake with the backend fails
+    #[error("TLS error: {0}")]
+    TlsError(String),
+
     /// Error when request times out
     #[error("Timeout error after {0} seconds")]
     TimeoutError(u64),
@@ -29,9 +38,31 @@ pub enum HttpClientError {
     },
 }
 
+impl HttpClientError {
+    /// Short label for what went wrong, used as the `cause` of `prox_upstream_errors_total`
+    pub fn cause(&self) -> &'static str {
+        match self {
+            HttpClientError::ConnectionError(_) => "connection",
+            HttpClientError::ConnectionRefused(_) => "connect_refused",
+            HttpClientError::TlsError(_) => "tls",
+            HttpClientError::TimeoutError(_) => "timeout",
+            HttpClientError::InvalidRequestError(_) => "invalid_request",
+            HttpClientError::BackendError { .. } => "backend_error",
+        }
+    }
+}
+
 /// Result type alias for HTTP client operations
 pub type HttpClientResult<T> = Result<T, HttpClientError>;
 
+/// What a backend answered a health check with
+#[derive(Debug, Clone)]
+pub struct HealthCheckResponse {
+    pub status: StatusCode,
+    /// The start of the response body, bounded so a large body can't stall the check
+    pub body: Bytes,
+}
+
 /// HttpClient defines the port (interface) for making HTTP requests to backends
 pub trait HttpClient: Send + Sync + 'static {
     /// Send an HTTP request to a backend server
@@ -50,13 +81,18 @@ pub trait HttpClient: Send + Sync + 'static {
     ///
     /// # Arguments
     /// * `url` - The URL to check
-    /// * `timeout_secs` - Timeout in seconds
+    /// * `method` - The method to request it with
+    /// * `headers` - Extra headers to send, e.g. credentials the health endpoint needs
+    /// * `timeout_secs` - Timeout in seconds, covering the body read
     ///
     /// # Returns
-    /// A future that resolves to true if the backend is healthy, false otherwise
+    /// A future that resolves to the response status and body prefix, or an error when
+    /// the backend couldn't be reached in time
     fn health_check(
         &self,
         url: &str,
+        method: Method,
+        headers: &HeaderMap,
         timeout_secs: u64,
-    ) -> impl std::future::Future<Output = HttpClientResult<bool>> + Send;
+    ) -> impl std::future::Future<Output = HttpClientResult<HealthCheckResponse>> + Send;
 }
@@ -1,22 +1,335 @@
-use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt, util::SubscriberInitExt};
+use std::sync::OnceLock;
+use std::time::Duration;
 
-pub fn init_tracing() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    tracing::info!("Initializing structured logging with JSON output");
+use http::{HeaderMap, HeaderName, HeaderValue};
+use opentelemetry::propagation::text_map_propagator::FieldIter;
+use opentelemetry::propagation::{
+    Extractor, Injector, TextMapCompositePropagator, TextMapPropagator,
+};
+use opentelemetry::trace::{
+    SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState, TracerProvider as _,
+};
+use opentelemetry::{Context, global};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use tracing::Level;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::{ParseError, Targets};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, layer::SubscriberExt, reload, util::SubscriberInitExt,
+};
+
+use crate::config::{LogFormat, TelemetryConfig};
+
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+/// Swaps the log filter when `telemetry.log_level` changes
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+pub fn init_tracing(
+    config: &TelemetryConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Gives spans W3C trace and span IDs, so requests join their caller's trace
+    let mut provider = SdkTracerProvider::builder()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sampling_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        );
+    if let Some(otlp) = &config.otlp {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(&otlp.endpoint)
+            .with_headers(otlp.headers.0.clone())
+            .with_timeout(Duration::from_secs(otlp.timeout_secs))
+            .build()?;
+        provider = provider.with_batch_exporter(exporter);
+    }
+    let provider = provider.build();
+    let tracer = provider.tracer("prox");
+    global::set_tracer_provider(provider.clone());
+    let _ = TRACER_PROVIDER.set(provider);
+    set_propagator(config);
+
+    let (log_filter, log_filter_handle) = reload::Layer::new(log_filter(config)?);
+    let _ = LOG_FILTER.set(log_filter_handle);
+    let log_layer = match config.log_format {
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+    };
 
     Registry::default()
-        .with(EnvFilter::from_default_env())
+        .with(log_layer.with_filter(log_filter))
+        // Request spans must exist whatever the log level, or there is no context to propagate
         .with(
-            tracing_subscriber::fmt::layer()
-                .json()
-                .with_current_span(false)
-                .with_span_list(true),
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer)
+                .with_filter(Targets::new().with_target("prox", Level::INFO)),
         )
         .init();
 
-    tracing::info!("Structured logging initialized successfully");
+    tracing::info!(
+        "Logging initialized ({:?} format); {}",
+        config.log_format,
+        match &config.otlp {
+            Some(otlp) => format!(
+                "exporting {:.0}% of new traces to {}",
+                config.sampling_ratio * 100.0,
+                otlp.endpoint
+            ),
+            None => "trace export disabled".to_string(),
+        }
+    );
     Ok(())
 }
 
+/// Apply the parts of a reloaded `telemetry` section that can change at runtime
+pub fn apply_config(config: &TelemetryConfig) {
+    set_propagator(config);
+    let Some(handle) = LOG_FILTER.get() else {
+        return;
+    };
+    // Validated with the config
+    match log_filter(config) {
+        Ok(filter) => {
+            if let Err(e) = handle.reload(filter) {
+                tracing::error!("Failed to update the log level: {}", e);
+            }
+        }
+        Err(e) => tracing::error!("Invalid log level, keeping the current one: {}", e),
+    }
+}
+
+/// `telemetry.log_level` if set, RUST_LOG otherwise
+fn log_filter(config: &TelemetryConfig) -> Result<EnvFilter, ParseError> {
+    match &config.log_level {
+        Some(level) => EnvFilter::try_new(level),
+        None => Ok(EnvFilter::from_default_env()),
+    }
+}
+
+fn set_propagator(config: &TelemetryConfig) {
+    if config.b3_propagation {
+        // Later propagators win on extraction, so W3C headers take precedence over B3
+        global::set_text_map_propagator(TextMapCompositePropagator::new(vec![
+            Box::new(B3Propagator::new()),
+            Box::new(TraceContextPropagator::new()),
+        ]));
+    } else {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+    }
+}
+
+/// Trace context sent by the client, to parent the request's span to
+pub fn extract_trace_context(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+/// Add `span`'s trace context to an outgoing request's headers
+pub fn inject_trace_context(span: &tracing::Span, headers: &mut HeaderMap) {
+    let context = span.context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
 pub fn shutdown_tracing() {
+    if let Some(provider) = TRACER_PROVIDER.get()
+        && let Err(e) = provider.shutdown()
+    {
+        tracing::warn!("Failed to shut down tracer provider: {}", e);
+    }
     tracing::info!("Tracing shutdown complete");
 }
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        // An empty `tracestate` says nothing
+        if value.is_empty() {
+            return;
+        }
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+const B3_SINGLE: &str = "b3";
+const B3_TRACE_ID: &str = "x-b3-traceid";
+const B3_SPAN_ID: &str = "x-b3-spanid";
+const B3_SAMPLED: &str = "x-b3-sampled";
+const B3_FLAGS: &str = "x-b3-flags";
+
+/// Zipkin B3 headers, reading either the single `b3` header or the `X-B3-*` set and
+/// writing the `X-B3-*` set
+#[derive(Debug)]
+struct B3Propagator {
+    fields: Vec<String>,
+}
+
+impl B3Propagator {
+    fn new() -> Self {
+        Self {
+            fields: [B3_TRACE_ID, B3_SPAN_ID, B3_SAMPLED]
+                .map(str::to_string)
+                .to_vec(),
+        }
+    }
+
+    fn extract_span_context(extractor: &dyn Extractor) -> Option<SpanContext> {
+        let (trace_id, span_id, sampled) = match extractor.get(B3_SINGLE) {
+            // {trace id}-{span id}[-{sampling}[-{parent span id}]]
+            Some(single) => {
+                let mut parts = single.split('-');
+                (parts.next()?, parts.next()?, parts.next())
+            }
+            None => (
+                extractor.get(B3_TRACE_ID)?,
+                extractor.get(B3_SPAN_ID)?,
+                extractor
+                    .get(B3_FLAGS)
+                    .filter(|flags| *flags == "1")
+                    .map(|_| "d")
+                    .or_else(|| extractor.get(B3_SAMPLED)),
+            ),
+        };
+
+        // 64-bit trace IDs are zero-extended to 128 bits
+        let trace_id = match trace_id.len() {
+            16 => TraceId::from_hex(&format!("{trace_id:0>32}")),
+            32 => TraceId::from_hex(trace_id),
+            _ => return None,
+        }
+        .ok()?;
+        let span_id = SpanId::from_hex(span_id)
+            .ok()
+            .filter(|_| span_id.len() == 16)?;
+        // Without a decision, sample rather than drop the caller's trace
+        let flags = match sampled {
+            Some("0") => TraceFlags::default(),
+            Some("1" | "d" | "true") | None => TraceFlags::SAMPLED,
+            Some(_) => return None,
+        };
+
+        let span_context = SpanContext::new(trace_id, span_id, flags, true, TraceState::NONE);
+        span_context.is_valid().then_some(span_context)
+    }
+}
+
+impl TextMapPropagator for B3Propagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        injector.set(B3_TRACE_ID, span_context.trace_id().to_string());
+        injector.set(B3_SPAN_ID, span_context.span_id().to_string());
+        let sampled = if span_context.is_sampled() { "1" } else { "0" };
+        injector.set(B3_SAMPLED, sampled.to_string());
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        match Self::extract_span_context(extractor) {
+            Some(span_context) => cx.with_remote_span_context(span_context),
+            None => cx.clone(),
+        }
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        FieldIter::new(&self.fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_static(name),
+                    HeaderValue::from_str(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_b3_extraction() {
+        let single = headers(&[("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1")]);
+        let span_context = B3Propagator::extract_span_context(&HeaderExtractor(&single)).unwrap();
+        assert_eq!(
+            span_context.trace_id().to_string(),
+            "80f198ee56343ba864fe8b2a57d3eff7"
+        );
+        assert_eq!(span_context.span_id().to_string(), "e457b5a2e4d86bd1");
+        assert!(span_context.is_sampled() && span_context.is_remote());
+
+        let multi = headers(&[
+            ("x-b3-traceid", "64fe8b2a57d3eff7"),
+            ("x-b3-spanid", "e457b5a2e4d86bd1"),
+            ("x-b3-sampled", "0"),
+        ]);
+        let span_context = B3Propagator::extract_span_context(&HeaderExtractor(&multi)).unwrap();
+        assert_eq!(
+            span_context.trace_id().to_string(),
+            "000000000000000064fe8b2a57d3eff7"
+        );
+        assert!(!span_context.is_sampled());
+
+        let malformed = headers(&[("b3", "not-a-trace")]);
+        assert!(B3Propagator::extract_span_context(&HeaderExtractor(&malformed)).is_none());
+    }
+
+    #[test]
+    fn test_w3c_round_trip() {
+        let propagator = TraceContextPropagator::new();
+        let incoming = headers(&[(
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        )]);
+        let cx = propagator.extract(&HeaderExtractor(&incoming));
+        assert_eq!(
+            cx.span().span_context().trace_id().to_string(),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+
+        let mut outgoing = HeaderMap::new();
+        propagator.inject_context(&cx, &mut HeaderInjector(&mut outgoing));
+        assert_eq!(
+            outgoing["traceparent"],
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+        );
+        B3Propagator::new().inject_context(&cx, &mut HeaderInjector(&mut outgoing));
+        assert_eq!(outgoing["x-b3-spanid"], "b7ad6b7169203331");
+        assert_eq!(outgoing["x-b3-sampled"], "1");
+    }
+}
@@ -0,0 +1,299 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use tokio::sync::{Mutex as TokioMutex, watch};
+use tokio::task::JoinHandle;
+
+use crate::adapters::http_client::HyperHttpClient;
+use crate::config::loader::{LoadOptions, load_config_with};
+use crate::config::models::ServerConfig;
+use crate::config::validation::ConfigValidator;
+use crate::core::ProxyService;
+use crate::core::config_history::ConfigSource;
+use crate::tracing_setup;
+use crate::utils::health_checker_utils::spawn_health_checker_task;
+use crate::utils::log_redaction;
+
+/// Swaps a new config into the running server. The file watcher, SIGHUP and the
+/// `/-/config` endpoints all go through here.
+#[derive(Clone)]
+pub struct ConfigReloader {
+    config_holder: Arc<RwLock<Arc<ServerConfig>>>,
+    proxy_service_holder: Arc<RwLock<Arc<ProxyService>>>,
+    http_client: Arc<HyperHttpClient>,
+    health_checker_handle: Arc<TokioMutex<Option<JoinHandle<()>>>>,
+    /// The file the server was started with, if any
+    config_path: Option<String>,
+    load_options: LoadOptions,
+    /// One reload at a time, so versions follow the order configs are installed in
+    reload_lock: Arc<TokioMutex<()>>,
+    /// The last applied config, for the listeners to pick up their settings
+    applied: watch::Sender<Arc<ServerConfig>>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        config_holder: Arc<RwLock<Arc<ServerConfig>>>,
+        proxy_service_holder: Arc<RwLock<Arc<ProxyService>>>,
+        http_client: Arc<HyperHttpClient>,
+        health_checker_handle: Arc<TokioMutex<Option<JoinHandle<()>>>>,
+    ) -> Self {
+        let applied = watch::Sender::new(
+            config_holder
+                .read()
+                .map(|config| config.clone())
+                .unwrap_or_else(|poisoned| poisoned.into_inner().clone()),
+        );
+        Self {
+            config_holder,
+            proxy_service_holder,
+            http_client,
+            health_checker_handle,
+            config_path: None,
+            load_options: LoadOptions::default(),
+            reload_lock: Arc::new(TokioMutex::new(())),
+            applied,
+        }
+    }
+
+    /// Reload from `config_path` in `reload_from_file`
+    pub fn with_config_path(mut self, config_path: impl Into<String>) -> Self {
+        self.config_path = Some(config_path.into());
+        self
+    }
+
+    /// Read the config file with `load_options`, as it was at startup
+    pub fn with_load_options(mut self, load_options: LoadOptions) -> Self {
+        self.load_options = load_options;
+        self
+    }
+
+    pub fn config_path(&self) -> Option<&str> {
+        self.config_path.as_deref()
+    }
+
+    /// Every config applied from now on
+    pub fn subscribe(&self) -> watch::Receiver<Arc<ServerConfig>> {
+        self.applied.subscribe()
+    }
+
+    /// Re-read and validate the config file, then apply it
+    pub async fn reload_from_file(&self) -> Result<Arc<ProxyService>> {
+        let config_path = self
+            .config_path
+            .as_deref()
+            .ok_or_else(|| anyhow!("The server was not started from a config file"))?;
+        tracing::info!("Reloading configuration from {}", config_path);
+        // `ConfigError` already includes its source in the message
+        let config = load_config_with(config_path, self.load_options.clone())
+            .await
+            .map_err(|e| anyhow!("Failed to load {config_path}: {e}"))?;
+        self.apply(config, ConfigSource::File).await
+    }
+
+    /// Install an already validated `config`: swap the config and proxy service, which
+    /// keeps the health of shared backends, and restart the health checker
+    pub async fn apply(
+        &self,
+        config: ServerConfig,
+        source: ConfigSource,
+    ) -> Result<Arc<ProxyService>> {
+        let _reloading = self.reload_lock.lock().await;
+        for warning in ConfigValidator::warnings(&config) {
+            tracing::warn!("Configuration warning: {}", warning);
+        }
+        tracing_setup::apply_config(&config.telemetry);
+        log_redaction::configure(&config.log_redaction);
+        let config = Arc::new(config);
+
+        let previous_config = std::mem::replace(
+            &mut *self
+                .config_holder
+                .write()
+                .map_err(|e| anyhow!("Failed to acquire config write lock: {}", e))?,
+            config.clone(),
+        );
+        for field in restart_required_changes(&previous_config, &config) {
+            tracing::warn!(
+                "{} changed, but only takes effect after a restart; the running server keeps the previous value",
+                field
+            );
+        }
+
+        let previous_proxy_service = self
+            .proxy_service_holder
+            .read()
+            .map_err(|e| anyhow!("Failed to acquire proxy service read lock: {}", e))?
+            .clone();
+        let proxy_service = Arc::new(previous_proxy_service.reload(config.clone(), source));
+        *self
+            .proxy_service_holder
+            .write()
+            .map_err(|e| anyhow!("Failed to acquire proxy service write lock: {}", e))? =
+            proxy_service.clone();
+        proxy_service.log_route_order();
+
+        let mut handle_guard = self.health_checker_handle.lock().await;
+        if let Some(old_handle) = handle_guard.take() {
+            old_handle.abort();
+        }
+        if config.health_check.enabled {
+            *handle_guard = Some(spawn_health_checker_task(
+                proxy_service.clone(),
+                self.http_client.clone(),
+                config.clone(),
+                format!("Config version {}", proxy_service.config_version()),
+            ));
+        }
+
+        tracing::info!(
+            "Applied configuration version {} ({:?})",
+            proxy_service.config_version(),
+            source
+        );
+        // The TCP listener restarts itself for `listen_addr`, `tls` and `http2_enabled`
+        self.applied.send_replace(config);
+        Ok(proxy_service)
+    }
+}
+
+/// Settings that differ between `previous` and `config` but are only read at startup
+fn restart_required_changes(previous: &ServerConfig, config: &ServerConfig) -> Vec<&'static str> {
+    fn differs<T: Serialize>(a: &T, b: &T) -> bool {
+        serde_json::to_value(a).ok() != serde_json::to_value(b).ok()
+    }
+    let (old, new) = (&previous.protocols, &config.protocols);
+    let (old_telemetry, new_telemetry) = (&previous.telemetry, &config.telemetry);
+    [
+        (
+            "protocols.http3_enabled",
+            old.http3_enabled != new.http3_enabled,
+        ),
+        (
+            "protocols.http3_listen_addr",
+            old.http3_listen_addr != new.http3_listen_addr,
+        ),
+        (
+            "protocols.http3_config",
+            differs(&old.http3_config, &new.http3_config),
+        ),
+        // The HTTP/3 listener loads its certificate once
+        (
+            "tls (for HTTP/3)",
+            old.http3_enabled && differs(&previous.tls, &config.tls),
+        ),
+        (
+            "admin.listen_addr",
+            previous.admin.listen_addr != config.admin.listen_addr,
+        ),
+        ("admin.tls", differs(&previous.admin.tls, &config.admin.tls)),
+        (
+            "access_log",
+            differs(&previous.access_log, &config.access_log),
+        ),
+        ("metrics", differs(&previous.metrics, &config.metrics)),
+        ("shutdown", differs(&previous.shutdown, &config.shutdown)),
+        (
+            "telemetry.service_name",
+            old_telemetry.service_name != new_telemetry.service_name,
+        ),
+        (
+            "telemetry.otlp",
+            differs(&old_telemetry.otlp, &new_telemetry.otlp),
+        ),
+        (
+            "telemetry.sampling_ratio",
+            old_telemetry.sampling_ratio != new_telemetry.sampling_ratio,
+        ),
+        (
+            "telemetry.log_format",
+            old_telemetry.log_format != new_telemetry.log_format,
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(field, changed)| changed.then_some(field))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reload_from_file_swaps_config_and_service() {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let write_config = |target: &str| {
+            std::fs::write(
+                &path,
+                format!(
+                    "listen_addr: \"127.0.0.1:8080\"\nroutes:\n  \"/\": {{ type: proxy, target: \"{target}\" }}\n"
+                ),
+            )
+            .unwrap()
+        };
+        write_config("http://a:8080");
+        let config = Arc::new(
+            load_config_with(&path, LoadOptions::default())
+                .await
+                .unwrap(),
+        );
+        let reloader = ConfigReloader::new(
+            Arc::new(RwLock::new(config.clone())),
+            Arc::new(RwLock::new(Arc::new(ProxyService::new(config)))),
+            Arc::new(HyperHttpClient::new()),
+            Arc::new(TokioMutex::new(None)),
+        );
+        assert!(reloader.reload_from_file().await.is_err());
+
+        let reloader = reloader.with_config_path(path.to_string_lossy());
+        let mut applied = reloader.subscribe();
+        write_config("http://b:8080");
+        let service = reloader.reload_from_file().await.unwrap();
+        assert!(applied.has_changed().unwrap());
+        assert!(Arc::ptr_eq(&applied.borrow_and_update(), service.config()));
+        assert_eq!(service.config_version(), 2);
+        assert_eq!(
+            ProxyService::collect_backends(service.config()),
+            ["http://b:8080"]
+        );
+        let installed = reloader.config_holder.read().unwrap().clone();
+        assert!(Arc::ptr_eq(&installed, service.config()));
+
+        // An invalid file leaves the running config alone
+        write_config("not a url");
+        assert!(reloader.reload_from_file().await.is_err());
+        assert_eq!(
+            reloader
+                .proxy_service_holder
+                .read()
+                .unwrap()
+                .config_version(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_restart_required_changes() {
+        let config: ServerConfig = serde_yaml::from_str(
+            "listen_addr: \"127.0.0.1:8080\"\nroutes:\n  \"/\": { type: proxy, target: \"http://a:8080\" }\n",
+        )
+        .unwrap();
+        let mut changed = config.clone();
+        // Applied on reload, by the proxy service or by restarting the TCP listener
+        changed.listen_addr = "127.0.0.1:9090".to_string();
+        changed.protocols.http2_enabled = false;
+        changed.telemetry.log_level = Some("debug".to_string());
+        assert!(restart_required_changes(&config, &changed).is_empty());
+
+        changed.protocols.http3_enabled = true;
+        changed.admin.listen_addr = Some("127.0.0.1:9901".to_string());
+        changed.shutdown.drain_timeout = "5s".to_string();
+        assert_eq!(
+            restart_required_changes(&config, &changed),
+            ["protocols.http3_enabled", "admin.listen_addr", "shutdown"]
+        );
+    }
+}
@@ -1,15 +1,38 @@
 use std::net::SocketAddr;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
-use tokio::sync::broadcast;
+use tokio::sync::{Notify, broadcast};
 use tokio::time::sleep;
 
+use crate::metrics;
+
 /// Unique identifier for a connection
 pub type ConnectionId = u64;
 
+/// Why prox asked a connection to close
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// Open for `connection_limits.max_connection_age`
+    MaxAge,
+    /// Served `connection_limits.max_requests_per_connection` requests
+    MaxRequests,
+    /// Its listener was replaced to apply new settings
+    ListenerRestart,
+}
+
+impl CloseReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CloseReason::MaxAge => "max_age",
+            CloseReason::MaxRequests => "max_requests",
+            CloseReason::ListenerRestart => "listener_restart",
+        }
+    }
+}
+
 /// Information about an active connection
 #[derive(Debug)]
 pub struct ConnectionInfo {
@@ -17,6 +40,10 @@ pub struct ConnectionInfo {
     pub remote_addr: SocketAddr,
     pub established_at: Instant,
     pub active_requests: AtomicU64,
+    /// Requests received over the connection's life
+    pub requests_served: AtomicU64,
+    close_reason: OnceLock<CloseReason>,
+    close_requested: Notify,
 }
 
 impl Clone for ConnectionInfo {
@@ -26,6 +53,9 @@ impl Clone for ConnectionInfo {
             remote_addr: self.remote_addr,
             established_at: self.established_at,
             active_requests: AtomicU64::new(self.active_requests.load(Ordering::Relaxed)),
+            requests_served: AtomicU64::new(self.requests_served.load(Ordering::Relaxed)),
+            close_reason: self.close_reason.clone(),
+            close_requested: Notify::new(),
         }
     }
 }
@@ -37,6 +67,46 @@ impl ConnectionInfo {
             remote_addr,
             established_at: Instant::now(),
             active_requests: AtomicU64::new(0),
+            requests_served: AtomicU64::new(0),
+            close_reason: OnceLock::new(),
+            close_requested: Notify::new(),
+        }
+    }
+
+    /// Count a request received on the connection, returning how many it has had
+    pub fn record_request(&self) -> u64 {
+        self.requests_served.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Ask whoever serves the connection to close it once its requests are done. Only the
+    /// first reason counts; returns whether this call set it.
+    pub fn request_close(&self, reason: CloseReason) -> bool {
+        if self.close_reason.set(reason).is_err() {
+            return false;
+        }
+        tracing::debug!(
+            "Closing connection: id={}, remote_addr={}, reason={}",
+            self.id,
+            self.remote_addr,
+            reason.as_str()
+        );
+        metrics::increment_connection_closed(reason.as_str());
+        self.close_requested.notify_one();
+        true
+    }
+
+    /// Why the connection is closing, if it is
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.close_reason.get().copied()
+    }
+
+    /// Wait until the connection is asked to close
+    pub async fn closing(&self) -> CloseReason {
+        loop {
+            if let Some(reason) = self.close_reason() {
+                return reason;
+            }
+            self.close_requested.notified().await;
         }
     }
 
@@ -188,8 +258,10 @@ impl ConnectionTracker {
         while start.elapsed() < timeout {
             let active_requests = self.total_active_requests();
             let connection_count = self.active_connection_count();
+            metrics::set_shutdown_drain_remaining(timeout.saturating_sub(start.elapsed()));
 
             if active_requests == 0 {
+                metrics::set_shutdown_drain_remaining(Duration::ZERO);
                 tracing::info!(
                     "All connections drained successfully in {:?} ({} connections remain idle)",
                     start.elapsed(),
@@ -228,6 +300,7 @@ impl ConnectionTracker {
             }
         }
 
+        metrics::set_shutdown_drain_remaining(Duration::ZERO);
         let remaining_requests = self.total_active_requests();
         let remaining_connections = self.active_connection_count();
 
@@ -432,4 +505,16 @@ mod tests {
 
         conn_info.decrement_requests();
     }
+
+    #[tokio::test]
+    async fn test_close_request_keeps_first_reason() {
+        let tracker = ConnectionTracker::new();
+        let conn_info = tracker.register_connection(test_addr());
+        assert_eq!(conn_info.close_reason(), None);
+        assert_eq!(conn_info.record_request(), 1);
+
+        assert!(conn_info.request_close(CloseReason::MaxRequests));
+        assert!(!conn_info.request_close(CloseReason::MaxAge));
+        assert_eq!(conn_info.closing().await, CloseReason::MaxRequests);
+    }
 }
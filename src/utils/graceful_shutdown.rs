@@ -1,12 +1,14 @@
-use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use tokio::signal;
 use tokio::sync::broadcast;
 use tokio::time::timeout;
 
+use crate::config::models::ShutdownConfig;
+
 /// Represents different shutdown reasons
 #[derive(Debug, Clone)]
 pub enum ShutdownReason {
@@ -24,8 +26,16 @@ pub struct GracefulShutdown {
     shutdown_tx: broadcast::Sender<ShutdownReason>,
     /// Flag indicating if shutdown has been initiated
     shutdown_initiated: Arc<AtomicBool>,
-    /// Maximum time to wait for graceful shutdown
+    /// When shutdown was initiated
+    initiated_at: Arc<OnceLock<Instant>>,
+    /// Maximum time to wait for graceful shutdown, and for in-flight requests to drain
     shutdown_timeout: Duration,
+    /// How long the listeners keep serving normally before draining
+    grace_period: Duration,
+    /// When to stop waiting for the listeners altogether
+    force_close_after: Option<Duration>,
+    /// Config reloads requested with SIGHUP
+    reload_tx: broadcast::Sender<()>,
 }
 
 impl GracefulShutdown {
@@ -40,15 +50,48 @@ impl GracefulShutdown {
         Self {
             shutdown_tx,
             shutdown_initiated: Arc::new(AtomicBool::new(false)),
+            initiated_at: Arc::new(OnceLock::new()),
             shutdown_timeout,
+            grace_period: Duration::ZERO,
+            force_close_after: None,
+            reload_tx: broadcast::channel(4).0,
+        }
+    }
+
+    /// Create a GracefulShutdown manager following the `shutdown` config section
+    pub fn from_config(config: &ShutdownConfig) -> Self {
+        Self {
+            grace_period: config.grace_period_before_drain(),
+            force_close_after: config.force_close_after(),
+            ..Self::with_timeout(config.drain_timeout())
         }
     }
 
+    /// How long in-flight requests get to finish once draining starts
+    pub fn drain_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+
+    /// How long the listeners keep serving normally after the shutdown signal
+    pub fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
+
+    /// How long after the shutdown signal to exit even if the listeners are still busy
+    pub fn force_close_after(&self) -> Option<Duration> {
+        self.force_close_after
+    }
+
     /// Get a receiver for shutdown signals
     pub fn subscribe(&self) -> broadcast::Receiver<ShutdownReason> {
         self.shutdown_tx.subscribe()
     }
 
+    /// Get a receiver for config reload requests (SIGHUP)
+    pub fn subscribe_reload(&self) -> broadcast::Receiver<()> {
+        self.reload_tx.subscribe()
+    }
+
     /// Check if shutdown has been initiated
     pub fn is_shutdown_initiated(&self) -> bool {
         self.shutdown_initiated.load(Ordering::Relaxed)
@@ -61,6 +104,7 @@ impl GracefulShutdown {
             .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
             .is_ok()
         {
+            let _ = self.initiated_at.set(Instant::now());
             tracing::info!("Shutdown manually triggered: {:?}", reason);
             let _ = self.shutdown_tx.send(reason);
         }
@@ -70,23 +114,34 @@ impl GracefulShutdown {
     /// Start listening for OS signals and manage shutdown process
     pub async fn run_signal_handler(&self) -> Result<()> {
         tracing::info!(
-            "Signal handler started. Listening for SIGTERM, SIGINT (graceful shutdown) and SIGUSR1 (restart)"
+            "Signal handler started. Listening for SIGTERM, SIGINT (graceful shutdown), SIGUSR1 (restart) and SIGHUP (config reload)"
         );
-
-        // Handle different signals concurrently
-        tokio::select! {
-            _ = signal::ctrl_c() => {
-                tracing::info!("Received SIGINT (Ctrl+C), initiating graceful shutdown...");
-                self.initiate_shutdown(ShutdownReason::Graceful);
-            }
-            _ = self.wait_for_sigterm() => {
-                tracing::info!("Received SIGTERM, initiating graceful shutdown...");
-                self.initiate_shutdown(ShutdownReason::Graceful);
-            }
-            _ = self.wait_for_sigusr1() => {
-                tracing::info!("Received SIGUSR1, initiating graceful restart...");
-                self.initiate_shutdown(ShutdownReason::Restart);
+        let mut sighup = Self::sighup_stream();
+
+        // Handle different signals concurrently; reloads don't end the handler
+        loop {
+            tokio::select! {
+                _ = signal::ctrl_c() => {
+                    tracing::info!("Received SIGINT (Ctrl+C), initiating graceful shutdown...");
+                    self.initiate_shutdown(ShutdownReason::Graceful);
+                }
+                _ = self.wait_for_sigterm() => {
+                    tracing::info!("Received SIGTERM, initiating graceful shutdown...");
+                    self.initiate_shutdown(ShutdownReason::Graceful);
+                }
+                _ = self.wait_for_sigusr1() => {
+                    tracing::info!("Received SIGUSR1, initiating graceful restart...");
+                    self.initiate_shutdown(ShutdownReason::Restart);
+                }
+                _ = Self::wait_for_sighup(&mut sighup) => {
+                    tracing::info!("Received SIGHUP, reloading configuration...");
+                    if self.reload_tx.send(()).is_err() {
+                        tracing::warn!("SIGHUP received but nothing reloads the configuration");
+                    }
+                    continue;
+                }
             }
+            break;
         }
 
         tracing::info!("Signal handler shutting down");
@@ -121,12 +176,42 @@ impl GracefulShutdown {
         std::future::pending::<()>().await;
     }
 
+    #[cfg(unix)]
+    fn sighup_stream() -> Option<tokio::signal::unix::Signal> {
+        use tokio::signal::unix::{SignalKind, signal};
+        signal(SignalKind::hangup())
+            .inspect_err(|e| tracing::error!("Failed to register SIGHUP handler: {}", e))
+            .ok()
+    }
+
+    #[cfg(unix)]
+    async fn wait_for_sighup(sighup: &mut Option<tokio::signal::unix::Signal>) {
+        match sighup {
+            Some(sighup) => {
+                sighup.recv().await;
+            }
+            None => std::future::pending::<()>().await,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn sighup_stream() -> Option<()> {
+        None
+    }
+
+    #[cfg(not(unix))]
+    async fn wait_for_sighup(_sighup: &mut Option<()>) {
+        // On non-Unix systems, SIGHUP is not available
+        std::future::pending::<()>().await;
+    }
+
     fn initiate_shutdown(&self, reason: ShutdownReason) {
         if self
             .shutdown_initiated
             .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
             .is_ok()
         {
+            let _ = self.initiated_at.set(Instant::now());
             tracing::info!("Processing shutdown signal: {:?}", reason);
             if let Err(e) = self.shutdown_tx.send(reason) {
                 tracing::error!("Failed to send shutdown signal: {}", e);
@@ -180,6 +265,9 @@ impl GracefulShutdown {
         ShutdownToken {
             receiver: self.subscribe(),
             shutdown_initiated: self.shutdown_initiated.clone(),
+            initiated_at: self.initiated_at.clone(),
+            grace_period: self.grace_period,
+            drain_timeout: self.shutdown_timeout,
         }
     }
 }
@@ -194,6 +282,9 @@ impl Default for GracefulShutdown {
 pub struct ShutdownToken {
     receiver: broadcast::Receiver<ShutdownReason>,
     shutdown_initiated: Arc<AtomicBool>,
+    initiated_at: Arc<OnceLock<Instant>>,
+    grace_period: Duration,
+    drain_timeout: Duration,
 }
 
 impl Clone for ShutdownToken {
@@ -201,6 +292,9 @@ impl Clone for ShutdownToken {
         Self {
             receiver: self.receiver.resubscribe(),
             shutdown_initiated: self.shutdown_initiated.clone(),
+            initiated_at: self.initiated_at.clone(),
+            grace_period: self.grace_period,
+            drain_timeout: self.drain_timeout,
         }
     }
 }
@@ -211,6 +305,24 @@ impl ShutdownToken {
         self.shutdown_initiated.load(Ordering::Relaxed)
     }
 
+    /// Whether the grace period after the shutdown signal is over, so new requests are
+    /// refused while in-flight ones finish
+    pub fn is_draining(&self) -> bool {
+        self.initiated_at
+            .get()
+            .is_some_and(|at| at.elapsed() >= self.grace_period)
+    }
+
+    /// How long the listeners keep serving normally after the shutdown signal
+    pub fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
+
+    /// How long in-flight requests get to finish once draining starts
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout
+    }
+
     /// Wait for shutdown signal (blocking)
     pub async fn cancelled(&mut self) -> ShutdownReason {
         match self.receiver.recv().await {
@@ -274,4 +386,24 @@ mod tests {
         assert!(elapsed >= Duration::from_millis(100));
         assert!(elapsed < Duration::from_millis(200)); // Should not take too much longer
     }
+
+    #[tokio::test]
+    async fn test_draining_starts_after_grace_period() {
+        let shutdown = GracefulShutdown::from_config(&ShutdownConfig {
+            grace_period_before_drain: "100ms".to_string(),
+            drain_timeout: "5s".to_string(),
+            force_close_after: Some("10s".to_string()),
+        });
+        assert_eq!(shutdown.drain_timeout(), Duration::from_secs(5));
+        assert_eq!(shutdown.force_close_after(), Some(Duration::from_secs(10)));
+        let token = shutdown.shutdown_token();
+        assert!(!token.is_draining());
+
+        shutdown.trigger_shutdown(ShutdownReason::Graceful).unwrap();
+        assert!(token.is_shutdown_requested());
+        assert!(!token.is_draining());
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(token.is_draining());
+    }
 }
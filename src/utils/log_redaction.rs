@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::RwLock;
+
+use hyper::HeaderMap;
+use once_cell::sync::Lazy;
+
+use crate::config::LogRedactionConfig;
+
+/// Logged in place of a redacted header value
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Redacted unless `log_redaction.redact_none` is set
+pub const DEFAULT_REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "proxy-authorization",
+];
+
+/// Lowercase names of the headers currently redacted; replaced when the config reloads
+static REDACTED_HEADERS: Lazy<RwLock<HashSet<String>>> =
+    Lazy::new(|| RwLock::new(redacted_headers(&LogRedactionConfig::default())));
+
+/// Apply a (re)loaded `log_redaction` section
+pub fn configure(config: &LogRedactionConfig) {
+    match REDACTED_HEADERS.write() {
+        Ok(mut headers) => *headers = redacted_headers(config),
+        Err(e) => tracing::error!("Failed to update redacted headers: {}", e),
+    }
+}
+
+fn redacted_headers(config: &LogRedactionConfig) -> HashSet<String> {
+    if config.redact_none {
+        return HashSet::new();
+    }
+    DEFAULT_REDACTED_HEADERS
+        .iter()
+        .map(|name| name.to_string())
+        .chain(config.headers.iter().map(|name| name.to_ascii_lowercase()))
+        .collect()
+}
+
+/// Whether values of header `name` are kept out of logs
+pub fn is_redacted(name: &str) -> bool {
+    REDACTED_HEADERS
+        .read()
+        // Fail closed: a poisoned lock shouldn't leak credentials
+        .map_or(true, |headers| headers.contains(&name.to_ascii_lowercase()))
+}
+
+/// `value`, or `[REDACTED]` if header `name` is redacted
+pub fn header_value<'a>(name: &str, value: &'a str) -> &'a str {
+    if is_redacted(name) { REDACTED } else { value }
+}
+
+/// Debug-formats a header map like `HeaderMap` does, with redacted values replaced
+pub struct RedactedHeaders<'a>(pub &'a HeaderMap);
+
+impl fmt::Debug for RedactedHeaders<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (name, value) in self.0 {
+            if is_redacted(name.as_str()) {
+                map.entry(name, &REDACTED);
+            } else {
+                map.entry(name, value);
+            }
+        }
+        map.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction_list() {
+        let extended = LogRedactionConfig {
+            headers: vec!["X-Session-Token".to_string()],
+            redact_none: false,
+        };
+        let headers = redacted_headers(&extended);
+        assert!(headers.contains("authorization") && headers.contains("x-session-token"));
+
+        let none = LogRedactionConfig {
+            redact_none: true,
+            ..extended
+        };
+        assert!(redacted_headers(&none).is_empty());
+    }
+
+    #[test]
+    fn test_redacted_headers_debug() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        headers.insert("accept", "text/html".parse().unwrap());
+        let logged = format!("{:?}", RedactedHeaders(&headers));
+        assert!(!logged.contains("secret"));
+        assert!(logged.contains("\"authorization\": \"[REDACTED]\""));
+        assert!(logged.contains("\"accept\": \"text/html\""));
+    }
+}
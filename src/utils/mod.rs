@@ -1,3 +1,6 @@
+pub mod config_reload;
 pub mod connection_tracker;
 pub mod graceful_shutdown;
 pub mod health_checker_utils;
+pub mod log_redaction;
+pub mod x509;
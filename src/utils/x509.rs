@@ -0,0 +1,340 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+/// The parts of an X.509 certificate the proxy cares about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// Issuer distinguished name, e.g. "CN=R11, O=Let's Encrypt, C=US"
+    pub issuer: String,
+    /// Subject distinguished name, e.g. "CN=ops-laptop, O=Example"
+    pub subject: String,
+    /// DNS names from the subjectAltName extension
+    pub dns_names: Vec<String>,
+    /// Contents of the serialNumber INTEGER
+    pub serial_number: Vec<u8>,
+    /// OCSP responder from the authorityInfoAccess extension
+    pub ocsp_url: Option<String>,
+}
+
+impl Certificate {
+    /// Whether the certificate is valid for `domain`
+    pub fn covers(&self, domain: &str) -> bool {
+        self.dns_names.iter().any(|name| name_matches(name, domain))
+    }
+}
+
+/// Whether certificate name `name` matches `domain`; a wildcard covers exactly one extra
+/// label, and a wildcard domain is only matched by the same wildcard
+pub fn name_matches(name: &str, domain: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    match name.strip_prefix("*.") {
+        Some(parent) => {
+            name == domain
+                || domain
+                    .split_once('.')
+                    .is_some_and(|(label, rest)| label != "*" && rest == parent)
+        }
+        None => name == domain,
+    }
+}
+
+const SEQUENCE: u8 = 0x30;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+/// `[3]` explicit tag wrapping the certificate extensions
+const EXTENSIONS: u8 = 0xa3;
+/// `[2]` implicit tag of a dNSName in a GeneralName
+const DNS_NAME: u8 = 0x82;
+/// `[6]` implicit tag of a uniformResourceIdentifier in a GeneralName
+const URI: u8 = 0x86;
+/// id-ce-subjectAltName (2.5.29.17)
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+/// id-pe-authorityInfoAccess (1.3.6.1.5.5.7.1.1)
+const AUTHORITY_INFO_ACCESS: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x01];
+/// id-ad-ocsp (1.3.6.1.5.5.7.48.1)
+const OCSP_ACCESS_METHOD: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01];
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+/// `[0]` explicit tag wrapping the certificate version
+const VERSION: u8 = 0xa0;
+
+/// Split one DER element off `input`: its tag, its contents and the remaining input
+pub(crate) fn read_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first & 0x80 == 0 {
+        (usize::from(first), rest)
+    } else {
+        let octets = usize::from(first & 0x7f);
+        if octets == 0 || octets > 4 || rest.len() < octets {
+            return None;
+        }
+        let len = rest[..octets]
+            .iter()
+            .fold(0, |len, byte| len << 8 | usize::from(*byte));
+        (len, &rest[octets..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// Read an element that must carry `tag`
+pub(crate) fn expect(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (found, contents, rest) = read_element(input)?;
+    (found == tag).then_some((contents, rest))
+}
+
+/// Parse a UTCTime (`YYMMDDHHMMSSZ`) or GeneralizedTime (`YYYYMMDDHHMMSSZ`)
+pub(crate) fn parse_time(tag: u8, contents: &[u8]) -> Option<DateTime<Utc>> {
+    let text = std::str::from_utf8(contents).ok()?;
+    let text = match tag {
+        // RFC 5280: two-digit years 50-99 are 19xx, 00-49 are 20xx
+        UTC_TIME => {
+            let century = if text.get(..2)? >= "50" { "19" } else { "20" };
+            format!("{century}{text}")
+        }
+        GENERALIZED_TIME => text.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&text, "%Y%m%d%H%M%SZ")
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Fields of a DER certificate's TBSCertificate, starting after the version
+fn tbs_fields(der: &[u8]) -> Option<&[u8]> {
+    let (certificate, _) = expect(der, SEQUENCE)?;
+    let (tbs, _) = expect(certificate, SEQUENCE)?;
+    match read_element(tbs)? {
+        (VERSION, _, rest) => Some(rest),
+        _ => Some(tbs),
+    }
+}
+
+/// Render a Name as "CN=..., O=...", keeping the common attribute types
+fn format_name(mut name: &[u8]) -> Option<String> {
+    let mut parts = Vec::new();
+    while !name.is_empty() {
+        let (set, rest) = expect(name, 0x31)?;
+        name = rest;
+        let (attribute, _) = expect(set, SEQUENCE)?;
+        let (oid, value) = expect(attribute, OBJECT_IDENTIFIER)?;
+        let label = match oid {
+            [0x55, 0x04, 0x03] => "CN",
+            [0x55, 0x04, 0x06] => "C",
+            [0x55, 0x04, 0x0a] => "O",
+            [0x55, 0x04, 0x0b] => "OU",
+            _ => continue,
+        };
+        let (_, value, _) = read_element(value)?;
+        parts.push(format!("{label}={}", String::from_utf8_lossy(value)));
+    }
+    Some(parts.join(", "))
+}
+
+/// Value of the extension identified by `id`, or `Some(None)` when it is absent
+fn extension<'a>(mut extensions: &'a [u8], id: &[u8]) -> Option<Option<&'a [u8]>> {
+    while !extensions.is_empty() {
+        let (extension, rest) = expect(extensions, SEQUENCE)?;
+        extensions = rest;
+        let (oid, mut fields) = expect(extension, OBJECT_IDENTIFIER)?;
+        if oid != id {
+            continue;
+        }
+        // Skip the optional critical flag
+        if let (0x01, _, rest) = read_element(fields)? {
+            fields = rest;
+        }
+        let (value, _) = expect(fields, OCTET_STRING)?;
+        return Some(Some(value));
+    }
+    Some(None)
+}
+
+/// DNS names of the subjectAltName extension, if present
+fn dns_names(extensions: &[u8]) -> Option<Vec<String>> {
+    let Some(value) = extension(extensions, SUBJECT_ALT_NAME)? else {
+        return Some(Vec::new());
+    };
+    let (mut general_names, _) = expect(value, SEQUENCE)?;
+    let mut names = Vec::new();
+    while !general_names.is_empty() {
+        let (tag, name, rest) = read_element(general_names)?;
+        general_names = rest;
+        if tag == DNS_NAME {
+            names.push(String::from_utf8_lossy(name).into_owned());
+        }
+    }
+    Some(names)
+}
+
+/// First OCSP responder URL of the authorityInfoAccess extension
+fn ocsp_url(extensions: &[u8]) -> Option<Option<String>> {
+    let Some(value) = extension(extensions, AUTHORITY_INFO_ACCESS)? else {
+        return Some(None);
+    };
+    let (mut descriptions, _) = expect(value, SEQUENCE)?;
+    while !descriptions.is_empty() {
+        let (description, rest) = expect(descriptions, SEQUENCE)?;
+        descriptions = rest;
+        let (method, location) = expect(description, OBJECT_IDENTIFIER)?;
+        if let (OCSP_ACCESS_METHOD, (URI, url, _)) = (method, read_element(location)?) {
+            return Some(Some(String::from_utf8_lossy(url).into_owned()));
+        }
+    }
+    Some(None)
+}
+
+/// Parse a DER-encoded certificate
+pub fn parse(der: &[u8]) -> Option<Certificate> {
+    let fields = tbs_fields(der)?;
+    // serialNumber and signature come before the issuer
+    let (serial_number, rest) = expect(fields, INTEGER)?;
+    let (_, _, rest) = read_element(rest)?;
+    let (issuer, rest) = expect(rest, SEQUENCE)?;
+    let (validity, rest) = expect(rest, SEQUENCE)?;
+    let (tag, not_before, validity) = read_element(validity)?;
+    let not_before = parse_time(tag, not_before)?;
+    let (tag, not_after, _) = read_element(validity)?;
+    let not_after = parse_time(tag, not_after)?;
+
+    // subjectPublicKeyInfo, then the optional unique IDs and extensions
+    let (subject, rest) = expect(rest, SEQUENCE)?;
+    let (_, _, mut rest) = read_element(rest)?;
+    let mut names = Vec::new();
+    let mut responder = None;
+    while !rest.is_empty() {
+        let (tag, contents, remaining) = read_element(rest)?;
+        rest = remaining;
+        if tag == EXTENSIONS {
+            let (extensions, _) = expect(contents, SEQUENCE)?;
+            names = dns_names(extensions)?;
+            responder = ocsp_url(extensions)?;
+        }
+    }
+
+    Some(Certificate {
+        not_before,
+        not_after,
+        issuer: format_name(issuer)?,
+        subject: format_name(subject)?,
+        dns_names: names,
+        serial_number: serial_number.to_vec(),
+        ocsp_url: responder,
+    })
+}
+
+/// The whole DER element at the start of `input`, header included
+fn whole_element(input: &[u8]) -> Option<&[u8]> {
+    let (_, _, rest) = read_element(input)?;
+    Some(&input[..input.len() - rest.len()])
+}
+
+/// The encoded issuer Name of a certificate, as hashed into OCSP requests
+pub fn issuer_name(der: &[u8]) -> Option<&[u8]> {
+    let (_, _, rest) = read_element(tbs_fields(der)?)?;
+    let (_, _, rest) = read_element(rest)?;
+    whole_element(rest)
+}
+
+/// The encoded subject Name of a certificate
+pub fn subject_name(der: &[u8]) -> Option<&[u8]> {
+    let mut rest = tbs_fields(der)?;
+    // serialNumber, signature, issuer and validity
+    for _ in 0..4 {
+        (_, _, rest) = read_element(rest)?;
+    }
+    whole_element(rest)
+}
+
+/// The subjectPublicKey bits of a certificate, without the unused-bits octet
+pub fn public_key(der: &[u8]) -> Option<&[u8]> {
+    let mut rest = tbs_fields(der)?;
+    for _ in 0..5 {
+        (_, _, rest) = read_element(rest)?;
+    }
+    let (key_info, _) = expect(rest, SEQUENCE)?;
+    let (_, _, key_info) = read_element(key_info)?;
+    let (bits, _) = expect(key_info, BIT_STRING)?;
+    bits.get(1..)
+}
+
+/// Parse the first (leaf) certificate of a PEM file
+pub fn parse_pem_file(path: &str) -> Option<Certificate> {
+    let file = std::fs::File::open(path).ok()?;
+    let cert = rustls_pemfile::certs(&mut std::io::BufReader::new(file)).next()?;
+    parse(&cert.ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_certificate() {
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec![
+            "app.example.com".to_string(),
+            "*.api.example.com".to_string(),
+        ])
+        .unwrap();
+        params.not_before = rcgen::date_time_ymd(2024, 3, 1);
+        // Dates from 2050 on are encoded as GeneralizedTime
+        params.not_after = rcgen::date_time_ymd(2051, 6, 30);
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(rcgen::DnType::OrganizationName, "Prox Test");
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "Prox Test CA");
+        params.serial_number = Some(rcgen::SerialNumber::from(vec![0x42, 0x01]));
+        // authorityInfoAccess with an OCSP responder
+        let url = b"http://ocsp.example.com";
+        let mut access = vec![0x30, 10 + 2 + url.len() as u8, 0x06, 8];
+        access.extend_from_slice(OCSP_ACCESS_METHOD);
+        access.extend_from_slice(&[URI, url.len() as u8]);
+        access.extend_from_slice(url);
+        let mut aia = vec![SEQUENCE, access.len() as u8];
+        aia.extend_from_slice(&access);
+        params
+            .custom_extensions
+            .push(rcgen::CustomExtension::from_oid_content(
+                &[1, 3, 6, 1, 5, 5, 7, 1, 1],
+                aia,
+            ));
+        let cert = params.self_signed(&key_pair).unwrap();
+
+        let parsed = parse(cert.der()).unwrap();
+        assert_eq!(
+            parsed.not_before,
+            Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parsed.not_after,
+            Utc.with_ymd_and_hms(2051, 6, 30, 0, 0, 0).unwrap()
+        );
+        assert_eq!(parsed.issuer, "O=Prox Test, CN=Prox Test CA");
+        assert_eq!(parsed.dns_names, ["app.example.com", "*.api.example.com"]);
+        assert_eq!(parsed.serial_number, [0x42, 0x01]);
+        assert_eq!(parsed.ocsp_url.as_deref(), Some("http://ocsp.example.com"));
+        assert_eq!(
+            issuer_name(cert.der()),
+            subject_name(cert.der()),
+            "self-signed"
+        );
+        assert_eq!(public_key(cert.der()), Some(key_pair.public_key_raw()));
+
+        assert!(parsed.covers("APP.example.com."));
+        assert!(parsed.covers("v1.api.example.com"));
+        assert!(parsed.covers("*.api.example.com"));
+        assert!(!parsed.covers("api.example.com"));
+        assert!(!parsed.covers("a.v1.api.example.com"));
+        assert!(!parsed.covers("*.example.com"));
+        assert!(parse(b"not a certificate").is_none());
+    }
+}